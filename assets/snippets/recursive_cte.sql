@@ -0,0 +1,11 @@
+-- Walks a hierarchy (e.g. parent/child rows) with a recursive common table expression.
+with recursive tree as (
+    select ${1:id}, ${2:parent_id}, 1 as depth
+    from ${3:$TABLE}
+    where ${2:parent_id} is null
+    union all
+    select child.${1:id}, child.${2:parent_id}, tree.depth + 1
+    from ${3:$TABLE} child
+    join tree on child.${2:parent_id} = tree.${1:id}
+)
+select * from tree;${0}
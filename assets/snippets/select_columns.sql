@@ -0,0 +1,5 @@
+-- Selects the chosen columns of a table.
+select $COLUMNS
+from $TABLE
+where ${1:true}
+limit ${2:100};${0}
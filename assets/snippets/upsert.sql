@@ -0,0 +1,5 @@
+-- Inserts a row, updating it instead if the key already exists (PostgreSQL and SQLite3 3.24+).
+insert into ${1:$TABLE} ($COLUMNS)
+values (${2:values})
+on conflict (${3:$COLUMN})
+do update set ${4:$COLUMN} = excluded.${4:$COLUMN};${0}
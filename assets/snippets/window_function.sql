@@ -0,0 +1,6 @@
+-- Ranks the rows of each partition and keeps a running total.
+select
+    $COLUMNS,
+    row_number() over (partition by ${1:$COLUMN} order by ${2:$COLUMN}) as rank,
+    sum(${3:$COLUMN}) over (partition by ${1:$COLUMN} order by ${2:$COLUMN}) as running_total
+from ${4:$TABLE};${0}
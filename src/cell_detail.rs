@@ -0,0 +1,248 @@
+use gtk::*;
+use gtk::prelude::*;
+use gdk_pixbuf::{Pixbuf, PixbufLoader, PixbufLoaderExt, InterpType};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Read, Write};
+use crate::tables::edit::EditValue;
+use crate::tables::detail::{self, Rendering};
+
+/// Bytes shown at the hex dumps. Larger values can still be saved to files.
+const MAX_DUMP_BYTES : usize = 64 * 1024;
+
+/// Images wider than this are scaled down to fit the pane.
+const MAX_IMAGE_WIDTH : i32 = 480;
+
+/// Pane showing the whole value of the selected cell: images are drawn, JSON and XML
+/// are indented, other binary data is shown as a hex dump and long text can be wrapped.
+/// Values can be saved to files, and files loaded into cells being edited.
+#[derive(Clone)]
+pub struct CellDetail {
+    pub pane : Box,
+    title : Label,
+    stack : Stack,
+    view : TextView,
+    image : Image,
+    save_btn : Button,
+    load_btn : Button,
+    value : Rc<RefCell<EditValue>>,
+    on_load : Rc<RefCell<Option<std::boxed::Box<dyn Fn(Vec<u8>)>>>>
+}
+
+impl CellDetail {
+
+    pub fn new() -> Self {
+        let pane = Box::new(Orientation::Vertical, 6);
+        pane.set_size_request(320, -1);
+        let bar = Box::new(Orientation::Horizontal, 6);
+        bar.set_margin_start(6);
+        bar.set_margin_end(6);
+        bar.set_margin_top(6);
+        let title = Label::new(Some("No cell selected"));
+        title.set_ellipsize(pango::EllipsizeMode::End);
+        title.set_xalign(0.0);
+        let wrap_toggle = ToggleButton::with_label("Wrap");
+        wrap_toggle.set_active(true);
+        wrap_toggle.set_tooltip_text(Some("Wrap long lines"));
+        let save_btn = Button::from_icon_name(Some("document-save-symbolic"), IconSize::Menu);
+        save_btn.set_tooltip_text(Some("Save value to file"));
+        let load_btn = Button::from_icon_name(Some("document-open-symbolic"), IconSize::Menu);
+        load_btn.set_tooltip_text(Some("Load file into the cell (while editing rows)"));
+        bar.pack_start(&title, true, true, 0);
+        bar.pack_start(&wrap_toggle, false, false, 0);
+        bar.pack_start(&save_btn, false, false, 0);
+        bar.pack_start(&load_btn, false, false, 0);
+
+        let view = TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.set_wrap_mode(WrapMode::WordChar);
+        view.set_left_margin(6);
+        view.set_right_margin(6);
+        let text_scroll = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        text_scroll.add(&view);
+        let image = Image::new();
+        let image_scroll = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        image_scroll.add(&image);
+        let stack = Stack::new();
+        stack.add_named(&text_scroll, "text");
+        stack.add_named(&image_scroll, "image");
+        pane.pack_start(&bar, false, false, 0);
+        pane.pack_start(&stack, true, true, 0);
+        pane.show_all();
+
+        {
+            let view = view.clone();
+            wrap_toggle.connect_toggled(move |btn| {
+                view.set_wrap_mode(if btn.get_active() { WrapMode::WordChar } else { WrapMode::None });
+            });
+        }
+        let value = Rc::new(RefCell::new(EditValue::Null));
+        {
+            let value = value.clone();
+            save_btn.connect_clicked(move |_| {
+                if let Ok(value) = value.try_borrow() {
+                    if let Err(e) = Self::save_value(&value) {
+                        println!("{}", e);
+                    }
+                } else {
+                    println!("Unable to borrow cell value");
+                }
+            });
+        }
+        let on_load : Rc<RefCell<Option<std::boxed::Box<dyn Fn(Vec<u8>)>>>> = Rc::new(RefCell::new(None));
+        {
+            let on_load = on_load.clone();
+            load_btn.connect_clicked(move |_| {
+                match Self::load_file() {
+                    Ok(Some(data)) => {
+                        if let Ok(on_load) = on_load.try_borrow() {
+                            if let Some(f) = on_load.as_ref() {
+                                f(data);
+                            }
+                        }
+                    },
+                    Ok(None) => { },
+                    Err(e) => println!("{}", e)
+                }
+            });
+        }
+        let detail = Self { pane, title, stack, view, image, save_btn, load_btn, value, on_load };
+        detail.clear();
+        detail
+    }
+
+    /// Sets what happens to the content of files loaded by the user.
+    pub fn connect_load<F>(&self, f : F)
+    where
+        F : Fn(Vec<u8>) + 'static
+    {
+        if let Ok(mut on_load) = self.on_load.try_borrow_mut() {
+            *on_load = Some(std::boxed::Box::new(f));
+        } else {
+            println!("Unable to borrow load callback");
+        }
+    }
+
+    fn save_value(value : &EditValue) -> Result<(), String> {
+        let data = match value {
+            EditValue::Null => return Err(String::from("Null values cannot be saved")),
+            EditValue::Text(t) => t.as_bytes(),
+            EditValue::Bytes(b) => &b[..]
+        };
+        let dialog = FileChooserNative::new(Some("Save value"), None::<&Window>, FileChooserAction::Save, Some("Save"), Some("Cancel"));
+        dialog.set_do_overwrite_confirmation(true);
+        if dialog.run() != ResponseType::Accept {
+            return Ok(());
+        }
+        let path = dialog.get_filename().ok_or(String::from("No file chosen"))?;
+        let mut f = File::create(&path).map_err(|e| format!("{}", e) )?;
+        f.write_all(data).map_err(|e| format!("{}", e) )
+    }
+
+    fn load_file() -> Result<Option<Vec<u8>>, String> {
+        let dialog = FileChooserNative::new(Some("Load file into cell"), None::<&Window>, FileChooserAction::Open, Some("Load"), Some("Cancel"));
+        if dialog.run() != ResponseType::Accept {
+            return Ok(None);
+        }
+        let path = dialog.get_filename().ok_or(String::from("No file chosen"))?;
+        let mut data = Vec::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_end(&mut data) )
+            .map_err(|e| format!("{}", e) )?;
+        Ok(Some(data))
+    }
+
+    fn load_pixbuf(data : &[u8]) -> Result<Pixbuf, String> {
+        let loader = PixbufLoader::new();
+        loader.write(data).map_err(|e| format!("{}", e) )?;
+        loader.close().map_err(|e| format!("{}", e) )?;
+        let pxb = loader.get_pixbuf().ok_or(String::from("Unable to decode image"))?;
+        let (w, h) = (pxb.get_width(), pxb.get_height());
+        if w > MAX_IMAGE_WIDTH {
+            let scaled_h = ((h as f64) * (MAX_IMAGE_WIDTH as f64) / (w as f64)).max(1.) as i32;
+            pxb.scale_simple(MAX_IMAGE_WIDTH, scaled_h, InterpType::Bilinear)
+                .ok_or(String::from("Unable to scale image"))
+        } else {
+            Ok(pxb)
+        }
+    }
+
+    fn show_text(&self, text : &str) {
+        if let Some(buffer) = self.view.get_buffer() {
+            buffer.set_text(text);
+        }
+        self.stack.set_visible_child_name("text");
+    }
+
+    pub fn clear(&self) {
+        self.title.set_text("No cell selected");
+        self.show_text("");
+        self.save_btn.set_sensitive(false);
+        self.load_btn.set_sensitive(false);
+    }
+
+    /// Shows the value of a cell, described by title. Loadable tells whether
+    /// files can be loaded into the cell.
+    pub fn show_value(&self, title : &str, value : EditValue, loadable : bool) {
+        let kind = match &value {
+            EditValue::Null => {
+                self.show_text("NULL");
+                "null"
+            },
+            EditValue::Text(t) => self.show_rendering(detail::render_text(t), &[]),
+            EditValue::Bytes(b) => self.show_rendering(detail::render_bytes(b, MAX_DUMP_BYTES), b)
+        };
+        self.title.set_text(&format!("{} ({})", title, kind));
+        self.title.set_tooltip_text(Some(title));
+        self.save_btn.set_sensitive(value != EditValue::Null);
+        self.load_btn.set_sensitive(loadable);
+        if let Ok(mut curr) = self.value.try_borrow_mut() {
+            *curr = value;
+        } else {
+            println!("Unable to borrow cell value");
+        }
+    }
+
+    /// Shows the rendering of a value (which holds data, for binary values), returning its kind.
+    fn show_rendering(&self, rendering : Rendering, data : &[u8]) -> &'static str {
+        match rendering {
+            Rendering::Image(fmt) => {
+                match Self::load_pixbuf(data) {
+                    Ok(pxb) => {
+                        self.image.set_from_pixbuf(Some(&pxb));
+                        self.stack.set_visible_child_name("image");
+                        match fmt {
+                            detail::ImageFormat::Png => "PNG image",
+                            detail::ImageFormat::Jpeg => "JPEG image",
+                            detail::ImageFormat::Svg => "SVG image"
+                        }
+                    },
+                    Err(e) => {
+                        println!("{}", e);
+                        self.show_text(&detail::hex_dump(data, MAX_DUMP_BYTES));
+                        "binary"
+                    }
+                }
+            },
+            Rendering::Hex(dump) => {
+                self.show_text(&dump);
+                "binary"
+            },
+            Rendering::Json(json) => {
+                self.show_text(&json);
+                "JSON"
+            },
+            Rendering::Xml(xml) => {
+                self.show_text(&xml);
+                "XML"
+            },
+            Rendering::Text(text) => {
+                self.show_text(&text);
+                "text"
+            }
+        }
+    }
+
+}
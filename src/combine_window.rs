@@ -0,0 +1,191 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::environment::TableEnvironment;
+use crate::tables::combine::{self, JoinKind};
+use crate::table_notebook::{TableNotebook, TableBar, TableSource};
+use crate::plots::plot_workspace::PlotWorkspace;
+use crate::status_stack::StatusStack;
+use crate::utils;
+
+/// Joins two tables, unites their rows or filters the rows of a table, adding the result after
+/// the tables of the environment. Since the tables are combined in memory, they can come
+/// from different queries, files, commands or connections.
+#[derive(Clone)]
+pub struct CombineWindow {
+    dialog : Dialog,
+    label : Label,
+    op_combo : ComboBoxText,
+    left_combo : ComboBoxText,
+    right_combo : ComboBoxText,
+    join_combo : ComboBoxText,
+    keys_entry : Entry,
+    filter_entry : Entry,
+    distinct_check : CheckButton,
+    tbl_nb : TableNotebook,
+    t_env : Rc<RefCell<TableEnvironment>>,
+    workspace : PlotWorkspace,
+    table_bar : TableBar,
+    status_stack : StatusStack
+}
+
+impl CombineWindow {
+
+    pub fn build(
+        builder : &Builder,
+        tbl_nb : TableNotebook,
+        t_env : Rc<RefCell<TableEnvironment>>,
+        workspace : PlotWorkspace,
+        table_bar : TableBar,
+        status_stack : StatusStack
+    ) -> Self {
+        let dialog : Dialog = builder.get_object("combine_dialog").unwrap();
+        let label : Label = builder.get_object("combine_label").unwrap();
+        let op_combo : ComboBoxText = builder.get_object("combine_op_combo").unwrap();
+        let left_combo : ComboBoxText = builder.get_object("combine_left_combo").unwrap();
+        let right_combo : ComboBoxText = builder.get_object("combine_right_combo").unwrap();
+        let join_combo : ComboBoxText = builder.get_object("combine_join_combo").unwrap();
+        let keys_entry : Entry = builder.get_object("combine_keys_entry").unwrap();
+        let filter_entry : Entry = builder.get_object("combine_filter_entry").unwrap();
+        let distinct_check : CheckButton = builder.get_object("combine_distinct_check").unwrap();
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            glib::signal::Inhibit(true)
+        });
+        let combine_win = Self {
+            dialog,
+            label,
+            op_combo,
+            left_combo,
+            right_combo,
+            join_combo,
+            keys_entry,
+            filter_entry,
+            distinct_check,
+            tbl_nb,
+            t_env,
+            workspace,
+            table_bar,
+            status_stack
+        };
+        {
+            let combine_win = combine_win.clone();
+            combine_win.op_combo.clone().connect_changed(move |_| {
+                combine_win.update_sensitive();
+            });
+        }
+        combine_win
+    }
+
+    fn operation(&self) -> String {
+        self.op_combo.get_active_id().map(|id| id.to_string() ).unwrap_or(String::from("join"))
+    }
+
+    /// Enables only the fields used by the chosen operation.
+    fn update_sensitive(&self) {
+        let op = self.operation();
+        self.right_combo.set_sensitive(&op[..] != "filter");
+        self.join_combo.set_sensitive(&op[..] == "join");
+        self.keys_entry.set_sensitive(&op[..] == "join");
+        self.distinct_check.set_sensitive(&op[..] == "union");
+        self.filter_entry.set_sensitive(&op[..] == "filter");
+    }
+
+    /// Lists the tables of the environment, choosing the table at the current page
+    /// as the first table and the one after it as the other table.
+    fn update_tables(&self) -> usize {
+        self.left_combo.remove_all();
+        self.right_combo.remove_all();
+        let n = match self.t_env.try_borrow() {
+            Ok(env) => {
+                for (i, tbl) in env.all_tables().iter().enumerate() {
+                    let (nrows, ncols) = tbl.shape();
+                    let name = tbl.table_info().0.unwrap_or(String::from("Table"));
+                    let text = format!("{}. {} ({} x {})", i + 1, name, nrows, ncols);
+                    self.left_combo.append(Some(&i.to_string()), &text);
+                    self.right_combo.append(Some(&i.to_string()), &text);
+                }
+                env.all_tables().len()
+            },
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                0
+            }
+        };
+        if n > 0 {
+            let curr = self.tbl_nb.get_page_index().min(n - 1);
+            self.left_combo.set_active(Some(curr as u32));
+            self.right_combo.set_active(Some(((curr + 1) % n) as u32));
+        }
+        n
+    }
+
+    fn active_table(combo : &ComboBoxText) -> Result<usize, String> {
+        combo.get_active_id()
+            .and_then(|id| id.as_str().parse::<usize>().ok() )
+            .ok_or(String::from("Choose a table"))
+    }
+
+    fn combine(&self) -> Result<(), String> {
+        let left_ix = Self::active_table(&self.left_combo)?;
+        let op = self.operation();
+        let tbl = match self.t_env.try_borrow() {
+            Ok(env) => {
+                let tbls = env.all_tables();
+                let left = tbls.get(left_ix).ok_or(String::from("Table not found"))?;
+                match &op[..] {
+                    "filter" => combine::filter(left, &self.filter_entry.get_text())?,
+                    _ => {
+                        let right_ix = Self::active_table(&self.right_combo)?;
+                        let right = tbls.get(right_ix).ok_or(String::from("Table not found"))?;
+                        if &op[..] == "union" {
+                            combine::union(left, right, self.distinct_check.get_active())?
+                        } else {
+                            let kind = self.join_combo.get_active_id()
+                                .and_then(|id| JoinKind::from_name(id.as_str()) )
+                                .unwrap_or(JoinKind::Inner);
+                            let keys = combine::parse_keys(&self.keys_entry.get_text(), &left.names(), &right.names())?;
+                            combine::join(left, right, &keys[..], kind)?
+                        }
+                    }
+                }
+            },
+            Err(_) => return Err(String::from("Unable to borrow table environment"))
+        };
+        let name = tbl.table_info().0.unwrap_or(String::from("Table"));
+        utils::add_table(
+            &self.t_env,
+            &self.tbl_nb,
+            TableSource::Derived(name),
+            tbl,
+            &self.workspace,
+            &self.table_bar,
+            &self.status_stack
+        )
+    }
+
+    /// Shows the dialog, which stays open, showing the error, if the tables cannot be combined.
+    pub fn show(&self) {
+        let n = self.update_tables();
+        self.update_sensitive();
+        if n == 0 {
+            self.label.set_text("No tables to combine");
+        } else {
+            self.label.set_text("Tables are combined in memory, so they can come from different connections.");
+        }
+        loop {
+            match self.dialog.run() {
+                ResponseType::Other(1) if n > 0 => {
+                    match self.combine() {
+                        Ok(_) => break,
+                        Err(e) => self.label.set_text(&e)
+                    }
+                },
+                _ => break
+            }
+        }
+        self.dialog.hide();
+    }
+
+}
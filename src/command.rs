@@ -21,7 +21,8 @@ use std::io::BufWriter;
 use std::io::Read;
 use std::sync::mpsc::{channel, Sender, Receiver};
 use std::process::Stdio;
-// use crate::table_popover::TablePopover;
+use std::time::{Duration, Instant};
+// use crate::table_popover::{TablePopover, CsvWindow};
 use std::sync::{Arc, Mutex};
 use crate::status_stack::Status;
 use crate::table_notebook::TableSource;
@@ -37,19 +38,19 @@ pub struct Output {
 #[derive(Debug)]
 pub struct Executor {
     // Command, with optional content to its standard input
-    cmd_send : Sender<(String, Option<String>)>,
+    cmd_send : Sender<(String, Option<Vec<u8>>)>,
     ans_recv : Receiver<Output>
 }
 
 impl Executor {
 
     pub fn new() -> Self {
-        let (cmd_send, cmd_recv) = channel::<(String, Option<String>)>();
+        let (cmd_send, cmd_recv) = channel::<(String, Option<Vec<u8>>)>();
         let (ans_send, ans_recv) = channel::<Output>();
         thread::spawn(move || {
             loop {
                 if let Ok((cmd, tbl)) = cmd_recv.recv() {
-                    match run_command(&cmd[..], tbl) {
+                    match run_command(&cmd[..], tbl, None) {
                         Ok(txt) => {
                             if let Err(e) = ans_send.send(Output { cmd, status : true, txt }) {
                                 println!("{}", e);
@@ -69,7 +70,7 @@ impl Executor {
         Self{ cmd_send, ans_recv }
     }
 
-    pub fn queue_command(&self, cmd : String, tbl_csv : Option<String>) -> Result<(), String> {
+    pub fn queue_command(&self, cmd : String, tbl_csv : Option<Vec<u8>>) -> Result<(), String> {
         match self.cmd_send.send((cmd, tbl_csv)) {
             Ok(_) => {
                 // cmd_entry.set_sensitive(false);
@@ -96,50 +97,85 @@ impl Executor {
     
 }
 
-fn run_command(cmd : &str, opt_tbl : Option<String>) -> Result<String, String> {
-    // TODO treat quoted arguments with whitespace as single units
-    let split_cmd : Vec<_> = cmd.split(' ').collect();
-    let cmd_name = split_cmd.get(0).ok_or(String::from("Command name missing"))?;
-    let mut cmd = Command::new(&cmd_name)
-        .args(split_cmd.get(1..).unwrap_or(&[]))
-        .stdin(Stdio::piped())
+/// Runs a command through the shell (so pipes, redirections, variables and quoted arguments
+/// work), writing the table to its standard input if any. The command is killed if it does not
+/// finish within the timeout. Returns its standard output.
+fn run_command(cmd : &str, opt_tbl : Option<Vec<u8>>, timeout : Option<Duration>) -> Result<String, String> {
+    if cmd.trim().is_empty() {
+        return Err(String::from("Command name missing"));
+    }
+    let mut child = Command::new("sh")
+        .args(&["-c", cmd])
+        .stdin(if opt_tbl.is_some() { Stdio::piped() } else { Stdio::null() })
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
         .map_err(|e| format!("{}", e))?;
-    
-    if let Some(tbl) = opt_tbl {
-        let mut outstdin = cmd.stdin.take().unwrap();
-        let mut writer = BufWriter::new(&mut outstdin);
-        writer.write_all(tbl.as_bytes()).map_err(|e| format!("{}", e))?;
+
+    // Pipes are written and read at their own threads, so commands exchanging more than the
+    // pipe buffer can finish.
+    let in_writer = match (opt_tbl, child.stdin.take()) {
+        (Some(tbl), Some(mut stdin)) => Some(thread::spawn(move || {
+            let mut writer = BufWriter::new(&mut stdin);
+            writer.write_all(&tbl).and_then(|_| writer.flush() )
+        })),
+        _ => None
+    };
+    let mut stdout = child.stdout.take().ok_or(String::from("Unable to read process stdout"))?;
+    let mut stderr = child.stderr.take().ok_or(String::from("Unable to read process stderr"))?;
+    let out_reader = thread::spawn(move || {
+        let mut content = Vec::new();
+        stdout.read_to_end(&mut content).map(|_| content )
+    });
+    let err_reader = thread::spawn(move || {
+        let mut content = Vec::new();
+        stderr.read_to_end(&mut content).map(|_| content )
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|e| format!("{}", e))? {
+            Some(status) => break status,
+            None if timeout.map(|t| start.elapsed() >= t ).unwrap_or(false) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "Command {} did not finish within {} seconds",
+                    cmd,
+                    timeout.map(|t| t.as_secs() ).unwrap_or(0)
+                ));
+            },
+            None => thread::sleep(Duration::from_millis(10))
+        }
+    };
+    if let Some(writer) = in_writer {
+        // A command which does not read its whole input (e.g. head) closes the pipe early.
+        if let Ok(Err(e)) = writer.join() {
+            println!("{}", e);
+        }
     }
-    
-    // let mut stdout = cmd.stdout.take().ok_or(format!("Unable to read process stdout"))?;
-    // let mut stderr = cmd.stderr.take().ok_or(format!("Unable to read process stderr"))?;
-    
-    let output = cmd.wait_with_output().map_err(|e| format!("{}", e))?;
-    
-    if output.status.success() {
-        let mut stdout_content = String::from_utf8(output.stdout.clone())
-            .map_err(|e| format!("{}", e))?;
-        //    output.stdout.read_to_string(&mut stdout_content)
-        //    .map_err(|e| format!("Error capturing stdout: {}", e))?;
-        Ok(stdout_content)
+    let stdout_content = out_reader.join()
+        .map_err(|_| String::from("Unable to read process stdout"))?
+        .map_err(|e| format!("{}", e))?;
+    let stderr_content = err_reader.join()
+        .map_err(|_| String::from("Unable to read process stderr"))?
+        .map_err(|e| format!("{}", e))?;
+    if status.success() {
+        String::from_utf8(stdout_content).map_err(|e| format!("{}", e))
     } else {
-        //let mut stderr_content = String::new();
-        // if let Err(e) = output.stderr.read_to_string(&mut stderr_content) {
-        //    println!("{}", e);
-        // }
-        let mut stderr_content = String::from_utf8(output.stderr.clone())
-            .map_err(|e| format!("{}", e))?;
-
-        let code = output.status.code()
+        let code = status.code()
             .map(|code| code.to_string())
             .unwrap_or(String::from("Unknown"));
-        Err(format!("Command error (Code: {}) {}", code, stderr_content))
+        Err(format!("Command error (Code: {}) {}", code, String::from_utf8_lossy(&stderr_content)))
     }
 }
 
+/// Runs a command as the executor does, without input, killing it if it does not finish within
+/// the timeout. Used from background threads, since the calling thread waits for the command.
+pub fn run_with_timeout(cmd : &str, timeout : Duration) -> Result<String, String> {
+    run_command(cmd, None, Some(timeout))
+}
+
 #[derive(Clone, Debug)]
 pub struct CommandWindow {
     pub win : Window,
@@ -149,7 +185,10 @@ pub struct CommandWindow {
     run_btn : Button,
     recent : RecentList,
     exec : Arc<Mutex<(Executor,String)>>,
-    expect_input : Rc<RefCell<bool>>
+    expect_input : Rc<RefCell<bool>>,
+
+    // Settings the table sent to the standard input is written with.
+    csv_window : CsvWindow
 }
 
 impl CommandWindow {
@@ -161,7 +200,7 @@ impl CommandWindow {
     fn queue_execution(
         exec : &Executor, 
         cmd : &str, 
-        stdin : Option<String>, 
+        stdin : Option<Vec<u8>>, 
         cmd_entry : &Entry, 
         clear_btn : &Button, 
         run_btn : &Button
@@ -181,7 +220,8 @@ impl CommandWindow {
     pub fn build(
         builder : &Builder, 
         table_notebook : &TableNotebook, 
-        tbl_env : Rc<RefCell<TableEnvironment>>
+        tbl_env : Rc<RefCell<TableEnvironment>>,
+        csv_window : &CsvWindow
     ) -> Self {
         let win : Window = builder.get_object("cmd_window").unwrap();
         let cmd_entry : Entry = builder.get_object("cmd_entry").unwrap();
@@ -211,6 +251,7 @@ impl CommandWindow {
             let table_notebook = table_notebook.clone();
             let exec = exec.clone();
             let expect_input = expect_input.clone();
+            let csv_window = csv_window.clone();
             run_btn.connect_clicked(move |run_btn| {
                 let g_txt = cmd_entry.get_text();
                 let txt = g_txt.as_str();
@@ -223,7 +264,10 @@ impl CommandWindow {
                             let (exec, tbl) = &*guard;
                             if *expect_input.borrow() {
                                 let ix = table_notebook.get_page_index();
-                                if let Some(tbl_csv) = t_env.all_tables().get(ix).map(|tbl| tbl.to_csv() ) {
+                                let settings = csv_window.settings();
+                                let opt_csv = t_env.all_tables().get(ix)
+                                    .map(|tbl| settings.csv.encoding.encode(&tbl.csv_with(&settings)) );
+                                if let Some(tbl_csv) = opt_csv {
                                     println!("Executing command with input table");
                                     Self::queue_execution(&exec, txt, Some(tbl_csv), &cmd_entry, &clear_btn, &run_btn);
                                     true
@@ -257,7 +301,8 @@ impl CommandWindow {
             recent,
             cmd_list,
             expect_input,
-            exec
+            exec,
+            csv_window : csv_window.clone()
         };
         list
     }
@@ -333,3 +378,11 @@ impl CommandWindow {
     }
 }
 
+#[test]
+fn run_shell_commands() {
+    let timeout = Duration::from_secs(5);
+    assert_eq!(run_with_timeout("printf '%s\\n' a b | paste -sd, -", timeout).unwrap(), "a,b\n");
+    assert_eq!(run_with_timeout("echo \"two  words\"", timeout).unwrap(), "two  words\n");
+    assert_eq!(run_command("tr a-z A-Z", Some(b"abc".to_vec()), None).unwrap(), "ABC");
+    assert!(run_with_timeout("sleep 5", Duration::from_millis(100)).is_err());
+}
@@ -0,0 +1,268 @@
+use gtk::*;
+use gtk::prelude::*;
+use sourceview::*;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use regex::Regex;
+use once_cell::sync::Lazy;
+use crate::tables::environment::DBObject;
+use crate::tables::split::{self, Dialect};
+
+/// Table names (with their aliases) at the from and join clauses of a statement.
+static TABLE_REGEX : Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(?:from|join)\s+([A-Za-z_][\w\.]*)(?:\s+(?:as\s+)?([A-Za-z_]\w*))?").unwrap()
+});
+
+/// Table name or alias qualifying the word being typed (e.g. "t.col").
+static QUALIFIER_REGEX : Lazy<Regex> = Lazy::new(|| Regex::new(r"([A-Za-z_]\w*)\.\w*$").unwrap() );
+
+const SQL_KEYWORDS : [&'static str; 72] = [
+    "select", "from", "where", "group", "by", "order", "having", "limit", "offset",
+    "insert", "into", "values", "update", "set", "delete", "create", "table", "view",
+    "index", "drop", "alter", "add", "column", "truncate", "join", "inner", "left",
+    "right", "full", "outer", "cross", "on", "using", "as", "distinct", "union",
+    "intersect", "except", "all", "and", "or", "not", "null", "is", "in", "exists",
+    "between", "like", "ilike", "case", "when", "then", "else", "end", "asc", "desc",
+    "with", "recursive", "returning", "primary", "key", "foreign", "references",
+    "default", "unique", "check", "begin", "commit", "rollback", "cast", "true", "false"
+];
+
+/// Words that may follow a table name at a from clause without being an alias.
+fn is_keyword(word : &str) -> bool {
+    let lower = word.to_lowercase();
+    SQL_KEYWORDS.iter().any(|k| *k == &lower[..])
+}
+
+/// Completion shared by all SQL editors. Each kind of word is offered by its own
+/// CompletionWords provider, which reads the words from a hidden buffer. The column
+/// provider is updated as the cursor moves, so it only offers the columns of the tables
+/// at the from clause of the current statement (or of a single table when the word being
+/// typed is qualified by a table name or alias).
+#[derive(Clone)]
+pub struct SqlCompletion {
+    schema_buffer : sourceview::Buffer,
+    column_buffer : sourceview::Buffer,
+    function_buffer : sourceview::Buffer,
+    providers : Vec<CompletionWords>,
+
+    /// Column names of each table, keyed by both the table name and its qualified name.
+    columns : Rc<RefCell<HashMap<String, Vec<String>>>>,
+
+    /// Lexical rules of the current connection, by which the text is split into statements.
+    dialect : Rc<Cell<Dialect>>,
+
+    /// Words currently at the column buffer, so it is only rewritten when the scope changes.
+    scoped : Rc<RefCell<String>>
+}
+
+impl SqlCompletion {
+
+    pub fn new() -> Self {
+        let schema_buffer = sourceview::Buffer::new(None::<&TextTagTable>);
+        let column_buffer = sourceview::Buffer::new(None::<&TextTagTable>);
+        let function_buffer = sourceview::Buffer::new(None::<&TextTagTable>);
+        let keyword_buffer = sourceview::Buffer::new(None::<&TextTagTable>);
+        keyword_buffer.set_text(&SQL_KEYWORDS.join(" ")[..]);
+        let sources = [
+            ("Columns", &column_buffer, 4),
+            ("Tables", &schema_buffer, 3),
+            ("Functions", &function_buffer, 2),
+            ("Keywords", &keyword_buffer, 1)
+        ];
+        let mut providers = Vec::new();
+        for (name, buffer, priority) in sources.iter() {
+            let words = CompletionWords::new(Some(*name), None::<&gdk_pixbuf::Pixbuf>);
+            words.set_property_priority(*priority);
+            words.register(*buffer);
+            providers.push(words);
+        }
+        Self {
+            schema_buffer,
+            column_buffer,
+            function_buffer,
+            providers,
+            columns : Rc::new(RefCell::new(HashMap::new())),
+            dialect : Rc::new(Cell::new(Dialect::Postgres)),
+            scoped : Rc::new(RefCell::new(String::new()))
+        }
+    }
+
+    /// Adds the completion providers to an editor view, and starts updating the
+    /// scope of the column provider as the cursor of the view moves.
+    pub fn attach(&self, view : &View) {
+        if let Some(completion) = view.get_completion() {
+            for provider in self.providers.iter() {
+                if let Err(e) = completion.add_provider(provider) {
+                    println!("Unable to add completion provider: {}", e);
+                }
+            }
+        }
+        if let Some(buffer) = view.get_buffer() {
+            let completion = self.clone();
+            buffer.connect_property_cursor_position_notify(move |buffer| {
+                let text = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), true)
+                    .map(|txt| txt.to_string() )
+                    .unwrap_or(String::new());
+                let pos = buffer.get_property_cursor_position() as usize;
+                completion.update_scope(&text, pos);
+            });
+        }
+    }
+
+    /// Replaces the schema, table and column names (called whenever the schema tree is repopulated).
+    pub fn update_schema(&self, objs : &[DBObject]) {
+        let mut names = Vec::new();
+        let mut columns = HashMap::new();
+        for obj in objs.iter() {
+            match obj {
+                DBObject::Schema{ name, children } => {
+                    names.push(name.clone());
+                    for child in children.iter() {
+                        if let DBObject::Table{ name : tbl_name, cols } = child {
+                            let col_names : Vec<String> = cols.iter().map(|c| c.0.clone() ).collect();
+                            columns.insert(format!("{}.{}", name, tbl_name), col_names.clone());
+                            columns.insert(tbl_name.clone(), col_names);
+                            names.push(tbl_name.clone());
+                        }
+                    }
+                },
+                DBObject::Table{ name, cols } => {
+                    columns.insert(name.clone(), cols.iter().map(|c| c.0.clone() ).collect());
+                    names.push(name.clone());
+                }
+            }
+        }
+        self.schema_buffer.set_text(&names.join(" ")[..]);
+        if let Ok(mut old_columns) = self.columns.try_borrow_mut() {
+            *old_columns = columns;
+        } else {
+            println!("Unable to borrow completion columns");
+        }
+        self.set_scoped_words(self.all_columns());
+    }
+
+    pub fn set_dialect(&self, dialect : Dialect) {
+        self.dialect.set(dialect);
+    }
+
+    /// Replaces the server and client function names.
+    pub fn update_functions(&self, names : &[String]) {
+        self.function_buffer.set_text(&names.join(" ")[..]);
+    }
+
+    pub fn clear(&self) {
+        self.update_schema(&[]);
+        self.update_functions(&[]);
+    }
+
+    fn all_columns(&self) -> Vec<String> {
+        let mut cols : Vec<String> = self.columns.borrow().values()
+            .flat_map(|cols| cols.iter().cloned() )
+            .collect();
+        cols.sort();
+        cols.dedup();
+        cols
+    }
+
+    fn set_scoped_words(&self, cols : Vec<String>) {
+        let words = cols.join(" ");
+        if let Ok(mut scoped) = self.scoped.try_borrow_mut() {
+            if *scoped != words {
+                self.column_buffer.set_text(&words[..]);
+                *scoped = words;
+            }
+        }
+    }
+
+    fn update_scope(&self, text : &str, pos : usize) {
+        let cols = {
+            let columns = match self.columns.try_borrow() {
+                Ok(columns) => columns,
+                Err(_) => return
+            };
+            match scoped_columns(text, pos, &columns, self.dialect.get()) {
+                Some(cols) => cols,
+                None => {
+                    drop(columns);
+                    self.all_columns()
+                }
+            }
+        };
+        self.set_scoped_words(cols);
+    }
+
+}
+
+/// Tables referenced at the from and join clauses of a statement, with their aliases.
+fn referenced_tables(stmt : &str) -> Vec<(String, Option<String>)> {
+    TABLE_REGEX.captures_iter(stmt)
+        .map(|cap| {
+            let alias = cap.get(2)
+                .map(|m| m.as_str().to_string() )
+                .filter(|a| !is_keyword(a) );
+            (cap[1].to_string(), alias)
+        })
+        .collect()
+}
+
+/// Columns to be offered at the character position pos (not byte position) of the text, or
+/// None if the statement at this position does not reference any known table. Statements are
+/// split as they are sent, so semicolons within literals, comments and function bodies are ignored.
+fn scoped_columns(
+    text : &str,
+    pos : usize,
+    columns : &HashMap<String, Vec<String>>,
+    dialect : Dialect
+) -> Option<Vec<String>> {
+    let byte_pos = text.char_indices().nth(pos).map(|(i, _)| i ).unwrap_or(text.len());
+    let spans = split::statement_spans(text, dialect).ok()?;
+    let (start, end) = *spans.get(split::statement_at(&spans, byte_pos)?)?;
+    let stmt = &text[start..end.max(byte_pos)];
+    let tables = referenced_tables(stmt);
+
+    // Word being typed is qualified by a table name or alias (e.g. "t.col")
+    if let Some(cap) = QUALIFIER_REGEX.captures(&text[start.min(byte_pos)..byte_pos]) {
+        let qualifier = &cap[1];
+        let tbl = tables.iter()
+            .find(|(_, alias)| alias.as_ref().map(|a| &a[..] == qualifier ).unwrap_or(false) )
+            .map(|(name, _)| &name[..] )
+            .unwrap_or(qualifier);
+        if let Some(cols) = columns.get(tbl) {
+            return Some(cols.clone());
+        }
+    }
+
+    let mut cols : Vec<String> = tables.iter()
+        .filter_map(|(name, _)| columns.get(&name[..]) )
+        .flat_map(|cols| cols.iter().cloned() )
+        .collect();
+    if cols.len() == 0 {
+        return None;
+    }
+    cols.sort();
+    cols.dedup();
+    Some(cols)
+}
+
+#[test]
+fn alias_scope() {
+    let mut columns = HashMap::new();
+    columns.insert(String::from("orders"), vec![String::from("id"), String::from("total")]);
+    columns.insert(String::from("clients"), vec![String::from("id"), String::from("name")]);
+    let text = "select o. from orders o inner join clients c on o.id = c.id;";
+    let pg = Dialect::Postgres;
+    assert_eq!(
+        scoped_columns(text, 9, &columns, pg),
+        Some(vec![String::from("id"), String::from("total")])
+    );
+    assert_eq!(scoped_columns(text, 3, &columns, pg).unwrap().len(), 3);
+    assert_eq!(scoped_columns("select 1;", 3, &columns, pg), None);
+
+    // The semicolon within the literal does not end the statement.
+    let text = "select ';', c. from clients c;";
+    assert_eq!(
+        scoped_columns(text, 14, &columns, pg),
+        Some(vec![String::from("id"), String::from("name")])
+    );
+}
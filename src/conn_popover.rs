@@ -16,6 +16,10 @@ use crate::plots::plot_workspace::PlotWorkspace;
 use crate::table_notebook::*;
 use crate::functions::registry::FunctionRegistry;
 use crate::schema_tree::SchemaTree;
+use crate::utils;
+use crate::tables::limits::QueryLimits;
+use crate::tables::safety::SafetyMode;
+use crate::tables::workspace::ConnProfile;
 
 #[derive(Clone)]
 pub struct ConnPopover {
@@ -27,6 +31,25 @@ pub struct ConnPopover {
     db_file_dialog : FileChooserDialog,
     db_file_img : Image,
     db_path : Rc<RefCell<Vec<PathBuf>>>,
+
+    /// Lists the open connections. Selecting an entry makes it the current connection.
+    pub conn_combo : ComboBoxText,
+    new_conn_btn : Button,
+
+    /// Statement timeout (seconds), maximum rows and maximum memory (MB) of the
+    /// current connection. Zero means no limit.
+    limit_spins : [SpinButton; 3],
+
+    /// Read-only and production flags of the current connection.
+    safety_checks : [CheckButton; 2],
+
+    /// Set while the switch and combo are being updated programmatically, so their
+    /// handlers do not open, close or switch connections.
+    syncing : Rc<RefCell<bool>>,
+
+    /// Parameters each open connection was created from, indexed by connection name,
+    /// so the current connection can be saved with the workspace.
+    profiles : Rc<RefCell<HashMap<String, ConnProfile>>>
 }
 
 impl ConnPopover {
@@ -63,6 +86,22 @@ impl ConnPopover {
             });
         }
 
+        let conn_combo : ComboBoxText =
+            builder.get_object("conn_combo").unwrap();
+        let new_conn_btn : Button =
+            builder.get_object("new_conn_btn").unwrap();
+        let timeout_spin : SpinButton =
+            builder.get_object("timeout_spin").unwrap();
+        let max_rows_spin : SpinButton =
+            builder.get_object("max_rows_spin").unwrap();
+        let max_mem_spin : SpinButton =
+            builder.get_object("max_mem_spin").unwrap();
+        let limit_spins = [timeout_spin, max_rows_spin, max_mem_spin];
+        let read_only_check : CheckButton =
+            builder.get_object("read_only_check").unwrap();
+        let production_check : CheckButton =
+            builder.get_object("production_check").unwrap();
+        let safety_checks = [read_only_check, production_check];
         let db_path = Rc::new(RefCell::new(Vec::new()));
         ConnPopover{
             btn,
@@ -72,28 +111,147 @@ impl ConnPopover {
             db_file_btn,
             db_file_dialog,
             db_path,
-            db_file_img
+            db_file_img,
+            conn_combo,
+            new_conn_btn,
+            limit_spins,
+            safety_checks,
+            syncing : Rc::new(RefCell::new(false)),
+            profiles : Rc::new(RefCell::new(HashMap::new()))
+        }
+    }
+
+    /// Records the parameters shown at the popover as the profile of a newly opened connection.
+    fn record_profile(&self, name : &str) {
+        let files = self.db_path.try_borrow().map(|p| p.clone() ).unwrap_or(Vec::new());
+        let profile = ConnProfile {
+            host : self.entries[0].get_text().to_string(),
+            user : self.entries[1].get_text().to_string(),
+            database : if files.is_empty() { self.entries[3].get_text().to_string() } else { String::new() },
+            files,
+            limits : self.read_limits(),
+            safety : self.read_safety()
+        };
+        if let Ok(mut profiles) = self.profiles.try_borrow_mut() {
+            profiles.insert(name.to_string(), profile);
+        } else {
+            println!("Unable to borrow connection profiles");
+        }
+    }
+
+    /// Profile of the current connection, with the limits and safety flags it has now.
+    pub fn current_profile(&self, t_env : &TableEnvironment) -> Option<ConnProfile> {
+        let name = t_env.active_connection()?;
+        let mut profile = self.profiles.try_borrow().ok()?.get(&name)?.clone();
+        profile.limits = t_env.limits();
+        profile.safety = t_env.safety();
+        Some(profile)
+    }
+
+    /// Fills the popover with a profile saved with the workspace. Connections to database
+    /// files are re-opened; remote connections are only pre-filled, since the password is
+    /// not saved.
+    pub fn restore_profile(&self, profile : &ConnProfile) {
+        if self.conn_switch.get_active() {
+            return;
+        }
+        self.clear_entries();
+        self.entries[0].set_text(&profile.host);
+        self.entries[1].set_text(&profile.user);
+        self.show_limits(&profile.limits);
+        self.show_safety(&profile.safety);
+        if let Ok(mut db_p) = self.db_path.try_borrow_mut() {
+            *db_p = profile.files.clone();
+        } else {
+            println!("Could not get mutable reference to db path");
+            return;
+        }
+        match profile.files.get(0) {
+            Some(path) => {
+                self.entries[3].set_text(path.to_str().unwrap_or("(Non UTF-8 path)"));
+                self.conn_switch.set_active(true);
+            },
+            None => {
+                self.entries[3].set_text(&profile.database);
+            }
+        }
+    }
+
+    fn read_limits(&self) -> QueryLimits {
+        let value = |spin : &SpinButton| {
+            let v = spin.get_value_as_int();
+            if v > 0 {
+                Some(v as usize)
+            } else {
+                None
+            }
+        };
+        QueryLimits {
+            timeout_ms : value(&self.limit_spins[0]).map(|s| s as u64 * 1000 ),
+            max_rows : value(&self.limit_spins[1]),
+            max_bytes : value(&self.limit_spins[2]).map(|mb| mb * 1024 * 1024 )
+        }
+    }
+
+    fn show_limits(&self, limits : &QueryLimits) {
+        *self.syncing.borrow_mut() = true;
+        self.limit_spins[0].set_value(limits.timeout_ms.map(|ms| ms / 1000 ).unwrap_or(0) as f64);
+        self.limit_spins[1].set_value(limits.max_rows.unwrap_or(0) as f64);
+        self.limit_spins[2].set_value(limits.max_bytes.map(|b| b / (1024 * 1024) ).unwrap_or(0) as f64);
+        *self.syncing.borrow_mut() = false;
+    }
+
+    fn read_safety(&self) -> SafetyMode {
+        SafetyMode {
+            read_only : self.safety_checks[0].get_active(),
+            production : self.safety_checks[1].get_active()
         }
     }
 
+    fn show_safety(&self, safety : &SafetyMode) {
+        *self.syncing.borrow_mut() = true;
+        self.safety_checks[0].set_active(safety.read_only);
+        self.safety_checks[1].set_active(safety.production);
+        *self.syncing.borrow_mut() = false;
+    }
+
+    /// Applies the limits and safety flags currently shown at the popover to a newly opened connection.
+    fn apply_limits(&self, t_env : &mut TableEnvironment) {
+        if let Err(e) = t_env.set_limits(self.read_limits()) {
+            println!("Unable to set query limits: {}", e);
+        }
+        if let Err(e) = t_env.set_safety(self.read_safety()) {
+            println!("Unable to set read-only mode: {}", e);
+        }
+    }
+
+    /// Name under which a remote connection is listed, in the form user@host/database.
+    fn remote_conn_name(entries : &[gtk::Entry; 4]) -> String {
+        format!(
+            "{}@{}/{}",
+            entries[1].get_text().as_str(),
+            entries[0].get_text().as_str(),
+            entries[3].get_text().as_str()
+        )
+    }
+
     fn try_remote_connection(
         conn_popover : &ConnPopover,
         t_env : &mut TableEnvironment
     ) -> Result<(), String> {
-        if t_env.is_engine_active() {
-            return Err(format!("Invalid connection state"));
-        }
         if conn_popover.check_entries_clear() {
             return Err(format!("Invalid connection parameters"));
         }
         match ConnPopover::generate_conn_str(&conn_popover.entries) {
             Ok(conn_str) => {
-                let res = t_env.update_source(
-                    EnvironmentSource::PostgreSQL((conn_str, "".into())),
-                    true
+                let res = t_env.open_connection(
+                    &Self::remote_conn_name(&conn_popover.entries),
+                    EnvironmentSource::PostgreSQL((conn_str, "".into()))
                 );
                 match res {
                     Ok(_) => {
+                        conn_popover.record_profile(&Self::remote_conn_name(&conn_popover.entries));
+                        conn_popover.apply_limits(t_env);
                         conn_popover.set_db_loaded_mode();
                         Ok(())
                     },
@@ -113,23 +271,26 @@ impl ConnPopover {
         opt_path : Option<PathBuf>,
         t_env : &mut TableEnvironment
     ) -> Result<(), String> {
-        if t_env.is_engine_active() {
-            return Err(format!("Invalid connection state"));
-        }
-
         #[cfg(feature="arrowext")]
         {
             let source = EnvironmentSource::Arrow(String::new());
-            if let Err(e) = t_env.update_source(source, true) {
+            if let Err(e) = t_env.open_connection("In-memory", source) {
                 println!("{}", e);
                 return Err(e);
             }
+            conn_popover.record_profile("In-memory");
             conn_popover.entries[3].set_text("(In-memory database)");
+            conn_popover.apply_limits(t_env);
             conn_popover.set_db_loaded_mode();
             return Ok(());
         }
         let source = EnvironmentSource::SQLite3((opt_path.clone(), String::new()));
-        if let Err(e) = t_env.update_source(source, true) {
+        let short_name = opt_path.as_ref()
+            .and_then(|path| path.file_name() )
+            .and_then(|name| name.to_str() )
+            .unwrap_or("In-memory")
+            .to_string();
+        if let Err(e) = t_env.open_connection(&short_name, source) {
             println!("{}", e);
             return Err(e);
         }
@@ -143,7 +304,9 @@ impl ConnPopover {
             }
             None => "(In-memory database)"
         };
+        conn_popover.record_profile(&short_name);
         conn_popover.entries[3].set_text(conn_name);
+        conn_popover.apply_limits(t_env);
         conn_popover.set_db_loaded_mode();
         Ok(())
     }
@@ -169,6 +332,73 @@ impl ConnPopover {
         }
     }
 
+    /// Fills the combo with the open connections, selecting the current one.
+    fn update_conn_combo(&self, t_env : &TableEnvironment) {
+        *self.syncing.borrow_mut() = true;
+        self.conn_combo.remove_all();
+        let names = t_env.connection_names();
+        for name in names.iter() {
+            self.conn_combo.append(Some(&name[..]), &name[..]);
+        }
+        if let Some(active) = t_env.active_connection() {
+            self.conn_combo.set_active_id(Some(&active[..]));
+        }
+        self.conn_combo.set_sensitive(names.len() > 0);
+        self.new_conn_btn.set_sensitive(names.len() > 0);
+        *self.syncing.borrow_mut() = false;
+    }
+
+    /// Shows the current connection at the (now insensitive) entries, after the user
+    /// switched to it or after the connection shown previously was closed.
+    fn show_current_connection(&self, t_env : &TableEnvironment) {
+        self.clear_entries();
+        if let Some(name) = t_env.active_connection() {
+            self.entries[3].set_text(&name);
+        }
+        self.show_limits(&t_env.limits());
+        self.show_safety(&t_env.safety());
+        self.set_db_loaded_mode();
+    }
+
+    /// Shows the tables of the current connection, keeping the pages of the previous
+    /// connection (None if it was closed) aside. The notebook is only rebuilt from the
+    /// tables kept by the current connection if no pages were kept for it.
+    fn show_connection_tables(
+        t_env : &TableEnvironment,
+        previous : Option<&str>,
+        table_notebook : &TableNotebook,
+        workspace : &PlotWorkspace,
+        table_bar : &TableBar
+    ) {
+        workspace.clear_mappings().map_err(|e| println!("{}", e) ).ok();
+        if let Some(current) = t_env.active_connection() {
+            if table_notebook.switch_session(previous, &current) {
+                return;
+            }
+        }
+        if t_env.all_tables().len() == 0 {
+            table_notebook.clear();
+        } else {
+            utils::set_tables_from_query(
+                t_env,
+                &mut table_notebook.clone(),
+                workspace.clone(),
+                table_bar.clone()
+            );
+        }
+    }
+
+    /// Sets the switch back on after the current connection was closed but another
+    /// connection remained open.
+    fn reconnect_with_delay(switch : Switch, syncing : Rc<RefCell<bool>>) {
+        glib::timeout_add_local(160, move || {
+            *syncing.borrow_mut() = true;
+            switch.set_active(true);
+            *syncing.borrow_mut() = false;
+            glib::Continue(false)
+        });
+    }
+
     fn disconnect_with_delay(
         _switch : Switch
     ) {
@@ -188,6 +418,7 @@ impl ConnPopover {
         sql_editor.set_active(false);
         workspace.set_active(false);
         table_notebook.clear();
+        table_notebook.clear_sessions();
         workspace.clear();
         //if let Ok(mut t_env) = table_env.try_borrow_mut() {
         t_env.clear();
@@ -206,12 +437,24 @@ impl ConnPopover {
         sql_editor : SqlEditor,
         workspace : PlotWorkspace,
         fn_reg : FunctionRegistry,
-        schema_tree : SchemaTree
+        schema_tree : SchemaTree,
+        table_bar : TableBar
     ) {
         let conn_popover = self.clone();
+        let table_env_c = table_env.clone();
+        let table_notebook_c = table_notebook.clone();
+        let sql_editor_c = sql_editor.clone();
+        let workspace_c = workspace.clone();
+        let schema_tree_c = schema_tree.clone();
+        let status_c = status.clone();
+        let table_bar_c = table_bar.clone();
         self.conn_switch.connect_state_set(move |switch, state| {
+            if *conn_popover.syncing.borrow() {
+                return Inhibit(false);
+            }
             if let Ok(mut t_env) = table_env.try_borrow_mut() {
                 if state {
+                    let previous = t_env.active_connection();
                     if let Ok(db_path) = conn_popover.db_path.try_borrow() {
                         match (db_path.len(), conn_popover.check_entries_clear()) {
                             (0, true) => {
@@ -288,10 +531,25 @@ impl ConnPopover {
                     } else {
                         println!("Could not acquire lock over DB path");
                     }
+
+                    // The tables of the previous connection are kept aside for when it becomes current again.
+                    if previous.is_some() && t_env.active_connection() != previous {
+                        Self::show_connection_tables(&t_env, previous.as_deref(), &table_notebook, &workspace, &table_bar);
+                    }
                 } else {
-                    // Disable remote connection
-                    if t_env.is_engine_active() {
-                        t_env.disable_engine();
+                    // Close the current connection only. If other connections remain open,
+                    // the most recent one becomes current and the switch stays on.
+                    if let Some(closed) = t_env.active_connection() {
+                        sql_editor.file_list.unbind_connection(&closed);
+                    }
+                    if let Some(_) = t_env.close_connection() {
+                        conn_popover.update_conn_combo(&t_env);
+                        conn_popover.show_current_connection(&t_env);
+                        Self::show_connection_tables(&t_env, None, &table_notebook, &workspace, &table_bar);
+                        schema_tree.repopulate(table_env.clone());
+                        status.update(Status::Connected);
+                        Self::reconnect_with_delay(switch.clone(), conn_popover.syncing.clone());
+                        return Inhibit(false);
                     }
                     conn_popover.set_non_db_mode();
                     conn_popover.clear_entries();
@@ -303,6 +561,7 @@ impl ConnPopover {
                         &mut t_env
                     );
                 }
+                conn_popover.update_conn_combo(&t_env);
             } else {
                 println!("Could not acquire lock over table environment");
             }
@@ -315,15 +574,21 @@ impl ConnPopover {
                         schema_tree.repopulate(table_env.clone());
                     },
                     _ => {
-                        fn_reg.set_sensitive(true);
-                        schema_tree.clear();
                         if let Ok(mut t_env) = table_env.try_borrow_mut() {
-                            Self::clear_session(
-                                sql_editor.clone(),
-                                workspace.clone(),
-                                table_notebook.clone(),
-                                &mut t_env
-                            );
+                            if t_env.is_engine_active() {
+                                // Opening a new connection failed, but the previously
+                                // opened ones are still available.
+                                conn_popover.show_current_connection(&t_env);
+                            } else {
+                                fn_reg.set_sensitive(true);
+                                schema_tree.clear();
+                                Self::clear_session(
+                                    sql_editor.clone(),
+                                    workspace.clone(),
+                                    table_notebook.clone(),
+                                    &mut t_env
+                                );
+                            }
                         } else {
                             println!("Failed to acquire lock over table environment");
                         }
@@ -333,6 +598,96 @@ impl ConnPopover {
             Inhibit(false)
         });
 
+        {
+            let conn_popover = self.clone();
+            let table_env_c = table_env_c.clone();
+            let status_c = status_c.clone();
+            self.conn_combo.connect_changed(move |combo| {
+                if *conn_popover.syncing.borrow() {
+                    return;
+                }
+                let name = match combo.get_active_id() {
+                    Some(name) => name.as_str().to_string(),
+                    None => return
+                };
+                if *sql_editor_c.query_sent.borrow() {
+                    status_c.update(Status::SqlErr(
+                        format!("Wait for the current query to finish before switching connections")
+                    ));
+                    if let Ok(t_env) = table_env_c.try_borrow() {
+                        conn_popover.update_conn_combo(&t_env);
+                    }
+                    return;
+                }
+                if let Ok(mut t_env) = table_env_c.try_borrow_mut() {
+                    let previous = t_env.active_connection();
+                    if let Err(e) = t_env.switch_connection(&name) {
+                        println!("{}", e);
+                        return;
+                    }
+                    conn_popover.show_current_connection(&t_env);
+                    Self::show_connection_tables(&t_env, previous.as_deref(), &table_notebook_c, &workspace_c, &table_bar_c);
+                } else {
+                    println!("Could not acquire lock over table environment");
+                    return;
+                }
+                schema_tree_c.repopulate(table_env_c.clone());
+            });
+        }
+
+        for spin in self.limit_spins.iter() {
+            let conn_popover = self.clone();
+            let table_env = table_env_c.clone();
+            let status = status_c.clone();
+            spin.connect_value_changed(move |_| {
+                if *conn_popover.syncing.borrow() {
+                    return;
+                }
+                if let Ok(mut t_env) = table_env.try_borrow_mut() {
+                    if t_env.is_engine_active() {
+                        if let Err(e) = t_env.set_limits(conn_popover.read_limits()) {
+                            status.update(Status::ConnectionErr(e));
+                        }
+                    }
+                } else {
+                    println!("Could not acquire lock over table environment");
+                }
+            });
+        }
+
+        for check in self.safety_checks.iter() {
+            let conn_popover = self.clone();
+            let table_env = table_env_c.clone();
+            let status = status_c.clone();
+            check.connect_toggled(move |_| {
+                if *conn_popover.syncing.borrow() {
+                    return;
+                }
+                if let Ok(mut t_env) = table_env.try_borrow_mut() {
+                    if t_env.is_engine_active() {
+                        if let Err(e) = t_env.set_safety(conn_popover.read_safety()) {
+                            status.update(Status::ConnectionErr(e));
+                        }
+                    }
+                } else {
+                    println!("Could not acquire lock over table environment");
+                }
+            });
+        }
+
+        {
+            let conn_popover = self.clone();
+            self.new_conn_btn.connect_clicked(move |_| {
+                // Turn the switch off without closing the current connection, so the
+                // entries can be filled with the parameters of another connection.
+                *conn_popover.syncing.borrow_mut() = true;
+                conn_popover.conn_switch.set_active(false);
+                *conn_popover.syncing.borrow_mut() = false;
+                conn_popover.set_non_db_mode();
+                conn_popover.clear_entries();
+            });
+        }
+
         {
             let conn_popover = self.clone();
             self.db_file_dialog.connect_response(move |dialog, resp| {
@@ -0,0 +1,121 @@
+use gtk::*;
+use gtk::prelude::*;
+use sourceview::View;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::environment::ErrorLocation;
+
+const ERROR_TAG : &'static str = "sql-error";
+
+/// Underlines the span of the last error reported by the engine at the editor holding the
+/// failed query sequence, showing the error message, detail and hint as a tooltip over it.
+/// A single error is marked at any time, and the mark is removed as soon as the text changes.
+#[derive(Clone)]
+pub struct ErrorMarks {
+
+    /// View holding the current mark, and the tooltip text shown over it.
+    marked : Rc<RefCell<Option<(View, String)>>>
+}
+
+impl ErrorMarks {
+
+    pub fn new() -> Self {
+        Self { marked : Rc::new(RefCell::new(None)) }
+    }
+
+    /// Creates the error tag at the view buffer, and starts showing the error tooltip.
+    pub fn configure(&self, view : &View) {
+        let buffer = view.get_buffer().unwrap();
+        let tag = TextTag::new(Some(ERROR_TAG));
+        tag.set_property_underline(pango::Underline::Error);
+        if let Some(tag_table) = buffer.get_tag_table() {
+            tag_table.add(&tag);
+        }
+        view.set_has_tooltip(true);
+        {
+            let marked = self.marked.clone();
+            view.connect_query_tooltip(move |view, x, y, _keyboard, tooltip| {
+                let marked = match marked.try_borrow() {
+                    Ok(marked) => marked,
+                    Err(_) => return false
+                };
+                match &*marked {
+                    Some((marked_view, text)) if marked_view == view => {
+                        let (buf_x, buf_y) = view.window_to_buffer_coords(TextWindowType::Widget, x, y);
+                        match view.get_iter_at_location(buf_x, buf_y) {
+                            Some(iter) if iter.has_tag(&tag) => {
+                                tooltip.set_text(Some(&text[..]));
+                                true
+                            },
+                            _ => false
+                        }
+                    },
+                    _ => false
+                }
+            });
+        }
+        {
+            let marks = self.clone();
+            let view = view.clone();
+            buffer.connect_changed(move |_| {
+                let is_marked = marks.marked.try_borrow()
+                    .map(|m| m.as_ref().map(|(v, _)| v == &view ).unwrap_or(false) )
+                    .unwrap_or(false);
+                if is_marked {
+                    marks.clear();
+                }
+            });
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut marked) = self.marked.try_borrow_mut() {
+            if let Some((view, _)) = marked.take() {
+                if let Some(buffer) = view.get_buffer() {
+                    buffer.remove_tag_by_name(ERROR_TAG, &buffer.get_start_iter(), &buffer.get_end_iter());
+                }
+            }
+        } else {
+            println!("Unable to borrow error marks");
+        }
+    }
+
+    /// Underlines the error and moves the cursor to it, if the view holds the query sequence
+    /// which failed starting at the character offset base. Returns whether the error was marked.
+    pub fn mark(&self, view : &View, base : i32, loc : &ErrorLocation) -> bool {
+        self.clear();
+        let buffer = match view.get_buffer() {
+            Some(buffer) => buffer,
+            None => return false
+        };
+        let end = base + loc.sql.chars().count() as i32;
+        let text = buffer.get_iter_at_offset(base).get_text(&buffer.get_iter_at_offset(end));
+        if text.map(|t| t.as_str() != &loc.sql[..] ).unwrap_or(true) {
+            return false;
+        }
+        let (start, end) = loc.span;
+        let start = base + loc.sql[..start].chars().count() as i32;
+        let end = base + loc.sql[..end].chars().count() as i32;
+        let mut start_iter = buffer.get_iter_at_offset(start);
+        let end_iter = buffer.get_iter_at_offset(end);
+        buffer.apply_tag_by_name(ERROR_TAG, &start_iter, &end_iter);
+        buffer.place_cursor(&start_iter);
+        view.scroll_to_iter(&mut start_iter, 0.1, false, 0.0, 0.0);
+        if let Ok(mut marked) = self.marked.try_borrow_mut() {
+            *marked = Some((view.clone(), tooltip_text(loc)));
+        }
+        true
+    }
+
+}
+
+fn tooltip_text(loc : &ErrorLocation) -> String {
+    let mut text = loc.message.clone();
+    if let Some(detail) = &loc.detail.detail {
+        text += &format!("\nDetail: {}", detail);
+    }
+    if let Some(hint) = &loc.detail.hint {
+        text += &format!("\nHint: {}", hint);
+    }
+    text
+}
@@ -12,7 +12,13 @@ use crate::{status_stack::StatusStack};
 use crate::status_stack::*;
 use sourceview::View;
 use super::sql_editor::SqlEditor;
+use crate::completion::SqlCompletion;
+use crate::error_marks::ErrorMarks;
+use crate::statement_marks::StatementMarks;
+use crate::snippet_window::SnippetStops;
+use crate::tables::workspace::OpenFile;
 use std::path::{Path, PathBuf};
+use std::fs;
 
 #[derive(Debug, Clone)]
 pub struct SqlFile {
@@ -25,7 +31,11 @@ pub struct SqlFile {
     path : Option<PathBuf>,
 
     // Whether the user saved the queries to disk since the last edits.
-    saved : bool
+    saved : bool,
+
+    // Name of the connection this file sends its queries to. Files are bound
+    // to the current connection the first time a query is sent from them.
+    conn : Option<String>
 }
 
 #[derive(Clone)]
@@ -35,6 +45,10 @@ pub struct FileList {
     last_ix : Rc<RefCell<usize>>,
     list_box : ListBox,
     close_confirm_dialog : Dialog,
+    pub completion : SqlCompletion,
+    pub error_marks : ErrorMarks,
+    pub statement_marks : StatementMarks,
+    pub snippet_stops : SnippetStops
 }
 
 impl FileList {
@@ -55,6 +69,12 @@ impl FileList {
         }
     }
 
+    /// Directory of the selected file, against which the includes of its queries are resolved.
+    /// None if the selected file was not saved yet.
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        self.current_selected_path()?.parent().map(|dir| dir.to_path_buf() )
+    }
+
     fn get_label_from_row(row : &ListBoxRow) -> Label {
         let bx_child = row.get_child().unwrap().downcast::<Box>().unwrap();
         let lbl_child = bx_child
@@ -90,6 +110,46 @@ impl FileList {
         }
     }
 
+    /// Connection the selected file is bound to, if any.
+    pub fn current_conn(&self) -> Option<String> {
+        let sel_ix = self.get_selected()?;
+        match self.files.try_borrow() {
+            Ok(files) => files.get(sel_ix).and_then(|f| f.conn.clone() ),
+            Err(_) => {
+                println!("Could not retrieve reference to file list");
+                None
+            }
+        }
+    }
+
+    /// Binds the selected file to the informed connection, if it is not bound yet.
+    pub fn bind_current(&self, conn : &str) {
+        if let Some(sel_ix) = self.get_selected() {
+            if let Ok(mut files) = self.files.try_borrow_mut() {
+                if let Some(f) = files.get_mut(sel_ix) {
+                    if f.conn.is_none() {
+                        f.conn = Some(conn.to_string());
+                    }
+                }
+            } else {
+                println!("Could not retrieve mutable reference to file list");
+            }
+        }
+    }
+
+    /// Removes the binding of all files to the informed connection (called when it is closed).
+    pub fn unbind_connection(&self, conn : &str) {
+        if let Ok(mut files) = self.files.try_borrow_mut() {
+            for f in files.iter_mut() {
+                if f.conn.as_ref().map(|c| &c[..] == conn ).unwrap_or(false) {
+                    f.conn = None;
+                }
+            }
+        } else {
+            println!("Could not retrieve mutable reference to file list");
+        }
+    }
+
     pub fn set_current_selected_path(&self, path : &Path) {
         if let Some(sel_ix) = self.get_selected() {
             if let Ok(mut files) = self.files.try_borrow_mut() {
@@ -119,11 +179,14 @@ impl FileList {
         }
     }
 
+    /// Connects the file selection to the editor. If the selected file is bound to a connection,
+    /// it is also selected at the connection combo, which makes it the current connection.
     pub fn connect_selected(
         &self,
         sql_editor : &SqlEditor,
         content_stack : Stack,
-        query_toggle : ToggleButton
+        query_toggle : ToggleButton,
+        conn_combo : ComboBoxText
     ) {
         let sql_editor = sql_editor.clone();
         let files = self.files.clone();
@@ -136,7 +199,8 @@ impl FileList {
                 // let mut past_ix = last_ix.borrow_mut();
                 let curr_ix = row.get_index() as usize;
                 println!("Currently selected index: {}", curr_ix);
-                if let Ok(mut files) = files.try_borrow_mut() {
+                let mut bound = None;
+                if let Ok(files) = files.try_borrow() {
                     // *past_ix = curr_ix as usize;
 
                     // Set visible child only when app is at edit mode. At connect_toggles,
@@ -168,23 +232,44 @@ impl FileList {
                     // Independent of whether at edit mode or not, update the current source
                     // of SqlEditor so the query update presses will read the right text buffer.
                     sql_editor.update_editor(content_stack.clone(), &new_name);
+
+                    bound = files.get(curr_ix).and_then(|f| f.conn.clone() );
                 } else {
                     println!("Failed retrieving reference to file list");
                 }
+
+                // The file list is not borrowed anymore, since switching the connection reads it.
+                if let Some(conn) = bound {
+                    if conn_combo.get_active_id().map(|id| id.as_str() != &conn[..] ).unwrap_or(true) {
+                        conn_combo.set_active_id(Some(&conn[..]));
+                    }
+                }
             }
         });
     }
 
-    pub fn build(builder : &Builder) -> Self {
+    pub fn build(builder : &Builder, completion : SqlCompletion, statement_marks : StatementMarks) -> Self {
         let files = Rc::new(RefCell::new(vec![SqlFile{
             name:String::from("Untitled 1"),
             path : None,
-            saved : true
+            saved : true,
+            conn : None
         }]));
         let list_box : ListBox = builder.get_object("sql_list_box").unwrap();
         let last_ix = Rc::new(RefCell::new(0));
         let close_confirm_dialog : Dialog = builder.get_object("close_confirm_dialog").unwrap();
-        let file_list = Self{ files, list_box, last_ix, close_confirm_dialog };
+        let error_marks = ErrorMarks::new();
+        let snippet_stops = SnippetStops::new();
+        let file_list = Self{
+            files,
+            list_box,
+            last_ix,
+            close_confirm_dialog,
+            completion,
+            error_marks,
+            statement_marks,
+            snippet_stops
+        };
         file_list
     }
 
@@ -209,7 +294,8 @@ impl FileList {
         self.files.borrow_mut().push(SqlFile{
             name : title.clone(),
             path : None,
-            saved : true
+            saved : true,
+            conn : None
         });
         self.list_box.unselect_all();
         let row = self.add_file_row(&title, content_stack.clone(), sql_editor.clone());
@@ -221,7 +307,7 @@ impl FileList {
         let stack_name = format!("queries_{}", n - 1);
         println!("Adding stack child named: {}", stack_name);
         content_stack.add_named(
-            &SqlEditor::new_source("", &sql_editor.refresh_btn.clone(), &self),
+            &SqlEditor::new_source("", &sql_editor, &self),
             &stack_name
         );
         content_stack.show_all();
@@ -353,7 +439,6 @@ impl FileList {
         list_name : &str,
         content_stack : Stack,
         query_toggle : ToggleButton,
-        refresh_btn : Button,
         sql_editor : SqlEditor
     ) {
         let mut sql_content = String::new();
@@ -365,7 +450,8 @@ impl FileList {
                 files.push(SqlFile{
                     name : list_name.to_string(),
                     path : Some(path.to_path_buf()),
-                    saved : true
+                    saved : true,
+                    conn : None
                 });
             } else {
                 println!("Unable to borrow files mutably");
@@ -378,7 +464,7 @@ impl FileList {
                 let new_name = format!("queries_{}", n);
                 println!("Adding {} to content stack", new_name);
                 content_stack.add_named(
-                    &SqlEditor::new_source(&sql_content, &refresh_btn, &self),
+                    &SqlEditor::new_source(&sql_content, &sql_editor, &self),
                     &new_name
                 );
                 content_stack.set_visible_child_name(&new_name);
@@ -395,6 +481,130 @@ impl FileList {
         self.select_last();
     }
 
+    fn view_at(content_stack : &Stack, ix : usize) -> Option<View> {
+        content_stack.get_child_by_name(&format!("queries_{}", ix))
+            .and_then(|child| child.downcast::<ScrolledWindow>().ok() )
+            .and_then(|sw| sw.get_child() )
+            .and_then(|child| child.downcast::<View>().ok() )
+    }
+
+    /// Files currently open, to be saved with the workspace, and the index of the selected file.
+    /// The text of each editor is kept only when it was never saved or when it differs from
+    /// the file on disk.
+    pub fn workspace_files(&self, content_stack : &Stack) -> (Vec<OpenFile>, usize) {
+        let mut open_files = Vec::new();
+        let files = match self.files.try_borrow() {
+            Ok(files) => files,
+            Err(_) => {
+                println!("Unable to borrow files");
+                return (open_files, 0);
+            }
+        };
+        let n_rows = self.list_box.get_children().len();
+        for (ix, f) in files.iter().take(n_rows).enumerate() {
+            let buffer = match Self::view_at(content_stack, ix).and_then(|view| view.get_buffer() ) {
+                Some(buffer) => buffer,
+                None => continue
+            };
+            let text = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), true)
+                .map(|txt| txt.to_string() )
+                .unwrap_or(String::new());
+            let cursor = buffer.get_insert()
+                .map(|mark| buffer.get_iter_at_mark(&mark).get_offset() as usize )
+                .unwrap_or(0);
+            let content = match &f.path {
+                Some(path) => {
+                    match fs::read_to_string(path) {
+                        Ok(saved) if saved == text => None,
+                        _ => Some(text)
+                    }
+                },
+                None => Some(text)
+            };
+            open_files.push(OpenFile { name : f.name.clone(), path : f.path.clone(), content, cursor });
+        }
+        let selected = self.list_box.get_selected_row()
+            .map(|row| row.get_index() as usize )
+            .unwrap_or(0);
+        (open_files, selected)
+    }
+
+    /// Replaces the initial untitled file by the files of a saved workspace. Files with
+    /// unsaved content are marked as such; files which were saved are read again from disk
+    /// (and skipped if they are not available anymore).
+    pub fn restore(
+        &self,
+        open_files : &[OpenFile],
+        selected : usize,
+        content_stack : Stack,
+        sql_editor : SqlEditor
+    ) {
+        // No row is selected while the buffers are filled, so their change
+        // signals do not mark any file as unsaved.
+        self.list_box.unselect_all();
+        let mut n = 0;
+        for open in open_files.iter() {
+            let content = match (&open.content, &open.path) {
+                (Some(content), _) => content.clone(),
+                (None, Some(path)) => match fs::read_to_string(path) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        println!("Unable to restore {}: {}", path.display(), e);
+                        continue;
+                    }
+                },
+                (None, None) => String::new()
+            };
+            let file = SqlFile {
+                name : open.name.clone(),
+                path : open.path.clone(),
+                saved : open.content.is_none(),
+                conn : None
+            };
+            let label = if file.saved {
+                file.name.clone()
+            } else {
+                format!("{}*", file.name)
+            };
+            if let Ok(mut files) = self.files.try_borrow_mut() {
+                if n == 0 {
+                    files.truncate(0);
+                }
+                files.push(file);
+            } else {
+                println!("Unable to borrow files mutably");
+                return;
+            }
+            if n == 0 {
+                match self.list_box.get_row_at_index(0) {
+                    Some(row) => Self::get_label_from_row(&row).set_text(&label),
+                    None => { self.add_file_row(&label, content_stack.clone(), sql_editor.clone()); }
+                }
+                if let Some(buffer) = Self::view_at(&content_stack, 0).and_then(|view| view.get_buffer() ) {
+                    buffer.set_text(&content);
+                }
+            } else {
+                content_stack.add_named(
+                    &SqlEditor::new_source(&content, &sql_editor, &self),
+                    &format!("queries_{}", n)
+                );
+                self.add_file_row(&label, content_stack.clone(), sql_editor.clone());
+            }
+            if let Some(buffer) = Self::view_at(&content_stack, n).and_then(|view| view.get_buffer() ) {
+                let pos = buffer.get_iter_at_offset(open.cursor as i32);
+                buffer.place_cursor(&pos);
+            }
+            n += 1;
+        }
+        content_stack.show_all();
+        if n > 0 {
+            let ix = if selected < n { selected } else { n - 1 };
+            if let Some(row) = self.list_box.get_row_at_index(ix as i32) {
+                self.list_box.select_row(Some(&row));
+            }
+        }
+    }
+
     pub fn get_selected(&self) -> Option<usize> {
         if self.list_box.get_children().len() == 0 {
             None
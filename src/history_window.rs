@@ -0,0 +1,210 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::environment::TableEnvironment;
+use crate::tables::history::{QueryHistory, HistoryEntry};
+use crate::sql_editor::SqlEditor;
+use crate::status_stack::Status;
+
+/// Maximum number of entries shown at the history list at once.
+const MAX_SHOWN : usize = 200;
+
+/// Window listing the queries recorded by the SqlListener at the history database,
+/// which lets the user search them and either re-open an entry in a fresh editor or
+/// send it again to the current engine.
+#[derive(Clone)]
+pub struct HistoryWindow {
+    pub win : Window,
+    search_entry : SearchEntry,
+    list : ListBox,
+    entries : Rc<RefCell<Vec<HistoryEntry>>>,
+    history : Option<Rc<QueryHistory>>
+}
+
+impl HistoryWindow {
+
+    fn build_row(entry : &HistoryEntry) -> ListBoxRow {
+        let first_line = entry.query.lines().next().unwrap_or("");
+        let query_lbl = Label::new(Some(first_line));
+        query_lbl.set_halign(Align::Start);
+        query_lbl.set_ellipsize(pango::EllipsizeMode::End);
+        query_lbl.set_tooltip_text(Some(&entry.query[..]));
+        let info_lbl = Label::new(Some(&entry.summary()[..]));
+        info_lbl.set_halign(Align::Start);
+        info_lbl.set_ellipsize(pango::EllipsizeMode::End);
+        if let Some(err) = &entry.error {
+            info_lbl.set_tooltip_text(Some(&err[..]));
+        }
+        info_lbl.get_style_context().add_class("dim-label");
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.pack_start(&query_lbl, false, false, 0);
+        bx.pack_start(&info_lbl, false, false, 0);
+        bx.set_margin_top(6);
+        bx.set_margin_bottom(6);
+        bx.set_margin_start(6);
+        bx.set_margin_end(6);
+        let row = ListBoxRow::new();
+        row.add(&bx);
+        row.set_selectable(true);
+        row
+    }
+
+    fn update_entries(
+        history : &Option<Rc<QueryHistory>>,
+        list : &ListBox,
+        entries : &Rc<RefCell<Vec<HistoryEntry>>>,
+        pattern : &str
+    ) {
+        for child in list.get_children() {
+            list.remove(&child);
+        }
+        let history = match history {
+            Some(h) => h,
+            None => return
+        };
+        match history.search(pattern, MAX_SHOWN) {
+            Ok(found) => {
+                for (i, entry) in found.iter().enumerate() {
+                    list.insert(&Self::build_row(entry), i as i32);
+                }
+                if let Ok(mut entries) = entries.try_borrow_mut() {
+                    *entries = found;
+                } else {
+                    println!("Unable to borrow history entries");
+                }
+            },
+            Err(e) => println!("Unable to search query history: {}", e)
+        }
+        list.show_all();
+    }
+
+    fn selected_entry(list : &ListBox, entries : &Rc<RefCell<Vec<HistoryEntry>>>) -> Option<HistoryEntry> {
+        let row = list.get_selected_row()?;
+        let ix = row.get_index();
+        if ix < 0 {
+            return None;
+        }
+        entries.borrow().get(ix as usize).cloned()
+    }
+
+    pub fn refresh(&self) {
+        let pattern = self.search_entry.get_text();
+        Self::update_entries(&self.history, &self.list, &self.entries, pattern.as_str());
+    }
+
+    pub fn build(
+        builder : &Builder,
+        sql_editor : &SqlEditor,
+        table_env : Rc<RefCell<TableEnvironment>>,
+        content_stack : Stack,
+        query_toggle : ToggleButton
+    ) -> Self {
+        let win : Window = builder.get_object("history_window").unwrap();
+        let search_entry : SearchEntry = builder.get_object("history_search_entry").unwrap();
+        let list : ListBox = builder.get_object("history_list").unwrap();
+        let remove_btn : Button = builder.get_object("history_remove_btn").unwrap();
+        let open_btn : Button = builder.get_object("history_open_btn").unwrap();
+        let run_btn : Button = builder.get_object("history_run_btn").unwrap();
+        let entries : Rc<RefCell<Vec<HistoryEntry>>> = Rc::new(RefCell::new(Vec::new()));
+        let history = match QueryHistory::open_default() {
+            Ok(h) => Some(Rc::new(h)),
+            Err(e) => {
+                println!("Unable to open query history: {}", e);
+                None
+            }
+        };
+
+        {
+            let history = history.clone();
+            let list = list.clone();
+            let entries = entries.clone();
+            search_entry.connect_search_changed(move |entry| {
+                let pattern = entry.get_text();
+                Self::update_entries(&history, &list, &entries, pattern.as_str());
+            });
+        }
+
+        {
+            let history = history.clone();
+            let list = list.clone();
+            let entries = entries.clone();
+            let search_entry = search_entry.clone();
+            win.connect_show(move |_| {
+                let pattern = search_entry.get_text();
+                Self::update_entries(&history, &list, &entries, pattern.as_str());
+            });
+        }
+
+        {
+            let history = history.clone();
+            let list = list.clone();
+            let entries = entries.clone();
+            let search_entry = search_entry.clone();
+            remove_btn.connect_clicked(move |_| {
+                let id = Self::selected_entry(&list, &entries).and_then(|e| e.id );
+                if let (Some(id), Some(h)) = (id, &history) {
+                    if let Err(e) = h.remove(id) {
+                        println!("{}", e);
+                    }
+                    let pattern = search_entry.get_text();
+                    Self::update_entries(&history, &list, &entries, pattern.as_str());
+                }
+            });
+        }
+
+        {
+            let list = list.clone();
+            let entries = entries.clone();
+            let sql_editor = sql_editor.clone();
+            open_btn.connect_clicked(move |_| {
+                if let Some(entry) = Self::selected_entry(&list, &entries) {
+                    sql_editor.add_fresh_editor(content_stack.clone(), query_toggle.clone());
+                    sql_editor.set_text(&entry.query[..]);
+                    if !query_toggle.get_active() {
+                        query_toggle.set_active(true);
+                    }
+                }
+            });
+        }
+
+        {
+            let list = list.clone();
+            let entries = entries.clone();
+            let sql_editor = sql_editor.clone();
+            run_btn.connect_clicked(move |_| {
+                if let Some(entry) = Self::selected_entry(&list, &entries) {
+                    // History entries hold the sequences as the engine received them (macros expanded),
+                    // and they are sent again without expansion, so they are confirmed as they are.
+                    if !SqlEditor::confirm_sequence(&table_env, &sql_editor.safety_dialog, &entry.query) {
+                        return;
+                    }
+                    if let Ok(mut t_env) = table_env.try_borrow_mut() {
+                        match t_env.prepare_and_send_query(entry.query.clone(), true) {
+                            Ok(_) => {
+                                if let Ok(mut sent) = sql_editor.query_sent.try_borrow_mut() {
+                                    *sent = true;
+                                }
+                                sql_editor.view.borrow().set_sensitive(false);
+                            },
+                            Err(e) => {
+                                sql_editor.status_stack.update(Status::SqlErr(e));
+                            }
+                        }
+                    } else {
+                        println!("Unable to borrow table environment");
+                    }
+                }
+            });
+        }
+
+        Self {
+            win,
+            search_entry,
+            list,
+            entries,
+            history
+        }
+    }
+
+}
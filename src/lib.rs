@@ -4,6 +4,8 @@ pub mod table_widget;
 
 pub mod table_notebook;
 
+pub mod table_editor;
+
 pub mod status_stack;
 
 pub mod sql_editor;
@@ -37,3 +39,25 @@ pub mod header_toggle;
 pub mod report;
 
 pub mod command;
+
+pub mod history_window;
+
+pub mod safety_dialog;
+
+pub mod completion;
+
+pub mod error_marks;
+
+pub mod statement_marks;
+
+pub mod snippet_window;
+
+pub mod profile_window;
+
+pub mod cell_detail;
+
+pub mod pivot_window;
+
+pub mod combine_window;
+
+pub mod transfer_window;
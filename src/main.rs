@@ -26,6 +26,21 @@ use gtk_queries::table_popover::*;
 use gtk_queries::header_toggle::HeaderToggle;
 use gtk_queries::command::CommandWindow;
 use gtk_queries::table_notebook::TableBar;
+use gtk_queries::table_editor::TableEditor;
+use gtk_queries::safety_dialog::SafetyDialog;
+use gtk_queries::history_window::HistoryWindow;
+use gtk_queries::completion::SqlCompletion;
+use gtk_queries::statement_marks::StatementMarks;
+use gtk_queries::snippet_window::SnippetWindow;
+use gtk_queries::profile_window::ProfileWindow;
+use gtk_queries::pivot_window::PivotWindow;
+use gtk_queries::combine_window::CombineWindow;
+use gtk_queries::transfer_window::TransferWindow;
+use gtk_queries::tables::workspace::{Workspace, WORKSPACE_PATH};
+use std::path::Path;
+
+/// Interval between two automatic saves of the workspace, in milliseconds.
+const AUTOSAVE_INTERVAL : u32 = 30000;
 
 #[derive(Clone)]
 pub struct QueriesApp {
@@ -42,7 +57,10 @@ pub struct QueriesApp {
     table_bar : TableBar,
     table_popover : TablePopover,
     cmd_window : CommandWindow,
-    csv_window : CsvWindow
+    csv_window : CsvWindow,
+    history_window : HistoryWindow,
+    snippet_window : SnippetWindow,
+    content_stack : Stack
 }
 
 /*fn adjust_sidebar_pos(btn : &ToggleButton, window : &Window, main_paned : &Paned) {
@@ -154,7 +172,8 @@ impl QueriesApp {
         let conn_btn : Button = builder.get_object("conn_btn").unwrap();
         // let popover_path = utils::glade_path("conn-popover.glade")
         //    .expect("Could not open glade path");
-        let schema_tree = SchemaTree::build(&builder);
+        let completion = SqlCompletion::new();
+        let schema_tree = SchemaTree::build(&builder, completion.clone());
         let conn_popover = ConnPopover::new_from_glade(
             builder.clone(),
             conn_btn
@@ -171,19 +190,24 @@ impl QueriesApp {
             sidebar_stack.clone()
         );
 
-        let cmd_window = CommandWindow::build(&builder, &tables_nb, table_env.clone());
         let csv_window = CsvWindow::build(&builder);
+        let cmd_window = CommandWindow::build(&builder, &tables_nb, table_env.clone(), &csv_window);
         
         let table_bar = TableBar::build(&builder);
+
+        // Shared by the editor, the table editor and the table copies, since it holds the single confirmation dialog.
+        let safety_dialog = SafetyDialog::build(&builder);
         let table_popover = TablePopover::build(
             &builder,
             plot_workspace.clone(),
             table_env.clone(),
             tables_nb.clone(),
             status_stack.clone(),
-            cmd_window.clone()
+            cmd_window.clone(),
+            safety_dialog.clone()
         );
         table_bar.hook(&table_popover, &plot_workspace.layout_toolbar);
+        table_bar.connect_detail(&tables_nb);
 
         cmd_window.connect_wait_command(
             table_env.clone(), 
@@ -203,7 +227,8 @@ impl QueriesApp {
             });
         }*/
 
-        let file_list = FileList::build(&builder);
+        let statement_marks = StatementMarks::new(tables_nb.clone(), header_toggle.table_toggle.clone());
+        let file_list = FileList::build(&builder, completion.clone(), statement_marks);
         let sql_editor = SqlEditor::build(
             builder.clone(),
             header_toggle.clone(),
@@ -213,7 +238,8 @@ impl QueriesApp {
             tables_nb.clone(),
             &file_list,
             plot_workspace.clone(),
-            table_bar.clone()
+            table_bar.clone(),
+            safety_dialog.clone()
         );
         file_list.add_file_row(
             "Untitled 1",
@@ -223,7 +249,8 @@ impl QueriesApp {
         file_list.connect_selected(
             &sql_editor,
             content_stack.clone(),
-            header_toggle.query_toggle.clone()
+            header_toggle.query_toggle.clone(),
+            conn_popover.conn_combo.clone()
         );
         conn_popover.hook_signals(
             table_env.clone(),
@@ -232,7 +259,8 @@ impl QueriesApp {
             sql_editor.clone(),
             plot_workspace.clone(),
             fn_reg.clone(),
-            schema_tree.clone()
+            schema_tree.clone(),
+            table_bar.clone()
         );
 
         header_toggle.connect_query_toggle(
@@ -273,6 +301,24 @@ impl QueriesApp {
             });
         }
 
+        let table_editor = TableEditor::build(
+            &builder,
+            table_env.clone(),
+            status_stack.clone(),
+            safety_dialog.clone()
+        );
+        {
+            let tables_nb = tables_nb.clone();
+            let file_list = file_list.clone();
+            let sql_editor = sql_editor.clone();
+            table_editor.connect_applied(move || {
+                tables_nb.nb.set_sensitive(false);
+                file_list.set_sensitive(false);
+                sql_editor.resend_last();
+            });
+        }
+        tables_nb.set_editor(table_editor);
+
         let fn_toggle = ToggleToolButton::new();
         let fn_img = Image::from_file("assets/icons/fn-dark.svg");
         fn_toggle.set_icon_widget(Some(&fn_img));
@@ -338,6 +384,46 @@ impl QueriesApp {
             sql_editor.connect_result_arrived(schema_tree.clone(), f);
         }
 
+        let history_window = HistoryWindow::build(
+            &builder,
+            &sql_editor,
+            table_env.clone(),
+            content_stack.clone(),
+            header_toggle.query_toggle.clone()
+        );
+
+        let snippet_window = SnippetWindow::build(
+            &builder,
+            &sql_editor,
+            schema_tree.clone(),
+            tables_nb.clone(),
+            table_env.clone()
+        );
+
+        let profile_window = ProfileWindow::build(&builder, tables_nb.clone(), table_env.clone());
+        let pivot_window = PivotWindow::build(
+            &builder,
+            tables_nb.clone(),
+            table_env.clone(),
+            plot_workspace.clone(),
+            table_bar.clone(),
+            status_stack.clone()
+        );
+        let combine_window = CombineWindow::build(
+            &builder,
+            tables_nb.clone(),
+            table_env.clone(),
+            plot_workspace.clone(),
+            table_bar.clone(),
+            status_stack.clone()
+        );
+        let transfer_window = TransferWindow::build(
+            &builder,
+            table_env.clone(),
+            status_stack.clone(),
+            schema_tree.clone()
+        );
+
         let main_menu = MainMenu::build(
             &builder,
             &sql_editor,
@@ -348,7 +434,13 @@ impl QueriesApp {
             table_env.clone(),
             sql_editor.clone(),
             cmd_window.clone(),
-            plot_workspace.layout_window.clone()
+            plot_workspace.layout_window.clone(),
+            history_window.clone(),
+            snippet_window.clone(),
+            profile_window,
+            pivot_window,
+            combine_window,
+            transfer_window
         );
         plot_workspace.layout_window.connect_window_show(
             // &plot_workspace.layout_window.win,
@@ -368,7 +460,64 @@ impl QueriesApp {
             table_bar,
             table_popover,
             cmd_window,
-            csv_window
+            csv_window,
+            history_window,
+            snippet_window,
+            content_stack
+        }
+    }
+
+    /// Current state of the editor, connection, plot layout and table settings.
+    fn workspace(&self) -> Workspace {
+        let (files, selected) = self.sql_editor.file_list.workspace_files(&self.content_stack);
+        let connection = match self.table_env.try_borrow() {
+            Ok(t_env) => self.conn_popover.current_profile(&t_env),
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                None
+            }
+        };
+
+        // Layouts shipped with queries are only starting points for new layouts.
+        let layout = self.plot_workspace.layout_path.try_borrow().ok()
+            .and_then(|path| path.clone() )
+            .filter(|path| !path.starts_with("assets/plot_layout/layout-") );
+        Workspace {
+            files,
+            selected,
+            connection,
+            layout,
+            table_settings : self.csv_window.settings()
+        }
+    }
+
+    fn save_workspace(&self) {
+        if let Err(e) = self.workspace().save(Path::new(WORKSPACE_PATH)) {
+            println!("{}", e);
+        }
+    }
+
+    fn restore_workspace(&self) {
+        let ws = match Workspace::load(Path::new(WORKSPACE_PATH)) {
+            Ok(Some(ws)) => ws,
+            Ok(None) => return,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        self.sql_editor.file_list.restore(
+            &ws.files,
+            ws.selected,
+            self.content_stack.clone(),
+            self.sql_editor.clone()
+        );
+        self.csv_window.restore(ws.table_settings);
+        if let Some(profile) = &ws.connection {
+            self.conn_popover.restore_profile(profile);
+        }
+        if let Some(layout) = &ws.layout {
+            self.plot_workspace.layout_window.open_layout(layout);
         }
     }
 
@@ -533,9 +682,27 @@ fn build_ui(app: &gtk::Application) {
         });
     }
 
+    {
+        let queries_app = queries_app.clone();
+        win.connect_delete_event(move |_, _| {
+            queries_app.save_workspace();
+            glib::signal::Inhibit(false)
+        });
+    }
+
+    // Periodic autosave, so a crash does not lose the unsaved editor contents.
+    {
+        let queries_app = queries_app.clone();
+        glib::timeout_add_local(AUTOSAVE_INTERVAL, move || {
+            queries_app.save_workspace();
+            glib::Continue(true)
+        });
+    }
+
     win.set_application(Some(app));
 
     win.show_all();
+    queries_app.restore_workspace();
     queries_app.header_toggle.table_toggle.set_active(true);
 }
 
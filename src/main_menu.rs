@@ -11,7 +11,7 @@ use gtk::prelude::*;
 // use crate::{status_stack::StatusStack };
 // use crate::status_stack::*;
 // use sourceview::View;
-use super::sql_editor::SqlEditor;
+use super::sql_editor::{SqlEditor, RunScope};
 // use std::io::Write;
 use crate::table_notebook::TableNotebook;
 use crate::plots::plotview::plot_view::PlotView;
@@ -19,6 +19,12 @@ use crate::utils;
 use crate::report;
 use crate::command::CommandWindow;
 use crate::plots::layout_window::LayoutWindow;
+use crate::history_window::HistoryWindow;
+use crate::snippet_window::SnippetWindow;
+use crate::profile_window::ProfileWindow;
+use crate::pivot_window::PivotWindow;
+use crate::combine_window::CombineWindow;
+use crate::transfer_window::TransferWindow;
 
 #[derive(Clone, Debug)]
 pub struct MainMenu {
@@ -31,12 +37,22 @@ pub struct MainMenu {
     sql_new_btn : ModelButton,
     sql_save_btn : ModelButton,
     menu_run_btn : ModelButton,
+    history_btn : ModelButton,
+    format_sql_btn : ModelButton,
+    run_statement_btn : ModelButton,
+    run_from_cursor_btn : ModelButton,
+    preview_expansion_btn : ModelButton,
+    snippet_btn : ModelButton,
     // jobs_btn : ModelButton,
     // jobs_window : Window,
     settings_window : Window,
     // pub cmd_window : CommandWindow,
     report_window : Window,
     report_btn : ModelButton,
+    profile_btn : ModelButton,
+    pivot_btn : ModelButton,
+    combine_btn : ModelButton,
+    transfer_btn : ModelButton,
     layout_btn : ModelButton,
     save_img_btn : ModelButton,
     // save_tbl_btn : ModelButton,
@@ -106,7 +122,13 @@ impl MainMenu {
         tbl_env : Rc<RefCell<TableEnvironment>>,
         editor : SqlEditor,
         cmd_window : CommandWindow,
-        layout_window : LayoutWindow
+        layout_window : LayoutWindow,
+        history_window : HistoryWindow,
+        snippet_window : SnippetWindow,
+        profile_window : ProfileWindow,
+        pivot_window : PivotWindow,
+        combine_window : CombineWindow,
+        transfer_window : TransferWindow
     ) -> Self {
         let main_menu : PopoverMenu = builder.get_object("main_menu").unwrap();
         let sql_new_btn : ModelButton = builder.get_object("sql_new_btn").unwrap();
@@ -115,9 +137,19 @@ impl MainMenu {
         let layout_btn : ModelButton = builder.get_object("layout_btn").unwrap();
         let settings_btn : ModelButton = builder.get_object("settings_btn").unwrap();
         let menu_run_btn : ModelButton = builder.get_object("menu_run_btn").unwrap();
+        let history_btn : ModelButton = builder.get_object("history_btn").unwrap();
+        let format_sql_btn : ModelButton = builder.get_object("format_sql_btn").unwrap();
+        let run_statement_btn : ModelButton = builder.get_object("run_statement_btn").unwrap();
+        let run_from_cursor_btn : ModelButton = builder.get_object("run_from_cursor_btn").unwrap();
+        let preview_expansion_btn : ModelButton = builder.get_object("preview_expansion_btn").unwrap();
+        let snippet_btn : ModelButton = builder.get_object("snippet_btn").unwrap();
         // let engine_window : Window = builder.get_object("engine_window").unwrap();
         let report_btn : ModelButton = builder.get_object("report_btn").unwrap();
         let report_window : Window = builder.get_object("report_window").unwrap();
+        let profile_btn : ModelButton = builder.get_object("profile_btn").unwrap();
+        let pivot_btn : ModelButton = builder.get_object("pivot_btn").unwrap();
+        let combine_btn : ModelButton = builder.get_object("combine_btn").unwrap();
+        let transfer_btn : ModelButton = builder.get_object("transfer_btn").unwrap();
         
         // Build report window
         let report_template_btn : FileChooserButton = builder.get_object("report_template_btn").unwrap();
@@ -173,6 +205,8 @@ impl MainMenu {
         utils::link_window(layout_btn.clone(), layout_window.win.clone());
         utils::link_window(report_btn.clone(), report_window.clone());
         utils::link_window(menu_run_btn.clone(), cmd_window.win.clone());
+        utils::link_window(history_btn.clone(), history_window.win.clone());
+        utils::link_window(snippet_btn.clone(), snippet_window.win.clone());
 
         {
             let main_menu = main_menu.clone();
@@ -225,6 +259,50 @@ impl MainMenu {
             });
         }
 
+        {
+            let sql_editor = sql_editor.clone();
+            format_sql_btn.connect_clicked(move |_btn| {
+                sql_editor.format_current();
+            });
+        }
+
+        {
+            let sql_editor = sql_editor.clone();
+            run_statement_btn.connect_clicked(move |_btn| {
+                sql_editor.run(RunScope::Statement);
+            });
+        }
+
+        {
+            let sql_editor = sql_editor.clone();
+            run_from_cursor_btn.connect_clicked(move |_btn| {
+                sql_editor.run(RunScope::FromCursor);
+            });
+        }
+
+        {
+            let sql_editor = sql_editor.clone();
+            preview_expansion_btn.connect_clicked(move |_btn| {
+                sql_editor.preview_expansion();
+            });
+        }
+
+        profile_btn.connect_clicked(move |_btn| {
+            profile_window.show();
+        });
+
+        pivot_btn.connect_clicked(move |_btn| {
+            pivot_window.show();
+        });
+
+        combine_btn.connect_clicked(move |_btn| {
+            combine_window.show();
+        });
+
+        transfer_btn.connect_clicked(move |_btn| {
+            transfer_window.show();
+        });
+
         {
             // let sql_save_dialog = sql_editor.sql_save_dialog.clone();
             let sql_editor = sql_editor.clone();
@@ -237,6 +315,12 @@ impl MainMenu {
             main_menu,
             main_toggle,
             menu_run_btn,
+            history_btn,
+            format_sql_btn,
+            run_statement_btn,
+            run_from_cursor_btn,
+            preview_expansion_btn,
+            snippet_btn,
             // engine_btn,
             // engine_window,
             settings_btn,
@@ -250,6 +334,10 @@ impl MainMenu {
             // save_tbl_btn,
             report_window,
             report_btn,
+            profile_btn,
+            pivot_btn,
+            combine_btn,
+            transfer_btn,
             // menu_run_btn,
             // cmd_window
             // jobs_btn,
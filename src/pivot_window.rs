@@ -0,0 +1,188 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::environment::TableEnvironment;
+use crate::tables::pivot::{Pivot, Aggregate};
+use crate::table_notebook::{TableNotebook, TableBar, TableSource};
+use crate::plots::plot_workspace::PlotWorkspace;
+use crate::status_stack::StatusStack;
+use crate::utils;
+
+/// Cross-tabulates the table at the current page: each column is marked as a row key,
+/// a column key or the value to aggregate, and the pivoted table is added after the
+/// tables of the environment.
+#[derive(Clone)]
+pub struct PivotWindow {
+    dialog : Dialog,
+    label : Label,
+    list : ListBox,
+    agg_combo : ComboBoxText,
+    apply_btn : Button,
+
+    /// Role of each column of the table being pivoted.
+    roles : Rc<RefCell<Vec<ComboBoxText>>>,
+    tbl_ix : Rc<RefCell<usize>>,
+    tbl_nb : TableNotebook,
+    t_env : Rc<RefCell<TableEnvironment>>,
+    workspace : PlotWorkspace,
+    table_bar : TableBar,
+    status_stack : StatusStack
+}
+
+impl PivotWindow {
+
+    pub fn build(
+        builder : &Builder,
+        tbl_nb : TableNotebook,
+        t_env : Rc<RefCell<TableEnvironment>>,
+        workspace : PlotWorkspace,
+        table_bar : TableBar,
+        status_stack : StatusStack
+    ) -> Self {
+        let dialog : Dialog = builder.get_object("pivot_dialog").unwrap();
+        let label : Label = builder.get_object("pivot_label").unwrap();
+        let list : ListBox = builder.get_object("pivot_list").unwrap();
+        let agg_combo : ComboBoxText = builder.get_object("pivot_agg_combo").unwrap();
+        let apply_btn : Button = builder.get_object("pivot_apply_btn").unwrap();
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            glib::signal::Inhibit(true)
+        });
+        Self {
+            dialog,
+            label,
+            list,
+            agg_combo,
+            apply_btn,
+            roles : Rc::new(RefCell::new(Vec::new())),
+            tbl_ix : Rc::new(RefCell::new(0)),
+            tbl_nb,
+            t_env,
+            workspace,
+            table_bar,
+            status_stack
+        }
+    }
+
+    /// Lists the columns of the table, each with a combo to choose its role. Only
+    /// one column can be the value, so choosing a new value resets the last one.
+    fn update(&self, tbl_ix : usize) {
+        *self.tbl_ix.borrow_mut() = tbl_ix;
+        for row in self.list.get_children() {
+            self.list.remove(&row);
+        }
+        let (name, names) = match self.t_env.try_borrow() {
+            Ok(env) => match env.all_tables().get(tbl_ix) {
+                Some(tbl) => (tbl.table_info().0.unwrap_or(String::from("Table")), tbl.names()),
+                None => (String::new(), Vec::new())
+            },
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                return;
+            }
+        };
+        if names.is_empty() {
+            self.label.set_text("No table to pivot");
+            self.apply_btn.set_sensitive(false);
+            return;
+        }
+        self.label.set_text(&format!(
+            "Choose the row keys, the column keys and the value column of {}. \
+            Each distinct combination of column keys becomes a new column.",
+            name
+        ));
+        self.apply_btn.set_sensitive(true);
+        let mut combos = Vec::new();
+        for name in names.iter() {
+            let row = Box::new(Orientation::Horizontal, 6);
+            row.set_margin_start(6);
+            row.set_margin_end(6);
+            row.set_margin_top(3);
+            row.set_margin_bottom(3);
+            let name_lbl = Label::new(Some(&name[..]));
+            name_lbl.set_xalign(0.0);
+            name_lbl.set_ellipsize(pango::EllipsizeMode::End);
+            let combo = ComboBoxText::new();
+            combo.append(Some("none"), "Ignore");
+            combo.append(Some("row"), "Row key");
+            combo.append(Some("col"), "Column key");
+            combo.append(Some("value"), "Value");
+            combo.set_active_id(Some("none"));
+            row.pack_start(&name_lbl, true, true, 0);
+            row.pack_start(&combo, false, false, 0);
+            self.list.insert(&row, -1);
+            combos.push(combo);
+        }
+        self.list.show_all();
+        for combo in combos.iter() {
+            let roles = self.roles.clone();
+            combo.connect_changed(move |combo| {
+                if combo.get_active_id().map(|id| id.as_str() == "value" ).unwrap_or(false) {
+                    if let Ok(roles) = roles.try_borrow() {
+                        for other in roles.iter().filter(|c| *c != combo ) {
+                            if other.get_active_id().map(|id| id.as_str() == "value" ).unwrap_or(false) {
+                                other.set_active_id(Some("none"));
+                            }
+                        }
+                    }
+                }
+            });
+        }
+        *self.roles.borrow_mut() = combos;
+    }
+
+    fn pivot(&self) -> Result<(), String> {
+        let tbl_ix = *self.tbl_ix.borrow();
+        let (mut rows, mut cols, mut value) = (Vec::new(), Vec::new(), None);
+        for (i, combo) in self.roles.borrow().iter().enumerate() {
+            match combo.get_active_id().as_ref().map(|id| id.as_str() ) {
+                Some("row") => rows.push(i),
+                Some("col") => cols.push(i),
+                Some("value") => value = Some(i),
+                _ => { }
+            }
+        }
+        let value = value.ok_or(String::from("Choose the value column"))?;
+        let agg = self.agg_combo.get_active_id()
+            .and_then(|id| Aggregate::from_name(id.as_str()) )
+            .ok_or(String::from("Choose an aggregate"))?;
+        let pivot = Pivot { rows, cols, value, agg };
+        let tbl = match self.t_env.try_borrow() {
+            Ok(env) => {
+                let tbl = env.all_tables().get(tbl_ix).ok_or(String::from("Table not found"))?;
+                pivot.apply(tbl)?
+            },
+            Err(_) => return Err(String::from("Unable to borrow table environment"))
+        };
+        let name = tbl.table_info().0.unwrap_or(String::from("Pivot"));
+        utils::add_table(
+            &self.t_env,
+            &self.tbl_nb,
+            TableSource::Derived(name),
+            tbl,
+            &self.workspace,
+            &self.table_bar,
+            &self.status_stack
+        )
+    }
+
+    /// Shows the columns of the table at the current page. The dialog stays
+    /// open, showing the error, if the table cannot be pivoted.
+    pub fn show(&self) {
+        self.update(self.tbl_nb.get_page_index());
+        loop {
+            match self.dialog.run() {
+                ResponseType::Other(1) => {
+                    match self.pivot() {
+                        Ok(_) => break,
+                        Err(e) => self.label.set_text(&e)
+                    }
+                },
+                _ => break
+            }
+        }
+        self.dialog.hide();
+    }
+
+}
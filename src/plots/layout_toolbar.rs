@@ -1304,11 +1304,11 @@ impl LayoutToolbar {
                 if let Some(tbl) = tbl_nb.expose_table(tbl_pos) {
                     tbl.set_selected(&tbl_ixs);
                     println!("Table indices: {:?}", tbl_ixs);
-                    if let Some(ev) = tbl.expose_event_box(tbl_ixs[0]) {
-                        self.mapping_popover.set_relative_to(Some(&ev));
+                    if let Some(header) = tbl.expose_header(tbl_ixs[0]) {
+                        self.mapping_popover.set_relative_to(Some(&header));
                         self.mapping_popover.show();
                     } else {
-                        println!("Could not retrieve event box for table {}", tbl_pos);
+                        println!("Could not retrieve column header for table {}", tbl_pos);
                     }
                 } else {
                     println!("Could not expose table at position {}", tbl_pos);
@@ -368,6 +368,13 @@ impl LayoutWindow {
         }
     }
 
+    /// Loads the layout at path (e.g. the layout saved with the workspace) by selecting
+    /// it at the file combo, which loads it as if the user picked it from the recent paths.
+    pub fn open_layout(&self, path : &str) {
+        self.file_combo.append(Some("restored"), path);
+        self.file_combo.set_active_id(Some("restored"));
+    }
+
     pub fn connect_window_show(&self, /*win : &Window,*/ layout_path : Rc<RefCell<Option<String>>>) {
         let file_combo = self.file_combo.clone();
         let recent = self.recent.clone();
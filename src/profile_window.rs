@@ -0,0 +1,74 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::environment::TableEnvironment;
+use crate::tables::profile;
+use crate::table_notebook::TableNotebook;
+
+/// Shows the profile (counts, moments, quantiles, histogram and most frequent values)
+/// of the selected result columns, or of all columns of the current table.
+#[derive(Clone)]
+pub struct ProfileWindow {
+    dialog : Dialog,
+    label : Label,
+    view : TextView,
+    tbl_nb : TableNotebook,
+    t_env : Rc<RefCell<TableEnvironment>>,
+
+    /// Index of the table profiled last.
+    tbl_ix : Rc<RefCell<usize>>
+}
+
+impl ProfileWindow {
+
+    pub fn build(builder : &Builder, tbl_nb : TableNotebook, t_env : Rc<RefCell<TableEnvironment>>) -> Self {
+        let dialog : Dialog = builder.get_object("profile_dialog").unwrap();
+        let label : Label = builder.get_object("profile_label").unwrap();
+        let view : TextView = builder.get_object("profile_view").unwrap();
+        let table_btn : Button = builder.get_object("profile_table_btn").unwrap();
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            glib::signal::Inhibit(true)
+        });
+        let profile_win = Self { dialog, label, view, tbl_nb, t_env, tbl_ix : Rc::new(RefCell::new(0)) };
+        {
+            let profile_win = profile_win.clone();
+            table_btn.connect_clicked(move |_| {
+                let tbl_ix = *profile_win.tbl_ix.borrow();
+                profile_win.update(tbl_ix, &[]);
+            });
+        }
+        profile_win
+    }
+
+    fn update(&self, tbl_ix : usize, cols : &[usize]) {
+        *self.tbl_ix.borrow_mut() = tbl_ix;
+        let report = match self.t_env.try_borrow() {
+            Ok(env) => env.all_tables().get(tbl_ix).map(|tbl| profile::table_report(tbl, cols) ),
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                return;
+            }
+        };
+        let msg = match (&report, cols.len()) {
+            (None, _) => "No table to profile",
+            (Some(_), 0) => "All columns of the current table.",
+            (Some(_), _) => "Selected columns (Ctrl+click the column headers to change the selection)."
+        };
+        self.label.set_text(msg);
+        if let Some(buffer) = self.view.get_buffer() {
+            buffer.set_text(&report.unwrap_or(String::new()));
+        }
+    }
+
+    /// Profiles the selected columns or, if no columns are selected, the table at the current page.
+    pub fn show(&self) {
+        let (tbl_ix, cols) = self.tbl_nb.selected_table_and_cols()
+            .unwrap_or((self.tbl_nb.get_page_index(), Vec::new()));
+        self.update(tbl_ix, &cols[..]);
+        self.dialog.run();
+        self.dialog.hide();
+    }
+
+}
@@ -0,0 +1,70 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::safety::{self, SafetyMode};
+use crate::tables::split::Dialect;
+
+/// Dialog shown before destructive statements are sent to a connection marked as
+/// production. The statements are only sent after the user types the connection name.
+#[derive(Clone)]
+pub struct SafetyDialog {
+    dialog : Dialog,
+    label : Label,
+    entry : Entry,
+    expected : Rc<RefCell<String>>
+}
+
+impl SafetyDialog {
+
+    pub fn build(builder : &Builder) -> Self {
+        let dialog : Dialog = builder.get_object("destructive_dialog").unwrap();
+        let label : Label = builder.get_object("destructive_label").unwrap();
+        let entry : Entry = builder.get_object("destructive_entry").unwrap();
+        let run_btn : Button = builder.get_object("destructive_run_btn").unwrap();
+        let expected = Rc::new(RefCell::new(String::new()));
+        {
+            let expected = expected.clone();
+            entry.connect_changed(move |entry| {
+                let matches = entry.get_text().as_str() == &expected.borrow()[..];
+                run_btn.set_sensitive(matches);
+            });
+        }
+        Self { dialog, label, entry, expected }
+    }
+
+    /// Returns whether the query sequence may be sent to the connection. Sequences
+    /// sent to connections not marked as production (or marked as read-only, which
+    /// refuse destructive statements anyway) never require confirmation.
+    pub fn confirm(
+        &self,
+        safety : &SafetyMode,
+        dialect : Dialect,
+        conn_name : Option<String>,
+        sql : &str
+    ) -> bool {
+        if !safety.production || safety.read_only {
+            return true;
+        }
+        let stmts = safety::destructive_statements(sql, dialect);
+        if stmts.len() == 0 {
+            return true;
+        }
+        let conn_name = conn_name.unwrap_or(String::from("production"));
+        let mut msg = format!(
+            "The following statement(s) will modify the production connection {}:\n\n",
+            conn_name
+        );
+        for stmt in stmts.iter() {
+            msg += &format!("{}\n", stmt.lines().next().unwrap_or(""));
+        }
+        msg += &format!("\nType {} to run them.", conn_name);
+        self.label.set_text(&msg);
+        *self.expected.borrow_mut() = conn_name;
+        self.entry.set_text("");
+        let resp = self.dialog.run();
+        self.dialog.hide();
+        resp == ResponseType::Other(1)
+    }
+
+}
@@ -17,6 +17,7 @@ use std::path::{Path, PathBuf};
 use glib::{types::Type, value::{Value, ToValue}};
 use gdk_pixbuf::Pixbuf;
 use std::collections::HashMap;
+use crate::completion::SqlCompletion;
 //use either::Either;
 
 /*/// Implemented by types which can be viewed by modifying the given widget
@@ -74,10 +75,11 @@ pub struct SchemaTree {
     model : TreeStore,
     type_icons : HashMap<DBType, Pixbuf>,
     tbl_icon : Pixbuf,
-    schema_icon : Pixbuf
+    schema_icon : Pixbuf,
+    completion : SqlCompletion
 }
 
-const ALL_TYPES : [DBType; 15] = [
+const ALL_TYPES : [DBType; 16] = [
     DBType::Bool,
     DBType::I16,
     DBType::I32,
@@ -88,6 +90,8 @@ const ALL_TYPES : [DBType; 15] = [
     DBType::Text,
     DBType::Date,
     DBType::Time,
+    DBType::Timestamp,
+    DBType::TimestampTz,
     DBType::Bytes,
     DBType::Json,
     DBType::Xml,
@@ -106,7 +110,7 @@ impl SchemaTree {
                 DBType::F32 | DBType::F64 | DBType::Numeric => "real.svg",
                 DBType::Text => "text.svg",
                 DBType::Date => "date.svg",
-                DBType::Time => "time.svg",
+                DBType::Time | DBType::Timestamp | DBType::TimestampTz => "time.svg",
                 DBType::Json => "json.svg",
                 DBType::Xml => "xml.svg",
                 DBType::Bytes => "binary.svg",
@@ -119,13 +123,14 @@ impl SchemaTree {
         type_icons
     }
 
-    pub fn build(builder : &Builder) -> Self {
+    pub fn build(builder : &Builder, completion : SqlCompletion) -> Self {
         let type_icons = Self::load_type_icons();
         let tbl_icon = Pixbuf::from_file_at_scale("assets/icons/grid-black.svg", 16, 16, true).unwrap();
         let schema_icon = Pixbuf::from_file_at_scale("assets/icons/db.svg", 16, 16, true).unwrap();
         let tree_view : TreeView = builder.get_object("schema_tree_view").unwrap();
         let model = utils::configure_tree_view(&tree_view);
-        Self{ tree_view, model, type_icons, tbl_icon, schema_icon }
+        tree_view.get_selection().set_mode(SelectionMode::Multiple);
+        Self{ tree_view, model, type_icons, tbl_icon, schema_icon, completion }
     }
 
     // grow_tree<T>(obj : T) for T : Display + Iterator<Item=&Self>
@@ -161,6 +166,11 @@ impl SchemaTree {
                 if &t_env.get_engine_name()[..] == "PostgreSQL" {
                     is_pg = true;
                 }
+                self.completion.set_dialect(t_env.dialect());
+                self.completion.update_schema(&objs);
+                if let Some(names) = t_env.function_names() {
+                    self.completion.update_functions(&names);
+                }
                 for obj in objs {
                     self.grow_tree(&self.model, None, /*self.model.get_iter_first().as_ref()*/ obj);
                 }
@@ -188,6 +198,52 @@ impl SchemaTree {
         }
     }
 
+    fn is_row_of(&self, iter : &TreeIter, icon : &Pixbuf) -> bool {
+        self.model.get_value(iter, 0).get::<Pixbuf>().ok().flatten().map(|pix| &pix == icon ).unwrap_or(false)
+    }
+
+    fn row_name(&self, iter : &TreeIter) -> Option<String> {
+        self.model.get_value(iter, 1).get::<String>().ok().flatten()
+    }
+
+    /// Schema, table and column names of the current selection. Selecting a column also
+    /// selects its table, and selecting a table selects its schema. Only the columns of the
+    /// first selected table are considered.
+    pub fn selected_names(&self) -> (Option<String>, Option<String>, Vec<String>) {
+        let (mut schema, mut table, mut cols) = (None, None, Vec::new());
+        let (paths, _) = self.tree_view.get_selection().get_selected_rows();
+        for path in paths.iter() {
+            let iter = match self.model.get_iter(path) {
+                Some(iter) => iter,
+                None => continue
+            };
+            let (tbl_iter, col) = if self.is_row_of(&iter, &self.tbl_icon) {
+                (iter, None)
+            } else if self.is_row_of(&iter, &self.schema_icon) {
+                if schema.is_none() {
+                    schema = self.row_name(&iter);
+                }
+                continue;
+            } else {
+                match self.model.iter_parent(&iter) {
+                    Some(parent) => (parent, self.row_name(&iter)),
+                    None => continue
+                }
+            };
+            let tbl_name = self.row_name(&tbl_iter);
+            if table.is_none() {
+                table = tbl_name.clone();
+                schema = self.model.iter_parent(&tbl_iter).and_then(|parent| self.row_name(&parent) );
+            }
+            if let Some(col) = col {
+                if tbl_name == table {
+                    cols.push(col);
+                }
+            }
+        }
+        (schema, table, cols)
+    }
+
     pub fn clear(&self) {
         // for child in self.tree_view.get_children() {
         //    self.tree_view.remove_child(&child);
@@ -195,6 +251,7 @@ impl SchemaTree {
         // self.tree_view.set_model(None::<&TreeStore>);
         self.model.clear();
         self.tree_view.show_all();
+        self.completion.clear();
     }
 
 }
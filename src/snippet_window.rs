@@ -0,0 +1,380 @@
+use gtk::*;
+use gtk::prelude::*;
+use gdk::keys;
+use sourceview::View;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use crate::tables::environment::TableEnvironment;
+use crate::tables::snippets::{Snippet, SnippetContext, SnippetLibrary};
+use crate::sql_editor::SqlEditor;
+use crate::schema_tree::SchemaTree;
+use crate::table_notebook::TableNotebook;
+use crate::status_stack::Status;
+
+/// Tab stops of the last snippet inserted at an editor. While the snippet is being filled, Tab
+/// selects the next stop and Shift+Tab the previous one; leaving a stop copies its text to the
+/// other stops with the same index. Escape (or Tab at the last stop) stops filling the snippet.
+#[derive(Clone)]
+pub struct SnippetStops {
+
+    /// View holding the snippet, the marks (start and end) of its stops, grouped by index
+    /// in visiting order, and the index of the group currently selected.
+    active : Rc<RefCell<Option<(View, Vec<Vec<(TextMark, TextMark)>>, usize)>>>
+}
+
+impl SnippetStops {
+
+    pub fn new() -> Self {
+        Self { active : Rc::new(RefCell::new(None)) }
+    }
+
+    /// Starts moving between the stops of the snippet inserted at the view with Tab and Shift+Tab.
+    pub fn configure(&self, view : &View) {
+        let stops = self.clone();
+        view.connect_key_press_event(move |view, ev_key| {
+            let filling = match stops.active.try_borrow() {
+                Ok(active) => active.as_ref().map(|(v, _, _)| v == view ).unwrap_or(false),
+                Err(_) => false
+            };
+            if !filling {
+                return glib::signal::Inhibit(false);
+            }
+            let key = ev_key.get_keyval();
+            if key == keys::constants::Tab {
+                stops.step(true);
+            } else if key == keys::constants::ISO_Left_Tab {
+                stops.step(false);
+            } else if key == keys::constants::Escape {
+                stops.finish();
+            } else {
+                return glib::signal::Inhibit(false);
+            }
+            glib::signal::Inhibit(true)
+        });
+    }
+
+    /// Replaces the selection of the view (or inserts at the cursor) by the text, and selects
+    /// the first tab stop. Stops are informed as character offsets relative to the text.
+    pub fn insert(&self, view : &View, text : &str, groups : &[Vec<(usize, usize)>]) {
+        self.finish();
+        let buffer = match view.get_buffer() {
+            Some(buffer) => buffer,
+            None => return
+        };
+        buffer.delete_selection(true, true);
+        let base = match buffer.get_insert() {
+            Some(insert) => buffer.get_iter_at_mark(&insert).get_offset() as usize,
+            None => return
+        };
+        buffer.insert_at_cursor(text);
+        let marks : Vec<Vec<(TextMark, TextMark)>> = groups.iter()
+            .map(|group| {
+                group.iter().filter_map(|(start, end)| {
+                    let start_iter = buffer.get_iter_at_offset((base + start) as i32);
+                    let end_iter = buffer.get_iter_at_offset((base + end) as i32);
+                    Some((buffer.create_mark(None, &start_iter, true)?, buffer.create_mark(None, &end_iter, false)?))
+                }).collect()
+            })
+            .collect();
+        view.grab_focus();
+        if marks.is_empty() {
+            return;
+        }
+        if let Ok(mut active) = self.active.try_borrow_mut() {
+            *active = Some((view.clone(), marks, 0));
+        } else {
+            println!("Unable to borrow snippet stops");
+            return;
+        }
+        self.select(0);
+    }
+
+    fn select(&self, ix : usize) {
+        if let Ok(active) = self.active.try_borrow() {
+            if let Some((view, groups, _)) = &*active {
+                if let (Some(buffer), Some((start, end))) = (view.get_buffer(), groups.get(ix).and_then(|g| g.first() )) {
+                    buffer.select_range(&buffer.get_iter_at_mark(start), &buffer.get_iter_at_mark(end));
+                }
+            }
+        }
+    }
+
+    /// Copies the text of the first stop of the current group to the other stops of the group,
+    /// and selects the next (or previous) group, finishing the snippet after the last group.
+    fn step(&self, forward : bool) {
+        let next = match self.active.try_borrow_mut() {
+            Ok(mut active) => match &mut *active {
+                Some((view, groups, current)) => {
+                    if let (Some(buffer), Some(group)) = (view.get_buffer(), groups.get(*current)) {
+                        mirror(&buffer, group);
+                    }
+                    let next = if forward {
+                        *current + 1
+                    } else {
+                        current.saturating_sub(1)
+                    };
+                    *current = next;
+                    next < groups.len()
+                },
+                None => false
+            },
+            Err(_) => false
+        };
+        if next {
+            let current = self.active.borrow().as_ref().map(|(_, _, current)| *current ).unwrap_or(0);
+            self.select(current);
+        } else {
+            self.finish();
+        }
+    }
+
+    /// Stops filling the current snippet, leaving the cursor at the end of its last stop.
+    pub fn finish(&self) {
+        if let Ok(mut active) = self.active.try_borrow_mut() {
+            if let Some((view, groups, _)) = active.take() {
+                if let Some(buffer) = view.get_buffer() {
+                    if let Some((_, end)) = groups.last().and_then(|g| g.first() ) {
+                        buffer.place_cursor(&buffer.get_iter_at_mark(end));
+                    }
+                    for (start, end) in groups.iter().flatten() {
+                        buffer.delete_mark(start);
+                        buffer.delete_mark(end);
+                    }
+                }
+            }
+        } else {
+            println!("Unable to borrow snippet stops");
+        }
+    }
+
+}
+
+fn mirror(buffer : &TextBuffer, group : &[(TextMark, TextMark)]) {
+    let text = match group.first() {
+        Some((start, end)) => buffer.get_text(&buffer.get_iter_at_mark(start), &buffer.get_iter_at_mark(end), false),
+        None => return
+    };
+    let text = text.map(|t| t.to_string() ).unwrap_or(String::new());
+    for (start, end) in group.iter().skip(1) {
+        let mut start_iter = buffer.get_iter_at_mark(start);
+        let mut end_iter = buffer.get_iter_at_mark(end);
+        buffer.delete(&mut start_iter, &mut end_iter);
+        buffer.insert(&mut buffer.get_iter_at_mark(start), &text);
+    }
+}
+
+/// Window listing the snippets of the library, which inserts the selected snippet at the
+/// current editor (filled with the objects selected at the schema tree or, if there are none,
+/// with the columns selected at the table notebook), and copies snippet files in and out
+/// of the library.
+#[derive(Clone)]
+pub struct SnippetWindow {
+    pub win : Window,
+    list : ListBox,
+    snippets : Rc<RefCell<Vec<Snippet>>>,
+    library : Rc<SnippetLibrary>
+}
+
+impl SnippetWindow {
+
+    fn build_row(snippet : &Snippet) -> ListBoxRow {
+        let name_lbl = Label::new(Some(&snippet.name.replace('_', " ")[..]));
+        name_lbl.set_halign(Align::Start);
+        name_lbl.set_tooltip_text(Some(&snippet.body[..]));
+        let descr_lbl = Label::new(Some(&snippet.description[..]));
+        descr_lbl.set_halign(Align::Start);
+        descr_lbl.set_ellipsize(pango::EllipsizeMode::End);
+        descr_lbl.get_style_context().add_class("dim-label");
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.pack_start(&name_lbl, false, false, 0);
+        bx.pack_start(&descr_lbl, false, false, 0);
+        bx.set_margin_top(6);
+        bx.set_margin_bottom(6);
+        bx.set_margin_start(6);
+        bx.set_margin_end(6);
+        let row = ListBoxRow::new();
+        row.add(&bx);
+        row.set_selectable(true);
+        row
+    }
+
+    fn update_snippets(library : &SnippetLibrary, list : &ListBox, snippets : &Rc<RefCell<Vec<Snippet>>>) {
+        for child in list.get_children() {
+            list.remove(&child);
+        }
+        match library.list() {
+            Ok(found) => {
+                for (i, snippet) in found.iter().enumerate() {
+                    list.insert(&Self::build_row(snippet), i as i32);
+                }
+                if let Ok(mut snippets) = snippets.try_borrow_mut() {
+                    *snippets = found;
+                } else {
+                    println!("Unable to borrow snippets");
+                }
+            },
+            Err(e) => println!("{}", e)
+        }
+        list.show_all();
+    }
+
+    fn selected_snippet(list : &ListBox, snippets : &Rc<RefCell<Vec<Snippet>>>) -> Option<Snippet> {
+        let row = list.get_selected_row()?;
+        let ix = row.get_index();
+        if ix < 0 {
+            return None;
+        }
+        snippets.borrow().get(ix as usize).cloned()
+    }
+
+    /// Objects selected at the schema tree, or columns selected at the current table.
+    fn selection(
+        schema_tree : &SchemaTree,
+        tables_nb : &TableNotebook,
+        table_env : &Rc<RefCell<TableEnvironment>>
+    ) -> SnippetContext {
+        let (schema, table, columns) = schema_tree.selected_names();
+        if table.is_some() {
+            return SnippetContext { schema, table, columns };
+        }
+        let cols = tables_nb.selected_cols();
+        let mut ctx = SnippetContext::default();
+        if let Ok(env) = table_env.try_borrow() {
+            if let Some(tbl) = env.all_tables().get(tables_nb.get_page_index()) {
+                let names = tbl.names();
+                ctx.columns = cols.iter().filter_map(|ix| names.get(*ix).cloned() ).collect();
+                ctx.table = tbl.table_info().1;
+            }
+        } else {
+            println!("Unable to borrow table environment");
+        }
+        ctx
+    }
+
+    pub fn refresh(&self) {
+        Self::update_snippets(&self.library, &self.list, &self.snippets);
+    }
+
+    pub fn build(
+        builder : &Builder,
+        sql_editor : &SqlEditor,
+        schema_tree : SchemaTree,
+        tables_nb : TableNotebook,
+        table_env : Rc<RefCell<TableEnvironment>>
+    ) -> Self {
+        let win : Window = builder.get_object("snippet_window").unwrap();
+        let list : ListBox = builder.get_object("snippet_list").unwrap();
+        let insert_btn : Button = builder.get_object("snippet_insert_btn").unwrap();
+        let remove_btn : Button = builder.get_object("snippet_remove_btn").unwrap();
+        let import_btn : Button = builder.get_object("snippet_import_btn").unwrap();
+        let export_btn : Button = builder.get_object("snippet_export_btn").unwrap();
+        let import_dialog : FileChooserDialog = builder.get_object("snippet_import_dialog").unwrap();
+        let export_dialog : FileChooserDialog = builder.get_object("snippet_export_dialog").unwrap();
+        let filter = FileFilter::new();
+        filter.add_pattern("*.sql");
+        import_dialog.set_filter(&filter);
+        let snippets : Rc<RefCell<Vec<Snippet>>> = Rc::new(RefCell::new(Vec::new()));
+        let library = Rc::new(SnippetLibrary::open_default());
+
+        {
+            let library = library.clone();
+            let list = list.clone();
+            let snippets = snippets.clone();
+            win.connect_show(move |_| {
+                Self::update_snippets(&library, &list, &snippets);
+            });
+        }
+
+        {
+            let list = list.clone();
+            let snippets = snippets.clone();
+            let sql_editor = sql_editor.clone();
+            let win = win.clone();
+            insert_btn.connect_clicked(move |_| {
+                if let Some(snippet) = Self::selected_snippet(&list, &snippets) {
+                    let ctx = Self::selection(&schema_tree, &tables_nb, &table_env);
+                    let (text, stops) = snippet.instantiate(&ctx);
+                    win.hide();
+                    sql_editor.insert_snippet(&text, &stops);
+                }
+            });
+        }
+
+        {
+            let library = library.clone();
+            let list = list.clone();
+            let snippets = snippets.clone();
+            remove_btn.connect_clicked(move |_| {
+                if let Some(snippet) = Self::selected_snippet(&list, &snippets) {
+                    if let Err(e) = library.remove(&snippet.name) {
+                        println!("{}", e);
+                    }
+                    Self::update_snippets(&library, &list, &snippets);
+                }
+            });
+        }
+
+        {
+            let import_dialog = import_dialog.clone();
+            import_btn.connect_clicked(move |_| {
+                import_dialog.run();
+                import_dialog.hide();
+            });
+        }
+
+        {
+            let export_dialog = export_dialog.clone();
+            export_btn.connect_clicked(move |_| {
+                export_dialog.run();
+                export_dialog.hide();
+            });
+        }
+
+        {
+            let library = library.clone();
+            let list = list.clone();
+            let snippets = snippets.clone();
+            let status_stack = sql_editor.status_stack.clone();
+            import_dialog.connect_response(move |dialog, resp| {
+                if let ResponseType::Other(1) = resp {
+                    let paths : Vec<PathBuf> = dialog.get_filenames();
+                    match library.import(&paths) {
+                        Ok(imported) => {
+                            let renamed : Vec<String> = imported.iter()
+                                .filter(|(name, saved)| name != saved )
+                                .map(|(name, saved)| format!("{} as {}", name, saved) )
+                                .collect();
+                            let msg = if renamed.is_empty() {
+                                format!("{} snippet(s) imported", imported.len())
+                            } else {
+                                format!("{} snippet(s) imported ({}, since the names were taken)", imported.len(), renamed.join(", "))
+                            };
+                            status_stack.update(Status::StatementExecuted(msg));
+                        },
+                        Err(e) => status_stack.update(Status::SqlErr(e))
+                    }
+                    Self::update_snippets(&library, &list, &snippets);
+                }
+            });
+        }
+
+        {
+            let library = library.clone();
+            let status_stack = sql_editor.status_stack.clone();
+            export_dialog.connect_response(move |dialog, resp| {
+                if let ResponseType::Other(1) = resp {
+                    if let Some(dir) = dialog.get_filename() {
+                        match library.export(&dir) {
+                            Ok(n) => println!("{} snippet(s) exported to {}", n, dir.display()),
+                            Err(e) => status_stack.update(Status::SqlErr(e))
+                        }
+                    }
+                }
+            });
+        }
+
+        Self { win, list, snippets, library }
+    }
+
+}
@@ -6,7 +6,7 @@ use std::cell::RefCell;
 use std::fs::File;
 use std::io::Read;
 use gdk::{self, keys};
-use crate::tables::{environment::TableEnvironment, environment::EnvironmentUpdate};
+use crate::tables::{environment::TableEnvironment, environment::EnvironmentUpdate, environment::ExpandedScript};
 use sourceview::{self, *};
 use gtk::prelude::*;
 use crate::status_stack::StatusStack;
@@ -22,6 +22,111 @@ use crate::plots::plot_workspace::PlotWorkspace;
 // use crate::table_popover::TablePopover;
 use crate::header_toggle::HeaderToggle;
 use crate::table_notebook::TableSource;
+use crate::safety_dialog::SafetyDialog;
+use crate::tables::format::{self, FormatOptions, KeywordCase};
+use crate::tables::split::{self, Dialect};
+use crate::tables::macros::{self, Commands};
+use std::sync::mpsc::TryRecvError;
+use std::mem;
+
+/// Formatting options, set at the source editor section of the settings window.
+#[derive(Clone)]
+pub struct FormatSettings {
+    case_combo : ComboBoxText,
+    indent_spin : SpinButton,
+    width_spin : SpinButton
+}
+
+impl FormatSettings {
+
+    pub fn build(builder : &Builder) -> Self {
+        let case_combo : ComboBoxText = builder.get_object("format_case_combo").unwrap();
+        let indent_spin : SpinButton = builder.get_object("format_indent_spin").unwrap();
+        let width_spin : SpinButton = builder.get_object("format_width_spin").unwrap();
+        Self { case_combo, indent_spin, width_spin }
+    }
+
+    pub fn options(&self) -> FormatOptions {
+        let keyword_case = match self.case_combo.get_active_id().as_ref().map(|id| id.as_str() ) {
+            Some("lower") => KeywordCase::Lower,
+            Some("preserve") => KeywordCase::Preserve,
+            _ => KeywordCase::Upper
+        };
+        FormatOptions {
+            keyword_case,
+            indent : self.indent_spin.get_value_as_int() as usize,
+            line_width : self.width_spin.get_value_as_int() as usize
+        }
+    }
+
+}
+
+/// Shows a query sequence with its variables and commands expanded, as it would be sent.
+/// Commands are kept as written unless the user asks to run them.
+#[derive(Clone)]
+pub struct ExpansionPreview {
+    dialog : Dialog,
+    label : Label,
+    view : TextView,
+    run_btn : Button
+}
+
+impl ExpansionPreview {
+
+    pub fn build(builder : &Builder) -> Self {
+        let dialog : Dialog = builder.get_object("expansion_dialog").unwrap();
+        let label : Label = builder.get_object("expansion_label").unwrap();
+        let view : TextView = builder.get_object("expansion_view").unwrap();
+        let run_btn : Button = builder.get_object("expansion_run_btn").unwrap();
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            glib::signal::Inhibit(true)
+        });
+        Self { dialog, label, view, run_btn }
+    }
+
+    /// Shows the expanded sequence (or the expansion error), with the session variables. If
+    /// can_run is true, the user may ask to run its commands, in which case true is returned.
+    pub fn show(&self, expanded : Result<String, String>, variables : &[(String, String)], can_run : bool) -> bool {
+        let mut msg = match variables.len() {
+            0 => String::from("No variables set."),
+            _ => variables.iter()
+                .map(|(name, value)| format!("{} = {}", name, value) )
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let text = match expanded {
+            Ok(text) => text,
+            Err(e) => {
+                msg += &format!("\n\nExpansion failed: {}", e);
+                String::new()
+            }
+        };
+        self.label.set_text(&msg);
+        if let Some(buffer) = self.view.get_buffer() {
+            buffer.set_text(&text);
+        }
+        self.run_btn.set_visible(can_run && text.contains("${"));
+        let resp = self.dialog.run();
+        self.dialog.hide();
+        resp == ResponseType::Other(1)
+    }
+
+}
+
+/// Portion of the editor text sent when the queries are executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunScope {
+
+    /// The selection or, if nothing is selected, the whole text.
+    Selection,
+
+    /// The statement holding the cursor.
+    Statement,
+
+    /// The statement holding the cursor and all statements after it.
+    FromCursor
+}
 
 pub enum ExecStatus {
     File(String, usize),
@@ -46,6 +151,13 @@ pub struct SqlEditor {
     update_btn : ToggleButton,
 
     pub query_sent : Rc<RefCell<bool>>,
+
+    /// Scope of the next execution. Reset to RunScope::Selection once the queries are sent.
+    run_scope : Rc<RefCell<RunScope>>,
+
+    /// Character offset, within the view, of the first character of the last query sequence sent.
+    sent_offset : Rc<RefCell<i32>>,
+
     pub sql_stack : Stack,
     pub status_stack : StatusStack,
     t_env : Rc<RefCell<TableEnvironment>>,
@@ -54,6 +166,17 @@ pub struct SqlEditor {
     query_file_label : Label,
     table_toggle : ToggleButton,
 
+    /// Asks for confirmation before destructive statements are sent to production connections.
+    pub safety_dialog : SafetyDialog,
+
+    /// Open connections. Selecting the connection a file is bound to makes it the current one
+    /// (and shows its tables) before the queries of the file are sent.
+    conn_combo : ComboBoxText,
+
+    format_settings : FormatSettings,
+
+    expansion_preview : ExpansionPreview,
+
     // Keeps status if clock was started at first position,
     // the update interval at second position (constant) and
     // time that ran since the last update (updated at each glib::timeout)
@@ -91,6 +214,42 @@ impl SqlEditor {
         }
     }
 
+    /// Reformats the selected text or, if there is no selection, the whole text of the current
+    /// editor. The change is a single user action, so it can be undone at once.
+    pub fn format_current(&self) {
+        let view = self.view.borrow();
+        let buffer = match view.get_buffer() {
+            Some(buffer) => buffer,
+            None => return
+        };
+        let (mut from, mut to) = buffer.get_selection_bounds()
+            .unwrap_or((buffer.get_start_iter(), buffer.get_end_iter()));
+        let text = match from.get_text(&to) {
+            Some(text) => text.to_string(),
+            None => return
+        };
+        let dialect = match self.t_env.try_borrow() {
+            Ok(env) => env.dialect(),
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                return;
+            }
+        };
+        match format::format_sql(&text, dialect, &self.format_settings.options()) {
+            Ok(formatted) => {
+                if formatted != text {
+                    buffer.begin_user_action();
+                    buffer.delete(&mut from, &mut to);
+                    buffer.insert(&mut from, &formatted);
+                    buffer.end_user_action();
+                }
+            },
+            Err(e) => {
+                self.status_stack.update(Status::SqlErr(format!("Unable to format SQL: {}", e)));
+            }
+        }
+    }
+
     fn connect_sql_save(editor : &SqlEditor) {
         let sql_save_dialog = editor.sql_save_dialog.clone();
         let editor = editor.clone();
@@ -133,11 +292,11 @@ impl SqlEditor {
         }
     }
 
-    pub fn new_source(content : &str, refresh_btn : &Button, file_list : &FileList) -> ScrolledWindow {
+    pub fn new_source(content : &str, sql_editor : &SqlEditor, file_list : &FileList) -> ScrolledWindow {
         let no_adj : Option<&Adjustment> = None;
         let sw = ScrolledWindow::new(no_adj, no_adj);
         let view = View::new();
-        Self::configure_view(&view, refresh_btn, file_list.clone());
+        Self::configure_view(&view, &sql_editor.refresh_btn, &sql_editor.run_scope, file_list.clone());
         view.get_buffer().map(|buf| buf.set_text(&content) );
         sw.add(&view);
         sw
@@ -204,59 +363,168 @@ impl SqlEditor {
         }
     }
 
-    /// Update the query. If there was a SQL parsing error,
-    /// return it. If there was no error, set the SQL sourceview
-    /// to insensitive (until no result arrived) and return Ok(()).
+    /// Text that will be sent by update_queries for the informed scope, with the character
+    /// offset of its first character within the buffer. Statements are found by the tokenizer
+    /// of the informed dialect, so semicolons within literals and comments are not boundaries.
+    pub fn query_range(view : &sourceview::View, scope : RunScope, dialect : Dialect) -> Option<(i32, String)> {
+        let buffer = view.get_buffer()?;
+        if scope == RunScope::Selection {
+            return match buffer.get_selection_bounds() {
+                Some((from,to,)) => {
+                    from.get_text(&to).map(|txt| (from.get_offset(), txt.to_string()) )
+                },
+                None => {
+                    buffer.get_text(
+                        &buffer.get_start_iter(),
+                        &buffer.get_end_iter(),
+                        true
+                    ).map(|txt| (0, txt.to_string()) )
+                }
+            };
+        }
+        let text = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), true)?.to_string();
+        let cursor = buffer.get_property_cursor_position() as usize;
+        let byte_pos = text.char_indices().nth(cursor).map(|(i, _)| i ).unwrap_or(text.len());
+        let spans = split::statement_spans(&text, dialect)
+            .map_err(|e| println!("Unable to split statements: {}", e) )
+            .ok()?;
+        let ix = split::statement_at(&spans, byte_pos)?;
+        let (start, end) = match scope {
+            RunScope::Statement => spans[ix],
+            _ => (spans[ix].0, text.len())
+        };
+        Some((text[..start].chars().count() as i32, text[start..end].to_string()))
+    }
+
+    /// Shows the selection (or the whole text) with its macros expanded, without sending it.
+    /// The \set lines it holds are only applied to a copy of the session variables, and its
+    /// commands are only run (at a background thread) if the user asks for it from the preview.
+    pub fn preview_expansion(&self) {
+        let preview = match self.t_env.try_borrow() {
+            Ok(env) => {
+                Self::query_range(&self.view.borrow(), RunScope::Selection, env.dialect())
+                    .map(|(_, txt)| {
+                        let dir = self.file_list.current_dir();
+                        let expanded = env.preview_expansion(&txt, dir.as_ref().map(|d| d.as_path() ));
+                        (txt, dir, expanded, env.variables())
+                    })
+            },
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                None
+            }
+        };
+        let (txt, dir, expanded, variables) = match preview {
+            Some(preview) => preview,
+            None => return
+        };
+        if !self.expansion_preview.show(expanded, &variables, true) {
+            return;
+        }
+        let receiver = match self.t_env.try_borrow() {
+            Ok(env) => env.expand_script(txt, dir, Commands::Run(macros::COMMAND_TIMEOUT)),
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                return;
+            }
+        };
+        let expansion_preview = self.expansion_preview.clone();
+        glib::timeout_add_local(20, move || {
+            match receiver.try_recv() {
+                Ok(expanded) => {
+                    expansion_preview.show(expanded.map(|exp| exp.sql().to_string() ), &variables, false);
+                    glib::Continue(false)
+                },
+                Err(TryRecvError::Empty) => glib::Continue(true),
+                Err(TryRecvError::Disconnected) => glib::Continue(false)
+            }
+        });
+    }
+
+    /// Inserts a filled snippet at the cursor of the current editor, selecting its first tab stop.
+    pub fn insert_snippet(&self, text : &str, stops : &[Vec<(usize, usize)>]) {
+        self.file_list.snippet_stops.insert(&self.view.borrow(), text, stops);
+    }
+
+    /// Sends the queries within the informed scope, as if the refresh button were clicked.
+    pub fn run(&self, scope : RunScope) {
+        if !self.refresh_btn.is_sensitive() {
+            return;
+        }
+        if let Ok(mut run_scope) = self.run_scope.try_borrow_mut() {
+            *run_scope = scope;
+        } else {
+            println!("Unable to borrow run scope");
+            return;
+        }
+        self.refresh_btn.emit_clicked();
+    }
+
+    /// Sends the last query sequence again, without reading the editor, so the results
+    /// show the changes applied to the database from the result tables.
+    pub fn resend_last(&self) {
+        match self.t_env.try_borrow_mut() {
+            Ok(mut env) => {
+                if let Err(e) = env.send_current_query(true) {
+                    println!("{}", e);
+                    return;
+                }
+            },
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                return;
+            }
+        }
+        self.view.borrow().set_sensitive(false);
+        if let Ok(mut sent) = self.query_sent.try_borrow_mut() {
+            *sent = true;
+        } else {
+            println!("Unable to borrow query sent status");
+        }
+    }
+
+    /// Sends a query sequence expanded from the text at the character offset of the editor.
+    /// If there was an error, return it. If statements were sent, set the SQL sourceview
+    /// to insensitive (until no result arrived) and return Ok(true). Sequences which
+    /// only set variables return Ok(false).
     pub fn update_queries(
-        //file_loaded : Rc<RefCell<bool>>,
         query_sent : Rc<RefCell<bool>>,
+        sent_offset : Rc<RefCell<i32>>,
         tbl_env : &mut TableEnvironment,
         view : &sourceview::View,
-        // status_stack : StatusStack
-        // nb : &TableNotebook
-    ) -> Result<(), String> {
-        /*if let Ok(loaded) = file_loaded.try_borrow() {
-            if *loaded {
-                tbl_env.send_current_query(true)?;
-                view.set_sensitive(false);
-                //nb.nb.set_sensitive(false);
-            } else {*/
-            if let Some(buffer) = view.get_buffer() {
-                let text : Option<String> = match buffer.get_selection_bounds() {
-                    Some((from,to,)) => {
-                        from.get_text(&to).map(|txt| txt.to_string())
-                    },
-                    None => {
-                        buffer.get_text(
-                            &buffer.get_start_iter(),
-                            &buffer.get_end_iter(),
-                            true
-                        ).map(|txt| txt.to_string())
-                    }
-                };
-                if let Some(txt) = text {
-                    println!("Updating query: {}", txt);
-                    tbl_env.prepare_and_send_query(txt, true)?;
-                    view.set_sensitive(false);
-                    // file_list.set_sensitive(false);
-                    // nb.nb.set_sensitive(false);
-                } else {
-                    println!("No text available to send");
-                }
-            } else {
-                return Err(format!("Could not retrieve text buffer"));
-            }
-        /*} else {
-            return Err(format!("Could not retrieve reference to file status"));
-        }*/
+        offset : i32,
+        expanded : ExpandedScript
+    ) -> Result<bool, String> {
+        println!("Updating query: {}", expanded.sql());
+        if !tbl_env.send_expanded(expanded)? {
+            return Ok(false);
+        }
+        view.set_sensitive(false);
+        if let Ok(mut sent_offset) = sent_offset.try_borrow_mut() {
+            *sent_offset = offset;
+        }
         if let Ok(mut sent) = query_sent.try_borrow_mut() {
             *sent = true;
-            //println!("Query sent");
         } else {
             return Err(format!("Unable to acquire lock over query sent status"))
         }
-        Ok(())
-        //println!("at update: {}", query_sent.borrow());
+        Ok(true)
+    }
+
+    /// Asks for a typed confirmation before a query sequence is sent to a production connection,
+    /// if it has destructive statements. sql must be the sequence as the engine will receive it
+    /// (i.e. with its macros expanded and includes inlined). The table environment is not
+    /// borrowed while the confirmation dialog runs.
+    pub fn confirm_sequence(t_env : &Rc<RefCell<TableEnvironment>>, safety_dialog : &SafetyDialog, sql : &str) -> bool {
+        let conn_state = t_env.try_borrow().ok()
+            .map(|env| (env.safety(), env.dialect(), env.active_connection()) );
+        match conn_state {
+            Some((safety, dialect, conn)) => safety_dialog.confirm(&safety, dialect, conn, sql),
+            None => {
+                println!("Unable to borrow table environment");
+                false
+            }
+        }
     }
 
     /*pub fn add_extra_toolbar(&self, tool_btn : ToggleToolButton, bx : gtk::Box) {
@@ -372,6 +640,17 @@ impl SqlEditor {
                             view_c.borrow().set_sensitive(true);
                             file_list.set_sensitive(true);
                             view_c.borrow().grab_focus();
+                            let offset = sql_editor.sent_offset.try_borrow().map(|o| *o ).unwrap_or(0);
+                            match t_env.last_error() {
+                                Some(loc) => { file_list.error_marks.mark(&view_c.borrow(), offset, &loc); },
+                                None => file_list.error_marks.clear()
+                            }
+                            match t_env.last_outcomes() {
+                                Some((sql, outcomes)) => {
+                                    file_list.statement_marks.mark(&view_c.borrow(), offset, &sql, &outcomes);
+                                },
+                                None => file_list.statement_marks.clear()
+                            }
                             *sent = false;
                             // println!("Sent set to false");
                             *(n_tries.borrow_mut()) = 0; 
@@ -410,7 +689,7 @@ impl SqlEditor {
     // TODO Query the folders /usr/share/gtksourceview-4/styles or
     // /usr/local/share/gtksourceview-4/styles for styles or
     // /usr/share/gtksourceview-3.0/styles
-    fn configure_view(view : &View, refresh_btn : &Button, file_list : FileList) {
+    fn configure_view(view : &View, refresh_btn : &Button, run_scope : &Rc<RefCell<RunScope>>, file_list : FileList) {
         let buffer = view.get_buffer().unwrap()
             .downcast::<sourceview::Buffer>().unwrap();
         let manager = StyleSchemeManager::new();
@@ -428,7 +707,11 @@ impl SqlEditor {
         let lang_manager = LanguageManager::get_default().unwrap();
         let lang = lang_manager.get_language("sql").unwrap();
         buffer.set_language(Some(&lang));
-        Self::connect_source_key_press(&view, &refresh_btn);
+        Self::connect_source_key_press(&view, &refresh_btn, run_scope);
+        file_list.completion.attach(&view);
+        file_list.error_marks.configure(&view);
+        file_list.statement_marks.configure(&view);
+        file_list.snippet_stops.configure(&view);
         let buffer = view.get_buffer().unwrap();
         buffer.connect_changed(move |_buf| {
             file_list.mark_current_unsaved();
@@ -457,7 +740,8 @@ impl SqlEditor {
         tables_nb : TableNotebook,
         file_list : &FileList,
         workspace : PlotWorkspace,
-        table_bar : TableBar
+        table_bar : TableBar,
+        safety_dialog : SafetyDialog
     ) -> Self {
         let view : View =
             builder.get_object("query_source").unwrap();
@@ -500,7 +784,8 @@ impl SqlEditor {
         //sql_toolbar.insert(&update_btn, 1);
         sql_toolbar.show_all();
 
-        Self::configure_view(&view, &refresh_btn, file_list.clone());
+        let run_scope = Rc::new(RefCell::new(RunScope::Selection));
+        Self::configure_view(&view, &refresh_btn, &run_scope, file_list.clone());
 
         let update_clock = Rc::new(RefCell::new((false, 0, 0)));
         {
@@ -635,6 +920,8 @@ impl SqlEditor {
             // sql_toggle,
             // file_loaded,
             query_sent : Rc::new(RefCell::new(false)),
+            run_scope,
+            sent_offset : Rc::new(RefCell::new(0)),
             sql_stack,
             status_stack,
             t_env,
@@ -646,6 +933,10 @@ impl SqlEditor {
             query_file_label,
             table_toggle : header_toggle.table_toggle.clone(),
             file_list : file_list.clone(),
+            safety_dialog,
+            conn_combo : builder.get_object("conn_combo").unwrap(),
+            format_settings : FormatSettings::build(&builder),
+            expansion_preview : ExpansionPreview::build(&builder),
         };
 
         Self::connect_sql_load(
@@ -656,7 +947,6 @@ impl SqlEditor {
             &file_list,
             content_stack,
             header_toggle.query_toggle.clone(),
-            sql_editor.refresh_btn.clone(),
             sql_editor.clone(),
             workspace,
             table_bar,
@@ -703,7 +993,6 @@ impl SqlEditor {
         file_list : &FileList,
         content_stack : Stack,
         query_toggle : ToggleButton,
-        refresh_btn : Button,
         sql_editor : SqlEditor,
         workspace : PlotWorkspace,
         table_bar : TableBar,
@@ -724,7 +1013,6 @@ impl SqlEditor {
                                         &name[..],
                                         content_stack.clone(),
                                         query_toggle.clone(),
-                                        refresh_btn.clone(),
                                         sql_editor.clone()
                                     );
                                 } else {
@@ -793,33 +1081,141 @@ impl SqlEditor {
             // println!("Now sending sql query: {:?}", self.get_text());
             let view = self.view.clone();
             let query_sent = self.query_sent.clone();
+            let run_scope = self.run_scope.clone();
+            let sent_offset = self.sent_offset.clone();
             let table_env = self.t_env.clone();
             let update_clock = self.update_clock.clone();
+            let file_list = self.file_list.clone();
+            let safety_dialog = self.safety_dialog.clone();
+            let status_stack = self.status_stack.clone();
+            let conn_combo = self.conn_combo.clone();
             let f = f.clone();
-            self.refresh_btn.connect_clicked(move |_btn|{
-                match table_env.try_borrow_mut() {
-                    Ok(mut env) => {
-                        let update_res = Self::update_queries(
-                            //file_loaded.clone(),
-                            query_sent.clone(),
-                            &mut env,
-                            &view.borrow().clone(),
-                        );
-                        if let Err(e) = f(update_res) {
-                            println!("{}", e);
-                        }
-                    },
-                    _ => { println!("Error recovering mutable reference to table environment"); }
-                }
-                if let Ok(mut update) = update_clock.try_borrow_mut() {
-                    if update.1 > 0 {
-                        update.0 = true;
-                        update.2 = 0;
-                        //btn.set_sensitive(false);
+            self.refresh_btn.connect_clicked(move |btn|{
+                let scope = run_scope.try_borrow_mut()
+                    .map(|mut scope| mem::replace(&mut *scope, RunScope::Selection) )
+                    .unwrap_or(RunScope::Selection);
+
+                // Queries of a bound file are sent to its connection, so it becomes the current one
+                // (the combo also shows its tables) before the queries are expanded for its dialect.
+                let bound = file_list.current_conn();
+                if let Some(bound) = &bound {
+                    let active = table_env.try_borrow().ok().and_then(|env| env.active_connection() );
+                    if active.as_ref() != Some(bound) {
+                        conn_combo.set_active_id(Some(&bound[..]));
                     }
-                } else {
-                    println!("Unabe to recover mutable reference to update clock");
                 }
+
+                // Macros and includes are expanded at a background thread (since commands might take
+                // long) and before the confirmation, so it classifies the statements as the engine
+                // will receive them.
+                let sent_view = view.borrow().clone();
+                let expanding = match table_env.try_borrow() {
+                    Ok(env) => Self::query_range(&sent_view, scope, env.dialect()).map(|(offset, txt)| {
+                        let commands = Commands::Run(macros::COMMAND_TIMEOUT);
+                        (offset, env.expand_script(txt, file_list.current_dir(), commands))
+                    }),
+                    Err(_) => {
+                        println!("Error recovering reference to table environment");
+                        return;
+                    }
+                };
+                let (offset, receiver) = match expanding {
+                    Some(expanding) => expanding,
+                    None => {
+                        println!("No text available to send");
+                        return;
+                    }
+                };
+                btn.set_sensitive(false);
+                let btn = btn.clone();
+                let query_sent = query_sent.clone();
+                let sent_offset = sent_offset.clone();
+                let table_env = table_env.clone();
+                let update_clock = update_clock.clone();
+                let file_list = file_list.clone();
+                let safety_dialog = safety_dialog.clone();
+                let status_stack = status_stack.clone();
+                let f = f.clone();
+                glib::timeout_add_local(20, move || {
+                    let expanded = match receiver.try_recv() {
+                        Ok(expanded) => expanded,
+                        Err(TryRecvError::Empty) => return glib::Continue(true),
+                        Err(TryRecvError::Disconnected) => {
+                            btn.set_sensitive(true);
+                            return glib::Continue(false);
+                        }
+                    };
+                    btn.set_sensitive(true);
+                    let expanded = match expanded {
+                        Ok(expanded) => expanded,
+                        Err(e) => {
+                            if let Err(e) = f(Err(e)) {
+                                println!("{}", e);
+                            }
+                            return glib::Continue(false);
+                        }
+                    };
+                    // The connection is verified again when the queries are sent, since the current
+                    // connection might have changed while the macros were expanded (or the switch
+                    // was refused because another query was running).
+                    if let Some(bound) = &bound {
+                        let active = table_env.try_borrow().ok().and_then(|env| env.active_connection() );
+                        if active.as_ref() != Some(bound) {
+                            let e = format!("Unable to switch to the connection {} this file is bound to", bound);
+                            if let Err(e) = f(Err(e)) {
+                                println!("{}", e);
+                            }
+                            return glib::Continue(false);
+                        }
+                    }
+                    if !Self::confirm_sequence(&table_env, &safety_dialog, expanded.sql()) {
+                        return glib::Continue(false);
+                    }
+                    match table_env.try_borrow_mut() {
+                        Ok(mut env) => {
+                            let update_res = Self::update_queries(
+                                query_sent.clone(),
+                                sent_offset.clone(),
+                                &mut env,
+                                &sent_view,
+                                offset,
+                                expanded
+                            );
+                            match update_res {
+                                Ok(false) => {
+                                    status_stack.update(Status::StatementExecuted(
+                                        String::from("Variables updated (no statements to execute)")
+                                    ));
+                                    return glib::Continue(false);
+                                },
+                                Ok(true) => {
+                                    if let Some(conn) = env.active_connection() {
+                                        file_list.bind_current(&conn);
+                                    }
+                                    if let Err(e) = f(Ok(())) {
+                                        println!("{}", e);
+                                    }
+                                },
+                                Err(e) => {
+                                    if let Err(e) = f(Err(e)) {
+                                        println!("{}", e);
+                                    }
+                                }
+                            }
+                        },
+                        _ => { println!("Error recovering mutable reference to table environment"); }
+                    }
+                    if let Ok(mut update) = update_clock.try_borrow_mut() {
+                        if update.1 > 0 {
+                            update.0 = true;
+                            update.2 = 0;
+                            //btn.set_sensitive(false);
+                        }
+                    } else {
+                        println!("Unabe to recover mutable reference to update clock");
+                    }
+                    glib::Continue(false)
+                });
             });
         }
         // Self::connect_source_key_press(&*self.view.borrow(), &self.refresh_btn);
@@ -848,7 +1244,9 @@ impl SqlEditor {
         });
     }*/
 
-    fn connect_source_key_press /*<F>*/ (view : &View, refresh_btn : &Button/*f : F*/)
+    /// Ctrl+Return sends the selection (or the whole text), Ctrl+Shift+Return sends the
+    /// statement under the cursor and Ctrl+Alt+Return sends the statements from the cursor on.
+    fn connect_source_key_press /*<F>*/ (view : &View, refresh_btn : &Button, run_scope : &Rc<RefCell<RunScope>> /*f : F*/)
         //where
         //    F : Fn(Result<(), String>) -> Result<(), String> + 'static,
         //    F : Clone
@@ -858,11 +1256,21 @@ impl SqlEditor {
         // let query_sent = self.query_sent.clone();
         // let table_env = self.t_env.clone();
         let refresh_btn = refresh_btn.clone();
+        let run_scope = run_scope.clone();
         // TODO verify that view is realized before accepting key press
         view.connect_key_press_event(move |_view, ev_key| {
-            if ev_key.get_state() == gdk::ModifierType::CONTROL_MASK && ev_key.get_keyval() == keys::constants::Return {
+            let scope = match ev_key.get_state() {
+                s if s == gdk::ModifierType::CONTROL_MASK => Some(RunScope::Selection),
+                s if s == gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::SHIFT_MASK => Some(RunScope::Statement),
+                s if s == gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::MOD1_MASK => Some(RunScope::FromCursor),
+                _ => None
+            };
+            if let (Some(scope), true) = (scope, ev_key.get_keyval() == keys::constants::Return) {
                 println!("Return clicked");
                 if refresh_btn.is_sensitive() {
+                    if let Ok(mut run_scope) = run_scope.try_borrow_mut() {
+                        *run_scope = scope;
+                    }
                     refresh_btn.emit_clicked();
                     /*match table_env.try_borrow_mut() {
                         Ok(mut env) => {
@@ -0,0 +1,129 @@
+use gtk::*;
+use gtk::prelude::*;
+use sourceview::*;
+use sourceview::View;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::tables::environment::StatementOutcome;
+use crate::table_notebook::TableNotebook;
+
+const OK_CATEGORY : &'static str = "statement-ok";
+
+const ERROR_CATEGORY : &'static str = "statement-error";
+
+/// Gutter marks placed at the first line of each statement of the last query sequence,
+/// showing whether the statement succeeded, its duration and the number of rows it returned
+/// as a tooltip. Activating the mark of a statement which returned rows shows its table at
+/// the notebook. Marks follow the text as it is edited, and are replaced at the next execution.
+#[derive(Clone)]
+pub struct StatementMarks {
+    tables_nb : TableNotebook,
+    table_toggle : ToggleButton,
+
+    /// View holding the current marks, and the outcome of each statement. Each mark
+    /// is named after the index of the outcome of its statement.
+    marked : Rc<RefCell<Option<(View, Vec<StatementOutcome>)>>>
+}
+
+impl StatementMarks {
+
+    pub fn new(tables_nb : TableNotebook, table_toggle : ToggleButton) -> Self {
+        Self { tables_nb, table_toggle, marked : Rc::new(RefCell::new(None)) }
+    }
+
+    /// Sets the icon and tooltip of the statement marks at the view gutter, and starts
+    /// showing the result table of a statement when its mark is activated.
+    pub fn configure(&self, view : &View) {
+        for (category, icon) in [(OK_CATEGORY, "emblem-ok-symbolic"), (ERROR_CATEGORY, "dialog-error-symbolic")].iter() {
+            let attrs = MarkAttributes::new();
+            attrs.set_icon_name(icon);
+            let marks = self.clone();
+            attrs.connect_query_tooltip_text(move |_, mark| {
+                marks.outcome(mark).map(|o| tooltip_text(&o) ).unwrap_or(String::new())
+            });
+            view.set_mark_attributes(category, &attrs, 1);
+        }
+        let marks = self.clone();
+        view.connect_line_mark_activated(move |view, iter, _ev| {
+            let buffer = match view.get_buffer().and_then(|b| b.downcast::<sourceview::Buffer>().ok() ) {
+                Some(buffer) => buffer,
+                None => return
+            };
+            let table = buffer.get_source_marks_at_line(iter.get_line(), None)
+                .iter()
+                .filter_map(|mark| marks.outcome(mark) )
+                .find_map(|outcome| outcome.table );
+            if let Some(ix) = table {
+                if ix < marks.tables_nb.len() {
+                    marks.tables_nb.set_page_index(ix);
+                    marks.table_toggle.set_active(true);
+                }
+            }
+        });
+    }
+
+    /// Outcome of the statement at which the mark was placed.
+    fn outcome(&self, mark : &Mark) -> Option<StatementOutcome> {
+        let name = mark.get_name()?;
+        let ix = name.as_str().trim_start_matches("statement-").parse::<usize>().ok()?;
+        match &*self.marked.try_borrow().ok()? {
+            Some((_, outcomes)) => outcomes.get(ix).cloned(),
+            None => None
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut marked) = self.marked.try_borrow_mut() {
+            if let Some((view, _)) = marked.take() {
+                if let Some(buffer) = view.get_buffer().and_then(|b| b.downcast::<sourceview::Buffer>().ok() ) {
+                    for category in [OK_CATEGORY, ERROR_CATEGORY].iter() {
+                        buffer.remove_source_marks(&buffer.get_start_iter(), &buffer.get_end_iter(), Some(*category));
+                    }
+                }
+            }
+        } else {
+            println!("Unable to borrow statement marks");
+        }
+    }
+
+    /// Marks the statements of the query sequence sql, if the view holds it starting at the
+    /// character offset base. Returns whether the statements were marked.
+    pub fn mark(&self, view : &View, base : i32, sql : &str, outcomes : &[StatementOutcome]) -> bool {
+        self.clear();
+        let buffer = match view.get_buffer().and_then(|b| b.downcast::<sourceview::Buffer>().ok() ) {
+            Some(buffer) => buffer,
+            None => return false
+        };
+        let end = base + sql.chars().count() as i32;
+        let text = buffer.get_iter_at_offset(base).get_text(&buffer.get_iter_at_offset(end));
+        if text.map(|t| t.as_str() != sql ).unwrap_or(true) {
+            return false;
+        }
+        for (ix, outcome) in outcomes.iter().enumerate() {
+            let offset = base + sql[..outcome.span.0].chars().count() as i32;
+            let category = if outcome.failed { ERROR_CATEGORY } else { OK_CATEGORY };
+            let name = format!("statement-{}", ix);
+            buffer.create_source_mark(Some(&name[..]), category, &buffer.get_iter_at_offset(offset));
+        }
+        if let Ok(mut marked) = self.marked.try_borrow_mut() {
+            *marked = Some((view.clone(), outcomes.to_vec()));
+        }
+        true
+    }
+
+}
+
+fn tooltip_text(outcome : &StatementOutcome) -> String {
+    let mut text = match (outcome.failed, outcome.nrows) {
+        (true, _) => format!("Failed: {}", outcome.message),
+        (false, Some(n)) => format!("{} row(s)", n),
+        (false, None) => outcome.message.clone()
+    };
+    if let Some(duration) = outcome.duration {
+        text += &format!("\nDuration: {} ms", duration);
+    }
+    if outcome.table.is_some() {
+        text += "\nActivate the mark to show the result table";
+    }
+    text
+}
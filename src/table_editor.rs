@@ -0,0 +1,230 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::table_widget::TableWidget;
+use crate::tables::environment::TableEnvironment;
+use crate::tables::edit::{self, EditTarget};
+use crate::status_stack::{StatusStack, Status};
+use crate::safety_dialog::SafetyDialog;
+
+/// Edits the rows of query results that read from a single table. Edits are made
+/// at the result grid (through its context menu), reviewed as the statements that
+/// apply them, and applied in a single transaction.
+#[derive(Clone)]
+pub struct TableEditor {
+    t_env : Rc<RefCell<TableEnvironment>>,
+    status_stack : StatusStack,
+    safety_dialog : SafetyDialog,
+    dialog : Dialog,
+    label : Label,
+    view : TextView,
+    apply_btn : Button,
+
+    /// Called after edits are applied, so the results are fetched again.
+    on_applied : Rc<RefCell<Option<std::boxed::Box<dyn Fn()>>>>
+}
+
+impl TableEditor {
+
+    pub fn build(
+        builder : &Builder,
+        t_env : Rc<RefCell<TableEnvironment>>,
+        status_stack : StatusStack,
+        safety_dialog : SafetyDialog
+    ) -> Self {
+        let dialog : Dialog = builder.get_object("edit_review_dialog").unwrap();
+        let label : Label = builder.get_object("edit_review_label").unwrap();
+        let view : TextView = builder.get_object("edit_review_view").unwrap();
+        let apply_btn : Button = builder.get_object("edit_review_apply_btn").unwrap();
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            glib::signal::Inhibit(true)
+        });
+        Self {
+            t_env,
+            status_stack,
+            safety_dialog,
+            dialog,
+            label,
+            view,
+            apply_btn,
+            on_applied : Rc::new(RefCell::new(None))
+        }
+    }
+
+    pub fn connect_applied<F>(&self, f : F)
+    where
+        F : Fn() + 'static
+    {
+        if let Ok(mut on_applied) = self.on_applied.try_borrow_mut() {
+            *on_applied = Some(std::boxed::Box::new(f));
+        } else {
+            println!("Unable to borrow applied callback");
+        }
+    }
+
+    /// Adds the editing menu to the grid showing the table at the informed index of the environment.
+    pub fn attach(&self, table_w : &TableWidget, tbl_ix : usize) {
+        let target : Rc<RefCell<Option<EditTarget>>> = Rc::new(RefCell::new(None));
+        let menu = Menu::new();
+        let edit_item = CheckMenuItem::with_label("Edit rows");
+        let insert_item = MenuItem::with_label("Insert row");
+        let delete_item = MenuItem::with_label("Delete selected rows");
+        let review_item = MenuItem::with_label("Review changes…");
+        let discard_item = MenuItem::with_label("Discard changes");
+        menu.append(&edit_item);
+        menu.append(&SeparatorMenuItem::new());
+        menu.append(&insert_item);
+        menu.append(&delete_item);
+        menu.append(&SeparatorMenuItem::new());
+        menu.append(&review_item);
+        menu.append(&discard_item);
+        menu.show_all();
+        {
+            let table_w = table_w.clone();
+            let items = [insert_item.clone(), delete_item.clone(), review_item.clone(), discard_item.clone()];
+            let edit_item = edit_item.clone();
+            menu.connect_show(move |_| {
+                let editing = table_w.is_editing();
+                edit_item.set_active(editing);
+                for item in items.iter() {
+                    item.set_sensitive(editing);
+                }
+            });
+        }
+        {
+            let table_w = table_w.clone();
+            let t_env = self.t_env.clone();
+            let editor = self.clone();
+            let target = target.clone();
+            edit_item.connect_activate(move |item| {
+                if !item.get_active() {
+                    table_w.stop_editing();
+                    return;
+                }
+                if table_w.is_editing() {
+                    return;
+                }
+                let new_target = match t_env.try_borrow() {
+                    Ok(env) => env.edit_target(tbl_ix),
+                    Err(_) => Err(String::from("Unable to borrow table environment"))
+                };
+                match new_target {
+                    Ok(new_target) => {
+                        table_w.start_editing(&new_target);
+                        if let Ok(mut target) = target.try_borrow_mut() {
+                            *target = Some(new_target);
+                        }
+                    },
+                    Err(e) => {
+                        item.set_active(false);
+                        editor.show_error(&format!("Results cannot be edited: {}", e));
+                    }
+                }
+            });
+        }
+        {
+            let table_w = table_w.clone();
+            insert_item.connect_activate(move |_| {
+                table_w.insert_row();
+            });
+        }
+        {
+            let table_w = table_w.clone();
+            delete_item.connect_activate(move |_| {
+                table_w.delete_selected_rows();
+            });
+        }
+        {
+            let table_w = table_w.clone();
+            discard_item.connect_activate(move |_| {
+                table_w.discard_edits();
+            });
+        }
+        {
+            let table_w = table_w.clone();
+            let editor = self.clone();
+            review_item.connect_activate(move |_| {
+                let target = match target.try_borrow() {
+                    Ok(target) => target.clone(),
+                    Err(_) => None
+                };
+                if let Some(target) = target {
+                    editor.review(&table_w, tbl_ix, &target);
+                }
+            });
+        }
+        table_w.set_context_menu(&menu);
+    }
+
+    /// Shows a message at the review dialog, which cannot be applied. The message is not
+    /// shown at the status stack, so the results remain visible.
+    fn show_error(&self, msg : &str) {
+        self.label.set_text(msg);
+        if let Some(buffer) = self.view.get_buffer() {
+            buffer.set_text("");
+        }
+        self.apply_btn.set_sensitive(false);
+        self.dialog.run();
+        self.dialog.hide();
+        self.apply_btn.set_sensitive(true);
+    }
+
+    /// Shows the statements applying the pending edits of the grid, applying them if the user confirms.
+    fn review(&self, table_w : &TableWidget, tbl_ix : usize, target : &EditTarget) {
+        let edits = match table_w.edits() {
+            Some(edits) if !edits.is_empty() => edits,
+            _ => {
+                self.show_error("No changes to apply");
+                return;
+            }
+        };
+        let env_state = self.t_env.try_borrow().ok().map(|env| {
+            let stmts = env.all_tables().get(tbl_ix)
+                .ok_or(String::from("Invalid table index"))
+                .and_then(|tbl| edits.statements(target, tbl) );
+            (stmts, env.safety(), env.active_connection())
+        });
+        let (stmts, safety, conn) = match env_state {
+            Some((Ok(stmts), safety, conn)) => (stmts, safety, conn),
+            Some((Err(e), _, _)) => {
+                self.show_error(&e);
+                return;
+            },
+            None => {
+                println!("Unable to borrow table environment");
+                return;
+            }
+        };
+        let preview = edit::preview(&stmts[..], target.dialect);
+        self.label.set_text(&format!("{} at {}. Applied in a single transaction.", edits.summary(), target.table));
+        if let Some(buffer) = self.view.get_buffer() {
+            buffer.set_text(&preview);
+        }
+        let resp = self.dialog.run();
+        self.dialog.hide();
+        if resp != ResponseType::Other(1) || !self.safety_dialog.confirm(&safety, target.dialect, conn, &preview) {
+            return;
+        }
+        let applied = match self.t_env.try_borrow_mut() {
+            Ok(mut env) => env.apply_edits(&stmts[..]),
+            Err(_) => Err(String::from("Unable to borrow table environment"))
+        };
+        match applied {
+            Ok(n) => {
+                table_w.stop_editing();
+                self.status_stack.update(Status::StatementExecuted(format!("{} row(s) affected at {}", n, target.table)));
+                if let Ok(on_applied) = self.on_applied.try_borrow() {
+                    if let Some(f) = on_applied.as_ref() {
+                        f();
+                    }
+                }
+            },
+            Err(e) => {
+                self.show_error(&format!("Changes not applied: {}", e));
+            }
+        }
+    }
+
+}
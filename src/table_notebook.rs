@@ -2,9 +2,11 @@ use gtk::*;
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::table_widget::*;
+use crate::tables::table::Table;
 use gtk::prelude::*;
 use crate::plots::plot_workspace::PlotWorkspace;
 use std::collections::HashMap;
+use std::mem;
 use gdk_pixbuf::Pixbuf;
 use super::table_popover::*;
 use super::tables::environment::TableEnvironment;
@@ -13,12 +15,16 @@ use super::command::*;
 use crate::table_popover::*;
 use crate::utils;
 use crate::plots::layout_toolbar::LayoutToolbar;
+use crate::table_editor::TableEditor;
 
 #[derive(Debug, Clone)]
 pub enum TableSource {
     Command(String),
     File(String),
-    Database(Option<String>, Option<String>)
+    Database(Option<String>, Option<String>),
+
+    /// Table calculated from the other tables (such as a pivot table), by its name.
+    Derived(String)
 }
 
 const ICONS : [&'static str; 5] = [
@@ -34,7 +40,8 @@ const ICONS : [&'static str; 5] = [
 pub struct TableBar {
     copy_from_toggle : ToggleButton,
     copy_to_toggle : ToggleButton,
-    draw_toggle : ToggleButton
+    draw_toggle : ToggleButton,
+    detail_toggle : ToggleButton
 }
 
 impl TableBar {
@@ -43,7 +50,16 @@ impl TableBar {
         let copy_from_toggle : ToggleButton = builder.get_object("copy_from_toggle").unwrap();
         let copy_to_toggle : ToggleButton = builder.get_object("copy_to_toggle").unwrap();
         let draw_toggle : ToggleButton = builder.get_object("draw_toggle").unwrap();
-        Self { copy_from_toggle, copy_to_toggle, draw_toggle }
+        let detail_toggle : ToggleButton = builder.get_object("detail_toggle").unwrap();
+        Self { copy_from_toggle, copy_to_toggle, draw_toggle, detail_toggle }
+    }
+
+    /// Shows the value of the selected cell of all tables while the detail toggle is active.
+    pub fn connect_detail(&self, tbl_nb : &TableNotebook) {
+        let tbl_nb = tbl_nb.clone();
+        self.detail_toggle.connect_toggled(move |btn| {
+            tbl_nb.set_detail_visible(btn.get_active());
+        });
     }
     
     pub fn set_copy_to(&self) {
@@ -95,12 +111,30 @@ impl TableBar {
     
 }
 
+/// Pages of the tables returned at a connection which is not the current one, kept
+/// aside so they are shown again (as they were left) when it becomes current.
+struct NotebookSession {
+    pages : Vec<(Widget, Option<Widget>)>,
+    tbls : Vec<TableWidget>,
+    sources : Vec<TableSource>,
+    page : Option<u32>
+}
+
 #[derive(Clone)]
 pub struct TableNotebook {
     pub nb : Notebook,
     pub tbls : Rc<RefCell<Vec<TableWidget>>>,
     icons : HashMap<&'static str, Pixbuf>,
-    sources : Rc<RefCell<Vec<TableSource>>>
+    sources : Rc<RefCell<Vec<TableSource>>>,
+
+    /// Edits the results of database queries, once set.
+    editor : Rc<RefCell<Option<TableEditor>>>,
+
+    /// Whether the tables show the value of their selected cell.
+    detail_visible : Rc<RefCell<bool>>,
+
+    /// Pages of the open connections other than the current one, by connection name.
+    sessions : Rc<RefCell<HashMap<String, NotebookSession>>>
 }
 
 impl TableNotebook {
@@ -118,7 +152,15 @@ impl TableNotebook {
     
         let sources = Rc::new(RefCell::new(Vec::new()));
         // let bar = TableBar::build(&builder);
-        let tbl_nb = TableNotebook{nb, tbls, icons, sources };
+        let tbl_nb = TableNotebook{
+            nb,
+            tbls,
+            icons,
+            sources,
+            editor : Rc::new(RefCell::new(None)),
+            detail_visible : Rc::new(RefCell::new(false)),
+            sessions : Rc::new(RefCell::new(HashMap::new()))
+        };
         {
             let tbl_nb = tbl_nb.clone();
             tbl_nb.nb.clone().connect_change_current_page(move |_, _| {
@@ -130,6 +172,25 @@ impl TableNotebook {
         tbl_nb
     }
 
+    pub fn set_editor(&self, editor : TableEditor) {
+        if let Ok(mut ed) = self.editor.try_borrow_mut() {
+            *ed = Some(editor);
+        } else {
+            println!("Unable to borrow table editor");
+        }
+    }
+
+    pub fn set_detail_visible(&self, visible : bool) {
+        *self.detail_visible.borrow_mut() = visible;
+        if let Ok(tbls) = self.tbls.try_borrow() {
+            for tbl in tbls.iter() {
+                tbl.set_detail_visible(visible);
+            }
+        } else {
+            println!("Unable to borrow table vector");
+        }
+    }
+
     pub fn clear(&self) {
         for w in self.nb.get_children() {
             self.nb.remove(&w);
@@ -142,6 +203,63 @@ impl TableNotebook {
         self.sources.borrow_mut().clear();
     }
 
+    /// Sets aside the pages shown (under the name of the previous connection, or discards them if
+    /// it was closed) and shows the pages kept for the current connection. Returns false if no pages
+    /// were kept for it, in which case the notebook is left empty.
+    pub fn switch_session(&self, previous : Option<&str>, current : &str) -> bool {
+        let (mut tbls, mut sources, mut sessions) = match (
+            self.tbls.try_borrow_mut(),
+            self.sources.try_borrow_mut(),
+            self.sessions.try_borrow_mut()
+        ) {
+            (Ok(tbls), Ok(sources), Ok(sessions)) => (tbls, sources, sessions),
+            _ => {
+                println!("Unable to borrow notebook sessions");
+                return false;
+            }
+        };
+        let page = self.nb.get_current_page();
+        let pages : Vec<(Widget, Option<Widget>)> = self.nb.get_children().into_iter()
+            .map(|w| { let lbl = self.nb.get_tab_label(&w); (w, lbl) })
+            .collect();
+        for (w, _) in pages.iter() {
+            self.nb.remove(w);
+        }
+        let left = NotebookSession {
+            pages,
+            tbls : mem::take(&mut *tbls),
+            sources : mem::take(&mut *sources),
+            page
+        };
+        if let Some(previous) = previous {
+            sessions.insert(previous.to_string(), left);
+        }
+        match sessions.remove(current) {
+            Some(session) => {
+                for (w, lbl) in session.pages.iter() {
+                    self.nb.append_page(w, lbl.as_ref());
+                }
+                for tbl in session.tbls.iter() {
+                    tbl.set_detail_visible(*self.detail_visible.borrow());
+                }
+                *tbls = session.tbls;
+                *sources = session.sources;
+                self.nb.set_current_page(session.page);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Discards the pages kept for all connections (called when the last connection is closed).
+    pub fn clear_sessions(&self) {
+        if let Ok(mut sessions) = self.sessions.try_borrow_mut() {
+            sessions.clear();
+        } else {
+            println!("Unable to borrow notebook sessions");
+        }
+    }
+
     pub fn set_page_index(&self, page : usize) {
         self.nb.set_property_page(page as i32);
     }
@@ -154,11 +272,11 @@ impl TableNotebook {
 
     pub fn create_error_table(&self, msg : &str) {
         let mut table_w = TableWidget::new();
-        self.nb.add(&(table_w.scroll_window));
+        self.nb.add(&(table_w.page));
         let img = Image::from_icon_name(Some("close-symbolic"), IconSize::Menu);
         let (label_bx, table_w) = self.create_table(&img, &Label::new(Some("Error")));
         table_w.show_message(msg);
-        self.nb.set_tab_label(&(table_w.scroll_window), Some(&label_bx));
+        self.nb.set_tab_label(&(table_w.page), Some(&label_bx));
     }
     
     fn create_table(&self, img : &Image, lbl : &Label) -> (gtk::Box, TableWidget) {
@@ -168,8 +286,9 @@ impl TableNotebook {
         let box_label = Box::new(Orientation::Horizontal, 0);
         box_label.pack_start(img, false, false, 0);
         box_label.pack_start(lbl, false, false, 0);
-        self.nb.add(&(table_w.scroll_window));
+        self.nb.add(&(table_w.page));
         self.nb.next_page();
+        table_w.set_detail_visible(*self.detail_visible.borrow());
         if let Ok(mut tbls) = self.tbls.try_borrow_mut() {
             tbls.push(table_w.clone());
         } else {
@@ -181,25 +300,26 @@ impl TableNotebook {
     pub fn create_data_table(
         &self,
         table_source : TableSource,
-        rows : Vec<Vec<String>>,
+        table : &Table,
         workspace : PlotWorkspace,
         table_bar : TableBar
     ) {
-        if rows.len() == 0 {
-            println!("No rows to display");
+        if table.shape().1 == 0 {
+            println!("No columns to display");
             return;
         }
         let (icon, mut name) = match table_source.clone() {
             TableSource::Command(cmd) => (format!("bash-symbolic"), format!("Std. out ({})", cmd.clone())),
             TableSource::File(path) => (format!("folder-documents-symbolic"), path.clone()),
+            TableSource::Derived(name) => (format!("view-grid-symbolic"), name.clone()),
             TableSource::Database(name, rel) => match (name, rel) {
                 (Some(name), Some(rel)) => (format!("{}.svg", rel), name.clone()),
                 (Some(name), None) => (format!("grid-black.svg"), name.clone()),
                 _ => (format!("grid-black.svg"), format!("Unknown"))
             }
         };
-        let (nrows, ncols) = (rows.len(), rows[0].len());
-        name += &format!(" ({} x {})", nrows - 1, ncols);
+        let (nrows, ncols) = table.shape();
+        name += &format!(" ({} x {})", nrows, ncols);
         let img = match self.icons.get(&icon[..]) {
             Some(pxb) => Image::from_pixbuf(Some(&self.icons[&icon[..]])),
             None => Image::from_icon_name(Some(&icon), IconSize::Menu)
@@ -230,11 +350,21 @@ impl TableNotebook {
         }
         box_label.show_all();
         self.nb.show_all();
-        self.nb.set_tab_label(&(table_w.scroll_window), Some(&ev_bx));
+        self.nb.set_tab_label(&(table_w.page), Some(&ev_bx));
         
-        table_w.update_data(rows);
+        table_w.update_table(table);
         table_w.show_data();
 
+        // The widget was pushed by create_table, so it sits at the last position, which is
+        // also the position of its table at the environment.
+        if let TableSource::Database(_, _) = table_source {
+            if let Ok(editor) = self.editor.try_borrow() {
+                if let Some(editor) = editor.as_ref() {
+                    editor.attach(&table_w, self.len() - 1);
+                }
+            }
+        }
+
         // Left-click events
         {
             let mapping_popover = workspace.layout_toolbar.mapping_popover.clone();
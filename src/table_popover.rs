@@ -16,14 +16,18 @@ use std::fs::File;
 use std::io::Write;
 use std::str::FromStr;
 use crate::tables::table::{Format, TableSettings, NullField, BoolField, Align};
+use crate::tables::csv_writer::{LineTerminator, Encoding};
 use std::default::Default;
 use crate::utils;
-use crate::status_stack::StatusStack;
+use crate::status_stack::{StatusStack, Status};
+use crate::safety_dialog::SafetyDialog;
 use std::io::BufWriter;
 use std::io::Read;
 use crate::command::{self, *};
+use crate::tables::diff::DiffKey;
+use crate::tables::upload::UploadMode;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct CsvWindow {
     tbl_bool_combo : ComboBoxText,
     tbl_null_combo : ComboBoxText,
@@ -31,6 +35,11 @@ pub struct CsvWindow {
     align_left_radio : RadioButton,
     align_center_radio : RadioButton,
     align_right_radio : RadioButton,
+    csv_delim_combo : ComboBoxText,
+    csv_terminator_combo : ComboBoxText,
+    csv_encoding_combo : ComboBoxText,
+    csv_header_check : CheckButton,
+    html_css_check : CheckButton,
     settings : Rc<RefCell<TableSettings>>
 }
 
@@ -42,13 +51,14 @@ impl CsvWindow {
         {
             let settings = settings.clone();
             tbl_bool_combo.connect_changed(move |combo| {
-                if let Some(txt) = combo.get_active_text().map(|s| s.to_string() ) {
-                    if let Ok(bool_field) = BoolField::from_str(&txt[..]) {
-                        settings.borrow_mut().bool_field = bool_field;
-                    } else {
-                        println!("Unable to parse format");
-                    }
-                }
+                let bool_field = match combo.get_active() {
+                    Some(1) => BoolField::WordUpper,
+                    Some(2) => BoolField::Char,
+                    Some(3) => BoolField::CharUpper,
+                    Some(4) => BoolField::Integer,
+                    _ => BoolField::Word
+                };
+                settings.borrow_mut().bool_field = bool_field;
             });
         }
 
@@ -56,13 +66,12 @@ impl CsvWindow {
         {
             let settings = settings.clone();
             tbl_null_combo.connect_changed(move |combo| {
-                if let Some(txt) = combo.get_active_text().map(|s| s.to_string() ) {
-                    if let Ok(null) = NullField::from_str(&txt[..]) {
-                        settings.borrow_mut().null_field = null;
-                    } else {
-                        println!("Unable to parse format");
-                    }
-                }
+                let null = match combo.get_active() {
+                    Some(1) => NullField::WordUpper,
+                    Some(2) => NullField::Word,
+                    _ => NullField::Omit
+                };
+                settings.borrow_mut().null_field = null;
             });
         }
 
@@ -99,6 +108,57 @@ impl CsvWindow {
                 settings.borrow_mut().align = Align::Right;
             });
         }
+
+        let csv_delim_combo : ComboBoxText = builder.get_object("csv_delim_combo").unwrap();
+        {
+            let settings = settings.clone();
+            csv_delim_combo.connect_changed(move |combo| {
+                let delimiter = match combo.get_active_id().as_ref().map(|id| id.as_str() ) {
+                    Some("semicolon") => ';',
+                    Some("tab") => '\t',
+                    Some("pipe") => '|',
+                    _ => ','
+                };
+                settings.borrow_mut().csv.delimiter = delimiter;
+            });
+        }
+        let csv_terminator_combo : ComboBoxText = builder.get_object("csv_terminator_combo").unwrap();
+        {
+            let settings = settings.clone();
+            csv_terminator_combo.connect_changed(move |combo| {
+                let terminator = match combo.get_active_id().as_ref().map(|id| id.as_str() ) {
+                    Some("lf") => LineTerminator::Lf,
+                    _ => LineTerminator::CrLf
+                };
+                settings.borrow_mut().csv.terminator = terminator;
+            });
+        }
+        let csv_encoding_combo : ComboBoxText = builder.get_object("csv_encoding_combo").unwrap();
+        {
+            let settings = settings.clone();
+            csv_encoding_combo.connect_changed(move |combo| {
+                let encoding = match combo.get_active_id().as_ref().map(|id| id.as_str() ) {
+                    Some("utf8_bom") => Encoding::Utf8Bom,
+                    Some("latin1") => Encoding::Latin1,
+                    _ => Encoding::Utf8
+                };
+                settings.borrow_mut().csv.encoding = encoding;
+            });
+        }
+        let csv_header_check : CheckButton = builder.get_object("csv_header_check").unwrap();
+        {
+            let settings = settings.clone();
+            csv_header_check.connect_toggled(move |check| {
+                settings.borrow_mut().csv.header = check.get_active();
+            });
+        }
+        let html_css_check : CheckButton = builder.get_object("html_css_check").unwrap();
+        {
+            let settings = settings.clone();
+            html_css_check.connect_toggled(move |check| {
+                settings.borrow_mut().inline_css = check.get_active();
+            });
+        }
         Self {
             tbl_bool_combo,
             tbl_null_combo,
@@ -106,9 +166,62 @@ impl CsvWindow {
             align_left_radio,
             align_center_radio,
             align_right_radio,
+            csv_delim_combo,
+            csv_terminator_combo,
+            csv_encoding_combo,
+            csv_header_check,
+            html_css_check,
             settings
         }
     }
+
+    pub fn settings(&self) -> TableSettings {
+        self.settings.borrow().clone()
+    }
+
+    /// Shows settings saved with the workspace. The widgets are set first, since their
+    /// signals also write to the settings.
+    pub fn restore(&self, settings : TableSettings) {
+        let bool_ix = match settings.bool_field {
+            BoolField::Word => 0,
+            BoolField::WordUpper => 1,
+            BoolField::Char => 2,
+            BoolField::CharUpper => 3,
+            BoolField::Integer => 4
+        };
+        self.tbl_bool_combo.set_active(Some(bool_ix));
+        let null_ix = match settings.null_field {
+            NullField::Omit => 0,
+            NullField::WordUpper => 1,
+            NullField::Word => 2
+        };
+        self.tbl_null_combo.set_active(Some(null_ix));
+        self.tbl_prec_spin.set_value(settings.prec as f64);
+        match settings.align {
+            Align::Left => self.align_left_radio.set_active(true),
+            Align::Center => self.align_center_radio.set_active(true),
+            Align::Right => self.align_right_radio.set_active(true)
+        }
+        let delim_id = match settings.csv.delimiter {
+            ';' => "semicolon",
+            '\t' => "tab",
+            '|' => "pipe",
+            _ => "comma"
+        };
+        self.csv_delim_combo.set_active_id(Some(delim_id));
+        self.csv_terminator_combo.set_active_id(Some(match settings.csv.terminator {
+            LineTerminator::CrLf => "crlf",
+            LineTerminator::Lf => "lf"
+        }));
+        self.csv_encoding_combo.set_active_id(Some(match settings.csv.encoding {
+            Encoding::Utf8 => "utf8",
+            Encoding::Utf8Bom => "utf8_bom",
+            Encoding::Latin1 => "latin1"
+        }));
+        self.csv_header_check.set_active(settings.csv.header);
+        self.html_css_check.set_active(settings.inline_css);
+        *self.settings.borrow_mut() = settings;
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -128,7 +241,13 @@ pub struct CopyToBox {
     clipboard_toggle : ToggleButton,
     program_toggle : ToggleButton,
     file_toggle : ToggleButton,
-    dst : Rc<RefCell<Destination>>
+    dst : Rc<RefCell<Destination>>,
+    export_diff_btn : Button,
+    diff_key_entry : Entry,
+
+    // Whether the save dialog should write the diff of the current table
+    // against its previous version instead of the table itself.
+    diff_mode : Rc<RefCell<bool>>
 }
 
 impl CopyToBox {
@@ -225,10 +344,14 @@ impl CopyToBox {
 
         let save_tbl_dialog : FileChooserDialog =
             builder.get_object("save_tbl_dialog").unwrap();
+        let export_diff_btn : Button = builder.get_object("export_diff_btn").unwrap();
+        let diff_key_entry : Entry = builder.get_object("diff_key_entry").unwrap();
+        let diff_mode = Rc::new(RefCell::new(false));
         {
             let settings = csv_window.settings.clone();
             let tables_nb = tables_nb.clone();
             let tbl_env = tbl_env.clone();
+            let diff_mode = diff_mode.clone();
             save_tbl_dialog.clone().connect_response(move |dialog, resp| {
                 let settings = settings.borrow().clone();
                 println!("Current table settings: {:?}", settings);
@@ -247,8 +370,14 @@ impl CopyToBox {
                                         _ => {
                                             if let Ok(mut f) = File::create(path) {
                                                 let idx = tables_nb.get_page_index();
-                                                if let Some(content) = t_env.get_text_at_index(idx, Some(settings)) {
-                                                    let _ = f.write_all(&content.into_bytes());
+                                                let encoding = settings.csv.encoding;
+                                                let opt_content = if *diff_mode.borrow() {
+                                                    t_env.get_diff_text_at_index(idx, Some(settings))
+                                                } else {
+                                                    t_env.get_text_at_index(idx, Some(settings))
+                                                };
+                                                if let Some(content) = opt_content {
+                                                    let _ = f.write_all(&encoding.encode(&content));
                                                 } else {
                                                     println!("Unable to get text at informed index");
                                                 }
@@ -318,6 +447,69 @@ impl CopyToBox {
             });
         }
         
+        {
+            let tables_nb = tables_nb.clone();
+            let tbl_env = tbl_env.clone();
+            diff_key_entry.connect_changed(move |entry| {
+                let txt = entry.get_text().as_str().trim().to_string();
+                let key = if txt.is_empty() {
+                    DiffKey::Row
+                } else {
+                    DiffKey::Column(txt)
+                };
+                let idx = tables_nb.get_page_index();
+                if let Ok(mut t_env) = tbl_env.try_borrow_mut() {
+                    if let Err(e) = t_env.set_diff_key(idx, key) {
+                        println!("{}", e);
+                    }
+                } else {
+                    println!("Unable to borrow table environment");
+                }
+            });
+        }
+
+        {
+            let dst = dst.clone();
+            let save_tbl_dialog = save_tbl_dialog.clone();
+            let settings = csv_window.settings.clone();
+            let tables_nb = tables_nb.clone();
+            let tbl_env = tbl_env.clone();
+            let diff_mode = diff_mode.clone();
+            export_diff_btn.connect_clicked(move |_btn| {
+                let idx = tables_nb.get_page_index();
+                match *dst.borrow() {
+                    Destination::File => {
+                        *diff_mode.borrow_mut() = true;
+                        save_tbl_dialog.run();
+                        save_tbl_dialog.hide();
+                        *diff_mode.borrow_mut() = false;
+                    },
+                    Destination::Clipboard => {
+                        let settings = settings.borrow().clone();
+                        if let Ok(t_env) = tbl_env.try_borrow() {
+                            if let Some(content) = t_env.get_diff_text_at_index(idx, Some(settings)) {
+                                let opt_clip = gdk::Display::get_default()
+                                    .and_then(|d| Clipboard::get_default(&d) );
+                                if let Some(clip) = opt_clip {
+                                    clip.set_text(&content);
+                                    clip.store();
+                                } else {
+                                    println!("Unable to get default gdk display and/or clipboard");
+                                }
+                            } else {
+                                println!("No diff available for the current table");
+                            }
+                        } else {
+                            println!("Unable to borrow table environment");
+                        }
+                    },
+                    Destination::Program => {
+                        println!("Diff can only be exported to a file or to the clipboard");
+                    }
+                }
+            });
+        }
+
         Self {
             // popover,
             save_tbl_btn,
@@ -327,7 +519,10 @@ impl CopyToBox {
             program_toggle,
             file_toggle,
             dst,
-            script_btn
+            script_btn,
+            export_diff_btn,
+            diff_key_entry,
+            diff_mode
         }
     }
 
@@ -338,7 +533,7 @@ struct CopyAction {
     dst : String,
     cols : Vec<String>,
     convert : bool,
-    create : bool
+    mode : UploadMode
 }
 
 #[derive(Clone)]
@@ -347,7 +542,7 @@ pub struct CopyFromBox {
     db_table_entry : Entry,
     // col_subset_entry : Entry,
     // col_subset_check : CheckButton,
-    create_check : CheckButton,
+    mode_combo : ComboBoxText,
     convert_check : CheckButton,
     copy_from_btn : Button,
     script_from_btn : Button,
@@ -361,21 +556,23 @@ impl CopyFromBox {
         tables_nb : &TableNotebook,
         tbl_env : &Rc<RefCell<TableEnvironment>>,
         csv_window : &CsvWindow,
-        cmd_window : &CommandWindow
+        cmd_window : &CommandWindow,
+        status_stack : &StatusStack,
+        safety_dialog : &SafetyDialog
     ) -> Self {
         // let popover : Popover = builder.get_object("copy_from_popover").unwrap();
         let action = Rc::new(RefCell::new(CopyAction{
             dst : String::new(),
             cols : Vec::new(),
             convert : false,
-            create : false
+            mode : UploadMode::Create
         }));
 
         let db_table_entry : Entry = builder.get_object("db_table_entry").unwrap();
         {
             let action = action.clone();
-            db_table_entry.connect_preedit_changed(move |_spin, txt| {
-                action.borrow_mut().dst = txt.to_string();
+            db_table_entry.connect_changed(move |entry| {
+                action.borrow_mut().dst = entry.get_text().to_string();
             });
         }
         
@@ -395,7 +592,16 @@ impl CopyFromBox {
         }*/
         
         // let col_subset_check : CheckButton = builder.get_object("col_subset_check").unwrap();
-        let create_check : CheckButton = builder.get_object("create_check").unwrap();
+        let mode_combo : ComboBoxText = builder.get_object("copy_mode_combo").unwrap();
+        {
+            let action = action.clone();
+            mode_combo.connect_changed(move |combo| {
+                let mode = combo.get_active_id()
+                    .and_then(|id| UploadMode::from_name(id.as_str()) )
+                    .unwrap_or(UploadMode::Create);
+                action.borrow_mut().mode = mode;
+            });
+        }
         let convert_check : CheckButton = builder.get_object("convert_check").unwrap();
         let copy_from_btn : Button = builder.get_object("copy_from_btn").unwrap();
         let script_from_btn : Button = builder.get_object("script_from_btn").unwrap();
@@ -404,18 +610,42 @@ impl CopyFromBox {
             let tables_nb = tables_nb.clone();
             let tbl_env = tbl_env.clone();
             let action = action.clone();
+            let status_stack = status_stack.clone();
+            let safety_dialog = safety_dialog.clone();
             copy_from_btn.connect_clicked(move |_btn| {
                 let idx = tables_nb.get_page_index();
-                if let Ok(mut t_env) = tbl_env.try_borrow_mut() {
-                    if let Ok(action) = action.try_borrow() {
-                        if let Err(e) = t_env.copy_to_database(idx, &action.dst[..], &action.cols[..]) {
-                            println!("{}", e);
-                        }
-                    } else {
+                let (dst, mode) = match action.try_borrow() {
+                    Ok(action) => (action.dst.clone(), action.mode),
+                    Err(_) => {
                         println!("Unable to borrow action");
+                        return;
                     }
-                } else {
-                    println!("Unable to borrow table environment");
+                };
+                let prepared = match tbl_env.try_borrow() {
+                    Ok(t_env) => t_env.copy_statements(idx, &dst[..], mode)
+                        .map(|stmts| (stmts, t_env.safety(), t_env.dialect(), t_env.active_connection()) ),
+                    Err(_) => Err(String::from("Unable to borrow table environment"))
+                };
+                let (stmts, safety, dialect, conn) = match prepared {
+                    Ok(prepared) => prepared,
+                    Err(e) => {
+                        status_stack.update(Status::SqlErr(format!("Table not copied to {}: {}", dst, e)));
+                        return;
+                    }
+                };
+
+                // Replacing drops the existing table, which requires a typed confirmation at production connections.
+                let sql : String = stmts.iter().map(|stmt| format!("{};\n", stmt.sql) ).collect();
+                if !safety_dialog.confirm(&safety, dialect, conn, &sql) {
+                    return;
+                }
+                let copied = match tbl_env.try_borrow_mut() {
+                    Ok(mut t_env) => t_env.apply_edits(&stmts[..]),
+                    Err(_) => Err(String::from("Unable to borrow table environment"))
+                };
+                match copied {
+                    Ok(n) => status_stack.update(Status::StatementExecuted(format!("{} rows copied to {}", n, dst))),
+                    Err(e) => status_stack.update(Status::SqlErr(format!("Table not copied to {}: {}", dst, e)))
                 }
             });
         }
@@ -424,7 +654,7 @@ impl CopyFromBox {
             db_table_entry,
             // col_subset_entry,
             // col_subset_check,
-            create_check,
+            mode_combo,
             convert_check,
             copy_from_btn,
             script_from_btn,
@@ -485,7 +715,8 @@ impl TablePopover {
         table_env : Rc<RefCell<TableEnvironment>>,
         tables_nb : TableNotebook,
         status_stack : StatusStack,
-        cmd_window : CommandWindow
+        cmd_window : CommandWindow,
+        safety_dialog : SafetyDialog
     ) -> Self {
         let popover : Popover = builder.get_object("table_popover").unwrap();
         // let command_box : Box = builder.get_object("command_box").unwrap();
@@ -501,7 +732,15 @@ impl TablePopover {
         // let finish_upload_btn : Button = builder.get_object("finish_upload_button").unwrap();
 
         let csv_window = CsvWindow::build(&builder);
-        let copy_from_bx = CopyFromBox::build(&builder, &tables_nb, &table_env, &csv_window, &cmd_window);
+        let copy_from_bx = CopyFromBox::build(
+            &builder,
+            &tables_nb,
+            &table_env,
+            &csv_window,
+            &cmd_window,
+            &status_stack,
+            &safety_dialog
+        );
         let copy_to_bx = CopyToBox::build(&builder, &tables_nb, &table_env, &csv_window, &cmd_window);
         let selected = Rc::new(RefCell::new(None));
         let copy_stack : Stack = builder.get_object("copy_stack").unwrap();
@@ -1,112 +1,532 @@
 use gtk::*;
 use gtk::prelude::*;
 use std::rc::Rc;
-use std::cell::{RefCell};
+use std::cell::{Cell, RefCell};
 use crate::tables::table::*;
+use crate::tables::column::ValueKind;
+use crate::tables::diff::{TableDiff, CellStatus};
+use crate::tables::row_view::{self, RowFilter, SortOrder};
+use crate::tables::edit::{EditSet, EditValue, EditTarget};
+use crate::cell_detail::CellDetail;
 use crate::utils;
-use gdk::prelude::*;
-use gdk::{Cursor, CursorType};
+
+/// Cell colors, read from the classes of tables.css. Cell renderers are not widgets, so
+/// they cannot take the classes themselves: the colors are looked up once, at the style
+/// context of the tree view, and set at the renderers when each cell is drawn.
+#[derive(Clone)]
+struct CellStyles {
+
+    /// Background of the cells of selected columns (.selected).
+    selected : Option<gdk::RGBA>,
+
+    /// Backgrounds of inserted (.inserted-cell), changed (.changed-cell) and deleted (.deleted-cell) cells.
+    inserted : Option<gdk::RGBA>,
+    changed : Option<gdk::RGBA>,
+    deleted : Option<gdk::RGBA>,
+
+    /// Text color of null values and deleted rows (.null-cell).
+    faded : gdk::RGBA
+}
+
+impl CellStyles {
+
+    fn from_context(ctx : &StyleContext) -> Self {
+        let background = |class : &str| -> Option<gdk::RGBA> {
+            ctx.save();
+            ctx.add_class(class);
+            let color = StyleContextExt::get_property(ctx, "background-color", StateFlags::NORMAL).get::<gdk::RGBA>().ok().flatten();
+            ctx.restore();
+            color.filter(|c| c.alpha > 0.0 )
+        };
+        let selected = background("selected");
+        let inserted = background("inserted-cell");
+        let changed = background("changed-cell");
+        let deleted = background("deleted-cell");
+        ctx.save();
+        ctx.add_class("null-cell");
+        let faded = ctx.get_color(StateFlags::NORMAL);
+        ctx.restore();
+        Self { selected, inserted, changed, deleted, faded }
+    }
+
+}
+
+/// Rows of the tree view, which the cell renderers map to the rows of the table. The store
+/// holds the position of each row and only grows, in batches (to the next power of two rows),
+/// while the filter shows its first rows: sorting and filtering the table change the number
+/// of rows shown without rows being added to or removed from the store.
+#[derive(Clone)]
+struct RowStore {
+    store : ListStore,
+    filter : TreeModelFilter,
+    shown : Rc<Cell<usize>>
+}
+
+impl RowStore {
+
+    fn new() -> Self {
+        let store = ListStore::new(&[glib::Type::U32]);
+        let filter = TreeModelFilter::new(&store, None);
+        let shown = Rc::new(Cell::new(0));
+        {
+            let shown = shown.clone();
+            filter.set_visible_func(move |model, iter| {
+                model.get_value(iter, 0).get_some::<u32>()
+                    .map(|pos| (pos as usize) < shown.get() )
+                    .unwrap_or(false)
+            });
+        }
+        Self { store, filter, shown }
+    }
+
+    /// Shows n rows. The filter is detached from the view while the store grows and
+    /// the rows are filtered again, so the view is not updated at every row.
+    fn resize(&self, tree_view : &TreeView, n : usize) {
+        if n != self.shown.get() {
+            tree_view.set_model(None::<&TreeModelFilter>);
+            let capacity = self.store.iter_n_children(None).max(0) as usize;
+            if n > capacity {
+                for pos in capacity..n.next_power_of_two() {
+                    self.store.insert_with_values(None, &[0], &[&(pos as u32)]);
+                }
+            }
+            self.shown.set(n);
+            self.filter.refilter();
+            tree_view.set_model(Some(&self.filter));
+        }
+        tree_view.queue_draw();
+    }
+
+    fn clear(&self, tree_view : &TreeView) {
+        tree_view.set_model(None::<&TreeModelFilter>);
+        self.shown.set(0);
+        self.store.clear();
+        tree_view.set_model(Some(&self.filter));
+    }
+
+}
+
+/// Number of rows read to estimate the initial width of each column.
+const WIDTH_SAMPLE : usize = 100;
+
+/// State read by the cell renderers. The row store holds one row for each row shown:
+/// the text of a cell is only taken from the table when the cell is drawn, by
+/// mapping the position of the row to a table row through order. This means only
+/// the rows scrolled into view are ever formatted, and sorting just rewrites order.
+struct GridState {
+    tbl : Option<Table>,
+
+    /// Table rows that pass the filters, in display order.
+    order : Vec<usize>,
+    sort : Option<(usize, SortOrder)>,
+    filters : Vec<Option<RowFilter>>,
+    selected : Vec<bool>,
+
+    /// Difference against the last output of the same query. Deleted rows
+    /// are shown after the table rows.
+    diff : Option<TableDiff>,
+
+    /// Pending edits, while the rows are being edited. Inserted rows are
+    /// shown after the table rows (before the rows deleted by the diff).
+    edits : Option<EditSet>,
+    editable : Vec<bool>,
+    binary : Vec<bool>,
+    styles : CellStyles
+}
+
+/// What a row of the store shows.
+#[derive(Debug, Clone, Copy)]
+enum GridRow {
+
+    /// Row of the table, by its index at the table.
+    Table(usize),
+
+    /// Row inserted by the user, by its position among the inserted rows.
+    Inserted(usize),
+
+    /// Row deleted since the last output of the same query, by its position at the diff.
+    Deleted(usize)
+}
 
 #[derive(Clone)]
 pub struct TableWidget {
-    grid : Grid,
-    pub scroll_window : ScrolledWindow,
-    box_container : Box,
+    tree_view : TreeView,
+    store : RowStore,
+
+    /// Notebook page holding the grid (or the message shown in its place) and the cell detail.
+    pub page : Box,
     msg : Label,
-    parent_ctx : StyleContext,
+    detail : CellDetail,
     provider : CssProvider,
+    columns : Rc<RefCell<Vec<TreeViewColumn>>>,
+    state : Rc<RefCell<GridState>>,
     nrows : usize,
-    ncols : usize,
-    selected : Rc<RefCell<Vec<(String, usize, bool)>>>
+    ncols : usize
 }
 
 impl TableWidget {
 
+    pub fn new() -> TableWidget {
+        let provider = utils::provider_from_path("tables.css")
+            .expect("Unable to load tables CSS");
+        let store = RowStore::new();
+        let tree_view = TreeView::new();
+        tree_view.set_model(Some(&store.filter));
+        tree_view.get_style_context().add_provider(&provider, 800);
+        let styles = CellStyles::from_context(&tree_view.get_style_context());
+        tree_view.set_fixed_height_mode(true);
+        tree_view.set_headers_clickable(true);
+        tree_view.set_enable_search(false);
+        tree_view.set_grid_lines(TreeViewGridLines::Both);
+        tree_view.set_vexpand(true);
+        tree_view.get_selection().set_mode(SelectionMode::Multiple);
+
+        // The tree view scrolls its rows under the header, which stays in place.
+        let scroll_window = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        scroll_window.set_shadow_type(ShadowType::None);
+        scroll_window.add(&tree_view);
+        let msg = Label::new(None);
+        let grid_box = Box::new(Orientation::Vertical, 0);
+        grid_box.pack_start(&scroll_window, true, true, 0);
+        grid_box.pack_start(&msg, true, true, 0);
+        let detail = CellDetail::new();
+        let paned = Paned::new(Orientation::Horizontal);
+        paned.pack1(&grid_box, true, false);
+        paned.pack2(&detail.pane, false, false);
+        let page = Box::new(Orientation::Vertical, 0);
+        page.pack_start(&paned, true, true, 0);
+        page.show_all();
+        msg.hide();
+        detail.pane.hide();
+        let state = Rc::new(RefCell::new(GridState {
+            tbl : None,
+            order : Vec::new(),
+            sort : None,
+            filters : Vec::new(),
+            selected : Vec::new(),
+            diff : None,
+            edits : None,
+            editable : Vec::new(),
+            binary : Vec::new(),
+            styles
+        }));
+        let columns = Rc::new(RefCell::new(Vec::new()));
+        {
+            let state = state.clone();
+            let columns = columns.clone();
+            let detail = detail.clone();
+            tree_view.connect_cursor_changed(move |tree_view| {
+                if detail.pane.get_visible() {
+                    Self::update_detail(&state, &columns, tree_view, &detail);
+                }
+            });
+        }
+        {
+            let state = state.clone();
+            let columns = columns.clone();
+            let tree_view = tree_view.clone();
+            let detail_c = detail.clone();
+            detail.connect_load(move |data| {
+                if let Some((pos, col)) = Self::cursor_cell(&columns, &tree_view) {
+                    if let Ok(mut state) = state.try_borrow_mut() {
+                        if let Err(e) = Self::load_cell(&mut state, col, pos, data) {
+                            println!("{}", e);
+                        }
+                    } else {
+                        println!("Unable to borrow grid state");
+                    }
+                }
+                tree_view.queue_draw();
+                Self::update_detail(&state, &columns, &tree_view, &detail_c);
+            });
+        }
+        TableWidget {
+            tree_view,
+            store,
+            page,
+            msg,
+            detail,
+            provider,
+            columns,
+            state,
+            nrows : 0,
+            ncols : 0
+        }
+    }
+
     pub fn new_from_table(tbl : &Table) -> Self {
         let mut tbl_wid = Self::new();
-        let data = tbl.text_rows();
-        tbl_wid.update_data(data);
+        tbl_wid.update_table(tbl);
         tbl_wid
     }
 
-    pub fn new() -> TableWidget {
-        let grid = Grid::new();
-        let _message = Label::new(None);
-        let provider = utils::provider_from_path("tables.css")
-            .expect("Unable to load tables CSS");
-        let parent_ctx = grid.get_style_context();
-        parent_ctx.add_provider(&provider,800);
-        let msg = Label::new(None);
-        let box_container = Box::new(Orientation::Vertical, 0);
-        box_container.pack_start(&grid, true, true, 0);
-        box_container.pack_start(&msg, true, true, 0);
-        let scroll_window = ScrolledWindow::new(
-            Some(&Adjustment::new(0.0, 0.0, 100.0, 10.0, 10.0, 100.0)),
-            Some(&Adjustment::new(0.0, 0.0, 100.0, 10.0, 10.0, 100.0))
-        );
-        scroll_window.set_shadow_type(ShadowType::None);
-        scroll_window.add(&box_container);
-        scroll_window.show_all();
-        let selected = Rc::new(RefCell::new(Vec::new()));
-        //let tbl = Table::new_empty(None);
-        TableWidget{grid, /*data,*/ scroll_window,
-            box_container, msg, parent_ctx, provider, selected, nrows : 0, ncols : 0, /*tbl*/ }
-    }
-
-    pub fn parent(&self) -> ScrolledWindow {
-        self.scroll_window.clone()
-    }
-
-    fn create_header_cell(
-        &self,
-        data : &str,
-        row : usize,
-        col : usize,
-        nrows : usize,
-        ncols : usize
-    ) -> gtk::EventBox {
-        let label = self.create_data_cell(data, row, col, nrows, ncols);
-        let ev_box = gtk::EventBox::new();
-        //ev_box.set_above_child(true);
-        //ev_box.set_visible_window(true);
-        ev_box.add(&label);
-        if let Ok(mut sel) = self.selected.try_borrow_mut() {
-            sel.push((data.to_string(), col, false));
-        }
-        ev_box
-    }
-
-    fn switch_to(
-        grid : Grid,
-        col : &mut (String, usize, bool),
-        ncols : usize,
-        selected : bool
-    ) {
-        Self::set_selected_style(grid.clone(), ncols, col.1, selected);
-        *col = (col.0.clone(), col.1, selected);
+    pub fn parent(&self) -> Box {
+        self.page.clone()
     }
 
-    fn switch_selected(grid : Grid, cols : &mut [(String, usize, bool)], pos : usize) {
-        let ncols = cols.len();
-        if let Some(col) = cols.get_mut(pos) {
-            if col.2 == true {
-                Self::switch_to(grid.clone(), col, ncols, false);
-            } else {
-                Self::switch_to(grid.clone(), col, ncols, true);
+    /// Estimates the initial width of a column from its name and its first values.
+    fn column_width(tbl : &Table, name : &str, col : usize) -> i32 {
+        let n_chars = (0..tbl.shape().0.min(WIDTH_SAMPLE))
+            .filter_map(|r| tbl.display_value(r, col) )
+            .map(|v| v.chars().count() )
+            .chain(Some(name.chars().count() + 2))
+            .max()
+            .unwrap_or(0);
+        (n_chars as i32 * 8 + 24).max(60).min(320)
+    }
+
+    fn grid_row(state : &GridState, pos : usize) -> GridRow {
+        let n_inserted = state.edits.as_ref().map(|e| e.n_inserted() ).unwrap_or(0);
+        if pos < state.order.len() {
+            GridRow::Table(state.order[pos])
+        } else if pos < state.order.len() + n_inserted {
+            GridRow::Inserted(pos - state.order.len())
+        } else {
+            GridRow::Deleted(pos - state.order.len() - n_inserted)
+        }
+    }
+
+    fn path_position(path : &TreePath) -> Option<usize> {
+        path.get_indices().get(0).map(|ix| *ix as usize )
+    }
+
+    fn render_cell(state : &GridState, col : usize, cell : &CellRendererText, model : &TreeModel, iter : &TreeIter) {
+        let pos = match model.get_path(iter).and_then(|p| Self::path_position(&p) ) {
+            Some(pos) => pos,
+            None => return
+        };
+        let col_editable = state.edits.is_some() && state.editable.get(col).cloned().unwrap_or(false);
+        let styles = &state.styles;
+        let (text, background, faded, struck, editable) = match Self::grid_row(state, pos) {
+            GridRow::Table(row) => {
+                let edited = state.edits.as_ref().and_then(|e| e.value(row, col) );
+                let deleted = state.edits.as_ref().map(|e| e.is_deleted(row) ).unwrap_or(false);
+                let (text, is_null) = match edited {
+                    Some(value) => (value.display().to_string(), *value == EditValue::Null),
+                    None => {
+                        let text = state.tbl.as_ref().and_then(|t| t.display_value(row, col) ).unwrap_or(String::new());
+                        let is_null = state.tbl.as_ref().and_then(|t| t.get_column(col) ).map(|c| c.is_null(row) ).unwrap_or(false);
+                        (text, is_null)
+                    }
+                };
+                let status = state.diff.as_ref().map(|d| d.cell_status(row, col) ).unwrap_or(CellStatus::Unchanged);
+                let background = match status {
+                    _ if deleted => styles.deleted,
+                    _ if edited.is_some() => styles.changed,
+                    _ if state.selected.get(col).cloned().unwrap_or(false) => styles.selected,
+                    CellStatus::Inserted => styles.inserted,
+                    CellStatus::Changed => styles.changed,
+                    CellStatus::Unchanged => None
+                };
+                (text, background, is_null || deleted, deleted, col_editable && !deleted)
+            },
+            GridRow::Inserted(ix) => {
+                match state.edits.as_ref().and_then(|e| e.inserted_value(ix, col) ) {
+                    Some(value) => (value.display().to_string(), styles.inserted, *value == EditValue::Null, false, col_editable),
+                    None => (String::from("DEFAULT"), styles.inserted, true, false, col_editable)
+                }
+            },
+            GridRow::Deleted(ix) => {
+                let deleted = state.diff.as_ref().and_then(|d| d.deleted.get(ix) );
+                let text = deleted.and_then(|row| row.get(col).cloned() ).unwrap_or(String::new());
+                (text, styles.deleted, true, false, false)
             }
+        };
+        cell.set_property_text(Some(&text[..]));
+        cell.set_property_cell_background_rgba(background.as_ref());
+        cell.set_property_cell_background_set(background.is_some());
+        cell.set_property_foreground_rgba(Some(&styles.faded));
+        cell.set_property_foreground_set(faded);
+        cell.set_property_strikethrough(struck);
+        cell.set_property_editable(editable);
+    }
+
+    /// Records the text entered at a cell as an edit. Entering the value the
+    /// cell had when fetched discards any previous edit of the cell.
+    fn edit_cell(state : &mut GridState, col : usize, pos : usize, text : &str) {
+        let row = Self::grid_row(state, pos);
+        let original = match row {
+            GridRow::Table(r) => state.tbl.as_ref().and_then(|t| t.display_value(r, col) ),
+            _ => None
+        };
+        let edits = match state.edits.as_mut() {
+            Some(edits) => edits,
+            None => return
+        };
+        match row {
+            GridRow::Table(r) => {
+                if original.as_ref().map(|o| &o[..] == text ).unwrap_or(false) {
+                    edits.unset_value(r, col);
+                } else {
+                    edits.set_value(r, col, EditValue::parse(text));
+                }
+            },
+            GridRow::Inserted(ix) => {
+                edits.set_inserted_value(ix, col, EditValue::parse(text));
+            },
+            GridRow::Deleted(_) => { }
+        }
+    }
+
+    /// Records the content of a file as the value of a cell: binary columns take the bytes as they
+    /// are, and other editable columns take them as text.
+    fn load_cell(state : &mut GridState, col : usize, pos : usize, data : Vec<u8>) -> Result<(), String> {
+        let value = if state.binary.get(col).cloned().unwrap_or(false) {
+            EditValue::Bytes(data)
+        } else if state.editable.get(col).cloned().unwrap_or(false) {
+            EditValue::Text(String::from_utf8(data).map_err(|_| String::from("File is not valid UTF-8 text"))?)
         } else {
-            println!("Invalid column index")
+            return Err(String::from("Column cannot be edited"));
+        };
+        let row = Self::grid_row(state, pos);
+        let edits = state.edits.as_mut().ok_or(String::from("Rows are not being edited"))?;
+        match row {
+            GridRow::Table(r) if !edits.is_deleted(r) => edits.set_value(r, col, value),
+            GridRow::Inserted(ix) => edits.set_inserted_value(ix, col, value),
+            _ => return Err(String::from("Row cannot be edited"))
         }
+        Ok(())
     }
 
-    fn set_selected_style(grid : Grid, ncols : usize, col : usize, selected : bool) {
-        for wid in grid.get_children().iter().skip(ncols - col - 1).step_by(ncols) {
-            let wid = if let Ok(ev) = wid.clone().downcast::<EventBox>() {
-                ev.get_child().unwrap()
-            } else {
-                wid.clone()
+    /// Position at the store and column index of the cell under the cursor.
+    fn cursor_cell(columns : &Rc<RefCell<Vec<TreeViewColumn>>>, tree_view : &TreeView) -> Option<(usize, usize)> {
+        let (path, tree_col) = tree_view.get_cursor();
+        let pos = Self::path_position(&path?)?;
+        let tree_col = tree_col?;
+        let col = columns.try_borrow().ok()?.iter().position(|c| c == &tree_col )?;
+        Some((pos, col))
+    }
+
+    /// Complete value of a cell (including the edits), with a description of where it is
+    /// and whether files can be loaded into it.
+    fn cell_value(state : &GridState, col : usize, pos : usize) -> (String, EditValue, bool) {
+        let name = state.tbl.as_ref().and_then(|t| t.names().get(col).cloned() ).unwrap_or(String::new());
+        let loadable = state.edits.is_some() &&
+            (state.binary.get(col).cloned().unwrap_or(false) || state.editable.get(col).cloned().unwrap_or(false));
+        match Self::grid_row(state, pos) {
+            GridRow::Table(row) => {
+                let deleted = state.edits.as_ref().map(|e| e.is_deleted(row) ).unwrap_or(false);
+                let title = format!("{}, row {}", name, row + 1);
+                if let Some(value) = state.edits.as_ref().and_then(|e| e.value(row, col) ) {
+                    return (title, value.clone(), loadable && !deleted);
+                }
+                let value = match state.tbl.as_ref().and_then(|t| t.get_column(col) ) {
+                    Some(column) if column.is_null(row) => EditValue::Null,
+                    Some(column) => match column.bytes_value(row) {
+                        Some(data) => EditValue::Bytes(data.to_vec()),
+                        None => EditValue::Text(
+                            column.exact_value(row)
+                                .or_else(|| state.tbl.as_ref()?.display_value(row, col) )
+                                .unwrap_or(String::new())
+                        )
+                    },
+                    None => EditValue::Null
+                };
+                (title, value, loadable && !deleted)
+            },
+            GridRow::Inserted(ix) => {
+                let value = state.edits.as_ref()
+                    .and_then(|e| e.inserted_value(ix, col).cloned() )
+                    .unwrap_or(EditValue::Null);
+                (format!("{}, new row {}", name, ix + 1), value, loadable)
+            },
+            GridRow::Deleted(ix) => {
+                let text = state.diff.as_ref()
+                    .and_then(|d| d.deleted.get(ix)?.get(col).cloned() )
+                    .unwrap_or(String::new());
+                (format!("{}, deleted row", name), EditValue::Text(text), false)
+            }
+        }
+    }
+
+    fn update_detail(
+        state : &Rc<RefCell<GridState>>,
+        columns : &Rc<RefCell<Vec<TreeViewColumn>>>,
+        tree_view : &TreeView,
+        detail : &CellDetail
+    ) {
+        match Self::cursor_cell(columns, tree_view) {
+            Some((pos, col)) => {
+                if let Ok(state) = state.try_borrow() {
+                    let (title, value, loadable) = Self::cell_value(&state, col, pos);
+                    detail.show_value(&title, value, loadable);
+                } else {
+                    println!("Unable to borrow grid state");
+                }
+            },
+            None => detail.clear()
+        }
+    }
+
+    /// Shows or hides the pane with the complete value of the cell under the cursor.
+    pub fn set_detail_visible(&self, visible : bool) {
+        self.detail.pane.set_visible(visible);
+        if visible {
+            Self::update_detail(&self.state, &self.columns, &self.tree_view, &self.detail);
+        }
+    }
+
+    /// Recalculates the rows shown from the current sorting and filters.
+    fn refresh(state : &Rc<RefCell<GridState>>, tree_view : &TreeView, store : &RowStore) {
+        let n = if let Ok(mut state) = state.try_borrow_mut() {
+            let filters : Vec<(usize, RowFilter)> = state.filters.iter()
+                .enumerate()
+                .filter_map(|(c, f)| f.clone().map(|f| (c, f)) )
+                .collect();
+            let order = match &state.tbl {
+                Some(tbl) => row_view::row_order(tbl, state.sort, &filters[..]),
+                None => Vec::new()
             };
-            let ctx = wid.get_style_context();
+            state.order = order;
+            let n_deleted = state.diff.as_ref().map(|d| d.deleted.len() ).unwrap_or(0);
+            let n_inserted = state.edits.as_ref().map(|e| e.n_inserted() ).unwrap_or(0);
+            state.order.len() + n_inserted + n_deleted
+        } else {
+            println!("Unable to borrow grid state");
+            return;
+        };
+        store.resize(tree_view, n);
+    }
+
+    fn control_pressed() -> bool {
+        gtk::get_current_event()
+            .and_then(|ev| ev.get_state() )
+            .map(|state| state.contains(gdk::ModifierType::CONTROL_MASK) )
+            .unwrap_or(false)
+    }
+
+    /// Cycles the sorting of a column between ascending, descending and unsorted.
+    fn switch_sort(
+        state : &Rc<RefCell<GridState>>,
+        columns : &[TreeViewColumn],
+        col : usize
+    ) {
+        if let Ok(mut state) = state.try_borrow_mut() {
+            state.sort = match state.sort {
+                Some((c, SortOrder::Ascending)) if c == col => Some((col, SortOrder::Descending)),
+                Some((c, SortOrder::Descending)) if c == col => None,
+                _ => Some((col, SortOrder::Ascending))
+            };
+            for (i, tree_col) in columns.iter().enumerate() {
+                match state.sort {
+                    Some((c, order)) if c == i => {
+                        tree_col.set_sort_indicator(true);
+                        tree_col.set_sort_order(match order {
+                            SortOrder::Ascending => SortType::Ascending,
+                            SortOrder::Descending => SortType::Descending
+                        });
+                    },
+                    _ => tree_col.set_sort_indicator(false)
+                }
+            }
+        } else {
+            println!("Unable to borrow grid state");
+        }
+    }
+
+    fn set_selected_style(columns : &[TreeViewColumn], col : usize, selected : bool) {
+        if let Some(btn) = columns.get(col).and_then(|c| c.get_button() ) {
+            let ctx = btn.get_style_context();
             if selected {
                 if !ctx.has_class("selected") {
                     ctx.add_class("selected");
@@ -119,48 +539,68 @@ impl TableWidget {
         }
     }
 
-    fn create_data_cell(
-        &self,
-        data : &str,
-        row : usize,
-        col : usize,
-        nrows : usize,
-        ncols : usize
-    ) -> Label {
-        let label = Label::new(Some(data));
-        label.set_hexpand(true);
-        let ctx = label.get_style_context();
-        ctx.set_parent(Some(&(self.parent_ctx)));
-        ctx.add_provider(&(self.provider),800); // PROVIDER_CONTEXT_USER
-        ctx.add_class("table-cell");
-
-        // Add this only when all columns have a title, maybe on a set_header method.
-        if row == 0 {
-            ctx.add_class("first-row");
-        }
-        if row == nrows - 1 {
-            ctx.add_class("last-row");
-        }
-        if col % 2 != 0 {
-            ctx.add_class("odd-col");
+    fn switch_selected(state : &mut GridState, columns : &[TreeViewColumn], col : usize) {
+        if let Some(sel) = state.selected.get_mut(col) {
+            *sel = !*sel;
+            Self::set_selected_style(columns, col, *sel);
         } else {
-            ctx.add_class("even-col");
-        }
-        if col == ncols-1 {
-            ctx.add_class("last-col");
+            println!("Invalid column index");
         }
-        if (row + 1) % 2 != 0 {
-            ctx.add_class("odd-row");
+    }
+
+    fn switch_all(state : &mut GridState, columns : &[TreeViewColumn]) {
+        let switch_to = !state.selected.iter().any(|s| *s);
+        for (i, sel) in state.selected.iter_mut().enumerate() {
+            *sel = switch_to;
+            Self::set_selected_style(columns, i, switch_to);
         }
+    }
 
-        label
+    /// Popover with the filter entry of a column, shown by right-clicking its header.
+    fn build_filter_popover(&self, col : usize, btn : &Widget, filter_img : &Image) -> Popover {
+        let popover = Popover::new(Some(btn));
+        let entry = SearchEntry::new();
+        entry.set_placeholder_text(Some("abc, > 10, = 0, null"));
+        entry.set_width_chars(24);
+        entry.set_margin_start(6);
+        entry.set_margin_end(6);
+        entry.set_margin_top(6);
+        entry.set_margin_bottom(6);
+        popover.add(&entry);
+        entry.show();
+        {
+            let state = self.state.clone();
+            let tree_view = self.tree_view.clone();
+            let store = self.store.clone();
+            let filter_img = filter_img.clone();
+            entry.connect_search_changed(move |entry| {
+                let filter = RowFilter::parse(&entry.get_text());
+                filter_img.set_visible(filter.is_some());
+                if let Ok(mut state) = state.try_borrow_mut() {
+                    if let Some(f) = state.filters.get_mut(col) {
+                        *f = filter;
+                    }
+                } else {
+                    println!("Unable to borrow grid state");
+                    return;
+                }
+                Self::refresh(&state, &tree_view, &store);
+            });
+        }
+        {
+            let popover = popover.clone();
+            entry.connect_activate(move |_| {
+                popover.hide();
+            });
+        }
+        popover
     }
 
     /// Returns selected columns, as a continuous index from the first
     /// column of the current table
     pub fn selected_cols(&self) -> Vec<usize> {
-        if let Ok(sel) = self.selected.try_borrow() {
-            sel.iter().filter(|s| s.2 == true ).map(|s| s.1 ).collect()
+        if let Ok(state) = self.state.try_borrow() {
+            state.selected.iter().enumerate().filter(|(_, s)| **s ).map(|(i, _)| i ).collect()
         } else {
             println!("Selected is borrowed");
             Vec::new()
@@ -168,82 +608,61 @@ impl TableWidget {
     }
 
     pub fn unselected_cols(&self) -> Vec<usize> {
-        let n = if let Ok(sel) = self.selected.try_borrow() {
-            sel.len()
+        if let Ok(state) = self.state.try_borrow() {
+            state.selected.iter().enumerate().filter(|(_, s)| !**s ).map(|(i, _)| i ).collect()
         } else {
             println!("Selected is borrowed");
-            return Vec::new();
-        };
-        let selected = self.selected_cols();
-        let mut unselected = Vec::new();
-        for i in 0..n {
-            if !selected.iter().any(|s| i == *s) {
-                unselected.push(i);
-            }
+            Vec::new()
         }
-        unselected
     }
 
     pub fn unselect_all(&self) {
-        let selected = self.selected_cols();
-        if let Ok(mut sel) = self.selected.try_borrow_mut() {
-            for s in selected {
-                Self::switch_selected(self.grid.clone(), &mut sel, s);
+        if let (Ok(mut state), Ok(columns)) = (self.state.try_borrow_mut(), self.columns.try_borrow()) {
+            for (i, sel) in state.selected.iter_mut().enumerate() {
+                if *sel {
+                    *sel = false;
+                    Self::set_selected_style(&columns[..], i, false);
+                }
             }
         } else {
             println!("Could not retrieve mutable reference to selected");
         }
+        self.tree_view.queue_draw();
     }
 
-    fn switch_all(grid : Grid, cols : &mut [(String, usize, bool)]) {
-        let switch_to = !cols.iter().any(|c| c.2);
-        let n = cols.len();
-        for i in 0..n {
-            Self::switch_to(grid.clone(), &mut cols[i], n, switch_to);
-        }
-    }
-
-    pub fn expose_event_box(&self, ix : usize) -> Option<EventBox> {
-        let children = self.grid.get_children();
-
-        // TODO correct "attempted to subtract with overflow: changing from one
-        // mapping to the other leads to search for inexistent table.
-        let children = self.grid.get_children();
-        let header_iter = children.iter()
-            .skip(self.ncols * self.nrows - self.ncols);
-        let n = header_iter.clone().count();
-        let wid = header_iter.skip(n-ix-1).next()?;
-        if let Ok(ev_box) = wid.clone().downcast::<EventBox>() {
-            Some(ev_box.clone())
-        } else {
-            None
-        }
+    /// Returns the header button of the column at the informed index.
+    pub fn expose_header(&self, ix : usize) -> Option<Widget> {
+        self.columns.try_borrow().ok()?.get(ix)?.get_button()
     }
 
     /// Function supplied by user should take all selected columns at
-    /// the third argument and the index of the last selected column at
-    /// the last argument.
+    /// the third argument and the index of the clicked column at
+    /// the last argument. Columns are selected by clicking their header
+    /// with Ctrl pressed (a plain click sorts the column).
     pub fn set_selected_action<F>(&self, f : F, btn : u32)
     where
         F : Clone,
-        for<'r,'s> F : Fn(&'r EventBox, &'s gdk::EventButton, Vec<usize>, usize)->Inhibit+'static
+        for<'r,'s> F : Fn(&'r Widget, &'s gdk::EventButton, Vec<usize>, usize)->Inhibit+'static
     {
-        let children = self.grid.get_children();
-        let header_iter = children.iter()
-            .skip(self.ncols * self.nrows - self.ncols);
-        let n = header_iter.clone().count();
-        for (i, wid) in header_iter.enumerate() {
-            if let Ok(ev_box) = wid.clone().downcast::<EventBox>() {
-                let selected = self.selected.clone();
+        let columns = if let Ok(columns) = self.columns.try_borrow() {
+            columns.clone()
+        } else {
+            println!("Unable to borrow grid columns");
+            return;
+        };
+        for (i, col) in columns.iter().enumerate() {
+            if let Some(header) = col.get_button() {
+                let state = self.state.clone();
                 let f = f.clone();
-                ev_box.connect_button_press_event(move |ev_box, ev| {
-                    if ev.get_button() == btn {
-                        if let Ok(sel) = selected.try_borrow() {
-                            let sel_ix : Vec<_> = sel.iter()
-                                .filter(|c| c.2)
-                                .map(|c| c.1)
+                header.connect_button_press_event(move |header, ev| {
+                    if ev.get_button() == btn && ev.get_state().contains(gdk::ModifierType::CONTROL_MASK) {
+                        if let Ok(state) = state.try_borrow() {
+                            let sel_ix : Vec<_> = state.selected.iter()
+                                .enumerate()
+                                .filter(|(_, s)| **s )
+                                .map(|(i, _)| i )
                                 .collect();
-                            f(ev_box, ev, sel_ix, n - i - 1);
+                            f(header, ev, sel_ix, i);
                         } else {
                             println!("Unable to retrieve reference to selected vector");
                         }
@@ -251,146 +670,297 @@ impl TableWidget {
                     glib::signal::Inhibit(false)
                 });
             } else {
-                println!("Could not convert widget to event box");
+                println!("Column header not created");
             }
         }
     }
 
-    pub fn update_data(&mut self, data : Vec<Vec<String>>) {
+    pub fn update_table(&mut self, tbl : &Table) {
         self.clear_table();
-        if data.is_empty() {
+        let (nrows, ncols) = tbl.shape();
+        if ncols == 0 {
             return;
         }
-        if data.len() >= 1 && data[0].is_empty() {
+        self.nrows = nrows;
+        self.ncols = ncols;
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            state.tbl = Some(tbl.clone());
+            state.sort = None;
+            state.filters = vec![None; ncols];
+            state.selected = vec![false; ncols];
+            state.diff = None;
+            state.edits = None;
+            state.editable = vec![false; ncols];
+            state.binary = vec![false; ncols];
+        } else {
+            println!("Unable to borrow grid state");
             return;
         }
-        let nrows = data.len();
-        let ncols = data[0].len();
-        self.nrows = nrows.min(200);
-        self.ncols = ncols;
-        self.update_table_dimensions(
-            self.nrows as i32, self.ncols as i32);
-        for (i,row) in data.iter().enumerate().take(200) {
-            for (j, col) in row.iter().enumerate() {
-                if i == 0 {
-                    let ev_box = self.create_header_cell(
-                        &col[..], i, j, self.nrows, self.ncols);
-                    self.grid.add(&ev_box);
-                    ev_box.realize();
-                    if let Some(win) = ev_box.get_window() {
-                        let disp = win.get_display();
-                        let cur = Cursor::new_for_display(&disp, CursorType::Hand2);
-                        win.set_cursor(Some(&cur));
-                        let mut ev_mask = ev_box.get_events();
-                        ev_mask.set(gdk::EventMask::BUTTON_PRESS_MASK, true);
-                        ev_mask.set(gdk::EventMask::POINTER_MOTION_MASK, true); //GDK_2BUTTON_PRESS
-                        {
-                            let selected = self.selected.clone();
-                            let grid = self.grid.clone();
-                            ev_box.connect_button_press_event(move |bx, ev| {
-                                if let Some(child) = bx.get_children().get(0) {
-                                    let label : Label = child.clone().downcast().unwrap();
-                                    let txt = label.get_text().to_string();
-                                    if let Ok(mut sel) = selected.try_borrow_mut() {
-                                        if ev.get_button() == 1 {
-                                            if ev.get_click_count() == Some(1) {
-                                                if let Some(pos) = sel.iter_mut().position(|c| &c.0[..] == &txt[..] ) {
-                                                    Self::switch_selected(grid.clone(), &mut sel[..], pos);
-                                                } else {
-                                                    println!("Invalid column name");
-                                                }
-                                            } else {
-                                                Self::switch_all(grid.clone(), &mut sel[..]);
-                                            }
-                                        }
-                                    } else {
-                                       println!("Selected vector mutably borrowed");
-                                       return glib::signal::Inhibit(false);
-                                    }
-                                } else {
-                                    println!("Label not present inside event box");
-                                }
-                                glib::signal::Inhibit(false)
-                            });
+        for (j, name) in tbl.names().iter().enumerate() {
+            let renderer = CellRendererText::new();
+            if tbl.get_column(j).map(|c| c.value_kind() == ValueKind::Number ).unwrap_or(false) {
+                renderer.set_property_xalign(1.0);
+            }
+            let col = TreeViewColumn::new();
+            col.pack_start(&renderer, true);
+            {
+                let state = self.state.clone();
+                let cell = renderer.clone();
+                CellLayoutExt::set_cell_data_func(&col, &renderer, Some(std::boxed::Box::new(move |_, _, model, iter| {
+                    if let Ok(state) = state.try_borrow() {
+                        Self::render_cell(&state, j, &cell, model, iter);
+                    }
+                })));
+            }
+            {
+                let state = self.state.clone();
+                let tree_view = self.tree_view.clone();
+                renderer.connect_edited(move |_, path, text| {
+                    if let Some(pos) = Self::path_position(&path) {
+                        if let Ok(mut state) = state.try_borrow_mut() {
+                            Self::edit_cell(&mut state, j, pos, text);
+                        } else {
+                            println!("Unable to borrow grid state");
                         }
-                        /*ev_mask.set(gdk::EventMask::TOUCH_MASK, true);
-                        ev_mask.set(gdk::EventMask::BUTTON1_MOTION_MASK, true);
-                        ev_mask.set(gdk::EventMask::BUTTON2_MOTION_MASK, true);
-                        ev_mask.set(gdk::EventMask::BUTTON3_MOTION_MASK, true);
-                        ev_box.set_events(ev_mask);
-                        ev_box.connect_drag_begin(move |bx, ctx| {
-                             if let Some(child) = bx.get_children().get(0) {
-                                let label : Label = child.clone().downcast().unwrap();
-                                if let Some(txt) = label.get_text() {
-                                    println!("{:?}", txt);
-                                }
-                            }
-                        });
-
-                        ev_box.connect_drag_end(move |bx, ctx| {
-                             if let Some(child) = bx.get_children().get(0) {
-                                let label : Label = child.clone().downcast().unwrap();
-                                if let Some(txt) = label.get_text() {
-                                    println!("{:?}", txt);
-                                }
-                            }
-                        });
-                        ev_box.connect_drag_motion(move |bx, ctx, _, _, _| {
-                             if let Some(child) = bx.get_children().get(0) {
-                                let label : Label = child.clone().downcast().unwrap();
-                                if let Some(txt) = label.get_text() {
-                                    println!("{:?}", txt);
+                    }
+                    tree_view.queue_draw();
+                });
+            }
+            let header = Box::new(Orientation::Horizontal, 4);
+            let label = Label::new(Some(&name[..]));
+            let filter_img = Image::from_icon_name(Some("edit-find-symbolic"), IconSize::Menu);
+            header.pack_start(&label, false, false, 0);
+            header.pack_start(&filter_img, false, false, 0);
+            header.set_tooltip_text(Some("Click to sort, Ctrl+click to select, right-click to filter"));
+            header.show_all();
+            filter_img.hide();
+            col.set_widget(Some(&header));
+            col.set_sizing(TreeViewColumnSizing::Fixed);
+            col.set_fixed_width(Self::column_width(tbl, name, j));
+            col.set_resizable(true);
+            col.set_reorderable(true);
+            col.set_clickable(true);
+            self.tree_view.append_column(&col);
+            {
+                let state = self.state.clone();
+                let columns = self.columns.clone();
+                let tree_view = self.tree_view.clone();
+                let store = self.store.clone();
+                col.connect_clicked(move |_| {
+                    // Ctrl+click selects the column instead
+                    if Self::control_pressed() {
+                        return;
+                    }
+                    if let Ok(columns) = columns.try_borrow() {
+                        Self::switch_sort(&state, &columns[..], j);
+                    }
+                    Self::refresh(&state, &tree_view, &store);
+                });
+            }
+            if let Some(btn) = col.get_button() {
+                btn.get_style_context().add_provider(&self.provider, 800);
+                let popover = self.build_filter_popover(j, &btn, &filter_img);
+                let state = self.state.clone();
+                let columns = self.columns.clone();
+                let tree_view = self.tree_view.clone();
+                btn.connect_button_press_event(move |_, ev| {
+                    match ev.get_button() {
+                        1 if ev.get_state().contains(gdk::ModifierType::CONTROL_MASK) => {
+                            if let (Ok(mut state), Ok(columns)) = (state.try_borrow_mut(), columns.try_borrow()) {
+                                if ev.get_event_type() == gdk::EventType::DoubleButtonPress {
+                                    Self::switch_all(&mut state, &columns[..]);
+                                } else {
+                                    Self::switch_selected(&mut state, &columns[..], j);
                                 }
+                            } else {
+                                println!("Selected vector mutably borrowed");
                             }
+                            tree_view.queue_draw();
                             glib::signal::Inhibit(false)
-                        });*/
+                        },
+                        3 => {
+                            popover.show();
+                            glib::signal::Inhibit(true)
+                        },
+                        _ => glib::signal::Inhibit(false)
                     }
-                    self.grid.set_cell_left_attach(&ev_box, j as i32);
-                    self.grid.set_cell_top_attach(&ev_box, i as i32);
-                } else {
-                    let cell = self.create_data_cell(
-                        &col[..], i, j, self.nrows, self.ncols);
-                    self.grid.add(&cell);
-                    self.grid.set_cell_left_attach(&cell, j as i32);
-                    self.grid.set_cell_top_attach(&cell, i as i32);
-                }
+                });
+            }
+            if let Ok(mut columns) = self.columns.try_borrow_mut() {
+                columns.push(col);
             }
         }
-        self.grid.show_all();
-        self.grid.queue_draw();
+        Self::refresh(&self.state, &self.tree_view, &self.store);
     }
 
-    fn clear_table(&self,) {
-        while self.grid.get_children().len() > 0 {
-            self.grid.remove_row(0);
+    fn clear_table(&mut self) {
+        for col in self.tree_view.get_columns() {
+            self.tree_view.remove_column(&col);
+        }
+        if let Ok(mut columns) = self.columns.try_borrow_mut() {
+            columns.clear();
+        }
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            state.tbl = None;
+            state.order.clear();
+            state.diff = None;
+            state.edits = None;
         }
+        self.store.clear(&self.tree_view);
+        self.detail.clear();
+        self.nrows = 0;
+        self.ncols = 0;
     }
 
-    fn update_table_dimensions(&self, nrows : i32, ncols : i32) {
+    pub fn set_selected(&self, new_sel : &[usize]) {
+        self.unselect_all();
+        if let (Ok(mut state), Ok(columns)) = (self.state.try_borrow_mut(), self.columns.try_borrow()) {
+            for i in new_sel.iter() {
+                Self::switch_selected(&mut state, &columns[..], *i);
+            }
+        } else {
+            println!("Failed to retrieve mutable reference to selected columns");
+        }
+        self.tree_view.queue_draw();
+    }
 
-        for c in 0..ncols {
-            self.grid.insert_column(c);
+    /// Highlights inserted and changed cells with respect to the last output of the same query,
+    /// and shows the rows that were deleted since then after the table rows.
+    pub fn show_diff(&self, diff : &TableDiff) {
+        if self.ncols == 0 {
+            return;
         }
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            state.diff = Some(diff.clone());
+        } else {
+            println!("Unable to borrow grid state");
+            return;
+        }
+        Self::refresh(&self.state, &self.tree_view, &self.store);
+        self.tree_view.set_tooltip_text(Some(&diff.summary()[..]));
+    }
 
-        for r in 0..nrows {
-            self.grid.insert_row(r);
+    /// Makes the cells of the editable columns of the target editable, and starts recording edits.
+    /// Rows can then be inserted and deleted, and files loaded into cells, until stop_editing is called.
+    pub fn start_editing(&self, target : &EditTarget) {
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            state.editable = target.columns.iter().map(|c| c.editable ).collect();
+            state.binary = target.columns.iter().map(|c| c.binary ).collect();
+            state.edits = Some(EditSet::new());
+        } else {
+            println!("Unable to borrow grid state");
+            return;
         }
+        Self::refresh(&self.state, &self.tree_view, &self.store);
+    }
 
+    /// Makes the grid read-only again, discarding any pending edits.
+    pub fn stop_editing(&self) {
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            state.edits = None;
+        } else {
+            println!("Unable to borrow grid state");
+            return;
+        }
+        Self::refresh(&self.state, &self.tree_view, &self.store);
     }
 
-    pub fn set_selected(&self, new_sel : &[usize]) {
-        self.unselect_all();
-        if let Ok(mut sel) = self.selected.try_borrow_mut() {
-            for i in new_sel.iter() {
-                Self::switch_selected(self.grid.clone(), &mut sel[..], *i);
+    pub fn is_editing(&self) -> bool {
+        self.state.try_borrow().map(|s| s.edits.is_some() ).unwrap_or(false)
+    }
+
+    /// Returns the pending edits, if the grid is being edited.
+    pub fn edits(&self) -> Option<EditSet> {
+        self.state.try_borrow().ok()?.edits.clone()
+    }
+
+    pub fn discard_edits(&self) {
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            if let Some(edits) = state.edits.as_mut() {
+                *edits = EditSet::new();
             }
         } else {
-            println!("Failed to retrieve mutable reference to selected columns");
+            println!("Unable to borrow grid state");
+            return;
+        }
+        Self::refresh(&self.state, &self.tree_view, &self.store);
+    }
+
+    /// Adds an empty row after the table rows, with all values set to the column defaults,
+    /// and starts editing its first editable cell.
+    pub fn insert_row(&self) {
+        let (pos, first_col) = if let Ok(mut state) = self.state.try_borrow_mut() {
+            let ncols = self.ncols;
+            let n_order = state.order.len();
+            let first_col = state.editable.iter().position(|e| *e );
+            match state.edits.as_mut() {
+                Some(edits) => (n_order + edits.insert_row(ncols), first_col),
+                None => return
+            }
+        } else {
+            println!("Unable to borrow grid state");
+            return;
+        };
+        Self::refresh(&self.state, &self.tree_view, &self.store);
+        let path = TreePath::from_indicesv(&[pos as i32]);
+        let col = first_col.and_then(|c| self.columns.try_borrow().ok()?.get(c).cloned() );
+        self.tree_view.set_cursor(&path, col.as_ref(), col.is_some());
+    }
+
+    /// Marks the selected table rows to be deleted (or unmarks them, if they were already
+    /// marked), and removes the selected inserted rows.
+    pub fn delete_selected_rows(&self) {
+        let (paths, _) = self.tree_view.get_selection().get_selected_rows();
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            let rows : Vec<GridRow> = paths.iter()
+                .filter_map(|p| Self::path_position(p) )
+                .map(|pos| Self::grid_row(&state, pos) )
+                .collect();
+            if let Some(edits) = state.edits.as_mut() {
+                let mut inserted = Vec::new();
+                for row in rows {
+                    match row {
+                        GridRow::Table(r) => edits.switch_deleted(r),
+                        GridRow::Inserted(ix) => inserted.push(ix),
+                        GridRow::Deleted(_) => { }
+                    }
+                }
+                inserted.sort();
+                for ix in inserted.iter().rev() {
+                    edits.remove_inserted(*ix);
+                }
+            }
+        } else {
+            println!("Unable to borrow grid state");
+            return;
         }
+        self.tree_view.get_selection().unselect_all();
+        Self::refresh(&self.state, &self.tree_view, &self.store);
+    }
+
+    /// Shows the menu when the rows are right-clicked, selecting the clicked row
+    /// if it was not selected already.
+    pub fn set_context_menu(&self, menu : &Menu) {
+        let menu = menu.clone();
+        self.tree_view.connect_button_press_event(move |tree_view, ev| {
+            if ev.get_button() != 3 {
+                return glib::signal::Inhibit(false);
+            }
+            let (x, y) = ev.get_position();
+            if let Some((Some(path), _, _, _)) = tree_view.get_path_at_pos(x as i32, y as i32) {
+                let selection = tree_view.get_selection();
+                if !selection.path_is_selected(&path) {
+                    selection.unselect_all();
+                    selection.select_path(&path);
+                }
+            }
+            menu.popup_at_pointer(Some(&**ev));
+            glib::signal::Inhibit(true)
+        });
     }
 
     pub fn show_message(&self, msg : &str) {
-        self.grid.hide();
+        self.tree_view.hide();
         self.msg.set_text(msg);
         self.msg.show();
     }
@@ -398,7 +968,7 @@ impl TableWidget {
     pub fn show_data(&self) {
         self.msg.set_text("");
         self.msg.hide();
-        self.grid.show();
+        self.tree_view.show();
     }
 
     pub fn dimensions(&self) -> (usize, usize) {
@@ -406,4 +976,3 @@ impl TableWidget {
     }
 
 }
-
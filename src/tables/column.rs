@@ -3,10 +3,21 @@ use std::marker::Sync;
 use rust_decimal::Decimal;
 use super::nullable_column::*;
 use num_traits::ToPrimitive;
+use std::cmp::Ordering;
+use super::split::Dialect;
 
 // TODO create Array<Column> for N-D Postgre arrays, that carries a vector of Columns
 // and a dimensionality metadata.
 
+/// How the values of a column are written by formats which distinguish
+/// numbers, booleans and strings (such as JSON and SQL scripts).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Number,
+    Bool,
+    Text
+}
+
 /// Densely packed column, where each variant is a vector of some
 /// element that implements postgres::types::ToSql.
 #[derive(Debug, Clone)]
@@ -135,6 +146,23 @@ impl<'a> Column {
         }
     }
     
+    /// Whether the value at the informed row is null (always false for columns which are not nullable).
+    pub fn is_null(&self, ix : usize) -> bool {
+        match self {
+            Column::Nullable(col) => col.is_null(ix),
+            _ => false
+        }
+    }
+
+    pub fn value_kind(&self) -> ValueKind {
+        match self {
+            Column::Bool(_) => ValueKind::Bool,
+            Column::Str(_) | Column::Bytes(_) => ValueKind::Text,
+            Column::Nullable(col) => col.value_kind(),
+            _ => ValueKind::Number
+        }
+    }
+
     pub fn display_content(&'a self, prec : usize) -> Vec<String> {
         match self {
             Column::Bool(v) => v.iter().map(|e| e.to_string() ).collect(),
@@ -160,6 +188,117 @@ impl<'a> Column {
         }
     }
 
+    /// Displays a single value, as written by display_content. Used by views which
+    /// only format the rows they show.
+    pub fn display_value(&self, ix : usize, prec : usize) -> String {
+        match self {
+            Column::Bool(v) => v[ix].to_string(),
+            Column::I8(v) => v[ix].to_string(),
+            Column::I16(v) => v[ix].to_string(),
+            Column::I32(v) => v[ix].to_string(),
+            Column::U32(v) => v[ix].to_string(),
+            Column::I64(v) => v[ix].to_string(),
+            Column::F32(v) => Self::display_with_precision(v[ix] as f64, prec),
+            Column::F64(v) => Self::display_with_precision(v[ix], prec),
+            Column::Numeric(v) => {
+                if let Some(f) = v[ix].round_dp(12).to_f64() {
+                    Self::display_with_precision(f, prec)
+                } else {
+                    v[ix].to_string()
+                }
+            },
+            Column::Str(v) => v[ix].clone(),
+            Column::Bytes(_) => format!("(Binary)"),
+            Column::Nullable(col) => col.display_value(ix, prec)
+        }
+    }
+
+    /// Value at the informed row as text that reads back to the same value (floats are written
+    /// with all their digits and decimals are not rounded). None for null and binary values.
+    pub fn exact_value(&self, ix : usize) -> Option<String> {
+        match self {
+            Column::F32(v) => Some(v[ix].to_string()),
+            Column::F64(v) => Some(v[ix].to_string()),
+            Column::Numeric(v) => Some(v[ix].to_string()),
+            Column::Bytes(_) => None,
+            Column::Nullable(col) => col.exact_value(ix),
+            _ => Some(self.display_value(ix, 0))
+        }
+    }
+
+    /// Content at the informed row of binary columns, if the value is not null.
+    pub fn bytes_value(&self, ix : usize) -> Option<&[u8]> {
+        match self {
+            Column::Bytes(v) => v.get(ix).map(|b| &b[..] ),
+            Column::Nullable(col) => col.bytes_value(ix),
+            _ => None
+        }
+    }
+
+    /// Value identifying the informed row when rows are grouped or matched by the column (None
+    /// for nulls). Numbers equal in value have the same key, whatever their types, and binary
+    /// values are written as hex digits.
+    pub fn key_value(&self, ix : usize) -> Option<String> {
+        match self {
+            Column::Numeric(v) => {
+                let value = v[ix].to_string();
+                if value.contains('.') {
+                    Some(value.trim_end_matches('0').trim_end_matches('.').to_string())
+                } else {
+                    Some(value)
+                }
+            },
+            Column::Bytes(v) => Some(v[ix].iter().map(|b| format!("{:02x}", b) ).collect()),
+            Column::Nullable(col) => col.key_value(ix),
+            _ => self.exact_value(ix)
+        }
+    }
+
+    /// Value at the informed row as a float, if the column is numeric and the value is not null.
+    pub fn numeric_value(&self, ix : usize) -> Option<f64> {
+        match self {
+            Column::I8(v) => Some(v[ix] as f64),
+            Column::I16(v) => Some(v[ix] as f64),
+            Column::I32(v) => Some(v[ix] as f64),
+            Column::U32(v) => Some(v[ix] as f64),
+            Column::I64(v) => Some(v[ix] as f64),
+            Column::F32(v) => Some(v[ix] as f64),
+            Column::F64(v) => Some(v[ix]),
+            Column::Numeric(v) => v[ix].to_f64(),
+            Column::Nullable(col) => col.numeric_value(ix),
+            _ => None
+        }
+    }
+
+    /// Compares the values at two rows using the column type (numbers are compared by value
+    /// rather than by their text). NaN is greater than any other float, and nulls are
+    /// greater than any value, so both are placed last on ascending order.
+    pub fn cmp_rows(&self, a : usize, b : usize) -> Ordering {
+        match self {
+            Column::Bool(v) => v[a].cmp(&v[b]),
+            Column::I8(v) => v[a].cmp(&v[b]),
+            Column::I16(v) => v[a].cmp(&v[b]),
+            Column::I32(v) => v[a].cmp(&v[b]),
+            Column::U32(v) => v[a].cmp(&v[b]),
+            Column::I64(v) => v[a].cmp(&v[b]),
+            Column::F32(v) => Self::cmp_floats(v[a] as f64, v[b] as f64),
+            Column::F64(v) => Self::cmp_floats(v[a], v[b]),
+            Column::Numeric(v) => v[a].cmp(&v[b]),
+            Column::Str(v) => v[a].cmp(&v[b]),
+            Column::Bytes(v) => v[a].cmp(&v[b]),
+            Column::Nullable(col) => col.cmp_rows(a, b)
+        }
+    }
+
+    fn cmp_floats(a : f64, b : f64) -> Ordering {
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+    }
+
     pub fn sqlite3_type(&self) -> String {
         match self {
             Column::I32(_) | Column::I64(_) => String::from("INT"),
@@ -169,6 +308,33 @@ impl<'a> Column {
         }
     }
 
+    /// Type of a database column able to store the values of this column, as named by the dialect.
+    pub fn sql_type(&self, dialect : Dialect) -> &'static str {
+        match dialect {
+            Dialect::Postgres => match self {
+                Column::Bool(_) => "boolean",
+                Column::I8(_) | Column::I16(_) => "smallint",
+                Column::I32(_) => "integer",
+                Column::U32(_) | Column::I64(_) => "bigint",
+                Column::F32(_) => "real",
+                Column::F64(_) => "double precision",
+                Column::Numeric(_) => "numeric",
+                Column::Str(_) => "text",
+                Column::Bytes(_) => "bytea",
+                Column::Nullable(col) => col.sql_type(dialect)
+            },
+            Dialect::Sqlite => match self {
+                Column::Bool(_) | Column::I8(_) | Column::I16(_) | Column::I32(_) |
+                Column::U32(_) | Column::I64(_) => "INTEGER",
+                Column::F32(_) | Column::F64(_) => "REAL",
+                Column::Numeric(_) => "NUMERIC",
+                Column::Str(_) => "TEXT",
+                Column::Bytes(_) => "BLOB",
+                Column::Nullable(col) => col.sql_type(dialect)
+            }
+        }
+    }
+
     pub fn truncate(&mut self, n : usize) {
         match self {
             Column::Bool(v) => v.truncate(n),
@@ -186,6 +352,86 @@ impl<'a> Column {
         }
     }
 
+    /// Column with the values at the informed rows, in order. Rows informed as None, and null
+    /// rows, are null at the new column (which is only nullable if any of them is).
+    pub fn take_rows(&self, rows : &[Option<usize>]) -> Column {
+        match self {
+            Column::Bool(v) => Self::take_values(v, rows),
+            Column::I8(v) => Self::take_values(v, rows),
+            Column::I16(v) => Self::take_values(v, rows),
+            Column::I32(v) => Self::take_values(v, rows),
+            Column::U32(v) => Self::take_values(v, rows),
+            Column::I64(v) => Self::take_values(v, rows),
+            Column::F32(v) => Self::take_values(v, rows),
+            Column::F64(v) => Self::take_values(v, rows),
+            Column::Numeric(v) => Self::take_values(v, rows),
+            Column::Str(v) => Self::take_values(v, rows),
+            Column::Bytes(v) => Self::take_values(v, rows),
+            Column::Nullable(col) => col.take_rows(rows)
+        }
+    }
+
+    /// Inner column holding the valid values, and the position of each row at it (None for null rows).
+    fn valid_rows(&self) -> (&Column, Vec<Option<usize>>) {
+        match self {
+            Column::Nullable(col) => col.valid_rows(),
+            _ => (self, (0..self.len()).map(|r| Some(r) ).collect())
+        }
+    }
+
+    /// Values of this column followed by the values of the other column. Columns holding
+    /// different types are converted: numbers to floats, and anything else to text.
+    pub fn concat(&self, other : &Column) -> Column {
+        let (a, a_rows) = self.valid_rows();
+        let (b, b_rows) = other.valid_rows();
+        match (a, b) {
+            (Column::Bool(a), Column::Bool(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::I8(a), Column::I8(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::I16(a), Column::I16(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::I32(a), Column::I32(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::U32(a), Column::U32(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::I64(a), Column::I64(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::F32(a), Column::F32(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::F64(a), Column::F64(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::Numeric(a), Column::Numeric(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::Str(a), Column::Str(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            (Column::Bytes(a), Column::Bytes(b)) => Self::concat_values(a, &a_rows, b, &b_rows),
+            _ if self.value_kind() == ValueKind::Number && other.value_kind() == ValueKind::Number => {
+                let values : Vec<Option<f64>> = (0..self.len()).map(|r| self.numeric_value(r) )
+                    .chain((0..other.len()).map(|r| other.numeric_value(r) ))
+                    .collect();
+                NullableColumn::from(values).to_column()
+            },
+            _ => {
+                let text = |col : &Column, r : usize| col.exact_value(r).or_else(|| col.key_value(r) );
+                let values : Vec<Option<String>> = (0..self.len()).map(|r| text(self, r) )
+                    .chain((0..other.len()).map(|r| text(other, r) ))
+                    .collect();
+                NullableColumn::from(values).to_column()
+            }
+        }
+    }
+
+    fn concat_values<T>(a : &[T], a_rows : &[Option<usize>], b : &[T], b_rows : &[Option<usize>]) -> Column
+    where
+        T : ToSql + Sync + Clone,
+        Column : From<Vec<T>>
+    {
+        let values : Vec<Option<T>> = a_rows.iter().map(|r| r.map(|r| a[r].clone() ) )
+            .chain(b_rows.iter().map(|r| r.map(|r| b[r].clone() ) ))
+            .collect();
+        NullableColumn::from(values).to_column()
+    }
+
+    fn take_values<T>(v : &[T], rows : &[Option<usize>]) -> Column
+    where
+        T : ToSql + Sync + Clone,
+        Column : From<Vec<T>>
+    {
+        let values : Vec<Option<T>> = rows.iter().map(|r| r.map(|r| v[r].clone() ) ).collect();
+        NullableColumn::from(values).to_column()
+    }
+
 }
 
 pub mod from {
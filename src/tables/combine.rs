@@ -0,0 +1,204 @@
+use super::table::Table;
+use super::column::Column;
+use super::expr::RowExpr;
+use std::collections::{HashMap, HashSet};
+
+/// Which rows without a match are kept by a join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+
+    /// Only pairs of matching rows.
+    Inner,
+
+    /// Also rows of the left table without a match, with nulls at the right columns.
+    Left,
+
+    /// Also rows of either table without a match.
+    Full
+}
+
+impl JoinKind {
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "inner" => Some(JoinKind::Inner),
+            "left" => Some(JoinKind::Left),
+            "full" => Some(JoinKind::Full),
+            _ => None
+        }
+    }
+
+}
+
+fn table_name(tbl : &Table) -> String {
+    tbl.table_info().0.unwrap_or(String::from("Table"))
+}
+
+/// Columns of the table at the informed rows (None rows are null).
+fn take_rows(tbl : &Table, rows : &[Option<usize>]) -> Vec<Column> {
+    (0..tbl.shape().1).filter_map(|c| tbl.get_column(c) ).map(|c| c.take_rows(rows) ).collect()
+}
+
+/// Values identifying a row at the key columns, or None if any of them is null (nulls never match).
+fn row_key(cols : &[&Column], row : usize) -> Option<Vec<String>> {
+    cols.iter().map(|c| c.key_value(row) ).collect()
+}
+
+/// Parses the key columns of a join, written as comma-separated column names present at both
+/// tables, or as pairs of left and right column names (left_name = right_name).
+pub fn parse_keys(text : &str, left_names : &[String], right_names : &[String]) -> Result<Vec<(usize, usize)>, String> {
+    let position = |names : &[String], name : &str, side : &str| {
+        names.iter().position(|n| n == name ).ok_or(format!("Column {} not found at the {} table", name, side))
+    };
+    text.split(',')
+        .map(|key| key.trim() )
+        .filter(|key| !key.is_empty() )
+        .map(|key| {
+            let (l, r) = match key.find('=') {
+                Some(pos) => (key[..pos].trim(), key[pos+1..].trim()),
+                None => (key, key)
+            };
+            Ok((position(left_names, l, "first")?, position(right_names, r, "other")?))
+        }).collect()
+}
+
+/// Joins two tables by pairs of key columns (left column index and right column index). The
+/// result has all columns of the left table followed by all columns of the right table, whose
+/// names get a numeric suffix when already used by the left table. Keys are matched by value,
+/// so integer and decimal columns can be joined.
+pub fn join(left : &Table, right : &Table, keys : &[(usize, usize)], kind : JoinKind) -> Result<Table, String> {
+    if keys.is_empty() {
+        return Err(String::from("Choose at least one pair of key columns"));
+    }
+    let left_cols : Vec<&Column> = keys.iter().filter_map(|(l, _)| left.get_column(*l) ).collect();
+    let right_cols : Vec<&Column> = keys.iter().filter_map(|(_, r)| right.get_column(*r) ).collect();
+    if left_cols.len() != keys.len() || right_cols.len() != keys.len() {
+        return Err(String::from("Invalid key column"));
+    }
+    let mut index : HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for r in 0..right.shape().0 {
+        if let Some(key) = row_key(&right_cols[..], r) {
+            index.entry(key).or_insert(Vec::new()).push(r);
+        }
+    }
+    let mut left_rows = Vec::new();
+    let mut right_rows = Vec::new();
+    let mut matched = vec![false; right.shape().0];
+    for l in 0..left.shape().0 {
+        match row_key(&left_cols[..], l).and_then(|key| index.get(&key) ) {
+            Some(rs) => {
+                for r in rs.iter() {
+                    left_rows.push(Some(l));
+                    right_rows.push(Some(*r));
+                    matched[*r] = true;
+                }
+            },
+            None => if kind != JoinKind::Inner {
+                left_rows.push(Some(l));
+                right_rows.push(None);
+            }
+        }
+    }
+    if kind == JoinKind::Full {
+        for r in (0..right.shape().0).filter(|r| !matched[*r] ) {
+            left_rows.push(None);
+            right_rows.push(Some(r));
+        }
+    }
+    let mut names = left.names();
+    for name in right.names() {
+        let unique = if names.contains(&name) {
+            (2..).map(|i| format!("{}_{}", name, i) ).find(|n| !names.contains(n) ).unwrap()
+        } else {
+            name
+        };
+        names.push(unique);
+    }
+    let mut cols = take_rows(left, &left_rows[..]);
+    cols.extend(take_rows(right, &right_rows[..]));
+    let name = format!("{} join {}", table_name(left), table_name(right));
+    Table::new(Some(name), names, cols).map_err(|e| e.to_string() )
+}
+
+/// Rows of the first table followed by the rows of the second table, matching columns by position
+/// (the names are taken from the first table). If distinct is true, repeated rows are kept only once.
+pub fn union(first : &Table, second : &Table, distinct : bool) -> Result<Table, String> {
+    let (ncols, other_ncols) = (first.shape().1, second.shape().1);
+    if ncols != other_ncols {
+        return Err(format!("Tables have different numbers of columns ({} and {})", ncols, other_ncols));
+    }
+    let mut cols : Vec<Column> = (0..ncols)
+        .filter_map(|c| Some(first.get_column(c)?.concat(second.get_column(c)?)) )
+        .collect();
+    if distinct {
+        let nrows = first.shape().0 + second.shape().0;
+        let mut seen : HashSet<Vec<Option<String>>> = HashSet::new();
+        let rows : Vec<Option<usize>> = (0..nrows)
+            .filter(|r| seen.insert(cols.iter().map(|c| c.key_value(*r) ).collect()) )
+            .map(|r| Some(r) )
+            .collect();
+        cols = cols.iter().map(|c| c.take_rows(&rows[..]) ).collect();
+    }
+    let name = format!("{} union {}", table_name(first), table_name(second));
+    Table::new(Some(name), first.names(), cols).map_err(|e| e.to_string() )
+}
+
+/// Rows of the table for which the expression (as parsed by RowExpr) is true.
+pub fn filter(tbl : &Table, expr : &str) -> Result<Table, String> {
+    let expr = RowExpr::parse(expr, &tbl.names()[..])?;
+    let rows : Vec<Option<usize>> = (0..tbl.shape().0)
+        .filter(|r| expr.accepts(tbl, *r) )
+        .map(|r| Some(r) )
+        .collect();
+    let name = format!("{} (filtered)", table_name(tbl));
+    Table::new(Some(name), tbl.names(), take_rows(tbl, &rows[..])).map_err(|e| e.to_string() )
+}
+
+#[test]
+fn combine_tables() {
+    use super::nullable_column::NullableColumn;
+    use rust_decimal::Decimal;
+    let orders = Table::new(
+        Some(String::from("orders")),
+        vec![String::from("id"), String::from("customer")],
+        vec![
+            Column::I32(vec![1, 2, 3]),
+            NullableColumn::from(vec![Some(10i64), Some(20), None]).to_column()
+        ]
+    ).unwrap();
+    let customers = Table::new(
+        Some(String::from("customers")),
+        vec![String::from("id"), String::from("name")],
+        vec![
+            Column::Numeric(vec![Decimal::new(100, 1), Decimal::new(300, 1)]),
+            Column::Str(vec!["ann".into(), "carl".into()])
+        ]
+    ).unwrap();
+    assert_eq!(parse_keys("customer = id", &orders.names(), &customers.names()).unwrap(), vec![(1, 0)]);
+    assert_eq!(parse_keys(" id ,", &orders.names(), &customers.names()).unwrap(), vec![(0, 0)]);
+    assert!(parse_keys("name", &orders.names(), &customers.names()).is_err());
+    let inner = join(&orders, &customers, &[(1, 0)], JoinKind::Inner).unwrap();
+    assert_eq!(inner.names(), vec![String::from("id"), String::from("customer"), String::from("id_2"), String::from("name")]);
+    assert_eq!(inner.shape(), (1, 4));
+    assert_eq!(inner.display_value(0, 3).unwrap(), "ann");
+    let left = join(&orders, &customers, &[(1, 0)], JoinKind::Left).unwrap();
+    assert_eq!(left.shape(), (3, 4));
+    assert!(left.get_column(3).unwrap().is_null(2));
+    let full = join(&orders, &customers, &[(1, 0)], JoinKind::Full).unwrap();
+    assert_eq!(full.shape(), (4, 4));
+    assert!(full.get_column(0).unwrap().is_null(3));
+    assert_eq!(full.display_value(3, 3).unwrap(), "carl");
+
+    let both = union(&orders, &orders, false).unwrap();
+    assert_eq!(both.shape(), (6, 2));
+    assert!(both.get_column(1).unwrap().is_null(5));
+    assert_eq!(union(&orders, &orders, true).unwrap().shape(), (3, 2));
+    let mixed = union(&orders, &customers, false).unwrap();
+    assert_eq!(mixed.get_column(0).unwrap().numeric_value(4), Some(30.0));
+    assert_eq!(mixed.display_value(3, 1).unwrap(), "ann");
+
+    let filtered = filter(&orders, "customer >= 15 or customer is null").unwrap();
+    assert_eq!(filtered.shape(), (2, 2));
+    assert_eq!(filtered.display_value(0, 0).unwrap(), "2");
+    assert!(filter(&orders, "price > 1").is_err());
+}
@@ -0,0 +1,115 @@
+/// Sequence written at the end of each record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineTerminator {
+
+    /// \r\n, as required by RFC 4180.
+    CrLf,
+
+    /// \n, as expected by most Unix tools.
+    Lf
+}
+
+/// Encoding of the bytes written to files and to the standard input of commands.
+/// Text copied to the clipboard is always UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+
+    /// UTF-8 preceded by a byte order mark, which some spreadsheet applications
+    /// require to recognize the file as UTF-8.
+    Utf8Bom,
+
+    /// ISO-8859-1. Characters outside the Latin-1 range are written as ?.
+    Latin1
+}
+
+impl Encoding {
+
+    pub fn encode(&self, text : &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Utf8Bom => {
+                let mut bytes = vec![0xEF, 0xBB, 0xBF];
+                bytes.extend(text.as_bytes());
+                bytes
+            },
+            Encoding::Latin1 => text.chars().map(|c| if (c as u32) < 256 { c as u8 } else { b'?' } ).collect()
+        }
+    }
+
+}
+
+/// Options of the CSV written by table exports. Fields are quoted as specified by RFC 4180:
+/// a field is enclosed in double quotes when it contains the delimiter, a quote or a line
+/// break, and quotes inside it are doubled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvDialect {
+    pub delimiter : char,
+    pub terminator : LineTerminator,
+
+    /// Whether the column names are written as the first record.
+    pub header : bool,
+    pub encoding : Encoding
+}
+
+impl Default for CsvDialect {
+
+    fn default() -> Self {
+        Self { delimiter : ',', terminator : LineTerminator::CrLf, header : true, encoding : Encoding::Utf8 }
+    }
+
+}
+
+impl CsvDialect {
+
+    fn needs_quotes(&self, field : &str) -> bool {
+        field.chars().any(|c| c == self.delimiter || c == '"' || c == '\n' || c == '\r' )
+    }
+
+    /// Appends a record to out. Each field is informed together with whether it is null.
+    /// Null fields are written as the null representation, without quotes; a field which is
+    /// not null but equals the representation (e.g. an empty string, when nulls are omitted)
+    /// is quoted, so both can be told apart when the file is read back.
+    pub fn write_record<'a>(
+        &self,
+        fields : impl Iterator<Item=(&'a str, bool)>,
+        null : &str,
+        out : &mut String
+    ) {
+        for (i, (field, is_null)) in fields.enumerate() {
+            if i >= 1 {
+                out.push(self.delimiter);
+            }
+            if is_null {
+                out.push_str(null);
+            } else if self.needs_quotes(field) || field == null {
+                out.push('"');
+                out.push_str(&field.replace('"', "\"\""));
+                out.push('"');
+            } else {
+                out.push_str(field);
+            }
+        }
+        match self.terminator {
+            LineTerminator::CrLf => out.push_str("\r\n"),
+            LineTerminator::Lf => out.push('\n')
+        }
+    }
+
+}
+
+#[test]
+fn write_quoted_records() {
+    let dialect = CsvDialect::default();
+    let mut out = String::new();
+    dialect.write_record(vec![("id", false), ("name", false)].into_iter(), "", &mut out);
+    dialect.write_record(vec![("1", false), ("Doe, \"Jo\"\nJr", false)].into_iter(), "", &mut out);
+    dialect.write_record(vec![("", true), ("", false)].into_iter(), "", &mut out);
+    assert_eq!(out, "id,name\r\n1,\"Doe, \"\"Jo\"\"\nJr\"\r\n,\"\"\r\n");
+    let dialect = CsvDialect { delimiter : ';', terminator : LineTerminator::Lf, ..Default::default() };
+    let mut out = String::new();
+    dialect.write_record(vec![("a,b", false), ("NULL", false), ("x", true)].into_iter(), "NULL", &mut out);
+    assert_eq!(out, "a,b;\"NULL\";NULL\n");
+    assert_eq!(Encoding::Latin1.encode("café €"), vec![b'c', b'a', b'f', 0xE9, b' ', b'?']);
+    assert_eq!(&Encoding::Utf8Bom.encode("a")[..], &[0xEF, 0xBB, 0xBF, b'a']);
+}
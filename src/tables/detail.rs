@@ -0,0 +1,190 @@
+use libxml::parser::Parser;
+use libxml::tree::document::SaveOptions;
+
+/// Number of bytes shown at each line of the hex dumps.
+const BYTES_PER_LINE : usize = 16;
+
+/// Image formats recognized by their content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Svg
+}
+
+/// How the value of a cell is shown at the detail pane.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rendering {
+    Image(ImageFormat),
+    Hex(String),
+    Json(String),
+    Xml(String),
+    Text(String)
+}
+
+/// Recognizes PNG and JPEG images by their signatures, and SVG images by their root element.
+pub fn image_format(data : &[u8]) -> Option<ImageFormat> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(ImageFormat::Png);
+    }
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(ImageFormat::Jpeg);
+    }
+    let head = String::from_utf8_lossy(&data[..data.len().min(1024)]).to_lowercase();
+    let head = head.trim_start();
+    if (head.starts_with("<?xml") || head.starts_with("<!doctype svg") || head.starts_with("<svg")) && head.contains("<svg") {
+        return Some(ImageFormat::Svg);
+    }
+    None
+}
+
+/// Offsets, bytes and printable characters of the data, up to max_bytes (the
+/// dump ends with the number of bytes omitted, if any).
+pub fn hex_dump(data : &[u8], max_bytes : usize) -> String {
+    let mut dump = String::new();
+    for (i, line) in data[..data.len().min(max_bytes)].chunks(BYTES_PER_LINE).enumerate() {
+        dump += &format!("{:08x}  ", i * BYTES_PER_LINE);
+        for j in 0..BYTES_PER_LINE {
+            match line.get(j) {
+                Some(b) => dump += &format!("{:02x} ", b),
+                None => dump += "   "
+            }
+            if j == BYTES_PER_LINE / 2 - 1 {
+                dump += " ";
+            }
+        }
+        let printable : String = line.iter()
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' } )
+            .collect();
+        dump += &format!(" |{}|\n", printable);
+    }
+    if data.len() > max_bytes {
+        dump += &format!("... ({} more bytes)\n", data.len() - max_bytes);
+    }
+    dump
+}
+
+/// Re-indents a JSON object or array, or returns None if the text is not one. Strings are
+/// copied as they are; the structure is only checked for balanced brackets.
+pub fn pretty_json(text : &str) -> Option<String> {
+    let text = text.trim();
+    let delimited = (text.starts_with('{') && text.ends_with('}')) || (text.starts_with('[') && text.ends_with(']'));
+    if !delimited {
+        return None;
+    }
+    let mut out = String::new();
+    let mut open : Vec<char> = Vec::new();
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut chars = text.chars().peekable();
+    let newline = |out : &mut String, depth : usize| {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    };
+    while let Some(c) = chars.next() {
+        if in_str {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_str = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_str = true;
+                out.push(c);
+            },
+            '{' | '[' => {
+                open.push(c);
+                out.push(c);
+                while chars.peek().map(|c| c.is_whitespace() ).unwrap_or(false) {
+                    chars.next();
+                }
+                // Empty objects and arrays are kept in a single line
+                match (c, chars.peek()) {
+                    ('{', Some('}')) | ('[', Some(']')) => { },
+                    _ => newline(&mut out, open.len())
+                }
+            },
+            '}' | ']' => {
+                let expected = match c { '}' => '{', _ => '[' };
+                if open.pop() != Some(expected) {
+                    return None;
+                }
+                if !out.ends_with('{') && !out.ends_with('[') {
+                    newline(&mut out, open.len());
+                }
+                out.push(c);
+            },
+            ',' => {
+                out.push(c);
+                newline(&mut out, open.len());
+            },
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => { },
+            c => out.push(c)
+        }
+    }
+    if open.is_empty() && !in_str {
+        Some(out)
+    } else {
+        None
+    }
+}
+
+/// Re-indents an XML document, or returns None if the text does not parse as one.
+pub fn pretty_xml(text : &str) -> Option<String> {
+    if !text.trim_start().starts_with('<') {
+        return None;
+    }
+    let doc = Parser::default().parse_string(text).ok()?;
+    let mut opts : SaveOptions = Default::default();
+    opts.format = true;
+    opts.non_significant_whitespace = true;
+    Some(doc.to_string_with_options(opts))
+}
+
+pub fn render_text(text : &str) -> Rendering {
+    if let Some(json) = pretty_json(text) {
+        Rendering::Json(json)
+    } else if let Some(xml) = pretty_xml(text) {
+        Rendering::Xml(xml)
+    } else {
+        Rendering::Text(text.to_string())
+    }
+}
+
+/// Images are shown as such, binary data which is valid UTF-8 as text, and anything else as a hex dump.
+pub fn render_bytes(data : &[u8], max_bytes : usize) -> Rendering {
+    if let Some(fmt) = image_format(data) {
+        Rendering::Image(fmt)
+    } else {
+        match std::str::from_utf8(data) {
+            Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace() ) => render_text(text),
+            _ => Rendering::Hex(hex_dump(data, max_bytes))
+        }
+    }
+}
+
+#[test]
+fn render_values() {
+    assert_eq!(image_format(b"\x89PNG\r\n\x1a\n\0\0"), Some(ImageFormat::Png));
+    assert_eq!(image_format(b"  <?xml version=\"1.0\"?>\n<svg width=\"2\"/>"), Some(ImageFormat::Svg));
+    assert_eq!(image_format(b"<html/>"), None);
+    let dump = hex_dump(&[0x41, 0x00, 0xff], 16);
+    assert!(dump.starts_with("00000000  41 00 ff "));
+    assert!(dump.trim_end().ends_with("|A..|"));
+    assert_eq!(hex_dump(&[0; 40], 16).lines().count(), 2);
+    assert_eq!(
+        pretty_json("{\"a\":[1, 2],\"b\":{}, \"c\":\"x, {y}\"}").unwrap(),
+        "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {},\n  \"c\": \"x, {y}\"\n}"
+    );
+    assert!(pretty_json("{\"a\":[1}").is_none());
+    assert!(pretty_json("plain").is_none());
+    assert_eq!(render_bytes(&[0x00, 0x01], 16), Rendering::Hex(hex_dump(&[0x00, 0x01], 16)));
+    assert_eq!(render_bytes(b"[]", 16), Rendering::Json(String::from("[]")));
+}
@@ -0,0 +1,227 @@
+use super::table::*;
+use super::column::*;
+use std::collections::{HashMap, HashSet};
+
+/// How rows of two versions of the same query output are matched against each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffKey {
+
+    /// Rows are identified by their full content. A row that changed at any field
+    /// shows up as a deleted row followed by an inserted row.
+    Row,
+
+    /// Rows are identified by the value at the named column, which allow
+    /// reporting changed cells for rows that preserved their key.
+    Column(String)
+}
+
+impl Default for DiffKey {
+    fn default() -> Self {
+        DiffKey::Row
+    }
+}
+
+/// Display status of a single cell of the most recent table, with respect to its previous version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellStatus {
+    Unchanged,
+    Inserted,
+    Changed
+}
+
+/// Row-level difference between two versions of a table with the same column names.
+/// Row indices refer to the data rows (header excluded) of the most recent table.
+#[derive(Debug, Clone)]
+pub struct TableDiff {
+    pub names : Vec<String>,
+
+    /// Rows of the new table not present at the old table.
+    pub inserted : HashSet<usize>,
+
+    /// Rows of the new table which preserved their key but had some field changed,
+    /// with the indices of the changed columns.
+    pub changed : HashMap<usize, HashSet<usize>>,
+
+    /// Rows of the old table not present at the new table, as text.
+    pub deleted : Vec<Vec<String>>
+}
+
+impl TableDiff {
+
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.changed.is_empty() && self.deleted.is_empty()
+    }
+
+    /// Called for every cell drawn, so both lookups are constant-time.
+    pub fn cell_status(&self, row : usize, col : usize) -> CellStatus {
+        if self.inserted.contains(&row) {
+            return CellStatus::Inserted;
+        }
+        match self.changed.get(&row) {
+            Some(cols) if cols.contains(&col) => CellStatus::Changed,
+            _ => CellStatus::Unchanged
+        }
+    }
+
+    /// Inserted rows, in the order they appear at the new table.
+    pub fn inserted_rows(&self) -> Vec<usize> {
+        let mut rows : Vec<usize> = self.inserted.iter().cloned().collect();
+        rows.sort();
+        rows
+    }
+
+    /// Changed rows, in the order they appear at the new table, with their changed columns.
+    pub fn changed_rows(&self) -> Vec<(usize, Vec<usize>)> {
+        let mut rows : Vec<(usize, Vec<usize>)> = self.changed.iter()
+            .map(|(r, cols)| {
+                let mut cols : Vec<usize> = cols.iter().cloned().collect();
+                cols.sort();
+                (*r, cols)
+            }).collect();
+        rows.sort();
+        rows
+    }
+
+    /// Short description of the diff, such as "2 inserted, 1 changed, 0 deleted".
+    pub fn summary(&self) -> String {
+        format!(
+            "{} inserted, {} changed, {} deleted",
+            self.inserted.len(),
+            self.changed.len(),
+            self.deleted.len()
+        )
+    }
+
+    /// Builds a table with a leading "change" column (insert, update or delete) followed by
+    /// the full content of every row that differs between the two versions, so the diff
+    /// can be exported with the same settings as any other table.
+    pub fn to_table(&self, new : &Table) -> Result<Table, &'static str> {
+        let new_rows = data_rows(new);
+        let mut change = Vec::new();
+        let mut fields : Vec<Vec<String>> = (0..self.names.len()).map(|_| Vec::new() ).collect();
+        let mut push_row = |kind : &str, row : &[String]| {
+            change.push(kind.to_string());
+            for (field, col) in row.iter().zip(fields.iter_mut()) {
+                col.push(field.clone());
+            }
+        };
+        for ix in self.inserted_rows() {
+            push_row("insert", &new_rows[ix][..]);
+        }
+        for (ix, _) in self.changed_rows() {
+            push_row("update", &new_rows[ix][..]);
+        }
+        for row in self.deleted.iter() {
+            push_row("delete", &row[..]);
+        }
+        let mut names = vec![String::from("change")];
+        names.extend(self.names.iter().cloned());
+        let mut cols = vec![Column::Str(change)];
+        cols.extend(fields.drain(..).map(|f| Column::Str(f) ));
+        Table::new(None, names, cols)
+    }
+
+}
+
+fn data_rows(tbl : &Table) -> Vec<Vec<String>> {
+    let mut rows = tbl.text_rows();
+    if rows.len() > 0 {
+        rows.remove(0);
+    }
+    rows
+}
+
+/// Compares two versions of a query output. Fails if the column names differ, since
+/// in this case the query changed in a way that makes a row-level comparison meaningless.
+pub fn diff_tables(old : &Table, new : &Table, key : &DiffKey) -> Result<TableDiff, String> {
+    let names = new.names();
+    if old.names() != names {
+        return Err(String::from("Tables have different columns"));
+    }
+    let old_rows = data_rows(old);
+    let new_rows = data_rows(new);
+    let mut diff = TableDiff {
+        names : names.clone(),
+        inserted : HashSet::new(),
+        changed : HashMap::new(),
+        deleted : Vec::new()
+    };
+    match key {
+        DiffKey::Row => {
+            // Count row multiplicities, so repeated rows are matched one-to-one.
+            let mut old_count : HashMap<&[String], usize> = HashMap::new();
+            for row in old_rows.iter() {
+                *old_count.entry(&row[..]).or_insert(0) += 1;
+            }
+            for (i, row) in new_rows.iter().enumerate() {
+                match old_count.get_mut(&row[..]) {
+                    Some(n) if *n > 0 => { *n -= 1; },
+                    _ => { diff.inserted.insert(i); }
+                }
+            }
+            for row in old_rows.iter() {
+                if let Some(n) = old_count.get_mut(&row[..]) {
+                    if *n > 0 {
+                        *n -= 1;
+                        diff.deleted.push(row.clone());
+                    }
+                }
+            }
+        },
+        DiffKey::Column(key_name) => {
+            let key_ix = names.iter().position(|n| n == key_name)
+                .ok_or(format!("Key column {} not found", key_name))?;
+            let mut old_by_key : HashMap<&str, &Vec<String>> = HashMap::new();
+            for row in old_rows.iter() {
+                if old_by_key.insert(&row[key_ix][..], row).is_some() {
+                    return Err(format!("Key column {} has repeated values", key_name));
+                }
+            }
+            for (i, row) in new_rows.iter().enumerate() {
+                match old_by_key.remove(&row[key_ix][..]) {
+                    Some(old_row) => {
+                        let changed_cols : HashSet<usize> = row.iter().zip(old_row.iter())
+                            .enumerate()
+                            .filter(|(_, (new_f, old_f))| new_f != old_f )
+                            .map(|(j, _)| j )
+                            .collect();
+                        if changed_cols.len() > 0 {
+                            diff.changed.insert(i, changed_cols);
+                        }
+                    },
+                    None => { diff.inserted.insert(i); }
+                }
+            }
+            for row in old_rows.iter() {
+                if old_by_key.contains_key(&row[key_ix][..]) {
+                    diff.deleted.push(row.clone());
+                }
+            }
+        }
+    }
+    Ok(diff)
+}
+
+#[test]
+fn diff_by_key() {
+    let old = Table::new(
+        None,
+        vec![String::from("id"), String::from("val")],
+        vec![Column::I64(vec![1, 2, 3]), Column::Str(vec!["a".into(), "b".into(), "c".into()])]
+    ).unwrap();
+    let new = Table::new(
+        None,
+        vec![String::from("id"), String::from("val")],
+        vec![Column::I64(vec![1, 3, 4]), Column::Str(vec!["a".into(), "x".into(), "d".into()])]
+    ).unwrap();
+    let diff = diff_tables(&old, &new, &DiffKey::Column(String::from("id"))).unwrap();
+    assert_eq!(diff.inserted_rows(), vec![2]);
+    assert_eq!(diff.changed_rows(), vec![(1, vec![1])]);
+    assert_eq!(diff.cell_status(1, 1), CellStatus::Changed);
+    assert_eq!(diff.cell_status(1, 0), CellStatus::Unchanged);
+    assert_eq!(diff.cell_status(2, 0), CellStatus::Inserted);
+    assert_eq!(diff.deleted, vec![vec![String::from("2"), String::from("b")]]);
+    let diff = diff_tables(&old, &new, &DiffKey::Row).unwrap();
+    assert_eq!(diff.inserted_rows(), vec![1, 2]);
+    assert_eq!(diff.deleted.len(), 2);
+}
@@ -0,0 +1,487 @@
+use super::table::Table;
+use super::split::Dialect;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Column of a database table, as informed by the engine catalog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableColumn {
+    pub name : String,
+
+    /// Declared type, as named by the engine (e.g. integer, character varying).
+    pub sql_type : String,
+
+    /// Whether the column is part of the primary key.
+    pub key : bool
+}
+
+/// Value written to a cell (or bound to a statement parameter).
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditValue {
+    Null,
+    Text(String),
+
+    /// Content loaded from a file, only written to binary columns.
+    Bytes(Vec<u8>)
+}
+
+impl EditValue {
+
+    /// Value typed at a cell. NULL (the same text used to display nulls) is taken as the null value.
+    pub fn parse(text : &str) -> Self {
+        if text == "NULL" {
+            EditValue::Null
+        } else {
+            EditValue::Text(text.to_string())
+        }
+    }
+
+    pub fn display(&self) -> &str {
+        match self {
+            EditValue::Null => "NULL",
+            EditValue::Text(t) => &t[..],
+            EditValue::Bytes(_) => "(Binary)"
+        }
+    }
+
+}
+
+/// Column of a query result which reads from the edited table.
+#[derive(Debug, Clone)]
+pub struct EditColumn {
+    pub name : String,
+    pub sql_type : String,
+
+    /// Whether the column identifies the rows (primary key columns, or the SQLite rowid
+    /// for tables without a primary key).
+    pub key : bool,
+
+    /// Whether the fetched values can be compared with the stored values exactly, so the
+    /// column is used to detect rows changed since they were fetched.
+    pub comparable : bool,
+
+    /// Whether the column can be edited as text.
+    pub editable : bool,
+
+    /// Whether the column holds binary data (bytea or blob), which is
+    /// only written from the contents of files.
+    pub binary : bool
+}
+
+/// Table edited through the result of a query, with the result columns mapped to the table columns.
+#[derive(Debug, Clone)]
+pub struct EditTarget {
+
+    /// Table name, as written at the query.
+    pub table : String,
+
+    /// One entry per result column.
+    pub columns : Vec<EditColumn>,
+    pub dialect : Dialect
+}
+
+/// Types read by the PostgreSQL engine into typed columns, which are written back exactly.
+const PG_EXACT_TYPES : [&'static str; 12] = [
+    "boolean",
+    "smallint",
+    "integer",
+    "bigint",
+    "real",
+    "double precision",
+    "numeric",
+    "text",
+    "character varying",
+    "timestamp without time zone",
+    "date",
+    "time without time zone"
+];
+
+fn is_rowid(name : &str) -> bool {
+    match &name.to_lowercase()[..] {
+        "rowid" | "oid" | "_rowid_" => true,
+        _ => false
+    }
+}
+
+pub(crate) fn quote_identifier(name : &str) -> String {
+    if is_rowid(name) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+impl EditTarget {
+
+    /// Maps the result columns (names) to the columns of the table. Fails if any result column
+    /// is not a table column, or if the rows returned cannot be identified.
+    pub fn new(table : &str, dialect : Dialect, names : &[String], table_cols : &[TableColumn]) -> Result<Self, String> {
+        if table_cols.is_empty() {
+            return Err(format!("Table {} not found", table));
+        }
+        let mut columns = Vec::new();
+        for name in names.iter() {
+            if let Some(tbl_col) = table_cols.iter().find(|c| &c.name == name ) {
+                let ty = tbl_col.sql_type.to_lowercase();
+                let (comparable, editable, binary) = match dialect {
+                    Dialect::Postgres => {
+                        let exact = PG_EXACT_TYPES.iter().any(|t| *t == &ty[..] );
+                        (exact, exact, ty == "bytea")
+                    },
+                    Dialect::Sqlite => (!ty.is_empty() && !ty.contains("blob"), !ty.contains("blob"), ty.contains("blob"))
+                };
+                columns.push(EditColumn {
+                    name : name.clone(),
+                    sql_type : tbl_col.sql_type.clone(),
+                    key : tbl_col.key,
+                    comparable,
+                    editable,
+                    binary
+                });
+            } else if dialect == Dialect::Sqlite && is_rowid(name) {
+                columns.push(EditColumn {
+                    name : name.clone(),
+                    sql_type : String::from("integer"),
+                    key : false,
+                    comparable : true,
+                    editable : false,
+                    binary : false
+                });
+            } else {
+                return Err(format!(
+                    "{} is not a column of {} (only queries returning table columns, without aliases or expressions, can be edited)",
+                    name,
+                    table
+                ));
+            }
+        }
+        let pk : Vec<&TableColumn> = table_cols.iter().filter(|c| c.key ).collect();
+        if pk.len() > 0 {
+            if let Some(missing) = pk.iter().find(|c| !names.iter().any(|n| n == &c.name ) ) {
+                return Err(format!("The query must return the primary key column {} to edit {}", missing.name, table));
+            }
+        } else {
+            match (dialect, columns.iter_mut().find(|c| is_rowid(&c.name) )) {
+                (Dialect::Sqlite, Some(rowid)) => {
+                    rowid.key = true;
+                },
+                (Dialect::Sqlite, None) => {
+                    return Err(format!("{} has no primary key: return its rowid (e.g. select rowid, * from {}) to edit it", table, table));
+                },
+                (Dialect::Postgres, _) => {
+                    return Err(format!("{} has no primary key", table));
+                }
+            }
+        }
+        Ok(Self { table : table.to_string(), columns, dialect })
+    }
+
+    /// Condition matching the row of the table as it was fetched: its key, and the values of
+    /// all comparable columns. A row changed (or removed) since it was fetched is not matched.
+    fn row_condition(&self, tbl : &Table, row : usize, params : &mut Params) -> Result<String, String> {
+        let mut conds = Vec::new();
+        for (c, col) in self.columns.iter().enumerate() {
+            if !col.key && !col.comparable {
+                continue;
+            }
+            let column = tbl.get_column(c).ok_or(format!("Missing column {}", col.name))?;
+            let name = quote_identifier(&col.name);
+            match column.exact_value(row) {
+                Some(value) if !column.is_null(row) => {
+                    conds.push(format!("{} = {}", name, params.push(EditValue::Text(value), &col.sql_type)));
+                },
+                _ if col.key => {
+                    return Err(format!("Row {} has no value for the key column {}", row + 1, col.name));
+                },
+                _ => {
+                    conds.push(format!("{} IS NULL", name));
+                }
+            }
+        }
+        Ok(conds.join(" AND "))
+    }
+
+    /// Verifies the value can be written to the column: text to editable columns,
+    /// binary data to binary columns and nulls to either.
+    fn check_value(&self, col : usize, value : &EditValue) -> Result<&EditColumn, String> {
+        match (self.columns.get(col), value) {
+            (Some(c), EditValue::Null) if c.editable || c.binary => Ok(c),
+            (Some(c), EditValue::Text(_)) if c.editable => Ok(c),
+            (Some(c), EditValue::Bytes(_)) if c.binary => Ok(c),
+            (Some(c), _) => Err(format!("Column {} cannot be edited with this value", c.name)),
+            (None, _) => Err(format!("Invalid column index: {}", col))
+        }
+    }
+
+}
+
+/// Parameters of a statement, bound as text (except binary data). PostgreSQL placeholders
+/// are cast to the column type, since the server does not convert text
+/// parameters implicitly; SQLite converts them by the column affinity.
+pub(crate) struct Params {
+    dialect : Dialect,
+    pub(crate) values : Vec<EditValue>
+}
+
+impl Params {
+
+    pub(crate) fn new(dialect : Dialect) -> Self {
+        Self { dialect, values : Vec::new() }
+    }
+
+    pub(crate) fn push(&mut self, value : EditValue, sql_type : &str) -> String {
+        let binary = match value {
+            EditValue::Bytes(_) => true,
+            _ => false
+        };
+        self.values.push(value);
+        let n = self.values.len();
+        match self.dialect {
+            Dialect::Postgres if binary => format!("${}::bytea", n),
+            Dialect::Postgres => format!("${}::text::{}", n, sql_type),
+            Dialect::Sqlite => format!("?{}", n)
+        }
+    }
+
+}
+
+/// Parameterised statement which applies part of an edit set.
+#[derive(Debug, Clone)]
+pub struct EditStatement {
+    pub sql : String,
+
+    pub params : Vec<EditValue>,
+
+    /// Whether the statement must affect exactly one row. Updates and deletes affecting no rows
+    /// mean the row was changed (or removed) since it was fetched.
+    pub check_row : bool,
+
+    /// What the statement does, to be reported on conflicts.
+    pub description : String
+}
+
+/// Changes made to a query result, not yet applied to the database. Rows are
+/// identified by their index at the result table; inserted rows by their position
+/// among the inserted rows.
+#[derive(Debug, Clone, Default)]
+pub struct EditSet {
+    updates : BTreeMap<(usize, usize), EditValue>,
+
+    /// Values of inserted rows. Columns left as None take their default values.
+    inserted : Vec<Vec<Option<EditValue>>>,
+    deleted : BTreeSet<usize>
+}
+
+impl EditSet {
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty() && self.inserted.is_empty() && self.deleted.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        let updated : BTreeSet<usize> = self.updates.keys()
+            .map(|(r, _)| *r )
+            .filter(|r| !self.deleted.contains(r) )
+            .collect();
+        format!(
+            "{} row(s) updated, {} inserted, {} deleted",
+            updated.len(),
+            self.inserted.len(),
+            self.deleted.len()
+        )
+    }
+
+    pub fn set_value(&mut self, row : usize, col : usize, value : EditValue) {
+        self.updates.insert((row, col), value);
+    }
+
+    /// Discards the edit of a cell, if any.
+    pub fn unset_value(&mut self, row : usize, col : usize) {
+        self.updates.remove(&(row, col));
+    }
+
+    pub fn value(&self, row : usize, col : usize) -> Option<&EditValue> {
+        self.updates.get(&(row, col))
+    }
+
+    /// Adds a row with all values unset, returning its position.
+    pub fn insert_row(&mut self, ncols : usize) -> usize {
+        self.inserted.push(vec![None; ncols]);
+        self.inserted.len() - 1
+    }
+
+    pub fn n_inserted(&self) -> usize {
+        self.inserted.len()
+    }
+
+    pub fn set_inserted_value(&mut self, ix : usize, col : usize, value : EditValue) {
+        if let Some(v) = self.inserted.get_mut(ix).and_then(|row| row.get_mut(col) ) {
+            *v = Some(value);
+        }
+    }
+
+    pub fn inserted_value(&self, ix : usize, col : usize) -> Option<&EditValue> {
+        self.inserted.get(ix)?.get(col)?.as_ref()
+    }
+
+    pub fn remove_inserted(&mut self, ix : usize) {
+        if ix < self.inserted.len() {
+            self.inserted.remove(ix);
+        }
+    }
+
+    /// Marks the row to be deleted, or unmarks it if it was already marked.
+    pub fn switch_deleted(&mut self, row : usize) {
+        if !self.deleted.remove(&row) {
+            self.deleted.insert(row);
+        }
+    }
+
+    pub fn is_deleted(&self, row : usize) -> bool {
+        self.deleted.contains(&row)
+    }
+
+    /// Statements applying the edits to the target table, which tbl (the table fetched by
+    /// the query) was read from: updates, then deletes, then inserts. Updates of deleted
+    /// rows are discarded.
+    pub fn statements(&self, target : &EditTarget, tbl : &Table) -> Result<Vec<EditStatement>, String> {
+        let mut stmts = Vec::new();
+        let mut rows : BTreeMap<usize, Vec<(usize, &EditValue)>> = BTreeMap::new();
+        for ((row, col), value) in self.updates.iter() {
+            if !self.deleted.contains(row) {
+                rows.entry(*row).or_insert(Vec::new()).push((*col, value));
+            }
+        }
+        for (row, changes) in rows {
+            let mut params = Params::new(target.dialect);
+            let mut sets = Vec::new();
+            for (col, value) in changes {
+                let col = target.check_value(col, value)?;
+                let p = params.push(value.clone(), &col.sql_type);
+                sets.push(format!("{} = {}", quote_identifier(&col.name), p));
+            }
+            let cond = target.row_condition(tbl, row, &mut params)?;
+            stmts.push(EditStatement {
+                sql : format!("UPDATE {} SET {} WHERE {}", target.table, sets.join(", "), cond),
+                params : params.values,
+                check_row : true,
+                description : format!("Update of row {}", row + 1)
+            });
+        }
+        for row in self.deleted.iter() {
+            let mut params = Params::new(target.dialect);
+            let cond = target.row_condition(tbl, *row, &mut params)?;
+            stmts.push(EditStatement {
+                sql : format!("DELETE FROM {} WHERE {}", target.table, cond),
+                params : params.values,
+                check_row : true,
+                description : format!("Delete of row {}", row + 1)
+            });
+        }
+        for (i, values) in self.inserted.iter().enumerate() {
+            let mut params = Params::new(target.dialect);
+            let mut names = Vec::new();
+            let mut placeholders = Vec::new();
+            for (col, value) in values.iter().enumerate() {
+                if let Some(value) = value {
+                    let col = target.check_value(col, value)?;
+                    names.push(quote_identifier(&col.name));
+                    placeholders.push(params.push(value.clone(), &col.sql_type));
+                }
+            }
+            let sql = if names.is_empty() {
+                format!("INSERT INTO {} DEFAULT VALUES", target.table)
+            } else {
+                format!("INSERT INTO {} ({}) VALUES ({})", target.table, names.join(", "), placeholders.join(", "))
+            };
+            stmts.push(EditStatement {
+                sql,
+                params : params.values,
+                check_row : false,
+                description : format!("Insert of new row {}", i + 1)
+            });
+        }
+        Ok(stmts)
+    }
+
+}
+
+/// Statements as shown for review, with the parameters of each one listed after it.
+pub fn preview(stmts : &[EditStatement], dialect : Dialect) -> String {
+    let prefix = match dialect {
+        Dialect::Postgres => "$",
+        Dialect::Sqlite => "?"
+    };
+    let mut text = String::new();
+    for stmt in stmts.iter() {
+        text += &format!("{};\n", stmt.sql);
+        if stmt.params.len() > 0 {
+            let params : Vec<String> = stmt.params.iter().enumerate().map(|(i, p)| {
+                match p {
+                    EditValue::Text(p) => format!("{}{} = '{}'", prefix, i + 1, p.replace('\'', "''")),
+                    EditValue::Bytes(b) => format!("{}{} = ({} bytes)", prefix, i + 1, b.len()),
+                    EditValue::Null => format!("{}{} = NULL", prefix, i + 1)
+                }
+            }).collect();
+            text += &format!("-- {}\n", params.join(", "));
+        }
+    }
+    text
+}
+
+#[test]
+fn edit_statements() {
+    use super::column::Column;
+    use super::nullable_column::NullableColumn;
+    let tbl = Table::new(
+        None,
+        vec![String::from("id"), String::from("name"), String::from("price")],
+        vec![
+            Column::I64(vec![1, 2]),
+            Column::Str(vec![String::from("Pencil"), String::from("Mug")]),
+            NullableColumn::from(vec![Some(1.5), None]).to_column()
+        ]
+    ).unwrap();
+    let table_cols = vec![
+        TableColumn { name : String::from("id"), sql_type : String::from("integer"), key : true },
+        TableColumn { name : String::from("name"), sql_type : String::from("text"), key : false },
+        TableColumn { name : String::from("price"), sql_type : String::from("double precision"), key : false },
+        TableColumn { name : String::from("photo"), sql_type : String::from("bytea"), key : false }
+    ];
+    let target = EditTarget::new("public.items", Dialect::Postgres, &tbl.names(), &table_cols).unwrap();
+    let mut edits = EditSet::new();
+    edits.set_value(0, 1, EditValue::parse("Pen"));
+    edits.set_value(1, 2, EditValue::parse("NULL"));
+    edits.switch_deleted(1);
+    let ix = edits.insert_row(3);
+    edits.set_inserted_value(ix, 1, EditValue::parse("Cup"));
+    assert_eq!(edits.summary(), "1 row(s) updated, 1 inserted, 1 deleted");
+    let stmts = edits.statements(&target, &tbl).unwrap();
+    assert_eq!(stmts.len(), 3);
+    assert_eq!(
+        stmts[0].sql,
+        "UPDATE public.items SET \"name\" = $1::text::text WHERE \"id\" = $2::text::integer AND \
+        \"name\" = $3::text::text AND \"price\" = $4::text::double precision"
+    );
+    assert_eq!(stmts[0].params, vec![EditValue::parse("Pen"), EditValue::parse("1"), EditValue::parse("Pencil"), EditValue::parse("1.5")]);
+    assert_eq!(stmts[1].sql, "DELETE FROM public.items WHERE \"id\" = $1::text::integer AND \"name\" = $2::text::text AND \"price\" IS NULL");
+    assert_eq!(stmts[2].sql, "INSERT INTO public.items (\"name\") VALUES ($1::text::text)");
+    assert!(stmts[0].check_row && !stmts[2].check_row);
+    let mut binary = EditSet::new();
+    binary.set_value(0, 1, EditValue::Bytes(vec![0x00, 0x01]));
+    assert!(binary.statements(&target, &tbl).is_err());
+    assert_eq!(preview(&stmts[2..], Dialect::Postgres), "INSERT INTO public.items (\"name\") VALUES ($1::text::text);\n-- $1 = 'Cup'\n");
+
+    let sqlite_cols : Vec<TableColumn> = table_cols.iter()
+        .map(|c| TableColumn { key : false, ..c.clone() })
+        .collect();
+    assert!(EditTarget::new("items", Dialect::Sqlite, &tbl.names(), &sqlite_cols).is_err());
+    let names = vec![String::from("rowid"), String::from("name")];
+    let target = EditTarget::new("items", Dialect::Sqlite, &names, &sqlite_cols).unwrap();
+    assert!(target.columns[0].key && !target.columns[0].editable);
+    let names = vec![String::from("id"), String::from("total")];
+    assert!(EditTarget::new("items", Dialect::Sqlite, &names, &sqlite_cols).is_err());
+}
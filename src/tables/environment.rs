@@ -12,7 +12,17 @@ use std::str::FromStr;
 use std::cmp::{Eq, PartialEq};
 use std::hash::Hash;
 use std::fmt;
-use super::postgre;
+use super::upload::UploadMode;
+use super::diff::{self, DiffKey, TableDiff};
+use super::limits::QueryLimits;
+use super::safety::SafetyMode;
+use super::split::{self, Dialect};
+use super::edit::{EditTarget, EditStatement};
+use super::macros::{self, Variables, Expansion, Commands};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::collections::HashMap;
+use std::mem;
 
 #[cfg(feature="arrowext")]
 use datafusion::execution::context::ExecutionContext;
@@ -37,6 +47,88 @@ pub enum EnvironmentUpdate {
 
 }
 
+/// Error reported for the last query sequence, located within the sequence text.
+#[derive(Debug, Clone)]
+pub struct ErrorLocation {
+
+    /// Query sequence, as sent by the user.
+    pub sql : String,
+
+    /// Byte position (start and end) of the offending span within the sequence.
+    pub span : (usize, usize),
+
+    pub message : String,
+
+    pub detail : ErrorDetail
+}
+
+/// Outcome of a single statement of the last query sequence.
+#[derive(Debug, Clone)]
+pub struct StatementOutcome {
+
+    /// Byte position (start and end) of the statement within the sequence. For statements
+    /// read from included files, the position of the include line.
+    pub span : (usize, usize),
+
+    pub failed : bool,
+
+    /// Message informed by the engine (empty for statements which returned rows).
+    pub message : String,
+
+    /// Execution time in milliseconds, if it was measured.
+    pub duration : Option<i64>,
+
+    /// Number of rows returned, for statements which returned rows.
+    pub nrows : Option<usize>,
+
+    /// Index of the table returned by the statement, if it is still held by the environment
+    /// (tables are discarded when a later statement of the same sequence fails or does not return rows).
+    pub table : Option<usize>
+}
+
+/// Where a statement of the last query sequence was written.
+enum StatementSource {
+
+    /// Byte position of the statement within the sequence written by the user.
+    Editor((usize, usize)),
+
+    /// Statement read from an included file, starting at line (counted from zero) of file.
+    /// span is the byte position of the include line within the sequence written by the user.
+    Included { span : (usize, usize), file : PathBuf, line : usize }
+}
+
+/// Byte position (start and end) of the text of the line (counted from zero), without surrounding whitespace.
+fn line_span(sql : &str, line : usize) -> Option<(usize, usize)> {
+    let start = sql.split('\n').take(line).map(|l| l.len() + 1 ).sum::<usize>();
+    let text = sql.get(start..)?.split('\n').next()?;
+    let offset = text.len() - text.trim_start().len();
+    Some((start + offset, start + text.trim_end().len()))
+}
+
+/// Index of the statement (among the spans of the expanded sequence) which yielded the failed result
+/// at result_ix. If each statement yielded a single result, the indices match; otherwise the statement
+/// informed by the engine is matched by its text, choosing the match closest to the result index.
+fn failed_statement(
+    expanded : &str,
+    spans : &[(usize, usize)],
+    result_ix : usize,
+    n_results : usize,
+    detail : Option<&ErrorDetail>
+) -> Option<usize> {
+    if spans.len() == n_results {
+        return Some(result_ix);
+    }
+    let detail = detail?;
+    let normalize = |s : &str| -> String {
+        s.trim().trim_end_matches(';').chars().filter(|c| !c.is_whitespace() ).flat_map(|c| c.to_lowercase() ).collect()
+    };
+    let sent = normalize(&detail.statement);
+    spans.iter().enumerate()
+        .filter(|(_, (start, end))| normalize(&expanded[*start..*end]) == sent )
+        .min_by_key(|(ix, _)| (*ix as i64 - result_ix as i64).abs() )
+        .map(|(ix, _)| ix )
+}
+
 pub struct TableEnvironment {
     source : EnvironmentSource,
     listener : SqlListener,
@@ -48,12 +140,97 @@ pub struct TableEnvironment {
     /// Stores queries which returned successfully.
     queries : Vec<String>,
 
+    /// Index of each query of self.queries within the executed sequence, which tells apart
+    /// repeated executions of the same query.
+    statements : Vec<usize>,
+
     /// Stores message results of non-select statements that returned successfully.
     exec_results : Vec<QueryResult>,
 
     last_update : Option<String>,
     history : Vec<EnvironmentUpdate>,
-    loader : Arc<Mutex<FunctionLoader>>
+    loader : Arc<Mutex<FunctionLoader>>,
+
+    /// Row-level differences of each table with respect to the table returned the last
+    /// time the same query was executed. 1:1 correspondence with self.tables.
+    diffs : Vec<Option<TableDiff>>,
+
+    /// How rows are matched when comparing successive outputs of each query (keyed by statement
+    /// index and query text).
+    diff_keys : HashMap<(usize, String), DiffKey>,
+
+    /// Name of the connection currently bound to the listener. Empty if no connection is open.
+    conn_name : String,
+
+    /// Other open connections, which can be swapped with the current one.
+    sessions : Vec<Session>,
+
+    /// First error reported for the last query sequence, if any.
+    last_error : Option<ErrorLocation>,
+
+    /// Last query sequence, with the outcome of each of its statements.
+    last_outcomes : Option<(String, Vec<StatementOutcome>)>,
+
+    /// Client-side variables, substituted into query sequences sent with send_expanded.
+    variables : Variables,
+
+    /// Last query sequence sent with send_expanded, as written (before its macros were expanded).
+    /// Empty if the last sequence was sent by other means.
+    script : String,
+
+    /// Expansion of script, which maps the lines of the sequence sent to the engine back to
+    /// the editor (or to the included files).
+    expansion : Expansion
+}
+
+/// Query sequence with its macros expanded, waiting to be sent by TableEnvironment::send_expanded.
+/// The variables set by the sequence are only kept for the session when it is sent.
+pub struct ExpandedScript {
+    script : String,
+    expansion : Expansion,
+    variables : Variables
+}
+
+impl ExpandedScript {
+
+    /// Query sequence as the engine will receive it.
+    pub fn sql(&self) -> &str {
+        &self.expansion.sql
+    }
+
+}
+
+/// State of a named connection which is open, but is not the one currently bound to the
+/// environment. Each session keeps its own listener (and therefore its own thread and engine),
+/// so the connection stays alive while the user works with another one.
+struct Session {
+    name : String,
+    source : EnvironmentSource,
+    listener : SqlListener,
+    tables : Vec<Table>,
+    queries : Vec<String>,
+    statements : Vec<usize>,
+    exec_results : Vec<QueryResult>,
+    last_update : Option<String>,
+    diffs : Vec<Option<TableDiff>>
+}
+
+impl Session {
+
+    fn empty() -> Self {
+        Self {
+            name : String::new(),
+            source : EnvironmentSource::File("".into(), "".into()),
+            listener : SqlListener::launch(),
+            tables : Vec::new(),
+            queries : Vec::new(),
+            statements : Vec::new(),
+            exec_results : Vec::new(),
+            last_update : None,
+            diffs : Vec::new()
+        }
+    }
+
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -68,6 +245,8 @@ pub enum DBType {
     Text,
     Date,
     Time,
+    Timestamp,
+    TimestampTz,
     Bytes,
     Json,
     Xml,
@@ -80,27 +259,74 @@ impl FromStr for DBType {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // SQLite keeps declared types as written, so case and length modifiers are ignored.
+        let s = s.to_lowercase();
+        let s = s.split('(').next().unwrap_or("").trim();
         match s {
-            "boolean" | "bool" | "BOOL" => Ok(Self::Bool),
-            "bigint" | "bigserial" => Ok(Self::I64),
-            "bit" | "bit varying" | "character" | "character varying" | "text" => Ok(Self::Text),
-            "date" | "DATE" => Ok(Self::Date),
+            "boolean" | "bool" => Ok(Self::Bool),
+            "bigint" | "bigserial" | "int8" => Ok(Self::I64),
+            "bit" | "bit varying" | "character" | "character varying" | "text" |
+            "varchar" | "char" | "clob" => Ok(Self::Text),
+            "date" => Ok(Self::Date),
             "json" | "jsonb" => Ok(Self::Json),
-            "numeric" => Ok(Self::Numeric),
-            "integer" | "int" | "INTEGER" | "INT" => Ok(Self::I32),
-            "smallint" | "smallserial" => Ok(Self::I16),
-            "real" | "REAL" | "double precision" => Ok(Self::F64),
-            "blob" | "BLOB" | "bytea" => Ok(Self::Bytes),
-            "time" | "time with time zone" | "time without time zone" |
-            "timestamp with time zone" | "timestamp without time zone" => Ok(Self::Time),
+            "numeric" | "decimal" => Ok(Self::Numeric),
+            "integer" | "int" | "int4" | "serial" => Ok(Self::I32),
+            "smallint" | "smallserial" | "int2" => Ok(Self::I16),
+            "real" | "double precision" | "double" | "float" | "float8" => Ok(Self::F64),
+            "float4" => Ok(Self::F32),
+            "blob" | "bytea" => Ok(Self::Bytes),
+            "time" | "time with time zone" | "time without time zone" => Ok(Self::Time),
+            "timestamp" | "datetime" | "timestamp without time zone" => Ok(Self::Timestamp),
+            "timestamptz" | "timestamp with time zone" => Ok(Self::TimestampTz),
             "xml" => Ok(Self::Xml),
-            "anyarray" | "array" | "ARRAY" => Ok(Self::Array),
+            "anyarray" | "array" => Ok(Self::Array),
             _ => Ok(Self::Unknown)
         }
     }
 
 }
 
+impl DBType {
+
+    /// Type of a column created to store values of this type at a database of the informed dialect.
+    /// None if the type has no counterpart: arrays and unknown types are then created from the
+    /// values read. SQLite has no date and time types, so times and timestamps are kept as text
+    /// (timestamps with time zone as ISO-8601 text carrying the offset).
+    pub fn sql_type(&self, dialect : Dialect) -> Option<&'static str> {
+        match dialect {
+            Dialect::Postgres => match self {
+                DBType::Bool => Some("boolean"),
+                DBType::I16 => Some("smallint"),
+                DBType::I32 => Some("integer"),
+                DBType::I64 => Some("bigint"),
+                DBType::F32 => Some("real"),
+                DBType::F64 => Some("double precision"),
+                DBType::Numeric => Some("numeric"),
+                DBType::Text => Some("text"),
+                DBType::Date => Some("date"),
+                DBType::Time => Some("time"),
+                DBType::Timestamp => Some("timestamp"),
+                DBType::TimestampTz => Some("timestamptz"),
+                DBType::Bytes => Some("bytea"),
+                DBType::Json => Some("jsonb"),
+                DBType::Xml => Some("xml"),
+                DBType::Array | DBType::Unknown => None
+            },
+            Dialect::Sqlite => match self {
+                DBType::Bool | DBType::I16 | DBType::I32 | DBType::I64 => Some("INTEGER"),
+                DBType::F32 | DBType::F64 => Some("REAL"),
+                DBType::Numeric => Some("NUMERIC"),
+                DBType::Text | DBType::Json | DBType::Xml => Some("TEXT"),
+                DBType::Date => Some("DATE"),
+                DBType::Time | DBType::Timestamp | DBType::TimestampTz => Some("TEXT"),
+                DBType::Bytes => Some("BLOB"),
+                DBType::Array | DBType::Unknown => None
+            }
+        }
+    }
+
+}
+
 #[derive(Debug)]
 pub enum DBObject {
 
@@ -130,9 +356,130 @@ impl TableEnvironment {
             tables : Vec::new(),
             last_update : None,
             queries : Vec::new(),
+            statements : Vec::new(),
             history : vec![EnvironmentUpdate::Clear],
             loader : loader.clone(),
-            exec_results : Vec::new()
+            exec_results : Vec::new(),
+            diffs : Vec::new(),
+            diff_keys : HashMap::new(),
+            conn_name : String::new(),
+            sessions : Vec::new(),
+            last_error : None,
+            last_outcomes : None,
+            variables : Variables::new(),
+            script : String::new(),
+            expansion : Expansion::default()
+        }
+    }
+
+    /// Exchanges the state of the current connection with the informed session.
+    fn swap_session(&mut self, session : &mut Session) {
+        mem::swap(&mut self.conn_name, &mut session.name);
+        mem::swap(&mut self.source, &mut session.source);
+        mem::swap(&mut self.listener, &mut session.listener);
+        mem::swap(&mut self.tables, &mut session.tables);
+        mem::swap(&mut self.queries, &mut session.queries);
+        mem::swap(&mut self.statements, &mut session.statements);
+        mem::swap(&mut self.exec_results, &mut session.exec_results);
+        mem::swap(&mut self.last_update, &mut session.last_update);
+        mem::swap(&mut self.diffs, &mut session.diffs);
+        self.history.push(EnvironmentUpdate::Clear);
+    }
+
+    fn unique_connection_name(&self, name : &str) -> String {
+        let names = self.connection_names();
+        let mut unique = name.to_string();
+        let mut n = 2;
+        while names.iter().any(|other| other == &unique) {
+            unique = format!("{} ({})", name, n);
+            n += 1;
+        }
+        unique
+    }
+
+    /// Opens a new named connection. If there is an active connection, it is kept open
+    /// at the background and can be recovered with switch_connection. Returns the name
+    /// actually assigned to the connection, which receives a numeric suffix if the informed
+    /// name is already in use.
+    pub fn open_connection(&mut self, name : &str, src : EnvironmentSource) -> Result<String, String> {
+        let name = self.unique_connection_name(name);
+        if self.is_engine_active() {
+            let mut session = Session::empty();
+            self.swap_session(&mut session);
+            if let Err(e) = self.update_source(src, true) {
+                self.swap_session(&mut session);
+                return Err(e);
+            }
+            self.sessions.push(session);
+        } else {
+            self.update_source(src, true)?;
+        }
+        self.conn_name = name.clone();
+        Ok(name)
+    }
+
+    /// Makes the named connection the current one, keeping the previous one open.
+    pub fn switch_connection(&mut self, name : &str) -> Result<(), String> {
+        if name == &self.conn_name[..] {
+            return Ok(());
+        }
+        let ix = self.sessions.iter().position(|s| &s.name[..] == name )
+            .ok_or(format!("No open connection named {}", name))?;
+        let mut session = self.sessions.remove(ix);
+        self.swap_session(&mut session);
+        self.sessions.insert(ix, session);
+        Ok(())
+    }
+
+    /// Closes the current connection. If other connections are open, the most recently
+    /// opened one becomes the current one and its name is returned.
+    pub fn close_connection(&mut self) -> Option<String> {
+        self.disable_engine();
+        match self.sessions.pop() {
+            Some(mut session) => {
+                // The closed connection is left at the session, which is dropped here
+                // together with its listener.
+                self.swap_session(&mut session);
+                Some(self.conn_name.clone())
+            },
+            None => {
+                self.conn_name.clear();
+                None
+            }
+        }
+    }
+
+    /// Names of all open connections, in alphabetical order.
+    pub fn connection_names(&self) -> Vec<String> {
+        let mut names : Vec<String> = self.sessions.iter().map(|s| s.name.clone() ).collect();
+        if !self.conn_name.is_empty() {
+            names.push(self.conn_name.clone());
+        }
+        names.sort();
+        names
+    }
+
+    /// Engine of the named open connection (the current one or one kept at the background), used
+    /// to work with several connections at once. If write is true, fails for read-only connections.
+    pub fn connection_engine(&self, name : &str, write : bool) -> Result<(Arc<Mutex<SqlEngine>>, SafetyMode), String> {
+        let listener = if name == &self.conn_name[..] {
+            &self.listener
+        } else {
+            &self.sessions.iter().find(|s| &s.name[..] == name )
+                .ok_or(format!("No open connection named {}", name))?
+                .listener
+        };
+        if write && listener.safety.read_only {
+            return Err(format!("Connection {} is read-only", name));
+        }
+        Ok((listener.engine.clone(), listener.safety))
+    }
+
+    pub fn active_connection(&self) -> Option<String> {
+        if self.conn_name.is_empty() {
+            None
+        } else {
+            Some(self.conn_name.clone())
         }
     }
 
@@ -150,7 +497,7 @@ impl TableEnvironment {
         &mut self,
         path : Option<PathBuf>
     ) -> Result<(), String> {
-        match SqlEngine::try_new_sqlite3(path, &self.loader) {
+        match SqlEngine::try_new_sqlite3(path, &self.loader, self.listener.safety.read_only) {
             Ok(engine) => {
                 self.update_engine(engine)?;
                 Ok(())
@@ -196,7 +543,10 @@ impl TableEnvironment {
         }
     }
 
-    fn update_engine(&mut self, engine : SqlEngine) -> Result<(), String> {
+    fn update_engine(&mut self, mut engine : SqlEngine) -> Result<(), String> {
+        engine.apply_limits(&self.limits())?;
+        engine.apply_safety(&self.listener.safety)?;
+        self.listener.dialect = engine.dialect();
         if let Ok(mut old_engine) = self.listener.engine.lock() {
             *old_engine = engine;
             Ok(())
@@ -205,6 +555,60 @@ impl TableEnvironment {
         }
     }
 
+    pub fn safety(&self) -> SafetyMode {
+        self.listener.safety
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.listener.dialect
+    }
+
+    /// Changes the read-only and production flags of the current connection. Since SQLite3
+    /// files can only be made read-only when opened, changing the read-only flag of a SQLite3
+    /// connection re-opens its file.
+    pub fn set_safety(&mut self, safety : SafetyMode) -> Result<(), String> {
+        let reopen = safety.read_only != self.listener.safety.read_only;
+        self.listener.safety = safety;
+        match self.source.clone() {
+            EnvironmentSource::SQLite3((Some(path), _)) if reopen => {
+                self.set_new_sqlite3_engine(Some(path))
+            },
+            _ => {
+                if let Ok(mut engine) = self.listener.engine.lock() {
+                    engine.apply_safety(&safety)
+                } else {
+                    Err("Error acquiring lock over engine when setting read-only mode".into())
+                }
+            }
+        }
+    }
+
+    /// Limits applied to the queries sent through the current connection.
+    pub fn limits(&self) -> QueryLimits {
+        if let Ok(limits) = self.listener.limits.lock() {
+            limits.clone()
+        } else {
+            println!("Unable to acquire lock over query limits");
+            QueryLimits::default()
+        }
+    }
+
+    /// Changes the limits of the current connection. Limits are kept by the listener,
+    /// so connections opened later start without any limits.
+    pub fn set_limits(&mut self, limits : QueryLimits) -> Result<(), String> {
+        if let Ok(mut engine) = self.listener.engine.lock() {
+            engine.apply_limits(&limits)?;
+        } else {
+            return Err("Error acquiring lock over engine when setting limits".into());
+        }
+        if let Ok(mut old_limits) = self.listener.limits.lock() {
+            *old_limits = limits;
+            Ok(())
+        } else {
+            Err("Error acquiring lock over query limits".into())
+        }
+    }
+
     /*fn on_notify(client : &mut Client, notif : &str) -> Result<(), String> {
         client.execute(&format!("listen {};", notif)[..], &[]).map_err(|e| format!("{}", e) )?;
         loop {
@@ -350,9 +754,9 @@ impl TableEnvironment {
         Ok(&self.tables[(self.tables.len() - n_funcs)..self.tables.len()])
     }*/
 
-    pub fn send_current_query(&mut self, parse : bool) -> Result<(), String> {
-        println!("Sending current query: {:?}", self.source);
-        let query = match self.source {
+    /// Query sequence last prepared for the current source.
+    fn current_query(&self) -> Option<String> {
+        match self.source {
             EnvironmentSource::PostgreSQL(ref db_pair) =>{
                 Some(db_pair.1.clone())
             },
@@ -366,8 +770,12 @@ impl TableEnvironment {
             },
 
             _ => None
-        };
-        if let Some(q) = query {
+        }
+    }
+
+    pub fn send_current_query(&mut self, parse : bool) -> Result<(), String> {
+        println!("Sending current query: {:?}", self.source);
+        if let Some(q) = self.current_query() {
             if q.chars().all(|c| c.is_whitespace() ) {
                 return Err(String::from("Empty query sequence"));
             }
@@ -428,6 +836,8 @@ impl TableEnvironment {
     }
 
     pub fn prepare_query(&mut self, sql : String) {
+        self.script.clear();
+        self.expansion = Expansion::default();
         match self.source {
             EnvironmentSource::PostgreSQL((_, ref mut q)) => {
                 *q = sql;
@@ -451,6 +861,104 @@ impl TableEnvironment {
         self.send_current_query(parse)
     }
 
+    /// Expands a query sequence written by the user at a background thread, so the ${command} macros
+    /// (which run only if commands says so) do not block the interface: its \set lines are applied to a
+    /// copy of the session variables, its includes are inlined (resolved relative to dir) and its macros
+    /// are expanded. Nothing is sent to the engine, so the expanded sequence can be confirmed (as the
+    /// engine will receive it) before being sent by send_expanded.
+    pub fn expand_script(
+        &self,
+        sql : String,
+        dir : Option<PathBuf>,
+        commands : Commands
+    ) -> Receiver<Result<ExpandedScript, String>> {
+        let mut variables = self.variables.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let expanded = macros::expand(&sql, dir.as_ref().map(|d| d.as_path() ), &mut variables, commands)
+                .map(|expansion| ExpandedScript { script : sql.clone(), expansion, variables });
+            if let Err(e) = sender.send(expanded) {
+                println!("{}", e);
+            }
+        });
+        receiver
+    }
+
+    /// Sends a query sequence expanded by expand_script, keeping the variables it has set. Returns
+    /// false if the sequence only set variables (so there were no statements to send).
+    pub fn send_expanded(&mut self, expanded : ExpandedScript) -> Result<bool, String> {
+        self.variables = expanded.variables;
+        let blank = |s : &str| s.chars().all(|c| c.is_whitespace() );
+        if blank(&expanded.expansion.sql) && !blank(&expanded.script) {
+            return Ok(false);
+        }
+        self.prepare_query(expanded.expansion.sql.clone());
+        self.script = expanded.script;
+        self.expansion = expanded.expansion;
+        self.send_current_query(true)?;
+        Ok(true)
+    }
+
+    /// Query sequence as it would be sent by send_expanded, without changing the session variables.
+    /// The ${command} macros are kept as written, so the preview has no side effects.
+    pub fn preview_expansion(&self, sql : &str, dir : Option<&Path>) -> Result<String, String> {
+        let mut variables = self.variables.clone();
+        macros::expand(sql, dir, &mut variables, Commands::Keep).map(|exp| exp.sql )
+    }
+
+    /// Session variables, as (name, value) pairs in alphabetical order.
+    pub fn variables(&self) -> Vec<(String, String)> {
+        self.variables.list()
+    }
+
+    /// Last query sequence as written by the user, with its meta-command lines blanked
+    /// (so its statements match the results of the expanded sequence which was sent).
+    fn last_sequence(&self) -> Option<(String, String)> {
+        let sql = if self.script.is_empty() {
+            self.current_query()?
+        } else {
+            self.script.clone()
+        };
+        let blanked = macros::blank_meta_commands(&sql);
+        Some((sql, blanked))
+    }
+
+    /// Where each statement of the last query sequence was written, or None if the statements
+    /// of the sequence (as sent) cannot be matched to the n_results results.
+    fn statement_sources(&self, sql : &str, blanked : &str, n_results : usize) -> Option<Vec<StatementSource>> {
+        let editor_spans = split::statement_spans(blanked, self.listener.dialect).ok()?;
+        if self.expansion.origins.is_empty() {
+            return if editor_spans.len() == n_results {
+                Some(editor_spans.into_iter().map(StatementSource::Editor).collect())
+            } else {
+                None
+            };
+        }
+        let spans = split::statement_spans(&self.expansion.sql, self.listener.dialect).ok()?;
+        if spans.len() != n_results {
+            return None;
+        }
+        let mut editor_spans = editor_spans.into_iter();
+        let mut sources = Vec::new();
+        for (start, _) in spans {
+            let origin = self.expansion.origin_at(start)?;
+            match &origin.include {
+                None => sources.push(StatementSource::Editor(editor_spans.next()?)),
+                Some((file, line)) => {
+                    sources.push(StatementSource::Included {
+                        span : line_span(sql, origin.line)?,
+                        file : file.clone(),
+                        line : *line
+                    });
+                }
+            }
+        }
+        if editor_spans.next().is_some() {
+            return None;
+        }
+        Some(sources)
+    }
+
     /// Searches update history retroactively, returning the last
     /// original table update column names, if any exist, and
     /// no clear events are present between this last table set
@@ -480,38 +988,74 @@ impl TableEnvironment {
     /// carrying the first error the database encountered. If the update is valid,
     /// return the update event that happened (Refresh or NewTables).
     pub fn maybe_update_from_query_results(&mut self) -> Option<Result<EnvironmentUpdate,String>> {
-        let results = self.listener.maybe_get_result()?;
+        let mut results = self.listener.maybe_get_result()?;
+        self.locate_error(&mut results);
+        self.record_outcomes(&results, true);
         // println!("Query results: {:?}", results);
+
+        // Keep the last output of each query, so the new output can be compared against it. Outputs
+        // are keyed by statement index as well, so a query repeated within the sequence is compared
+        // against its own previous output.
+        let previous : HashMap<(usize, String), Table> = self.statements.drain(..)
+            .zip(self.queries.drain(..))
+            .zip(self.tables.drain(..))
+            .collect();
         self.tables.clear();
+        self.diffs.clear();
         self.queries.clear();
+        self.statements.clear();
         self.exec_results.clear();
         if results.len() == 0 {
+            self.diff_keys.clear();
             self.history.push(EnvironmentUpdate::Clear);
             return Some(Ok(EnvironmentUpdate::Clear));
         }
         let mut new_cols : Vec<Vec<String>> = Vec::new();
         let mut opt_err = None;
         let mut any_valid = false;
-        for r in results {
+        for (stmt_ix, r) in results.into_iter().enumerate() {
             match r {
                 QueryResult::Valid(query, tbl) => {
                     new_cols.push(tbl.names());
+                    let stmt_key = (stmt_ix, query);
+                    let diff = previous.get(&stmt_key).and_then(|old| {
+                        let key = self.diff_keys.get(&stmt_key).cloned().unwrap_or_default();
+                        diff::diff_tables(old, &tbl, &key)
+                            .map_err(|e| println!("Unable to compare table versions: {}", e) )
+                            .ok()
+                    });
+                    self.diffs.push(diff);
                     self.tables.push(tbl);
-                    self.queries.push(query);
+                    self.statements.push(stmt_key.0);
+                    self.queries.push(stmt_key.1);
                     any_valid = true;
                 },
-                QueryResult::Invalid(msg) => {
+                QueryResult::Invalid(msg, _) => {
                     self.tables.clear();
+                    self.diffs.clear();
+                    self.queries.clear();
+                    self.statements.clear();
                     self.history.push(EnvironmentUpdate::Clear);
                     opt_err = Some(msg.clone());
                 },
                 QueryResult::Statement(_) | QueryResult::Modification(_) => {
                     self.tables.clear();
+                    self.diffs.clear();
+                    self.queries.clear();
+                    self.statements.clear();
                     self.exec_results.push(r.clone());
                     self.history.push(EnvironmentUpdate::Clear);
                 }
             }
         }
+
+        // Only the tables shown are compared against their next output, so the previous outputs are
+        // dropped here, and the keys of queries which are not shown anymore are forgotten.
+        drop(previous);
+        let (statements, queries) = (&self.statements, &self.queries);
+        self.diff_keys.retain(|(stmt_ix, query), _| {
+            statements.iter().zip(queries.iter()).any(|(ix, q)| ix == stmt_ix && q == query )
+        });
         if let Some(msg) = opt_err {
             self.history.push(EnvironmentUpdate::Clear);
             Some(Err(msg))
@@ -543,14 +1087,16 @@ impl TableEnvironment {
     }
 
     pub fn maybe_update_from_statement(&mut self) -> Option<Result<String, String>> {
-        let results = self.listener.maybe_get_result()?;
+        let mut results = self.listener.maybe_get_result()?;
+        self.locate_error(&mut results);
+        self.record_outcomes(&results, false);
         self.exec_results.clear();
         for r in results.iter() {
             match r {
                 QueryResult::Statement(_) | QueryResult::Modification(_) => {
                     self.exec_results.push(r.clone());
                 },
-                QueryResult::Invalid(e) => {
+                QueryResult::Invalid(e, _) => {
                     return Some(Err(e.clone()));
                 },
                 _ => { }
@@ -560,7 +1106,7 @@ impl TableEnvironment {
             println!("Last statement: {:?}", r);
             match r {
                 QueryResult::Statement(s) => Some(Ok(s.clone())),
-                QueryResult::Invalid(e) => Some(Err(e.clone())),
+                QueryResult::Invalid(e, _) => Some(Err(e.clone())),
                 QueryResult::Modification(m) => Some(Ok(m.clone())),
                 QueryResult::Valid(_, _) => None,
             }
@@ -569,6 +1115,136 @@ impl TableEnvironment {
         }
     }
 
+    /// Locates the first error of the results within the query sequence. The error is located at the
+    /// expanded sequence (as sent) and its position is translated back to the text written by the user
+    /// through the expansion. Errors of statements read from included files are located at the include
+    /// line, and their message (at the results as well) is prefixed by the file and line of the error.
+    fn locate_error(&mut self, results : &mut [QueryResult]) {
+        self.last_error = None;
+        let failed = results.iter().enumerate().find_map(|(ix, r)| match r {
+            QueryResult::Invalid(msg, detail) => Some((ix, msg.clone(), detail.clone())),
+            _ => None
+        });
+        let (ix, message, detail) = match failed {
+            Some(failed) => failed,
+            None => return
+        };
+        let (sql, blanked) = match self.last_sequence() {
+            Some(seq) => seq,
+            None => return
+        };
+        let expanded = if self.expansion.origins.is_empty() {
+            blanked
+        } else {
+            self.expansion.sql.clone()
+        };
+        let spans = match split::statement_spans(&expanded, self.listener.dialect) {
+            Ok(spans) => spans,
+            Err(_) => return
+        };
+        let stmt_ix = match failed_statement(&expanded, &spans, ix, results.len(), detail.as_ref()) {
+            Some(stmt_ix) => stmt_ix,
+            None => return
+        };
+        let (start, end) = match &detail {
+            Some(detail) => match split::locate_error(&expanded, self.listener.dialect, stmt_ix, detail) {
+                Some(span) => span,
+                None => return
+            },
+            None => spans[stmt_ix]
+        };
+        if self.expansion.origins.is_empty() {
+            // Blanking the meta-commands keeps the byte positions of the text written by the user.
+            if let Some(detail) = detail {
+                self.last_error = Some(ErrorLocation { sql, span : (start, end), message, detail });
+            }
+            return;
+        }
+        let origin = match self.expansion.origin_at(start) {
+            Some(origin) => origin.clone(),
+            None => return
+        };
+        match origin.include {
+            None => {
+                if let (Some(span), Some(detail)) = (self.expansion.original_span(start, end), detail) {
+                    self.last_error = Some(ErrorLocation { sql, span, message, detail });
+                }
+            },
+            Some((file, line)) => {
+                let message = format!("{}, line {}: {}", file.display(), line + 1, message);
+                if let QueryResult::Invalid(ref mut msg, _) = results[ix] {
+                    *msg = message.clone();
+                }
+                if let (Some(span), Some(detail)) = (line_span(&sql, origin.line), detail) {
+                    self.last_error = Some(ErrorLocation { sql, span, message, detail });
+                }
+            }
+        }
+    }
+
+    /// Error reported for the last query sequence, if the engine informed which statement failed.
+    pub fn last_error(&self) -> Option<ErrorLocation> {
+        self.last_error.clone()
+    }
+
+    /// Matches each result to the statement which yielded it. If tables_updated is true,
+    /// the tables returned by the statements replace the environment tables (following the
+    /// same rules as maybe_update_from_query_results), so each outcome records the index of its table.
+    fn record_outcomes(&mut self, results : &[QueryResult], tables_updated : bool) {
+        self.last_outcomes = None;
+        let (sql, blanked) = match self.last_sequence() {
+            Some(seq) => seq,
+            None => return
+        };
+        let sources = match self.statement_sources(&sql, &blanked, results.len()) {
+            Some(sources) => sources,
+            None => return
+        };
+        let durations = self.listener.last_durations();
+        let mut outcomes : Vec<StatementOutcome> = Vec::new();
+        let mut n_tables = 0;
+        for (ix, (r, source)) in results.iter().zip(sources.iter()).enumerate() {
+            let (failed, mut message, nrows) = match r {
+                QueryResult::Valid(_, tbl) => (false, String::new(), Some(tbl.shape().0)),
+                QueryResult::Invalid(msg, _) => (true, msg.clone(), None),
+                QueryResult::Statement(msg) | QueryResult::Modification(msg) => (false, msg.clone(), None)
+            };
+            let table = match r {
+                QueryResult::Valid(_, _) if tables_updated => {
+                    n_tables += 1;
+                    Some(n_tables - 1)
+                },
+                _ => {
+                    for outcome in outcomes.iter_mut() {
+                        outcome.table = None;
+                    }
+                    n_tables = 0;
+                    None
+                }
+            };
+            let duration = durations.get(ix).cloned();
+            let span = match source {
+                StatementSource::Editor(span) => *span,
+                StatementSource::Included { span, file, line } => {
+
+                    // Error messages were already prefixed by locate_error.
+                    if !failed && !message.is_empty() {
+                        message = format!("{}, line {}: {}", file.display(), line + 1, message);
+                    }
+                    *span
+                }
+            };
+            outcomes.push(StatementOutcome { span, failed, message, duration, nrows, table });
+        }
+        self.last_outcomes = Some((sql, outcomes));
+    }
+
+    /// Last query sequence, with the outcome of each of its statements. None if
+    /// the results could not be matched to the statements of the sequence.
+    pub fn last_outcomes(&self) -> Option<(String, Vec<StatementOutcome>)> {
+        self.last_outcomes.clone()
+    }
+
     pub fn any_modification_result(&self) -> bool {
         for res in self.exec_results.iter() {
             match res {
@@ -588,6 +1264,7 @@ impl TableEnvironment {
     ) -> Result<(),String> {
         if clear {
             self.tables.clear();
+            self.diffs.clear();
             self.history.push(EnvironmentUpdate::Clear);
         }
         //println!("{:?}", src );
@@ -597,6 +1274,7 @@ impl TableEnvironment {
             EnvironmentSource::File(path, content) => {
                 //println!("Received source at update_from_source: {}", content);
                 self.tables.clear();
+                self.diffs.clear();
                 let p = Path::new(&path);
                 let _p = p.file_stem().ok_or("Could not extract table name from path".to_string())?;
                 //let _tbl_name = Some(p.to_str().ok_or("Could not convert table path to str".to_string())?.to_string());
@@ -765,6 +1443,41 @@ impl TableEnvironment {
     }
 
 
+    /// Returns the difference between the table at the given index and the output
+    /// of the previous execution of the same query, if the query was executed before.
+    pub fn get_diff(&self, idx : usize) -> Option<&TableDiff> {
+        self.diffs.get(idx).and_then(|d| d.as_ref() )
+    }
+
+    /// Sets how the rows of the query that generated the table at the given index are
+    /// matched against the next output of the same query. The new key is applied
+    /// from the next refresh on.
+    pub fn set_diff_key(&mut self, idx : usize, key : DiffKey) -> Result<(), &'static str> {
+        let query = self.queries.get(idx).ok_or("No table at informed index")?;
+        let stmt_ix = self.statements.get(idx).cloned().ok_or("No table at informed index")?;
+        self.diff_keys.insert((stmt_ix, query.clone()), key);
+        Ok(())
+    }
+
+    /// Gets the textual representation of the diff of the table at the given index,
+    /// formatted with the informed settings.
+    pub fn get_diff_text_at_index(&self, idx : usize, opt_fmt : Option<TableSettings>) -> Option<String> {
+        let tbl = self.tables.get(idx)?;
+        let diff = self.get_diff(idx)?;
+        match diff.to_table(tbl) {
+            Ok(mut diff_tbl) => {
+                if let Some(fmt) = opt_fmt {
+                    diff_tbl.update_format(fmt);
+                }
+                Some(diff_tbl.to_string())
+            },
+            Err(e) => {
+                println!("{}", e);
+                None
+            }
+        }
+    }
+
     pub fn all_tables(&self) -> &[Table] {
         &self.tables[..]
     }
@@ -811,12 +1524,14 @@ impl TableEnvironment {
         tbl : Table
     ) -> Result<(), &'static str> {
         self.tables.push(tbl);
+        self.diffs.push(None);
         self.history.push(EnvironmentUpdate::NewExternal);
         Ok(())
     }
 
     pub fn update_from_current_source(&mut self) {
         self.tables.clear();
+        self.diffs.clear();
         match self.source {
             EnvironmentSource::Stream(ref s) => {
                 if let Some(c) = s.get_last_content() {
@@ -846,23 +1561,42 @@ impl TableEnvironment {
         }
     }
 
-    /// Copies a table in the current environment to the database. Useful
-    /// to call copy from/to from the TablePopover GUI.
-    pub fn copy_to_database(&mut self, tbl_ix : usize, dst : &str, cols : &[String]) -> Result<(), String> {
-        if let Ok(mut engine) = self.listener.engine.lock() {
-            match *engine {
-                SqlEngine::PostgreSql{ ref mut conn, .. } => {
-                    if let Some(tbl) = self.tables.get_mut(tbl_ix) {
-                        postgre::copy_table_to_postgres(conn, tbl, dst, cols)
-                    } else {
-                        Err(String::from("Invalid index"))
-                    }
-                },
-                _ => unimplemented!()
-            }
-        } else {
-            Err(String::from("Engine unavailable for copy"))
+    /// Statements which copy a table in the current environment to a table of the database. Used by
+    /// the copy from box of the TablePopover GUI, which applies them with apply_edits after they are
+    /// confirmed (at production connections).
+    pub fn copy_statements(&self, tbl_ix : usize, dst : &str, mode : UploadMode) -> Result<Vec<EditStatement>, String> {
+        if self.listener.safety.read_only {
+            return Err(String::from("Connection is read-only"));
+        }
+        let tbl = self.tables.get(tbl_ix).ok_or(String::from("Invalid index"))?;
+        let mut engine = self.listener.engine.try_lock()
+            .map_err(|_| String::from("The connection is busy (wait for the running query to finish)") )?;
+        engine.upload_statements(tbl, dst, mode)
+    }
+
+    /// Maps the table at the informed index to the database table its query read from,
+    /// failing if the query read from more than one table, or if its rows cannot be identified.
+    pub fn edit_target(&self, tbl_ix : usize) -> Result<EditTarget, String> {
+        if self.listener.safety.read_only {
+            return Err(String::from("Connection is read-only"));
         }
+        let tbl = self.tables.get(tbl_ix).ok_or(String::from("Invalid index"))?;
+        let name = match tbl.table_info() {
+            (Some(name), None) if !name.is_empty() && !name.contains(" : ") => name,
+            _ => return Err(String::from("Only queries reading from a single table can be edited"))
+        };
+        let table_cols = self.listener.engine.try_lock()
+            .map_err(|_| String::from("The connection is busy (wait for the running query to finish)") )?
+            .table_columns(&name)?;
+        EditTarget::new(&name, self.listener.dialect, &tbl.names()[..], &table_cols[..])
+    }
+
+    /// Applies statements generated from table edits in a single transaction. Fails
+    /// (instead of blocking the interface) if the engine is running another query.
+    pub fn apply_edits(&mut self, stmts : &[EditStatement]) -> Result<usize, String> {
+        let mut engine = self.listener.engine.try_lock()
+            .map_err(|_| String::from("The connection is busy (wait for the running query to finish)") )?;
+        engine.apply_edits(stmts)
     }
 
     pub fn remove_udfs(&self, lib_name : &str) {
@@ -875,6 +1609,7 @@ impl TableEnvironment {
 
     pub fn clear(&mut self) {
         self.tables.clear();
+        self.diffs.clear();
     }
 
     // Pass this to environment source
@@ -921,6 +1656,28 @@ impl TableEnvironment {
         }
     }
 
+    /// Names of the server functions and of the functions of all loaded client libraries.
+    /// Returns None when the engine is busy with a query (the caller keeps its previous list).
+    pub fn function_names(&self) -> Option<Vec<String>> {
+        let mut names = Vec::new();
+        if let Ok(mut engine) = self.listener.engine.try_lock() {
+            names.extend(engine.get_function_names().unwrap_or(Vec::new()));
+        } else {
+            println!("The connection is busy (function names not updated)");
+            return None;
+        }
+        if let Ok(loader) = self.loader.lock() {
+            for lib in loader.lib_list() {
+                names.extend(lib.function_names().iter().map(|n| n.to_string() ));
+            }
+        } else {
+            println!("Unable to acquire lock over function loader");
+        }
+        names.sort();
+        names.dedup();
+        Some(names)
+    }
+
     pub fn db_info(&self) -> Option<Vec<DBObject>> {
         if let Ok(mut engine) = self.listener.engine.lock() {
             engine.get_db_info()
@@ -932,3 +1689,16 @@ impl TableEnvironment {
 
 }
 
+
+#[test]
+fn match_failed_statement() {
+    let sql = "select 1; \\gexec\nselect 2;\nSELECT  2 ;";
+    let spans = vec![(0, 8), (17, 25), (27, 37)];
+    let detail = ErrorDetail { statement : String::from("select 2"), ..Default::default() };
+
+    // A statement yielded more than one result, so the failed one is found by its text.
+    assert_eq!(failed_statement(sql, &spans, 3, 4, Some(&detail)), Some(2));
+    assert_eq!(failed_statement(sql, &spans, 1, 4, Some(&detail)), Some(1));
+    assert_eq!(failed_statement(sql, &spans, 1, 3, None), Some(1));
+    assert_eq!(failed_statement(sql, &spans, 1, 4, None), None);
+}
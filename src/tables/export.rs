@@ -0,0 +1,293 @@
+use super::column::ValueKind;
+use super::table::{TableSettings, Align, BoolField};
+
+// Text formats (CSV, Markdown, HTML and LaTeX) write nulls and booleans with the representations
+// chosen at the table settings. Typed formats write them as their own literals: JSON always uses
+// null, true and false; SQL scripts use NULL, and write booleans as 1 and 0 when that is the
+// chosen representation (since SQLite3 has no boolean type) or as TRUE and FALSE otherwise.
+
+/// Column prepared to be exported.
+#[derive(Debug, Clone)]
+pub struct ExportColumn {
+    pub name : String,
+    pub kind : ValueKind,
+
+    /// Values as text, with numbers already written with the configured precision.
+    /// None marks null values.
+    pub values : Vec<Option<String>>
+}
+
+impl ExportColumn {
+
+    /// Value as written by the text formats.
+    pub fn text<'a>(&'a self, row : usize, settings : &TableSettings) -> &'a str {
+        match &self.values[row] {
+            None => settings.null_field.repr(),
+            Some(v) if self.kind == ValueKind::Bool => settings.bool_field.repr(&v[..] == "true"),
+            Some(v) => &v[..]
+        }
+    }
+
+    /// Whether the value is a number JSON and SQL can represent without quotes (NaN and
+    /// infinite values are written as strings).
+    fn is_literal_number(&self, value : &str) -> bool {
+        self.kind == ValueKind::Number && value.parse::<f64>().map(|f| f.is_finite() ).unwrap_or(false)
+    }
+
+}
+
+fn n_rows(cols : &[ExportColumn]) -> usize {
+    cols.get(0).map(|c| c.values.len() ).unwrap_or(0)
+}
+
+fn align_name(align : &Align) -> &'static str {
+    match align {
+        Align::Left => "left",
+        Align::Center => "center",
+        Align::Right => "right"
+    }
+}
+
+pub fn to_markdown(cols : &[ExportColumn], settings : &TableSettings) -> String {
+    let escape = |s : &str| s.replace('|', "\\|").replace('\n', " ");
+    let mut md = String::new();
+    for col in cols.iter() {
+        md += &format!("|{}", escape(&col.name));
+    }
+    md += "|\n";
+    let header_sep = match settings.align {
+        Align::Left => "|:---",
+        Align::Center => "|:---:",
+        Align::Right => "|---:",
+    };
+    md += &header_sep.repeat(cols.len());
+    md += "|\n";
+    for r in 0..n_rows(cols) {
+        for col in cols.iter() {
+            md += &format!("|{}", escape(col.text(r, settings)));
+        }
+        md += "|\n";
+    }
+    md
+}
+
+fn escape_html(s : &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// HTML table. With inline CSS, borders and padding are set at the style attribute of each
+/// element, so the table keeps its appearance where style sheets are stripped (e.g. when
+/// pasted into e-mails, wikis or tickets).
+pub fn to_html(cols : &[ExportColumn], settings : &TableSettings) -> String {
+    let align = align_name(&settings.align);
+    let (table_style, header_style, cell_style) = if settings.inline_css {
+        let cell = format!("border: 1px solid #cccccc; padding: 4px 8px; text-align: {};", align);
+        (
+            String::from(" style=\"border-collapse: collapse;\""),
+            format!(" style=\"{} background-color: #f2f2f2;\"", cell),
+            format!(" style=\"{}\"", cell)
+        )
+    } else {
+        let cell = format!(" style=\"text-align: {};\"", align);
+        (String::new(), cell.clone(), cell)
+    };
+    let mut html = format!("<table{}>\n  <thead>\n    <tr>", table_style);
+    for col in cols.iter() {
+        html += &format!("<th{}>{}</th>", header_style, escape_html(&col.name));
+    }
+    html += "</tr>\n  </thead>\n  <tbody>\n";
+    for r in 0..n_rows(cols) {
+        html += "    <tr>";
+        for col in cols.iter() {
+            html += &format!("<td{}>{}</td>", cell_style, escape_html(col.text(r, settings)));
+        }
+        html += "</tr>\n";
+    }
+    html += "  </tbody>\n</table>\n";
+    html
+}
+
+fn escape_latex(s : &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => escaped += "\\textbackslash{}",
+            '~' => escaped += "\\textasciitilde{}",
+            '^' => escaped += "\\textasciicircum{}",
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '\n' => escaped.push(' '),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// LaTeX tabular environment, with the horizontal rules of the booktabs package.
+pub fn to_latex(cols : &[ExportColumn], settings : &TableSettings) -> String {
+    let spec = match settings.align {
+        Align::Left => "l",
+        Align::Center => "c",
+        Align::Right => "r"
+    };
+    let row = |fields : Vec<String>| format!("{} \\\\\n", fields.join(" & "));
+    let mut tex = String::from("% Requires \\usepackage{booktabs}\n");
+    tex += &format!("\\begin{{tabular}}{{{}}}\n\\toprule\n", spec.repeat(cols.len()));
+    tex += &row(cols.iter().map(|c| escape_latex(&c.name) ).collect());
+    tex += "\\midrule\n";
+    for r in 0..n_rows(cols) {
+        tex += &row(cols.iter().map(|c| escape_latex(c.text(r, settings)) ).collect());
+    }
+    tex += "\\bottomrule\n\\end{tabular}\n";
+    tex
+}
+
+fn json_string(s : &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_value(col : &ExportColumn, row : usize) -> String {
+    match &col.values[row] {
+        None => String::from("null"),
+        Some(v) if col.kind == ValueKind::Bool => if &v[..] == "true" { String::from("true") } else { String::from("false") },
+        Some(v) if col.is_literal_number(v) => v.clone(),
+        Some(v) => json_string(v)
+    }
+}
+
+fn json_record(cols : &[ExportColumn], row : usize) -> String {
+    let fields : Vec<String> = cols.iter()
+        .map(|c| format!("{}: {}", json_string(&c.name), json_value(c, row)) )
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// JSON array with one object per row.
+pub fn to_json_records(cols : &[ExportColumn]) -> String {
+    let records : Vec<String> = (0..n_rows(cols)).map(|r| format!("  {}", json_record(cols, r)) ).collect();
+    if records.is_empty() {
+        String::from("[]\n")
+    } else {
+        format!("[\n{}\n]\n", records.join(",\n"))
+    }
+}
+
+/// JSON object with one array of values per column.
+pub fn to_json_columns(cols : &[ExportColumn]) -> String {
+    let fields : Vec<String> = cols.iter().map(|c| {
+        let values : Vec<String> = (0..c.values.len()).map(|r| json_value(c, r) ).collect();
+        format!("  {}: [{}]", json_string(&c.name), values.join(", "))
+    }).collect();
+    if fields.is_empty() {
+        String::from("{}\n")
+    } else {
+        format!("{{\n{}\n}}\n", fields.join(",\n"))
+    }
+}
+
+/// Newline-delimited JSON: one object per row, one row per line.
+pub fn to_ndjson(cols : &[ExportColumn]) -> String {
+    (0..n_rows(cols)).map(|r| json_record(cols, r) + "\n" ).collect()
+}
+
+/// Quotes the identifier unless it is a plain lowercase identifier. Each part of a
+/// schema-qualified name is quoted separately.
+fn sql_identifier(name : &str) -> String {
+    name.split('.').map(|part| {
+        let plain = part.chars().next().map(|c| c.is_ascii_lowercase() || c == '_' ).unwrap_or(false) &&
+            part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' );
+        if plain {
+            part.to_string()
+        } else {
+            format!("\"{}\"", part.replace('"', "\"\""))
+        }
+    }).collect::<Vec<_>>().join(".")
+}
+
+fn sql_value(col : &ExportColumn, row : usize, settings : &TableSettings) -> String {
+    match &col.values[row] {
+        None => String::from("NULL"),
+        Some(v) if col.kind == ValueKind::Bool => {
+            let value = &v[..] == "true";
+            match (&settings.bool_field, value) {
+                (BoolField::Integer, true) => String::from("1"),
+                (BoolField::Integer, false) => String::from("0"),
+                (_, true) => String::from("TRUE"),
+                (_, false) => String::from("FALSE")
+            }
+        },
+        Some(v) if col.is_literal_number(v) => v.clone(),
+        Some(v) => format!("'{}'", v.replace('\'', "''"))
+    }
+}
+
+/// Script with one insert statement per row, into the informed table.
+pub fn to_sql_insert(cols : &[ExportColumn], table : &str, settings : &TableSettings) -> String {
+    let names : Vec<String> = cols.iter().map(|c| sql_identifier(&c.name) ).collect();
+    let prefix = format!("INSERT INTO {} ({}) VALUES (", sql_identifier(table), names.join(", "));
+    let mut sql = String::new();
+    for r in 0..n_rows(cols) {
+        let values : Vec<String> = cols.iter().map(|c| sql_value(c, r, settings) ).collect();
+        sql += &format!("{}{});\n", prefix, values.join(", "));
+    }
+    sql
+}
+
+#[test]
+fn export_formats() {
+    let cols = vec![
+        ExportColumn {
+            name : String::from("name"),
+            kind : ValueKind::Text,
+            values : vec![Some(String::from("A & \"B\"")), None]
+        },
+        ExportColumn {
+            name : String::from("Total %"),
+            kind : ValueKind::Number,
+            values : vec![Some(String::from("1.5")), Some(String::from("NaN"))]
+        },
+        ExportColumn {
+            name : String::from("ok"),
+            kind : ValueKind::Bool,
+            values : vec![Some(String::from("true")), Some(String::from("false"))]
+        }
+    ];
+    let mut settings = TableSettings::default();
+    settings.align = Align::Right;
+    assert_eq!(
+        to_json_records(&cols),
+        "[\n  {\"name\": \"A & \\\"B\\\"\", \"Total %\": 1.5, \"ok\": true},\n  \
+        {\"name\": null, \"Total %\": \"NaN\", \"ok\": false}\n]\n"
+    );
+    assert_eq!(to_json_columns(&cols), "{\n  \"name\": [\"A & \\\"B\\\"\", null],\n  \"Total %\": [1.5, \"NaN\"],\n  \"ok\": [true, false]\n}\n");
+    assert_eq!(to_ndjson(&cols).lines().count(), 2);
+    assert_eq!(
+        to_sql_insert(&cols, "public.Report", &settings).lines().next().unwrap(),
+        "INSERT INTO public.\"Report\" (name, \"Total %\", ok) VALUES ('A & \"B\"', 1.5, TRUE);"
+    );
+    settings.bool_field = BoolField::Char;
+    let tex = to_latex(&cols, &settings);
+    assert!(tex.contains("\\begin{tabular}{rrr}\n\\toprule\nname & Total \\% & ok \\\\\n\\midrule\n"));
+    assert!(tex.contains("A \\& \"B\" & 1.5 & t \\\\\n"));
+    let html = to_html(&cols, &settings);
+    assert!(html.contains("<td style=\"text-align: right;\">A &amp; &quot;B&quot;</td>"));
+    assert!(html.contains("<td style=\"text-align: right;\"></td>"));
+    settings.inline_css = true;
+    assert!(to_html(&cols, &settings).starts_with("<table style=\"border-collapse: collapse;\">"));
+    assert_eq!(to_markdown(&cols, &settings).lines().nth(1).unwrap(), "|---:|---:|---:|");
+}
@@ -0,0 +1,362 @@
+use super::table::Table;
+use super::column::ValueKind;
+use super::row_view::Comparison;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Text(String),
+    Number(f64),
+    Op(Comparison),
+    Open,
+    Close
+}
+
+/// Operand of a comparison: a column (by index) or a literal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(usize),
+    Number(f64),
+    Text(String),
+    Null
+}
+
+/// Condition on the rows of a table, parsed from a SQL-like expression such as
+/// score >= 10 and (name like 'A%' or "last seen" is null). Column names are
+/// written as they are or between double quotes, and text between single quotes.
+/// Comparisons involving nulls are unknown, and only rows for which the condition
+/// is true pass, as in a SQL where clause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowExpr {
+    Compare(Operand, Comparison, Operand),
+    Like(Operand, String),
+    IsNull(Operand),
+    Not(Box<RowExpr>),
+    And(Box<RowExpr>, Box<RowExpr>),
+    Or(Box<RowExpr>, Box<RowExpr>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Number(f64),
+    Text(String)
+}
+
+fn tokenize(text : &str) -> Result<Vec<Token>, String> {
+    let chars : Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => { tokens.push(Token::Open); i += 1; },
+            ')' => { tokens.push(Token::Close); i += 1; },
+            '\'' | '"' => {
+                // Quotes are escaped by doubling them, as in SQL.
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match (chars.get(i), chars.get(i + 1)) {
+                        (Some(q), Some(next)) if *q == c && *next == c => { s.push(c); i += 2; },
+                        (Some(q), _) if *q == c => { i += 1; break; },
+                        (Some(other), _) => { s.push(*other); i += 1; },
+                        (None, _) => return Err(String::from("Unterminated quote"))
+                    }
+                }
+                tokens.push(if c == '\'' { Token::Text(s) } else { Token::Quoted(s) });
+            },
+            '<' | '>' | '=' | '!' => {
+                let two : String = chars[i..(i + 2).min(chars.len())].iter().collect();
+                let (cmp, len) = match &two[..] {
+                    "<=" => (Comparison::LessEq, 2),
+                    ">=" => (Comparison::GreaterEq, 2),
+                    "!=" | "<>" => (Comparison::NotEqual, 2),
+                    _ => match c {
+                        '<' => (Comparison::Less, 1),
+                        '>' => (Comparison::Greater, 1),
+                        '=' => (Comparison::Equal, 1),
+                        _ => return Err(String::from("Invalid operator !"))
+                    }
+                };
+                tokens.push(Token::Op(cmp));
+                i += len;
+            },
+            _ => {
+                let start = i;
+                let digit_at = |i : usize| chars.get(i).map(|c| c.is_ascii_digit() || *c == '.' ).unwrap_or(false);
+                if c == '-' && digit_at(i + 1) {
+                    i += 1;
+                }
+                let is_number = digit_at(i);
+
+                // Signs are only part of numbers written with exponents, such as 1e-3.
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.' ||
+                    (is_number && (chars[i] == '-' || chars[i] == '+') && chars[i - 1].to_ascii_lowercase() == 'e')
+                ) {
+                    i += 1;
+                }
+                if i == start {
+                    return Err(format!("Unexpected character {}", c));
+                }
+                let word : String = chars[start..i].iter().collect();
+                match word.parse::<f64>() {
+                    Ok(n) if is_number => tokens.push(Token::Number(n)),
+                    _ => tokens.push(Token::Word(word))
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, with or binding looser than and, and and looser than not.
+struct Parser<'a> {
+    tokens : Vec<Token>,
+    pos : usize,
+    names : &'a [String]
+}
+
+impl<'a> Parser<'a> {
+
+    fn peek_keyword(&self, kw : &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) => w.eq_ignore_ascii_case(kw),
+            _ => false
+        }
+    }
+
+    fn expect_keyword(&mut self, kw : &str) -> Result<(), String> {
+        if self.peek_keyword(kw) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected {}", kw))
+        }
+    }
+
+    fn column(&self, name : &str) -> Result<usize, String> {
+        self.names.iter().position(|n| n == name )
+            .or_else(|| self.names.iter().position(|n| n.eq_ignore_ascii_case(name) ) )
+            .ok_or(format!("Unknown column {}", name))
+    }
+
+    fn or(&mut self) -> Result<RowExpr, String> {
+        let mut expr = self.and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            expr = RowExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<RowExpr, String> {
+        let mut expr = self.not()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            expr = RowExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<RowExpr, String> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            Ok(RowExpr::Not(Box::new(self.not()?)))
+        } else {
+            self.condition()
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(String::from("Incomplete expression"))?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Operand::Number(n)),
+            Token::Text(s) => Ok(Operand::Text(s)),
+            Token::Quoted(name) => Ok(Operand::Column(self.column(&name)?)),
+            Token::Word(w) if w.eq_ignore_ascii_case("null") => Ok(Operand::Null),
+            Token::Word(w) => Ok(Operand::Column(self.column(&w)?)),
+            _ => Err(String::from("Expected a column or a value"))
+        }
+    }
+
+    fn condition(&mut self) -> Result<RowExpr, String> {
+        if self.tokens.get(self.pos) == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.or()?;
+            if self.tokens.get(self.pos) != Some(&Token::Close) {
+                return Err(String::from("Unbalanced parenthesis"));
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        if self.peek_keyword("is") {
+            self.pos += 1;
+            let negated = self.peek_keyword("not");
+            if negated {
+                self.pos += 1;
+            }
+            self.expect_keyword("null")?;
+            let expr = RowExpr::IsNull(left);
+            return Ok(if negated { RowExpr::Not(Box::new(expr)) } else { expr });
+        }
+        let negated = self.peek_keyword("not");
+        if negated {
+            self.pos += 1;
+        }
+        if self.peek_keyword("like") {
+            self.pos += 1;
+            let pattern = match self.tokens.get(self.pos) {
+                Some(Token::Text(s)) => s.clone(),
+                _ => return Err(String::from("Expected a quoted pattern after like"))
+            };
+            self.pos += 1;
+            let expr = RowExpr::Like(left, pattern);
+            return Ok(if negated { RowExpr::Not(Box::new(expr)) } else { expr });
+        }
+        if negated {
+            return Err(String::from("Expected like after not"));
+        }
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Op(cmp)) => {
+                self.pos += 1;
+                let right = self.operand()?;
+                Ok(RowExpr::Compare(left, cmp, right))
+            },
+            _ => Err(String::from("Expected a comparison (=, !=, <, <=, >, >=, like or is null)"))
+        }
+    }
+
+}
+
+/// Matches text against a SQL like pattern, where % matches any sequence of characters and _ any character.
+fn like(text : &[char], pattern : &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|i| like(&text[i..], rest) ),
+        Some(('_', rest)) => !text.is_empty() && like(&text[1..], rest),
+        Some((c, rest)) => text.first() == Some(c) && like(&text[1..], rest)
+    }
+}
+
+impl RowExpr {
+
+    /// Parses the expression, resolving column names against the informed names
+    /// (exactly, or else ignoring case).
+    pub fn parse(text : &str, names : &[String]) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err(String::from("Empty expression"));
+        }
+        let mut parser = Parser { tokens, pos : 0, names };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(String::from("Unexpected content after the expression"));
+        }
+        Ok(expr)
+    }
+
+    fn value(tbl : &Table, operand : &Operand, row : usize) -> Value {
+        match operand {
+            Operand::Column(c) => match tbl.get_column(*c) {
+                Some(col) if col.is_null(row) => Value::Null,
+                Some(col) if col.value_kind() == ValueKind::Number => {
+                    col.numeric_value(row).map(Value::Number).unwrap_or(Value::Null)
+                },
+                Some(col) => Value::Text(col.exact_value(row).or_else(|| tbl.display_value(row, *c) ).unwrap_or(String::new())),
+                None => Value::Null
+            },
+            Operand::Number(n) => Value::Number(*n),
+            Operand::Text(s) => Value::Text(s.clone()),
+            Operand::Null => Value::Null
+        }
+    }
+
+    /// Numbers are compared by value (also against text holding a number), anything else as text.
+    fn compare(a : &Value, b : &Value) -> Option<Ordering> {
+        match (a, b) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Number(a), Value::Text(b)) => match b.trim().parse::<f64>() {
+                Ok(b) => a.partial_cmp(&b),
+                Err(_) => Some(a.to_string().cmp(b))
+            },
+            (Value::Text(_), Value::Number(_)) => Self::compare(b, a).map(|o| o.reverse() ),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b))
+        }
+    }
+
+    /// Evaluates the expression with three-valued logic (None is unknown).
+    fn eval(&self, tbl : &Table, row : usize) -> Option<bool> {
+        match self {
+            RowExpr::Compare(a, cmp, b) => {
+                let ord = Self::compare(&Self::value(tbl, a, row), &Self::value(tbl, b, row))?;
+                Some(match cmp {
+                    Comparison::Less => ord == Ordering::Less,
+                    Comparison::LessEq => ord != Ordering::Greater,
+                    Comparison::Greater => ord == Ordering::Greater,
+                    Comparison::GreaterEq => ord != Ordering::Less,
+                    Comparison::Equal => ord == Ordering::Equal,
+                    Comparison::NotEqual => ord != Ordering::Equal
+                })
+            },
+            RowExpr::Like(a, pattern) => match Self::value(tbl, a, row) {
+                Value::Null => None,
+                Value::Number(n) => Some(like(&n.to_string().chars().collect::<Vec<_>>(), &pattern.chars().collect::<Vec<_>>())),
+                Value::Text(t) => Some(like(&t.chars().collect::<Vec<_>>(), &pattern.chars().collect::<Vec<_>>()))
+            },
+            RowExpr::IsNull(a) => Some(Self::value(tbl, a, row) == Value::Null),
+            RowExpr::Not(e) => e.eval(tbl, row).map(|v| !v ),
+            RowExpr::And(a, b) => match (a.eval(tbl, row), b.eval(tbl, row)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None
+            },
+            RowExpr::Or(a, b) => match (a.eval(tbl, row), b.eval(tbl, row)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None
+            }
+        }
+    }
+
+    /// Whether the row passes the condition (i.e. the condition is true, rather than false or unknown).
+    pub fn accepts(&self, tbl : &Table, row : usize) -> bool {
+        self.eval(tbl, row) == Some(true)
+    }
+
+}
+
+#[test]
+fn filter_expressions() {
+    use super::column::Column;
+    use super::nullable_column::NullableColumn;
+    let tbl = Table::new(
+        None,
+        vec![String::from("name"), String::from("score"), String::from("last seen")],
+        vec![
+            Column::Str(vec!["Ann".into(), "bob".into(), "Al's".into()]),
+            NullableColumn::from(vec![Some(9.5), None, Some(12.0)]).to_column(),
+            NullableColumn::from(vec![None, Some(String::from("2020")), Some(String::from("2021"))]).to_column()
+        ]
+    ).unwrap();
+    let rows = |text : &str| -> Vec<usize> {
+        let expr = RowExpr::parse(text, &tbl.names()).unwrap();
+        (0..3).filter(|r| expr.accepts(&tbl, *r) ).collect()
+    };
+    assert_eq!(rows("score > 9"), vec![0, 2]);
+    assert_eq!(rows("not score > 10"), vec![0]);
+    assert_eq!(rows("name like 'A%' and \"last seen\" is not null"), vec![2]);
+    assert_eq!(rows("name = 'Al''s' or score is null"), vec![1, 2]);
+    assert_eq!(rows("(score >= 1e1 or NAME not like '_o_') and \"last seen\" >= 2020"), vec![2]);
+    assert_eq!(rows("\"last seen\" <> '2020'"), vec![2]);
+    assert_eq!(rows("score > -1.5e-1 and score < 10"), vec![0]);
+    assert!(RowExpr::parse("height > 2", &tbl.names()).is_err());
+    assert!(RowExpr::parse("(score > 2", &tbl.names()).is_err());
+    assert!(RowExpr::parse("score 2", &tbl.names()).is_err());
+}
@@ -0,0 +1,301 @@
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::tokenizer::{Tokenizer, Token, Whitespace};
+use super::sql::parse_sql;
+use super::split::{self, Dialect};
+
+/// Words written according to the keyword case option. Other words (identifiers, type and
+/// function names) are always written as they were found.
+const KEYWORDS : [&'static str; 70] = [
+    "select", "from", "where", "group", "by", "order", "having", "limit", "offset",
+    "insert", "into", "values", "update", "set", "delete", "create", "table", "view",
+    "index", "drop", "alter", "add", "column", "truncate", "join", "inner", "left",
+    "right", "full", "outer", "cross", "natural", "on", "using", "as", "distinct", "union",
+    "intersect", "except", "all", "and", "or", "not", "null", "is", "in", "exists",
+    "between", "like", "ilike", "case", "when", "then", "else", "end", "asc", "desc",
+    "with", "recursive", "returning", "primary", "key", "foreign", "references",
+    "default", "unique", "check", "cast", "true", "false"
+];
+
+/// Keywords which start a new line (at the indentation level of the query they belong to).
+const CLAUSES : [&'static str; 16] = [
+    "select", "from", "where", "group", "order", "having", "limit", "offset", "union",
+    "intersect", "except", "values", "set", "returning", "join", "with"
+];
+
+/// Keywords which may precede join, and so start a new line when they do.
+const JOIN_MODIFIERS : [&'static str; 7] = ["inner", "left", "right", "full", "outer", "cross", "natural"];
+
+/// Keywords followed by a space (instead of being attached) before an opening parenthesis.
+const SPACED_BEFORE_PAREN : [&'static str; 18] = [
+    "in", "values", "as", "using", "exists", "on", "and", "or", "not", "from", "join",
+    "where", "over", "into", "when", "then", "else", "select"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+    Preserve
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub keyword_case : KeywordCase,
+
+    /// Number of spaces per indentation level.
+    pub indent : usize,
+
+    /// Lines are broken (at the space closest to the limit) when they would exceed this number of characters.
+    pub line_width : usize
+}
+
+impl Default for FormatOptions {
+
+    fn default() -> Self {
+        Self { keyword_case : KeywordCase::Upper, indent : 4, line_width : 80 }
+    }
+
+}
+
+/// Accumulates the formatted text, breaking lines when they exceed the line width.
+struct Writer<'a> {
+    opts : &'a FormatOptions,
+    out : String,
+    line_start : usize,
+    at_line_start : bool
+}
+
+impl<'a> Writer<'a> {
+
+    fn new(opts : &'a FormatOptions) -> Self {
+        Self { opts, out : String::new(), line_start : 0, at_line_start : true }
+    }
+
+    /// Starts a new line at the informed indentation level. If the current line is
+    /// still empty, only its indentation is changed.
+    fn newline(&mut self, level : usize) {
+        if self.at_line_start {
+            self.out.truncate(self.line_start);
+        } else {
+            let trimmed = self.out.trim_end().len();
+            self.out.truncate(trimmed);
+            self.out.push('\n');
+            self.line_start = self.out.len();
+        }
+        self.out += &" ".repeat(level * self.opts.indent);
+        self.at_line_start = true;
+    }
+
+    /// Appends a token, preceded by a space if spaced is true. Lines are only broken at
+    /// spaces, starting continuation lines at the informed level.
+    fn push(&mut self, text : &str, spaced : bool, cont_level : usize) {
+        if !self.at_line_start && spaced {
+            let width = self.out[self.line_start..].chars().count() + 1 + text.chars().count();
+            if width > self.opts.line_width {
+                self.newline(cont_level);
+            } else {
+                self.out.push(' ');
+            }
+        }
+        self.out += text;
+        self.at_line_start = false;
+    }
+
+}
+
+fn keyword(tk : &Token) -> Option<String> {
+    match tk {
+        Token::Word(w) if w.quote_style.is_none() => Some(w.value.to_lowercase()),
+        _ => None
+    }
+}
+
+fn is_keyword(tk : Option<&Token>, word : &str) -> bool {
+    tk.and_then(keyword).map(|w| &w[..] == word ).unwrap_or(false)
+}
+
+fn is_operator(tk : &Token) -> bool {
+    match tk {
+        Token::Eq | Token::Neq | Token::Lt | Token::Gt | Token::LtEq | Token::GtEq |
+        Token::Plus | Token::Minus | Token::Mult | Token::Div | Token::Mod => true,
+        _ => false
+    }
+}
+
+fn token_text(tk : &Token, case : KeywordCase) -> String {
+    match tk {
+        Token::Word(w) if w.quote_style.is_none() && KEYWORDS.contains(&&w.value.to_lowercase()[..]) => {
+            match case {
+                KeywordCase::Upper => w.value.to_uppercase(),
+                KeywordCase::Lower => w.value.to_lowercase(),
+                KeywordCase::Preserve => w.value.clone()
+            }
+        },
+        Token::Whitespace(Whitespace::SingleLineComment(c)) => format!("--{}", c.trim_end()),
+
+        // Literals are kept unescaped by the tokenizer.
+        Token::SingleQuotedString(s) => format!("'{}'", s.replace("'", "''")),
+        Token::NationalStringLiteral(s) => format!("N'{}'", s.replace("'", "''")),
+        other => other.to_string()
+    }
+}
+
+/// Formats a single statement, or returns None if it cannot be parsed.
+fn format_statement(stmt : &str, opts : &FormatOptions) -> Option<String> {
+    parse_sql(stmt).ok()?;
+    let dialect = PostgreSqlDialect{};
+    let tokens : Vec<Token> = Tokenizer::new(&dialect, stmt).tokenize().ok()?
+        .into_iter()
+        .filter(|tk| match tk {
+            Token::Whitespace(Whitespace::Space) | Token::Whitespace(Whitespace::Newline) |
+            Token::Whitespace(Whitespace::Tab) => false,
+            _ => true
+        })
+        .collect();
+    let mut writer = Writer::new(opts);
+
+    // Whether each open parenthesis holds a subquery (the other parenthesis hold
+    // expressions or lists, within which lines are not broken at clauses).
+    let mut parens : Vec<bool> = Vec::new();
+    let mut depth = 0;
+    let mut prev : Option<&Token> = None;
+    let mut attach_next = false;
+    let mut pending_between = false;
+    for (ix, tk) in tokens.iter().enumerate() {
+        let next = tokens[ix+1..].iter().find(|tk| match tk { Token::Whitespace(_) => false, _ => true });
+        let in_expr = parens.last().cloned() == Some(false);
+        let word = keyword(tk);
+        let mut spaced = !attach_next;
+        attach_next = false;
+        match tk {
+            Token::Comma | Token::RParen | Token::Period | Token::SemiColon |
+            Token::DoubleColon | Token::LBracket | Token::RBracket => spaced = false,
+            Token::LParen => {
+                let is_call = match prev {
+                    Some(Token::Word(w)) => {
+                        w.quote_style.is_some() || !SPACED_BEFORE_PAREN.contains(&&w.value.to_lowercase()[..])
+                    },
+                    _ => false
+                };
+                if is_call {
+                    spaced = false;
+                }
+            },
+            _ => { }
+        }
+        if let Some(w) = &word {
+            let starts_clause = !in_expr && match &w[..] {
+                "join" => !prev.and_then(keyword).map(|p| JOIN_MODIFIERS.contains(&&p[..]) ).unwrap_or(false),
+                w if JOIN_MODIFIERS.contains(&w) => {
+                    !prev.and_then(keyword).map(|p| JOIN_MODIFIERS.contains(&&p[..]) ).unwrap_or(false) &&
+                        (is_keyword(next, "join") || is_keyword(next, "outer"))
+                },
+                "from" => !is_keyword(prev, "delete") && !is_keyword(prev, "distinct"),
+                "set" => !is_keyword(prev, "character"),
+                w => CLAUSES.contains(&w)
+            };
+            let starts_condition = !in_expr && (&w[..] == "and" || &w[..] == "or");
+            if &w[..] == "between" {
+                pending_between = true;
+            }
+            if starts_clause {
+                writer.newline(depth);
+                writer.push(&token_text(tk, opts.keyword_case), false, depth + 1);
+                prev = Some(tk);
+                continue;
+            }
+            if starts_condition {
+                if pending_between && &w[..] == "and" {
+                    pending_between = false;
+                } else {
+                    writer.newline(depth + 1);
+                    writer.push(&token_text(tk, opts.keyword_case), false, depth + 1);
+                    prev = Some(tk);
+                    continue;
+                }
+            }
+        }
+        match tk {
+            Token::LParen => {
+                let subquery = is_keyword(next, "select") || is_keyword(next, "with");
+                writer.push("(", spaced, depth + 1);
+                parens.push(subquery);
+                if subquery {
+                    depth += 1;
+                } else {
+                    attach_next = true;
+                }
+            },
+            Token::RParen => {
+                if parens.pop() == Some(true) {
+                    depth -= 1;
+                    writer.newline(depth);
+                }
+                writer.push(")", false, depth + 1);
+            },
+            Token::Period | Token::DoubleColon | Token::LBracket => {
+                writer.push(&tk.to_string(), spaced, depth + 1);
+                attach_next = true;
+            },
+            Token::Minus | Token::Plus if prev.map(|p| is_operator(p) ).unwrap_or(true) ||
+                prev.map(|p| match p { Token::Comma | Token::LParen => true, _ => false }).unwrap_or(false) ||
+                prev.and_then(keyword).map(|p| KEYWORDS.contains(&&p[..]) ).unwrap_or(false) => {
+
+                // Unary sign, attached to its operand.
+                writer.push(&tk.to_string(), spaced, depth + 1);
+                attach_next = true;
+            },
+            Token::Whitespace(Whitespace::SingleLineComment(_)) => {
+                writer.push(&token_text(tk, opts.keyword_case), true, depth + 1);
+                writer.newline(depth + 1);
+            },
+            other => {
+                writer.push(&token_text(other, opts.keyword_case), spaced, depth + 1);
+            }
+        }
+        if let Token::Whitespace(_) = tk {
+            // Comments do not change the token spacing rules.
+        } else {
+            prev = Some(tk);
+        }
+    }
+    Some(writer.out.trim_end().to_string())
+}
+
+/// Reformats a query sequence, breaking lines at clauses and conditions, indenting subqueries and
+/// normalizing the keyword case. Comments are preserved. Text between statements is kept as it
+/// is, and so are statements which cannot be parsed (e.g. those with engine-specific syntax).
+/// Fails only when the sequence cannot be split into statements.
+pub fn format_sql(sql : &str, dialect : Dialect, opts : &FormatOptions) -> Result<String, String> {
+    let mut out = String::new();
+    let mut last_end = 0;
+    for (start, end) in split::statement_spans(sql, dialect)? {
+        out += &sql[last_end..start];
+        match format_statement(&sql[start..end], opts) {
+            Some(formatted) => out += &formatted,
+            None => out += &sql[start..end]
+        }
+        last_end = end;
+    }
+    out += &sql[last_end..];
+    Ok(out)
+}
+
+#[test]
+fn format_queries() {
+    let opts = FormatOptions::default();
+    let sql = "select a, count(*) from t -- all rows\nwhere x = -1 and y between 1 and 2 group by a;\n\
+        select * frm t;";
+    assert_eq!(
+        format_sql(sql, Dialect::Postgres, &opts).unwrap(),
+        "SELECT a, count(*)\nFROM t -- all rows\nWHERE x = -1\n    AND y BETWEEN 1 AND 2\nGROUP BY a;\n\
+        select * frm t;"
+    );
+
+    let sql = "select s.a from (select a from t where b in (1, 2)) s";
+    let formatted = format_sql(sql, Dialect::Postgres, &opts).unwrap();
+    assert_eq!(
+        formatted,
+        "SELECT s.a\nFROM (\n    SELECT a\n    FROM t\n    WHERE b IN (1, 2)\n) s"
+    );
+}
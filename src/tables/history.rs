@@ -0,0 +1,174 @@
+use rusqlite::{self, Connection, params};
+use std::path::Path;
+use chrono::Local;
+use super::sql::QueryResult;
+
+/// Path of the local database holding the query history, relative to the
+/// working directory (the same convention used by registry/commands.csv).
+pub const HISTORY_PATH : &'static str = "registry/history.db";
+
+const CREATE_HISTORY : &'static str = r#"
+create table if not exists history(
+    id integer primary key,
+    query text not null,
+    conn text,
+    executed_at text,
+    duration_ms integer,
+    nrows integer,
+    error text
+);
+"#;
+
+/// A single query sequence sent to the engine, with the summary of what
+/// the engine returned for it.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id : Option<i64>,
+    pub query : String,
+    pub conn : String,
+    pub executed_at : String,
+    pub duration_ms : i64,
+
+    /// Total number of rows over all tables returned by the sequence
+    /// (None if the sequence did not return any tables).
+    pub nrows : Option<i64>,
+
+    /// First error message returned by the engine, if any.
+    pub error : Option<String>
+}
+
+impl HistoryEntry {
+
+    /// Summarizes the results of a query sequence into a new (not yet persisted) entry.
+    pub fn from_results(
+        query : &str,
+        conn : &str,
+        duration_ms : i64,
+        results : &[QueryResult]
+    ) -> Self {
+        let mut nrows : Option<i64> = None;
+        let mut error : Option<String> = None;
+        for res in results.iter() {
+            match res {
+                QueryResult::Valid(_, tbl) => {
+                    *(nrows.get_or_insert(0)) += tbl.shape().0 as i64;
+                },
+                QueryResult::Invalid(msg, _) => {
+                    if error.is_none() {
+                        error = Some(msg.clone());
+                    }
+                },
+                _ => { }
+            }
+        }
+        Self {
+            id : None,
+            query : query.trim().to_string(),
+            conn : conn.to_string(),
+            executed_at : Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            duration_ms,
+            nrows,
+            error
+        }
+    }
+
+    /// Short one-line description of the entry, used by the history panel.
+    pub fn summary(&self) -> String {
+        let outcome = match (&self.error, self.nrows) {
+            (Some(_), _) => String::from("Error"),
+            (None, Some(n)) => format!("{} row(s)", n),
+            (None, None) => String::from("Ok")
+        };
+        format!("{} · {} · {} ms · {}", self.executed_at, self.conn, self.duration_ms, outcome)
+    }
+
+}
+
+/// Persistent store of executed queries, kept at a local SQLite database.
+/// Each instance keeps its own connection, so the listener thread (which records
+/// entries) and the GUI (which searches them) can open the same file independently.
+pub struct QueryHistory {
+    conn : Connection
+}
+
+impl QueryHistory {
+
+    pub fn open(path : &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("{}", e) )?;
+        conn.execute_batch(CREATE_HISTORY).map_err(|e| format!("{}", e) )?;
+        Ok(Self { conn })
+    }
+
+    pub fn open_default() -> Result<Self, String> {
+        Self::open(Path::new(HISTORY_PATH))
+    }
+
+    pub fn record(&self, entry : &HistoryEntry) -> Result<i64, String> {
+        self.conn.execute(
+            "insert into history (query, conn, executed_at, duration_ms, nrows, error) \
+            values (?1, ?2, ?3, ?4, ?5, ?6);",
+            params![
+                entry.query,
+                entry.conn,
+                entry.executed_at,
+                entry.duration_ms,
+                entry.nrows,
+                entry.error
+            ]
+        ).map_err(|e| format!("{}", e) )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Returns the most recent entries (newest first) whose query text or connection
+    /// contain the informed pattern. An empty pattern matches all entries.
+    pub fn search(&self, pattern : &str, limit : usize) -> Result<Vec<HistoryEntry>, String> {
+        let like = format!("%{}%", pattern.trim());
+        let mut stmt = self.conn.prepare(
+            "select id, query, conn, executed_at, duration_ms, nrows, error from history \
+            where query like ?1 or conn like ?1 order by id desc limit ?2;"
+        ).map_err(|e| format!("{}", e) )?;
+        let rows = stmt.query_map(params![like, limit as i64], |row| {
+            Ok(HistoryEntry {
+                id : row.get(0)?,
+                query : row.get(1)?,
+                conn : row.get::<_, Option<String>>(2)?.unwrap_or(String::new()),
+                executed_at : row.get::<_, Option<String>>(3)?.unwrap_or(String::new()),
+                duration_ms : row.get::<_, Option<i64>>(4)?.unwrap_or(0),
+                nrows : row.get(5)?,
+                error : row.get(6)?
+            })
+        }).map_err(|e| format!("{}", e) )?;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(row.map_err(|e| format!("{}", e) )?);
+        }
+        Ok(entries)
+    }
+
+    pub fn remove(&self, id : i64) -> Result<(), String> {
+        self.conn.execute("delete from history where id = ?1;", params![id])
+            .map_err(|e| format!("{}", e) )?;
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        self.conn.execute("delete from history;", rusqlite::NO_PARAMS)
+            .map_err(|e| format!("{}", e) )?;
+        Ok(())
+    }
+
+}
+
+#[test]
+fn record_and_search() {
+    let hist = QueryHistory {
+        conn : Connection::open_in_memory().unwrap()
+    };
+    hist.conn.execute_batch(CREATE_HISTORY).unwrap();
+    let entry = HistoryEntry::from_results("select 1;", "SQLite3 (in-memory)", 2, &[]);
+    hist.record(&entry).unwrap();
+    let found = hist.search("select", 10).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].query, "select 1;");
+    assert!(hist.search("update", 10).unwrap().is_empty());
+}
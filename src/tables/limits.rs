@@ -0,0 +1,92 @@
+/// Bounds on the resources a single query may use. Limits are kept by the SqlListener,
+/// so each open connection has its own. A None field means the resource is not limited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryLimits {
+
+    /// Maximum time a single statement may run, in milliseconds.
+    pub timeout_ms : Option<u64>,
+
+    /// Maximum number of rows a single query may fetch.
+    pub max_rows : Option<usize>,
+
+    /// Maximum (estimated) size of the data a single query may fetch, in bytes.
+    pub max_bytes : Option<usize>
+}
+
+impl QueryLimits {
+
+    pub fn is_unlimited(&self) -> bool {
+        self.timeout_ms.is_none() && self.max_rows.is_none() && self.max_bytes.is_none()
+    }
+
+    /// Starts counting the rows of a new result.
+    pub fn counter(&self) -> FetchCounter {
+        FetchCounter {
+            max_rows : self.max_rows,
+            max_bytes : self.max_bytes,
+            rows : 0,
+            bytes : 0
+        }
+    }
+
+    /// Message reported when the engine cancelled a statement because of the timeout.
+    pub fn timeout_message(&self) -> String {
+        match self.timeout_ms {
+            Some(ms) => format!("Statement cancelled after reaching the timeout of {} ms set for this connection", ms),
+            None => String::from("Statement cancelled")
+        }
+    }
+
+}
+
+/// Accumulates the rows (and their estimated size) fetched by a query, failing as soon
+/// as any of the limits is exceeded, so the remaining rows are never fetched.
+#[derive(Debug, Clone)]
+pub struct FetchCounter {
+    max_rows : Option<usize>,
+    max_bytes : Option<usize>,
+    rows : usize,
+    bytes : usize
+}
+
+impl FetchCounter {
+
+    pub fn add_row(&mut self, bytes : usize) -> Result<(), String> {
+        self.rows += 1;
+        self.bytes += bytes;
+        if let Some(max) = self.max_rows {
+            if self.rows > max {
+                return Err(format!("Query returned more than {} row(s), the maximum set for this connection", max));
+            }
+        }
+        if let Some(max) = self.max_bytes {
+            if self.bytes > max {
+                return Err(format!("Query result exceeded {}, the maximum memory set for this connection", format_bytes(max)));
+            }
+        }
+        Ok(())
+    }
+
+}
+
+fn format_bytes(n : usize) -> String {
+    if n >= 1024 * 1024 {
+        format!("{:.1} MB", n as f64 / (1024. * 1024.))
+    } else if n >= 1024 {
+        format!("{:.1} KB", n as f64 / 1024.)
+    } else {
+        format!("{} bytes", n)
+    }
+}
+
+#[test]
+fn counter_limits() {
+    let limits = QueryLimits { timeout_ms : None, max_rows : Some(2), max_bytes : Some(100) };
+    let mut counter = limits.counter();
+    assert!(counter.add_row(10).is_ok());
+    assert!(counter.add_row(10).is_ok());
+    assert!(counter.add_row(10).is_err());
+    let mut counter = limits.counter();
+    assert!(counter.add_row(101).is_err());
+    assert!(QueryLimits::default().counter().add_row(usize::MAX).is_ok());
+}
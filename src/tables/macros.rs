@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::command;
+use super::split;
+
+/// Client-side variables, set by \set lines of the query sequences and kept for the
+/// whole session (i.e. they are shared by all connections and editors).
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values : HashMap<String, String>
+}
+
+impl Variables {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name : &str) -> Option<&str> {
+        self.values.get(name).map(|v| &v[..] )
+    }
+
+    pub fn set(&mut self, name : &str, value : &str) {
+        self.values.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn unset(&mut self, name : &str) {
+        self.values.remove(name);
+    }
+
+    /// Variable names and values, in alphabetical order.
+    pub fn list(&self) -> Vec<(String, String)> {
+        let mut vars : Vec<_> = self.values.iter().map(|(k, v)| (k.clone(), v.clone()) ).collect();
+        vars.sort();
+        vars
+    }
+
+}
+
+/// How a value is written into the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+
+    /// As a string literal, unless the value is a number.
+    Literal,
+
+    /// As an identifier, quoted only if required (qualified names are quoted part by part).
+    Identifier,
+
+    /// Verbatim.
+    Raw
+}
+
+/// Whether value is written exactly as the database would print it back (so it can be left
+/// unquoted without losing leading zeros or turning text identifiers into numbers).
+fn is_number(value : &str) -> bool {
+    if let Ok(n) = value.parse::<i64>() {
+        return n.to_string() == value;
+    }
+    match value.find('.') {
+        Some(dot) => {
+            let (int, frac) = (&value[..dot], &value[dot+1..]);
+            is_number(int) && !frac.is_empty() && frac.chars().all(|c| c.is_ascii_digit() ) && !frac.ends_with('0')
+        },
+        None => false
+    }
+}
+
+fn is_name(name : &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' ),
+        _ => false
+    }
+}
+
+fn quote(value : &str, quoting : Quoting) -> String {
+    match quoting {
+        Quoting::Literal if is_number(value) => value.to_string(),
+        Quoting::Literal => format!("'{}'", value.replace("'", "''")),
+        Quoting::Identifier => value.split('.')
+            .map(|part| {
+                if is_name(part) && part.chars().all(|c| !c.is_ascii_uppercase() ) {
+                    part.to_string()
+                } else {
+                    format!("\"{}\"", part.replace("\"", "\"\""))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("."),
+        Quoting::Raw => value.to_string()
+    }
+}
+
+/// Parses a \set or \unset line, returning the variable name and its new value (None for \unset).
+/// Values may be written between single quotes, so they can hold leading or trailing spaces.
+fn parse_meta_command(line : &str) -> Option<Result<(String, Option<String>), String>> {
+    let line = line.trim();
+    let (cmd, args) = match line.find(char::is_whitespace) {
+        Some(pos) => (&line[..pos], line[pos..].trim()),
+        None => (line, "")
+    };
+    if cmd != "\\set" && cmd != "\\unset" {
+        return None;
+    }
+    let (name, value) = match args.find(char::is_whitespace) {
+        Some(pos) => (&args[..pos], args[pos..].trim()),
+        None => (args, "")
+    };
+    if !is_name(name) {
+        return Some(Err(format!("Invalid variable name at {}", line)));
+    }
+    if cmd == "\\unset" {
+        return Some(Ok((name.to_string(), None)));
+    }
+    let value = if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len()-1].replace("''", "'")
+    } else {
+        value.to_string()
+    };
+    Some(Ok((name.to_string(), Some(value))))
+}
+
+/// Replaces the \set, \unset and include lines by spaces, so the statements of the sequence
+/// can be split (the byte position of each statement is preserved).
+pub fn blank_meta_commands(sql : &str) -> String {
+    sql.split('\n')
+        .map(|line| match (parse_meta_command(line), include_path(line)) {
+            (None, None) => line.to_string(),
+            _ => " ".repeat(line.len())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Path of an include line (\i path, \include path or -- include path.sql), if the line is one.
+/// Comments are only includes if they name a single .sql file, so ordinary comments starting
+/// with the word include are kept.
+fn include_path(line : &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with("--") {
+        let comment = line[2..].trim_start();
+        if !comment.starts_with("include") || !comment[7..].starts_with(char::is_whitespace) {
+            return None;
+        }
+        let path = comment[7..].trim().trim_matches('\'');
+        return if path.ends_with(".sql") && !path.contains(char::is_whitespace) {
+            Some(path)
+        } else {
+            None
+        };
+    }
+    let (cmd, arg) = match line.find(char::is_whitespace) {
+        Some(pos) => (&line[..pos], line[pos..].trim()),
+        None => (line, "")
+    };
+    match cmd {
+        "\\i" | "\\include" => Some(arg.trim_matches('\'')),
+        _ => None
+    }
+}
+
+/// Time a ${command} may take before it is killed and the expansion fails.
+pub const COMMAND_TIMEOUT : Duration = Duration::from_secs(30);
+
+/// How the ${command} macros of a sequence are expanded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Commands {
+
+    /// Runs each command, failing if it does not finish within the timeout. Since the
+    /// expansion waits for the commands, it should run away from the main thread.
+    Run(Duration),
+
+    /// Keeps the macros as written, so the sequence can be previewed without side effects.
+    Keep
+}
+
+/// Runs a command, returning its standard output without the trailing newline.
+fn run_command(cmd : &str, timeout : Duration) -> Result<String, String> {
+    let cmd = cmd.trim();
+    match command::run_with_timeout(cmd, timeout) {
+        Ok(out) => Ok(out.trim_end_matches(|c| c == '\n' || c == '\r' ).to_string()),
+        Err(e) => Err(format!("Command {} failed: {}", cmd, e))
+    }
+}
+
+/// Literal or comment a line ends within, carried to the next line.
+#[derive(Debug, Clone, PartialEq)]
+enum Within {
+
+    /// Literal or quoted identifier, closed by the quote character.
+    Quote(char),
+
+    /// Block comment.
+    Comment,
+
+    /// Dollar-quoted string (e.g. a function body), closed by its tag ($$ or $tag$).
+    Dollar(String)
+}
+
+/// Expands the macros of a single line, skipping literals, quoted identifiers, dollar-quoted
+/// strings and comments (the quoting and comment state is carried between lines). The byte
+/// range of each macro within line is pushed to replaced with the range of its value within out.
+fn expand_line(
+    line : &str,
+    vars : &Variables,
+    commands : Commands,
+    state : &mut Option<Within>,
+    out : &mut String,
+    replaced : &mut Vec<((usize, usize), (usize, usize))>
+) -> Result<(), String> {
+    let mut chars = line.char_indices().peekable();
+    let mut prev : Option<char> = None;
+    while let Some((ix, c)) = chars.next() {
+        let after_word = prev.map(|p| p.is_alphanumeric() || p == '_' || p == '$' ).unwrap_or(false);
+        prev = Some(c);
+        let closing = match state {
+            Some(Within::Comment) if line[ix..].starts_with("*/") => Some(String::from("*/")),
+            Some(Within::Dollar(tag)) if line[ix..].starts_with(&tag[..]) => Some(tag.clone()),
+            _ => None
+        };
+        if let Some(closing) = closing {
+            out.push_str(&closing);
+            while chars.peek().map(|(i, _)| *i < ix + closing.len() ).unwrap_or(false) {
+                chars.next();
+            }
+            prev = closing.chars().last();
+            *state = None;
+            continue;
+        }
+        if state.is_some() {
+            if *state == Some(Within::Quote(c)) {
+                *state = None;
+            }
+            out.push(c);
+            continue;
+        }
+        match c {
+            '\'' | '"' => *state = Some(Within::Quote(c)),
+            '-' if line[ix..].starts_with("--") => {
+                out.push_str(&line[ix..]);
+                return Ok(());
+            },
+            '/' if line[ix..].starts_with("/*") => {
+                out.push_str("/*");
+                chars.next();
+                prev = Some('*');
+                *state = Some(Within::Comment);
+                continue;
+            },
+            '$' if line[ix..].starts_with("$(") => {
+                let end = line[ix..].find(')').ok_or(format!("Unterminated variable at {}", line))? + ix;
+                let inner = &line[ix+2..end];
+                let (name, quoting) = match inner.find(':') {
+                    Some(pos) => match &inner[pos+1..] {
+                        "ident" => (&inner[..pos], Quoting::Identifier),
+                        "raw" => (&inner[..pos], Quoting::Raw),
+                        other => return Err(format!("Unknown variable modifier: {}", other))
+                    },
+                    None => (inner, Quoting::Literal)
+                };
+                let value = vars.get(name.trim()).ok_or(format!("Undefined variable: {}", name.trim()))?;
+                let start = out.len();
+                out.push_str(&quote(value, quoting));
+                replaced.push(((ix, end + 1), (start, out.len())));
+                while chars.peek().map(|(i, _)| *i <= end ).unwrap_or(false) {
+                    chars.next();
+                }
+                prev = Some(')');
+                continue;
+            },
+            '$' if line[ix..].starts_with("${") => {
+                let end = line[ix..].find('}').ok_or(format!("Unterminated command at {}", line))? + ix;
+                let start = out.len();
+                match commands {
+                    Commands::Run(timeout) => {
+                        let output = run_command(&line[ix+2..end], timeout)?;
+                        out.push_str(&quote(&output, Quoting::Literal));
+                    },
+                    Commands::Keep => out.push_str(&line[ix..=end])
+                }
+                replaced.push(((ix, end + 1), (start, out.len())));
+                while chars.peek().map(|(i, _)| *i <= end ).unwrap_or(false) {
+                    chars.next();
+                }
+                prev = Some('}');
+                continue;
+            },
+            '$' if !after_word => {
+                if let Some(tag) = split::dollar_tag(line, ix) {
+                    let tag = tag.to_string();
+                    out.push_str(&tag);
+                    while chars.peek().map(|(i, _)| *i < ix + tag.len() ).unwrap_or(false) {
+                        chars.next();
+                    }
+                    prev = Some('$');
+                    *state = Some(Within::Dollar(tag));
+                    continue;
+                }
+            },
+            _ => { }
+        }
+        out.push(c);
+    }
+    Ok(())
+}
+
+/// Where a line of an expanded query sequence came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineOrigin {
+
+    /// Line of the sequence as written by the user. Lines read from included files
+    /// carry the line of the (outermost) include which brought them in.
+    pub line : usize,
+
+    /// Included file and the line within it, for lines read from included files.
+    pub include : Option<(PathBuf, usize)>
+}
+
+/// Query sequence with its macros expanded and its includes inlined.
+#[derive(Debug, Clone, Default)]
+pub struct Expansion {
+
+    pub sql : String,
+
+    /// Origin of each line of sql.
+    pub origins : Vec<LineOrigin>,
+
+    /// Pieces of sql written at the editor, ordered by their position within sql.
+    pub segments : Vec<Segment>
+}
+
+/// Piece of the expanded sequence which starts at byte position expanded and was written at the
+/// original (start, end) byte range of the editor text. Verbatim pieces were copied unchanged, so
+/// each byte maps to a single byte; the others are the values of macros (or the text of includes),
+/// which map as a whole to the text which yielded them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub expanded : usize,
+    pub original : (usize, usize),
+    pub verbatim : bool
+}
+
+impl Expansion {
+
+    /// Origin of the line holding the byte position of sql.
+    pub fn origin_at(&self, pos : usize) -> Option<&LineOrigin> {
+        let line = self.sql.get(..pos)?.matches('\n').count();
+        self.origins.get(line)
+    }
+
+    /// Original byte range of the editor text which yielded the byte position of sql.
+    fn original_at(&self, pos : usize) -> Option<(usize, usize)> {
+        let seg = &self.segments[self.segments.iter().rposition(|s| s.expanded <= pos )?];
+        if seg.verbatim {
+            let orig = seg.original.0 + (pos - seg.expanded);
+            Some((orig, orig + 1))
+        } else {
+            Some(seg.original)
+        }
+    }
+
+    /// Byte range (start and end) of the editor text which yielded the byte range of sql.
+    pub fn original_span(&self, start : usize, end : usize) -> Option<(usize, usize)> {
+        let (orig_start, _) = self.original_at(start)?;
+        let (_, orig_end) = self.original_at(end.max(start + 1) - 1)?;
+        Some((orig_start, orig_end.max(orig_start)))
+    }
+
+}
+
+struct Expander<'a> {
+    vars : &'a mut Variables,
+    commands : Commands,
+
+    /// Canonical paths of the files being included, outermost first.
+    stack : Vec<PathBuf>,
+    expansion : Expansion
+}
+
+impl<'a> Expander<'a> {
+
+    fn push_line(&mut self, origin : LineOrigin) {
+        if self.expansion.origins.len() > 0 {
+            self.expansion.sql.push('\n');
+        }
+        self.expansion.origins.push(origin);
+    }
+
+    /// Records that the text starting at offset bytes from the end of the expansion was written
+    /// at the original range of the editor text.
+    fn push_segment(&mut self, offset : usize, original : (usize, usize), verbatim : bool) {
+        let expanded = self.expansion.sql.len() + offset;
+        self.expansion.segments.push(Segment { expanded, original, verbatim });
+    }
+
+    /// Expands the text of the editor (file is None) or of an included file, resolving
+    /// includes relative to dir. editor_line is the line of the outermost include, if any.
+    fn expand_text(
+        &mut self,
+        text : &str,
+        file : Option<&Path>,
+        dir : Option<&Path>,
+        editor_line : Option<usize>
+    ) -> Result<(), String> {
+        let mut state = None;
+        let mut line_start = 0;
+        for (ix, line) in text.split('\n').enumerate() {
+            let orig_start = line_start;
+            line_start += line.len() + 1;
+            let located = |e : String| match file {
+                Some(file) => format!("{}, line {}: {}", file.display(), ix + 1, e),
+                None => e
+            };
+            let origin = LineOrigin {
+                line : editor_line.unwrap_or(ix),
+                include : file.map(|f| (f.to_path_buf(), ix) )
+            };
+            if state.is_none() {
+                match parse_meta_command(line) {
+                    Some(Ok((name, value))) => {
+                        match value {
+                            Some(value) => self.vars.set(&name, &value),
+                            None => self.vars.unset(&name)
+                        }
+                        self.push_line(origin);
+                        if file.is_none() {
+                            self.push_segment(0, (orig_start, orig_start + line.len()), false);
+                        }
+                        continue;
+                    },
+                    Some(Err(e)) => return Err(located(e)),
+                    None => { }
+                }
+                if let Some(path) = include_path(line) {
+                    let path = match dir {
+                        Some(dir) => dir.join(path),
+                        None if Path::new(path).is_absolute() => PathBuf::from(path),
+                        None => return Err(located(format!(
+                            "Unable to include {}: relative paths require the query file to be saved", path
+                        )))
+                    };
+                    let canonical = fs::canonicalize(&path)
+                        .map_err(|e| located(format!("Unable to include {}: {}", path.display(), e)) )?;
+                    if self.stack.contains(&canonical) {
+                        let cycle : Vec<String> = self.stack.iter()
+                            .skip_while(|p| **p != canonical )
+                            .chain(Some(&canonical))
+                            .map(|p| p.display().to_string() )
+                            .collect();
+                        return Err(located(format!("Include cycle: {}", cycle.join(" -> "))));
+                    }
+                    let content = fs::read_to_string(&canonical)
+                        .map_err(|e| located(format!("Unable to include {}: {}", path.display(), e)) )?;
+                    if file.is_none() {
+                        self.push_segment(0, (orig_start, orig_start + line.len()), false);
+                    }
+                    self.stack.push(canonical.clone());
+                    self.expand_text(&content, Some(&path), canonical.parent(), Some(origin.line))?;
+                    self.stack.pop();
+                    continue;
+                }
+            }
+            self.push_line(origin);
+            let mut out = String::new();
+            let mut replaced = Vec::new();
+            expand_line(line, self.vars, self.commands, &mut state, &mut out, &mut replaced).map_err(located)?;
+            if file.is_none() {
+                let line_end = orig_start + line.len();
+                self.push_segment(0, (orig_start, line_end), true);
+                for ((start, end), (out_start, out_end)) in replaced {
+                    self.push_segment(out_start, (orig_start + start, orig_start + end), false);
+                    self.push_segment(out_end, (orig_start + end, line_end), true);
+                }
+            }
+            self.expansion.sql += &out;
+        }
+        Ok(())
+    }
+
+}
+
+/// Applies the \set and \unset lines of the sequence to the variables (in the order they appear),
+/// and substitutes $(name) by the variable value and ${command} by the standard output of the
+/// command (or keeps it, depending on commands). Values are written as string literals (unless they
+/// are numbers); $(name:ident) writes the value as an identifier and $(name:raw) writes it verbatim.
+/// Macros within literals, quoted identifiers, dollar-quoted strings and comments are kept as they are. The \set lines are replaced by empty lines, and
+/// include lines (\i path or -- include path) are replaced by the (expanded) content of the file.
+/// Relative paths are resolved against dir (or, within included files, against the directory of
+/// the including file). Errors within included files are prefixed by the file path and line.
+pub fn expand(sql : &str, dir : Option<&Path>, vars : &mut Variables, commands : Commands) -> Result<Expansion, String> {
+    let mut expander = Expander { vars, commands, stack : Vec::new(), expansion : Expansion::default() };
+    expander.expand_text(sql, None, dir, None)?;
+    Ok(expander.expansion)
+}
+
+#[test]
+fn expand_variables() {
+    let commands = Commands::Keep;
+    let mut vars = Variables::new();
+    let sql = "\\set schema Sales\n\\set since '2020-01-01'\n\\set n 10\n\
+        select '$(n)', $(n) from $(schema:ident).t -- $(missing)\nwhere d > $(since);";
+    assert_eq!(
+        expand(sql, None, &mut vars, commands).unwrap().sql,
+        "\n\n\nselect '$(n)', 10 from \"Sales\".t -- $(missing)\nwhere d > '2020-01-01';"
+    );
+    vars.set("name", "O'Brien");
+    assert_eq!(expand("select $(name)", None, &mut vars, commands).unwrap().sql, "select 'O''Brien'");
+    vars.set("id", "0123");
+    vars.set("exp", "1e5");
+    vars.set("price", "-12.5");
+    assert_eq!(expand("select $(id), $(exp), $(price)", None, &mut vars, commands).unwrap().sql, "select '0123', '1e5', -12.5");
+    assert!(expand("select $(other)", None, &mut vars, commands).is_err());
+    assert_eq!(blank_meta_commands("\\set a 1\nselect 1"), "        \nselect 1");
+
+    // Dollar-quoted bodies may hold apostrophes, and their macros are kept.
+    let body = "create function f() returns text as $body$\nselect 'don''t', $(name); -- isn't\n$body$ language sql; select $(name), a$b$";
+    assert_eq!(
+        expand(body, None, &mut vars, commands).unwrap().sql,
+        "create function f() returns text as $body$\nselect 'don''t', $(name); -- isn't\n$body$ language sql; select 'O''Brien', a$b$"
+    );
+    assert_eq!(expand("select $$it's$$, $(name)", None, &mut vars, commands).unwrap().sql, "select $$it's$$, 'O''Brien'");
+
+    // Commands are only run when asked to, and killed if they take too long.
+    assert_eq!(expand("select ${echo 1}", None, &mut vars, commands).unwrap().sql, "select ${echo 1}");
+    let run = Commands::Run(COMMAND_TIMEOUT);
+    assert_eq!(expand("select ${echo 1}", None, &mut vars, run).unwrap().sql, "select 1");
+    assert_eq!(expand("select ${printf '%s\\n' 1 2 | paste -sd, -}", None, &mut vars, run).unwrap().sql, "select '1,2'");
+    assert_eq!(expand("select ${echo \"a  b\"}", None, &mut vars, run).unwrap().sql, "select 'a  b'");
+    assert!(expand("select ${sleep 5}", None, &mut vars, Commands::Run(Duration::from_millis(100))).is_err());
+}
+
+#[test]
+fn expand_includes() {
+    let dir = std::env::temp_dir().join(format!("queries-includes-{}", std::process::id()));
+    fs::create_dir_all(dir.join("shared")).unwrap();
+    fs::write(dir.join("shared/cte.sql"), "\\set n 2\nselect $(n);\n-- include more.sql").unwrap();
+    fs::write(dir.join("shared/more.sql"), "select 3;").unwrap();
+    fs::write(dir.join("shared/bad.sql"), "select 1;\nselect $(missing);").unwrap();
+    fs::write(dir.join("a.sql"), "\\i b.sql").unwrap();
+    fs::write(dir.join("b.sql"), "-- include a.sql").unwrap();
+    let commands = Commands::Keep;
+    let mut vars = Variables::new();
+    let exp = expand("select 1;\n\\i shared/cte.sql\nselect 4;", Some(&dir), &mut vars, commands).unwrap();
+    assert_eq!(exp.sql, "select 1;\n\nselect 2;\nselect 3;\nselect 4;");
+    assert_eq!(exp.origins.len(), 5);
+    assert_eq!(exp.origin_at(exp.sql.find("select 3").unwrap()).unwrap().line, 1);
+    assert!(exp.origins[3].include.as_ref().map(|(f, l)| f.ends_with("shared/more.sql") && *l == 0 ).unwrap_or(false));
+    assert_eq!(exp.origins[4], LineOrigin { line : 2, include : None });
+    let err = expand("\\include shared/bad.sql", Some(&dir), &mut vars, commands).unwrap_err();
+    assert!(err.contains("bad.sql, line 2: Undefined variable"));
+    let err = expand("\\i a.sql", Some(&dir), &mut vars, commands).unwrap_err();
+    assert!(err.contains("Include cycle"));
+    assert_eq!(blank_meta_commands("\\i x.sql\n-- include y.sql\n-- included").trim(), "-- included");
+
+    // Comments which do not name a .sql file are not includes.
+    let sql = "-- include the customer table\nselect 1;";
+    assert_eq!(expand(sql, Some(&dir), &mut vars, commands).unwrap().sql, sql);
+    assert_eq!(include_path("-- include 'shared/more.sql'"), Some("shared/more.sql"));
+
+    // Relative includes need the directory of the (saved) query file.
+    let err = expand("\\i shared/more.sql", None, &mut vars, commands).unwrap_err();
+    assert!(err.contains("relative paths require the query file to be saved"));
+    let absolute = format!("\\i {}", dir.join("shared/more.sql").display());
+    assert_eq!(expand(&absolute, None, &mut vars, commands).unwrap().sql, "select 3;");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn map_expanded_positions() {
+    let mut vars = Variables::new();
+    let sql = "\\set schema Sales\nselect * from $(schema:ident).t\nwhere x = 1;";
+    let expansion = expand(sql, None, &mut vars, Commands::Keep).unwrap();
+    assert_eq!(expansion.sql, "\nselect * from \"Sales\".t\nwhere x = 1;");
+
+    // Text after the macro maps by its own offset, and the macro value maps to the whole macro.
+    let t = expansion.sql.find(".t").unwrap() + 1;
+    assert_eq!(expansion.original_span(t, t + 1), Some((sql.find(").t").unwrap() + 2, sql.find(").t").unwrap() + 3)));
+    let x = expansion.sql.find("x =").unwrap();
+    assert_eq!(expansion.original_span(x, x + 1), Some((sql.find("x =").unwrap(), sql.find("x =").unwrap() + 1)));
+    let value = expansion.sql.find("\"Sales\"").unwrap();
+    let mac = sql.find("$(schema").unwrap();
+    assert_eq!(expansion.original_span(value + 1, value + 3), Some((mac, mac + "$(schema:ident)".len())));
+}
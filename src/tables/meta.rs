@@ -0,0 +1,219 @@
+use super::split::Dialect;
+
+/// Display settings changed by meta-commands. Kept by the listener thread for the lifetime
+/// of the connection, and applied to the results of every statement executed after the change.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetaState {
+
+    /// Append the execution time to the message of each statement (\timing).
+    pub timing : bool,
+
+    /// Show tables with one row per field instead of one row per record (\x).
+    pub expanded : bool
+}
+
+/// Client-side (psql-style) backslash commands. Variables (\set and \unset) and includes (\i)
+/// are resolved before the sequence is sent (see macros::expand), so they are not represented here.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaCommand {
+
+    /// \d: relations, or the columns of a single relation when its name is informed.
+    Describe(Option<String>),
+
+    /// \dt
+    Tables(Option<String>),
+
+    /// \dv
+    Views(Option<String>),
+
+    /// \df
+    Functions(Option<String>),
+
+    /// \dn
+    Schemas(Option<String>),
+
+    /// \l
+    Databases,
+
+    /// \timing, optionally followed by on or off (toggles the setting otherwise).
+    Timing(Option<bool>),
+
+    /// \x, optionally followed by on or off (toggles the setting otherwise).
+    Expanded(Option<bool>),
+
+    /// \copy: same arguments as the copy statement, always executed at the client.
+    Copy(String),
+
+    /// \echo
+    Echo(String)
+}
+
+fn parse_switch(arg : &str) -> Result<Option<bool>, String> {
+    match arg {
+        "" => Ok(None),
+        "on" => Ok(Some(true)),
+        "off" => Ok(Some(false)),
+        other => Err(format!("Expected on or off, found {}", other))
+    }
+}
+
+fn optional(arg : &str) -> Option<String> {
+    if arg.is_empty() {
+        None
+    } else {
+        Some(arg.to_string())
+    }
+}
+
+impl MetaCommand {
+
+    /// Parses a meta-command statement (starting with a backslash).
+    pub fn parse(stmt : &str) -> Result<Self, String> {
+        let stmt = stmt.trim();
+        if !stmt.starts_with('\\') {
+            return Err(format!("Not a meta-command: {}", stmt));
+        }
+        let (name, arg) = match stmt.find(char::is_whitespace) {
+            Some(pos) => (&stmt[1..pos], stmt[pos..].trim()),
+            None => (&stmt[1..], "")
+        };
+        match name.trim_end_matches('+') {
+            "d" => Ok(MetaCommand::Describe(optional(arg))),
+            "dt" => Ok(MetaCommand::Tables(optional(arg))),
+            "dv" => Ok(MetaCommand::Views(optional(arg))),
+            "df" => Ok(MetaCommand::Functions(optional(arg))),
+            "dn" => Ok(MetaCommand::Schemas(optional(arg))),
+            "l" | "list" => Ok(MetaCommand::Databases),
+            "timing" => Ok(MetaCommand::Timing(parse_switch(arg)?)),
+            "x" => Ok(MetaCommand::Expanded(parse_switch(arg)?)),
+            "copy" if arg.is_empty() => Err(String::from("\\copy requires arguments")),
+            "copy" => Ok(MetaCommand::Copy(arg.to_string())),
+            "echo" => Ok(MetaCommand::Echo(arg.to_string())),
+            "set" | "unset" | "i" | "include" => {
+                Err(format!("\\{} is only available for queries sent from the editor", name))
+            },
+            other => Err(format!("Unsupported meta-command: \\{}", other))
+        }
+    }
+
+    /// Catalog query which implements the command, if it is a listing command.
+    pub fn catalog_query(&self, dialect : Dialect) -> Option<String> {
+        match (self, dialect) {
+            (MetaCommand::Describe(None), Dialect::Postgres) => Some(format!(
+                "select n.nspname as \"Schema\", c.relname as \"Name\", \
+                case c.relkind when 'r' then 'table' when 'v' then 'view' when 'm' then 'materialized view' \
+                when 'S' then 'sequence' when 'f' then 'foreign table' when 'p' then 'partitioned table' end as \"Type\" \
+                from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+                where c.relkind in ('r', 'v', 'm', 'S', 'f', 'p') {} order by 1, 2",
+                USER_SCHEMAS
+            )),
+            (MetaCommand::Describe(Some(rel)), Dialect::Postgres) => {
+                let (schema, name) = split_qualified(rel);
+                let schema_filter = schema.map(|s| format!("and table_schema = {} ", literal(s)) )
+                    .unwrap_or(String::new());
+                Some(format!(
+                    "select column_name as \"Column\", data_type as \"Type\", is_nullable as \"Nullable\", \
+                    column_default as \"Default\" from information_schema.columns \
+                    where table_name = {} {}order by table_schema, ordinal_position",
+                    literal(name), schema_filter
+                ))
+            },
+            (MetaCommand::Tables(pattern), Dialect::Postgres) | (MetaCommand::Views(pattern), Dialect::Postgres) => {
+                let kind = if let MetaCommand::Tables(_) = self { "BASE TABLE" } else { "VIEW" };
+                Some(format!(
+                    "select table_schema as \"Schema\", table_name as \"Name\" from information_schema.tables \
+                    where table_type = '{}' and table_schema not in ('pg_catalog', 'information_schema') {}\
+                    order by 1, 2",
+                    kind, pattern_filter("table_name", pattern)
+                ))
+            },
+            (MetaCommand::Functions(pattern), Dialect::Postgres) => Some(format!(
+                "select n.nspname as \"Schema\", p.proname as \"Name\", \
+                pg_get_function_result(p.oid) as \"Result type\", \
+                pg_get_function_arguments(p.oid) as \"Argument types\" \
+                from pg_proc p join pg_namespace n on n.oid = p.pronamespace \
+                where true {} {}order by 1, 2",
+                USER_SCHEMAS, pattern_filter("p.proname", pattern)
+            )),
+            (MetaCommand::Schemas(pattern), Dialect::Postgres) => Some(format!(
+                "select n.nspname as \"Name\", pg_get_userbyid(n.nspowner) as \"Owner\" \
+                from pg_namespace n where true {} {}order by 1",
+                USER_SCHEMAS, pattern_filter("n.nspname", pattern)
+            )),
+            (MetaCommand::Databases, Dialect::Postgres) => Some(String::from(
+                "select datname as \"Name\", pg_get_userbyid(datdba) as \"Owner\", \
+                pg_encoding_to_char(encoding) as \"Encoding\" from pg_database order by 1"
+            )),
+            (MetaCommand::Describe(None), Dialect::Sqlite) => Some(String::from(
+                "select name as \"Name\", type as \"Type\" from sqlite_master \
+                where type in ('table', 'view') and name not like 'sqlite_%' order by 1"
+            )),
+            (MetaCommand::Describe(Some(rel)), Dialect::Sqlite) => Some(format!(
+                "select name as \"Column\", type as \"Type\", \
+                case \"notnull\" when 1 then 'NO' else 'YES' end as \"Nullable\", \
+                dflt_value as \"Default\" from pragma_table_info({}) order by cid",
+                literal(rel)
+            )),
+            (MetaCommand::Tables(pattern), Dialect::Sqlite) | (MetaCommand::Views(pattern), Dialect::Sqlite) => {
+                let kind = if let MetaCommand::Tables(_) = self { "table" } else { "view" };
+                Some(format!(
+                    "select name as \"Name\" from sqlite_master where type = '{}' \
+                    and name not like 'sqlite_%' {}order by 1",
+                    kind, pattern_filter("name", pattern)
+                ))
+            },
+            (MetaCommand::Functions(pattern), Dialect::Sqlite) => Some(format!(
+                "select distinct name as \"Name\" from pragma_function_list where true {}order by 1",
+                pattern_filter("name", pattern)
+            )),
+
+            // Attached databases are the closest SQLite3 equivalent of both schemas and databases.
+            (MetaCommand::Schemas(_), Dialect::Sqlite) | (MetaCommand::Databases, Dialect::Sqlite) => Some(String::from(
+                "select name as \"Name\", file as \"File\" from pragma_database_list order by seq"
+            )),
+            _ => None
+        }
+    }
+
+}
+
+/// Excludes the system schemas of Postgres from catalog listings.
+const USER_SCHEMAS : &'static str = "and n.nspname not in ('pg_catalog', 'information_schema') \
+    and n.nspname !~ '^pg_toast' and n.nspname !~ '^pg_temp'";
+
+fn literal(value : &str) -> String {
+    format!("'{}'", value.replace("'", "''"))
+}
+
+/// Splits schema.name, if the name is qualified.
+fn split_qualified(rel : &str) -> (Option<&str>, &str) {
+    match rel.find('.') {
+        Some(pos) => (Some(&rel[..pos]), &rel[pos+1..]),
+        None => (None, rel)
+    }
+}
+
+/// Filter for a psql-style name pattern (where * matches any sequence of characters and
+/// ? matches any single character). Schema qualifiers are ignored.
+fn pattern_filter(column : &str, pattern : &Option<String>) -> String {
+    match pattern {
+        Some(pattern) => {
+            let name = split_qualified(pattern).1;
+            let like = name.replace("%", "\\%").replace("_", "\\_").replace("*", "%").replace("?", "_");
+            format!("and {} like {} escape '\\' ", column, literal(&like))
+        },
+        None => String::new()
+    }
+}
+
+#[test]
+fn parse_meta_commands() {
+    assert_eq!(MetaCommand::parse("\\d+ public.t").unwrap(), MetaCommand::Describe(Some(String::from("public.t"))));
+    assert_eq!(MetaCommand::parse("\\timing off").unwrap(), MetaCommand::Timing(Some(false)));
+    assert_eq!(MetaCommand::parse("\\x").unwrap(), MetaCommand::Expanded(None));
+    assert!(MetaCommand::parse("\\timing maybe").is_err());
+    assert!(MetaCommand::parse("\\q").is_err());
+    let query = MetaCommand::parse("\\dt sales_*").unwrap().catalog_query(Dialect::Sqlite).unwrap();
+    assert!(query.contains("like 'sales\\_%' escape"));
+    assert!(MetaCommand::Echo(String::new()).catalog_query(Dialect::Postgres).is_none());
+}
@@ -14,6 +14,48 @@ pub mod column;
 
 pub mod nullable_column;
 
+pub mod history;
+
+pub mod diff;
+
+pub mod limits;
+
+pub mod safety;
+
+pub mod split;
+
+pub mod format;
+
+pub mod macros;
+
+pub mod meta;
+
+pub mod snippets;
+
+pub mod workspace;
+
+pub mod csv_writer;
+
+pub mod export;
+
+pub mod row_view;
+
+pub mod edit;
+
+pub mod profile;
+
+pub mod detail;
+
+pub mod pivot;
+
+pub mod expr;
+
+pub mod combine;
+
+pub mod upload;
+
+pub mod transfer;
+
 // Engine-specific modules
 
 mod sqlite;
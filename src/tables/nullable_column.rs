@@ -3,6 +3,8 @@ use postgres::types::{ToSql, FromSql};
 use std::marker::Sync;
 use std::convert::{TryFrom, TryInto};
 use std::mem;
+use std::cmp::Ordering;
+use super::split::Dialect;
 
 /// Represents an incomplete column of information, holding
 /// the indices from which the valid column entries refer to,
@@ -22,6 +24,55 @@ impl<'a> NullableColumn {
         self.n
     }
 
+    pub fn is_null(&self, ix : usize) -> bool {
+        self.null_ix.binary_search(&ix).is_ok()
+    }
+
+    pub fn value_kind(&self) -> ValueKind {
+        self.col.value_kind()
+    }
+
+    /// Position of a valid row at the inner column (i.e. discounting the nulls before it),
+    /// or None if the row is null.
+    fn valid_ix(&self, ix : usize) -> Option<usize> {
+        match self.null_ix.binary_search(&ix) {
+            Ok(_) => None,
+            Err(n_before) => Some(ix - n_before)
+        }
+    }
+
+    pub fn display_value(&self, ix : usize, prec : usize) -> String {
+        match self.valid_ix(ix) {
+            Some(valid) => self.col.display_value(valid, prec),
+            None => String::from(Self::NULL)
+        }
+    }
+
+    pub fn exact_value(&self, ix : usize) -> Option<String> {
+        self.col.exact_value(self.valid_ix(ix)?)
+    }
+
+    pub fn key_value(&self, ix : usize) -> Option<String> {
+        self.col.key_value(self.valid_ix(ix)?)
+    }
+
+    pub fn bytes_value(&self, ix : usize) -> Option<&[u8]> {
+        self.col.bytes_value(self.valid_ix(ix)?)
+    }
+
+    pub fn numeric_value(&self, ix : usize) -> Option<f64> {
+        self.col.numeric_value(self.valid_ix(ix)?)
+    }
+
+    pub fn cmp_rows(&self, a : usize, b : usize) -> Ordering {
+        match (self.valid_ix(a), self.valid_ix(b)) {
+            (Some(a), Some(b)) => self.col.cmp_rows(a, b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal
+        }
+    }
+
     pub fn from_col(col : Column) -> Self {
         let n = col.ref_content().len();
         //let mut valid_ix = Vec::new();
@@ -84,10 +135,23 @@ impl<'a> NullableColumn {
         full_refs
     }
 
+    pub fn sql_type(&self, dialect : Dialect) -> &'static str {
+        self.col.sql_type(dialect)
+    }
+
     pub fn truncate(&mut self, n : usize) {
         self.col.truncate(n);
     }
 
+    pub fn valid_rows(&self) -> (&Column, Vec<Option<usize>>) {
+        (&self.col, (0..self.n).map(|r| self.valid_ix(r) ).collect())
+    }
+
+    pub fn take_rows(&self, rows : &[Option<usize>]) -> Column {
+        let valid : Vec<Option<usize>> = rows.iter().map(|r| r.and_then(|r| self.valid_ix(r) ) ).collect();
+        self.col.take_rows(&valid[..])
+    }
+
 }
 
 impl<T> From<Vec<Option<T>>> for NullableColumn
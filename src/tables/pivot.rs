@@ -0,0 +1,257 @@
+use super::table::Table;
+use super::column::{Column, ValueKind};
+use super::nullable_column::NullableColumn;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+
+/// Largest number of value columns a pivot can create (one for each distinct combination
+/// of the column keys), since wider tables cannot be usefully shown.
+const MAX_PIVOT_COLS : usize = 1000;
+
+/// How the values falling at the same cell of a pivot table are summarized. Null values are
+/// ignored, as SQL aggregates do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Mean,
+    Count,
+    Min,
+    Max
+}
+
+impl Aggregate {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Sum => "sum",
+            Aggregate::Mean => "mean",
+            Aggregate::Count => "count",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max"
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "sum" => Some(Aggregate::Sum),
+            "mean" => Some(Aggregate::Mean),
+            "count" => Some(Aggregate::Count),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            _ => None
+        }
+    }
+
+}
+
+/// Cross-tabulation of a table: each distinct combination of the row keys becomes a row, each
+/// distinct combination of the column keys becomes a column, and each cell aggregates the value
+/// column over the rows having both combinations. Combinations never seen together are null.
+#[derive(Debug, Clone)]
+pub struct Pivot {
+    pub rows : Vec<usize>,
+    pub cols : Vec<usize>,
+    pub value : usize,
+    pub agg : Aggregate
+}
+
+/// Rows of a table grouped by the values of some of its columns, ordered as
+/// if the table were sorted by these columns.
+struct Groups {
+
+    /// First row of each group.
+    first : Vec<usize>,
+
+    /// Group of each table row.
+    of_row : Vec<usize>
+}
+
+impl Groups {
+
+    fn new(tbl : &Table, keys : &[usize]) -> Self {
+        let cols : Vec<&Column> = keys.iter().filter_map(|k| tbl.get_column(*k) ).collect();
+        let nrows = tbl.shape().0;
+        let mut index : HashMap<Vec<Option<String>>, usize> = HashMap::new();
+        let mut first = Vec::new();
+        let mut unsorted = Vec::with_capacity(nrows);
+        for r in 0..nrows {
+            let key : Vec<Option<String>> = cols.iter().map(|c| c.key_value(r) ).collect();
+            let n = first.len();
+            let group = *index.entry(key).or_insert(n);
+            if group == n {
+                first.push(r);
+            }
+            unsorted.push(group);
+        }
+
+        // Groups are numbered by first appearance, then renumbered by sort order.
+        let mut sorted : Vec<usize> = (0..first.len()).collect();
+        sorted.sort_by(|a, b| {
+            cols.iter()
+                .map(|c| c.cmp_rows(first[*a], first[*b]) )
+                .find(|ord| *ord != Ordering::Equal )
+                .unwrap_or(Ordering::Equal)
+        });
+        let mut renumber = vec![0; first.len()];
+        for (new, old) in sorted.iter().enumerate() {
+            renumber[*old] = new;
+        }
+        Self {
+            first : sorted.iter().map(|g| first[*g] ).collect(),
+            of_row : unsorted.iter().map(|g| renumber[*g] ).collect()
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.first.len()
+    }
+
+}
+
+impl Pivot {
+
+    /// Name of the value column created for a combination of the column
+    /// keys (the values at the keys joined by slashes).
+    fn column_name(tbl : &Table, keys : &[usize], row : usize) -> String {
+        let values : Vec<String> = keys.iter()
+            .map(|k| tbl.display_value(row, *k).unwrap_or(String::new()) )
+            .collect();
+        values.join(" / ")
+    }
+
+    /// Aggregates the value column over the rows of the cells of the column group g, for each of the
+    /// n row groups. Cells are only kept for the combinations which appear at the table, so the
+    /// missing ones are null.
+    fn aggregate(
+        &self,
+        tbl : &Table,
+        cells : &HashMap<(usize, usize), Vec<usize>>,
+        g : usize,
+        n : usize
+    ) -> Result<Column, String> {
+        let col = tbl.get_column(self.value).ok_or(String::from("Invalid value column"))?;
+        let cell = |r : usize| cells.get(&(g, r));
+        match self.agg {
+            Aggregate::Count => {
+                let counts : Vec<Option<i64>> = (0..n)
+                    .map(|r| cell(r).map(|rows| rows.iter().filter(|row| !col.is_null(**row) ).count() as i64 ) )
+                    .collect();
+                Ok(NullableColumn::from(counts).to_column())
+            },
+            Aggregate::Sum | Aggregate::Mean => {
+                if col.value_kind() != ValueKind::Number {
+                    return Err(format!("The {} of a non-numeric column cannot be calculated", self.agg.name()));
+                }
+                let sums : Vec<Option<f64>> = (0..n)
+                    .map(|r| {
+                        let values : Vec<f64> = cell(r)?.iter().filter_map(|row| col.numeric_value(*row) ).collect();
+                        if values.is_empty() {
+                            return None;
+                        }
+                        let sum = values.iter().sum::<f64>();
+                        match self.agg {
+                            Aggregate::Mean => Some(sum / values.len() as f64),
+                            _ => Some(sum)
+                        }
+                    }).collect();
+                Ok(NullableColumn::from(sums).to_column())
+            },
+            Aggregate::Min | Aggregate::Max => {
+                // Keeps the type of the value column by taking the extreme rows.
+                let extremes : Vec<Option<usize>> = (0..n)
+                    .map(|r| {
+                        let valid = cell(r)?.iter().filter(|row| !col.is_null(**row) );
+                        match self.agg {
+                            Aggregate::Min => valid.min_by(|a, b| col.cmp_rows(**a, **b) ).cloned(),
+                            _ => valid.max_by(|a, b| col.cmp_rows(**a, **b) ).cloned()
+                        }
+                    }).collect();
+                Ok(col.take_rows(&extremes[..]))
+            }
+        }
+    }
+
+    pub fn apply(&self, tbl : &Table) -> Result<Table, String> {
+        let ncols = tbl.shape().1;
+        if self.rows.iter().chain(self.cols.iter()).chain(Some(&self.value)).any(|c| *c >= ncols ) {
+            return Err(String::from("Invalid column index"));
+        }
+        if self.rows.iter().any(|r| self.cols.contains(r) ) {
+            return Err(String::from("A column cannot be both a row key and a column key"));
+        }
+        if self.rows.contains(&self.value) || self.cols.contains(&self.value) {
+            return Err(String::from("The value column cannot be a key"));
+        }
+        let row_groups = Groups::new(tbl, &self.rows[..]);
+        let col_groups = Groups::new(tbl, &self.cols[..]);
+        if col_groups.len() > MAX_PIVOT_COLS {
+            return Err(format!(
+                "Column keys have {} distinct combinations (at most {} are supported)",
+                col_groups.len(),
+                MAX_PIVOT_COLS
+            ));
+        }
+
+        // Rows falling at each cell, by column group and row group. Most combinations of the keys
+        // might never appear together, so only the cells which have rows are kept.
+        let mut cells : HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for r in 0..tbl.shape().0 {
+            cells.entry((col_groups.of_row[r], row_groups.of_row[r])).or_insert_with(Vec::new).push(r);
+        }
+
+        let tbl_names = tbl.names();
+        let mut names = Vec::new();
+        let mut cols = Vec::new();
+        let first_rows : Vec<Option<usize>> = row_groups.first.iter().map(|r| Some(*r) ).collect();
+        for k in self.rows.iter() {
+            names.push(tbl_names[*k].clone());
+            cols.push(tbl.get_column(*k).unwrap().take_rows(&first_rows[..]));
+        }
+        for (g, first) in col_groups.first.iter().enumerate() {
+            names.push(if self.cols.is_empty() {
+                format!("{}({})", self.agg.name(), tbl_names[self.value])
+            } else {
+                Self::column_name(tbl, &self.cols[..], *first)
+            });
+            cols.push(self.aggregate(tbl, &cells, g, row_groups.len())?);
+        }
+        if cols.is_empty() {
+            return Err(String::from("Table has no rows to pivot"));
+        }
+        let name = tbl.table_info().0.unwrap_or(String::from("Table"));
+        Table::new(Some(format!("{} (pivot)", name)), names, cols).map_err(|e| e.to_string() )
+    }
+
+}
+
+#[test]
+fn pivot_table() {
+    let tbl = Table::new(
+        None,
+        vec![String::from("region"), String::from("year"), String::from("sales")],
+        vec![
+            Column::Str(vec!["south".into(), "north".into(), "north".into(), "south".into(), "north".into()]),
+            Column::I32(vec![2021, 2021, 2020, 2020, 2021]),
+            NullableColumn::from(vec![Some(1.0), Some(2.0), Some(3.0), None, Some(4.0)]).to_column()
+        ]
+    ).unwrap();
+    let pivot = Pivot { rows : vec![0], cols : vec![1], value : 2, agg : Aggregate::Sum };
+    let out = pivot.apply(&tbl).unwrap();
+    assert_eq!(out.names(), vec![String::from("region"), String::from("2020"), String::from("2021")]);
+    assert_eq!(out.shape(), (2, 3));
+    assert_eq!(out.display_value(0, 0).unwrap(), "north");
+    let sales = out.get_column(2).unwrap();
+    assert_eq!(sales.numeric_value(0), Some(6.0));
+    assert_eq!(sales.numeric_value(1), Some(1.0));
+
+    // The south/2020 cell only has a null value, so its sum is null, but its count is zero.
+    assert!(out.get_column(1).unwrap().is_null(1));
+    let counts = Pivot { agg : Aggregate::Count, ..pivot.clone() }.apply(&tbl).unwrap();
+    assert_eq!(counts.get_column(1).unwrap().numeric_value(1), Some(0.0));
+    let max = Pivot { agg : Aggregate::Max, rows : vec![], ..pivot.clone() }.apply(&tbl).unwrap();
+    assert_eq!(max.shape(), (1, 2));
+    assert_eq!(max.get_column(1).unwrap().numeric_value(0), Some(4.0));
+    let text_sum = Pivot { rows : vec![1], cols : vec![], value : 0, agg : Aggregate::Sum };
+    assert!(text_sum.apply(&tbl).is_err());
+    assert!(Pivot { rows : vec![1], ..pivot }.apply(&tbl).is_err());
+}
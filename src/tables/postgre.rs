@@ -1,14 +1,64 @@
-use postgres::{self, Client, types::FromSql, types::ToSql };
+use postgres::{self, Client, IsolationLevel, types::FromSql, types::ToSql };
 use rust_decimal::Decimal;
 use super::column::*;
 use super::nullable_column::*;
 use super::table::*;
 use postgres::types::Type;
-use std::io::Write;
+use postgres::error::{SqlState, ErrorPosition};
+use super::sql::{QueryResult, ErrorDetail};
+use super::limits::QueryLimits;
+use super::edit::{TableColumn, EditStatement, EditValue};
 use std::error::Error;
-use crate::tables::table::{self, Table, Align, Format, TableSettings, BoolField, NullField};
+use crate::tables::table::{Table, Align, Format, TableSettings, BoolField, NullField};
 use crate::utils;
 
+/// Estimated size of the values at a row, in bytes. Text and binary fields are
+/// measured by borrowing the row buffer; other fields are assumed to take 8 bytes.
+pub fn row_size(row : &postgres::row::Row) -> usize {
+    (0..row.len()).map(|i| {
+        if let Ok(Some(s)) = row.try_get::<usize, Option<&str>>(i) {
+            s.len()
+        } else if let Ok(Some(b)) = row.try_get::<usize, Option<&[u8]>>(i) {
+            b.len()
+        } else {
+            8
+        }
+    }).sum()
+}
+
+/// Sets the server-side statement timeout for the session (zero disables it).
+pub fn set_statement_timeout(conn : &mut Client, limits : &QueryLimits) -> Result<(), String> {
+    let ms = limits.timeout_ms.unwrap_or(0);
+    conn.batch_execute(&format!("set statement_timeout = {};", ms)[..])
+        .map_err(|e| format!("{}", e) )
+}
+
+/// Error message for a failed statement, distinguishing statements cancelled by the server
+/// because the statement timeout was reached.
+pub fn error_message(e : &postgres::Error, limits : &QueryLimits) -> String {
+    match e.code() {
+        Some(code) if code == &SqlState::QUERY_CANCELED && limits.timeout_ms.is_some() => {
+            limits.timeout_message()
+        },
+        _ => e.to_string()
+    }
+}
+
+/// Failed result for a statement, carrying the error position, hint and detail informed by the server.
+pub fn error_result(e : &postgres::Error, stmt : &str, limits : &QueryLimits) -> QueryResult {
+    let mut detail = ErrorDetail::from_statement(stmt);
+    if let Some(db_err) = e.as_db_error() {
+        // Positions informed by the server start at one. Internal positions refer to
+        // queries generated by the server (e.g. function bodies), not to the statement.
+        if let Some(ErrorPosition::Original(pos)) = db_err.position() {
+            detail.position = Some((*pos as usize).saturating_sub(1));
+        }
+        detail.hint = db_err.hint().map(|h| h.to_string() );
+        detail.detail = db_err.detail().map(|d| d.to_string() );
+    }
+    QueryResult::Invalid(error_message(e, limits), Some(detail))
+}
+
 pub fn col_as_opt_vec<'a, T>(
     rows : &'a [postgres::row::Row],
     ix : usize
@@ -77,32 +127,108 @@ pub fn try_any_float(rows : &[postgres::row::Row], ix : usize) -> Result<Nullabl
     }
 }*/
 
-pub fn copy_table_to_postgres(
-    client : &mut Client,
-    tbl : &mut Table,
-    dst : &str,
-    cols : &[String]
-) -> Result<(), String> {
-    let copy_stmt = match cols.len() {
-        0 => format!("COPY {} FROM stdin", dst),
-        n => {
-            let mut cols_agg = String::new();
-            for i in 0..n {
-                cols_agg += &cols[n];
-                if i <= n-1 {
-                    cols_agg += ",";
-                }
-            }
-            format!("COPY {} ({}) FROM stdin", dst, cols_agg)
+/// Columns of a table (as named at a query, resolved by the search path), with their
+/// types and whether they belong to the primary key.
+pub fn table_columns(conn : &mut Client, table : &str) -> Result<Vec<TableColumn>, String> {
+    let query = "select a.attname::text, format_type(a.atttypid, null)::text, \
+        coalesce(a.attnum = any(i.indkey), false) \
+        from pg_attribute a left join pg_index i on i.indrelid = a.attrelid and i.indisprimary \
+        where a.attrelid = $1::text::regclass and a.attnum > 0 and not a.attisdropped \
+        order by a.attnum;";
+    let rows = conn.query(query, &[&table]).map_err(|e| format!("{}", e) )?;
+    let mut cols = Vec::new();
+    for row in rows.iter() {
+        cols.push(TableColumn {
+            name : row.try_get(0).map_err(|e| format!("{}", e) )?,
+            sql_type : row.try_get(1).map_err(|e| format!("{}", e) )?,
+            key : row.try_get(2).map_err(|e| format!("{}", e) )?
+        });
+    }
+    Ok(cols)
+}
+
+/// Binds null to text parameters.
+static NULL_PARAM : Option<&'static str> = None;
+
+/// Executes the statements in a single transaction, returning the number of affected rows.
+/// The transaction is rolled back if any statement fails, or if any statement that should
+/// affect a single row does not.
+pub fn apply_statements(conn : &mut Client, stmts : &[EditStatement]) -> Result<usize, String> {
+    let mut tr = conn.transaction().map_err(|e| format!("{}", e) )?;
+    let mut n = 0;
+    for stmt in stmts.iter() {
+        let params : Vec<&(dyn ToSql + Sync)> = stmt.params.iter()
+            .map(|p| match p {
+                EditValue::Null => &NULL_PARAM as &(dyn ToSql + Sync),
+                EditValue::Text(t) => t as &(dyn ToSql + Sync),
+                EditValue::Bytes(b) => b as &(dyn ToSql + Sync)
+            })
+            .collect();
+        let changed = tr.execute(&stmt.sql[..], &params[..])
+            .map_err(|e| format!("{} failed: {}", stmt.description, e) )?;
+        if stmt.check_row && changed != 1 {
+            return Err(format!("{} not applied: the row was changed or removed since it was fetched", stmt.description));
         }
-    };
-    println!("{}", copy_stmt);
-    let mut writer = client.copy_in(&copy_stmt[..])
+        n += changed as usize;
+    }
+    tr.commit().map_err(|e| format!("{}", e) )?;
+    Ok(n)
+}
+
+/// Types read into table columns by build_table_from_postgre. Values of other types
+/// cannot be read, so they must be cast to text by the query.
+const DECODED_TYPES : [Type; 13] = [
+    Type::BOOL,
+    Type::BYTEA,
+    Type::TEXT,
+    Type::VARCHAR,
+    Type::FLOAT8,
+    Type::FLOAT4,
+    Type::INT4,
+    Type::INT8,
+    Type::INT2,
+    Type::TIMESTAMP,
+    Type::DATE,
+    Type::TIME,
+    Type::NUMERIC
+];
+
+/// Runs a query in a read-only transaction, passing its rows to f in tables of at most chunk rows,
+/// which are fetched from the server as they are needed, with the number of rows returned by the
+/// query. Since the transaction sees a single snapshot of the database, the rows are consistent
+/// and counted at the same snapshot even if written while they are read. Fails if a column has a
+/// type which cannot be read. Returns the number of rows read.
+pub fn stream_query(
+    conn : &mut Client,
+    query : &str,
+    chunk : usize,
+    f : &mut dyn FnMut(Table, usize) -> Result<(), String>
+) -> Result<usize, String> {
+    let mut tr = conn.build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .map_err(|e| format!("{}", e) )?;
+    let count = format!("select count(*) from ({}) as counted", query);
+    let total : i64 = tr.query_one(&count[..], &[])
+        .and_then(|row| row.try_get(0) )
         .map_err(|e| format!("{}", e) )?;
-    let tbl_content = table::full_csv_display(tbl, cols.into());
-    writer.write_all(tbl_content.as_bytes()).map_err(|e| format!("{}", e) )?;
-    writer.finish().map_err(|e| format!("{}", e) )?;
-    Ok(())
+    let stmt = tr.prepare(query).map_err(|e| format!("{}", e) )?;
+    if let Some(col) = stmt.columns().iter().find(|c| !DECODED_TYPES.contains(c.type_()) ) {
+        return Err(format!("Column {} has type {}, which cannot be read (cast it to text)", col.name(), col.type_()));
+    }
+    let portal = tr.bind(&stmt, &[]).map_err(|e| format!("{}", e) )?;
+    let mut n = 0;
+    loop {
+        let rows = tr.query_portal(&portal, chunk as i32).map_err(|e| format!("{}", e) )?;
+        if rows.is_empty() {
+            break;
+        }
+        n += rows.len();
+        f(build_table_from_postgre(&rows[..])?, total as usize)?;
+    }
+    tr.commit().map_err(|e| format!("{}", e) )?;
+    Ok(n)
 }
 
 pub fn build_table_from_postgre(rows : &[postgres::row::Row]) -> Result<Table, &'static str> {
@@ -0,0 +1,233 @@
+use super::table::Table;
+use super::column::{Column, ValueKind};
+use std::collections::HashMap;
+
+/// Quantiles informed for numeric columns.
+const QUANTILES : [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+
+/// Number of bins of the histograms.
+const N_BINS : usize = 16;
+
+/// Number of most frequent values informed for each column.
+const TOP_K : usize = 5;
+
+/// Bars used to draw the histograms, from the shortest to the tallest.
+const BARS : [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Moments, quantiles and histogram of the valid values of a numeric column.
+#[derive(Debug, Clone)]
+pub struct NumericSummary {
+    pub mean : f64,
+
+    /// Sample standard deviation (zero for a single value).
+    pub stddev : f64,
+
+    /// Pairs of probability and quantile (linearly interpolated between the closest values).
+    pub quantiles : Vec<(f64, f64)>,
+
+    /// Counts at N_BINS bins of equal width spanning range.
+    pub histogram : Vec<usize>,
+    pub range : (f64, f64)
+}
+
+impl NumericSummary {
+
+    /// Summarizes the values, or returns None if there are no finite values.
+    pub fn new(values : &[f64]) -> Option<Self> {
+        let mut sorted : Vec<f64> = values.iter().cloned().filter(|v| v.is_finite() ).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap() );
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let stddev = if sorted.len() > 1 {
+            (sorted.iter().map(|v| (v - mean).powi(2) ).sum::<f64>() / (n - 1.)).sqrt()
+        } else {
+            0.
+        };
+        let quantiles = QUANTILES.iter().map(|p| (*p, quantile(&sorted[..], *p)) ).collect();
+        let range = (sorted[0], sorted[sorted.len() - 1]);
+        let mut histogram = vec![0; N_BINS];
+        let width = (range.1 - range.0) / N_BINS as f64;
+        for v in sorted.iter() {
+            let bin = if width > 0. {
+                (((v - range.0) / width) as usize).min(N_BINS - 1)
+            } else {
+                0
+            };
+            histogram[bin] += 1;
+        }
+        Some(Self { mean, stddev, quantiles, histogram, range })
+    }
+
+    /// Histogram drawn as a line of bars, scaled by the most populated bin.
+    pub fn sparkline(&self) -> String {
+        let max = self.histogram.iter().cloned().max().unwrap_or(0);
+        self.histogram.iter().map(|c| {
+            if *c == 0 || max == 0 {
+                ' '
+            } else {
+                BARS[((*c * BARS.len() - 1) / max).min(BARS.len() - 1)]
+            }
+        }).collect()
+    }
+
+}
+
+/// Quantile of sorted values, interpolating linearly between the closest ranks.
+fn quantile(sorted : &[f64], p : f64) -> f64 {
+    let pos = p * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+/// Summary of a table column.
+#[derive(Debug, Clone)]
+pub struct ColumnProfile {
+    pub name : String,
+    pub kind : ValueKind,
+
+    /// Number of rows (null or not).
+    pub count : usize,
+    pub nulls : usize,
+
+    /// Number of distinct valid values.
+    pub distinct : usize,
+
+    /// Smallest and largest valid values, ordered as when the column is sorted.
+    pub min : Option<String>,
+    pub max : Option<String>,
+
+    /// Present for numeric columns with at least one valid value.
+    pub numeric : Option<NumericSummary>,
+
+    /// Most frequent valid values, with their counts.
+    pub top : Vec<(String, usize)>
+}
+
+impl ColumnProfile {
+
+    pub fn new(tbl : &Table, ix : usize) -> Option<Self> {
+        let col = tbl.get_column(ix)?;
+        let name = tbl.names().get(ix)?.clone();
+        let count = tbl.shape().0;
+        let valid : Vec<usize> = (0..count).filter(|r| !col.is_null(*r) ).collect();
+        let nulls = count - valid.len();
+
+        let mut freqs : HashMap<String, usize> = HashMap::new();
+        for r in valid.iter() {
+            let value = col.exact_value(*r)
+                .or_else(|| tbl.display_value(*r, ix) )
+                .unwrap_or(String::new());
+            *freqs.entry(value).or_insert(0) += 1;
+        }
+        let distinct = freqs.len();
+        let mut top : Vec<(String, usize)> = freqs.into_iter().collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0) ) );
+        top.truncate(TOP_K);
+
+        let min = valid.iter().min_by(|a, b| col.cmp_rows(**a, **b) ).and_then(|r| tbl.display_value(*r, ix) );
+        let max = valid.iter().max_by(|a, b| col.cmp_rows(**a, **b) ).and_then(|r| tbl.display_value(*r, ix) );
+        let numeric = match col.value_kind() {
+            ValueKind::Number => NumericSummary::new(&Self::numeric_values(tbl, ix, col, &valid[..])[..]),
+            _ => None
+        };
+        Some(Self { name, kind : col.value_kind(), count, nulls, distinct, min, max, numeric, top })
+    }
+
+    /// Valid values of a numeric column. Complete columns of primitive types are converted as a whole.
+    fn numeric_values(tbl : &Table, ix : usize, col : &Column, valid : &[usize]) -> Vec<f64> {
+        let converted = match col {
+            Column::Nullable(_) => None,
+            _ => tbl.get_columns(&[ix]).try_numeric(0)
+        };
+        converted.unwrap_or_else(|| valid.iter().filter_map(|r| col.numeric_value(*r) ).collect() )
+    }
+
+    /// Profile as text, with numbers written with the informed precision.
+    pub fn report(&self, prec : usize) -> String {
+        let kind = match self.kind {
+            ValueKind::Number => "number",
+            ValueKind::Bool => "boolean",
+            ValueKind::Text => "text"
+        };
+        let null_pct = if self.count > 0 { 100. * self.nulls as f64 / self.count as f64 } else { 0. };
+        let mut text = format!("{} ({})\n", self.name, kind);
+        text += &format!(
+            "  count {}   nulls {} ({:.1}%)   distinct {}\n",
+            self.count,
+            self.nulls,
+            null_pct,
+            self.distinct
+        );
+        if let (Some(min), Some(max)) = (&self.min, &self.max) {
+            text += &format!("  min {}   max {}\n", min, max);
+        }
+        if let Some(num) = &self.numeric {
+            text += &format!("  mean {:.*}   stddev {:.*}\n", prec, num.mean, prec, num.stddev);
+            let quantiles : Vec<String> = num.quantiles.iter()
+                .map(|(p, q)| format!("p{} {:.*}", (p * 100.).round(), prec, q) )
+                .collect();
+            text += &format!("  {}\n", quantiles.join("   "));
+            text += &format!(
+                "  histogram {} [{:.*}, {:.*}]\n",
+                num.sparkline(),
+                prec,
+                num.range.0,
+                prec,
+                num.range.1
+            );
+        }
+        if self.top.len() > 0 {
+            let top : Vec<String> = self.top.iter()
+                .map(|(value, n)| format!("{} ({})", value, n) )
+                .collect();
+            text += &format!("  top {}\n", top.join(", "));
+        }
+        text
+    }
+
+}
+
+/// Profiles of the informed columns (or of all columns, if none are informed) as text.
+pub fn table_report(tbl : &Table, cols : &[usize]) -> String {
+    let (nrows, ncols) = tbl.shape();
+    let all : Vec<usize> = (0..ncols).collect();
+    let cols = if cols.is_empty() { &all[..] } else { cols };
+    let name = tbl.table_info().0.unwrap_or(String::from("Table"));
+    let mut text = format!("{} ({} rows x {} columns)\n\n", name, nrows, ncols);
+    let profiles : Vec<String> = cols.iter()
+        .filter_map(|ix| ColumnProfile::new(tbl, *ix) )
+        .map(|p| p.report(tbl.precision()) )
+        .collect();
+    text += &profiles.join("\n");
+    text
+}
+
+#[test]
+fn profile_columns() {
+    use super::nullable_column::NullableColumn;
+    let tbl = Table::new(
+        None,
+        vec![String::from("score"), String::from("name")],
+        vec![
+            NullableColumn::from(vec![Some(1.0), None, Some(2.0), Some(3.0), Some(4.0)]).to_column(),
+            Column::Str(vec!["a".into(), "b".into(), "a".into(), "c".into(), "a".into()])
+        ]
+    ).unwrap();
+    let score = ColumnProfile::new(&tbl, 0).unwrap();
+    assert_eq!((score.count, score.nulls, score.distinct), (5, 1, 4));
+    assert_eq!(score.min.as_ref().map(|m| &m[..]), tbl.display_value(0, 0).as_ref().map(|m| &m[..]));
+    let num = score.numeric.unwrap();
+    assert!((num.mean - 2.5).abs() < 1E-10);
+    assert!((num.stddev - (5.0f64 / 3.).sqrt()).abs() < 1E-10);
+    assert!((num.quantiles[2].1 - 2.5).abs() < 1E-10);
+    assert_eq!(num.histogram.iter().sum::<usize>(), 4);
+    assert_eq!(num.range, (1.0, 4.0));
+    let name = ColumnProfile::new(&tbl, 1).unwrap();
+    assert!(name.numeric.is_none());
+    assert_eq!(name.top[0], (String::from("a"), 3));
+    assert_eq!(name.distinct, 3);
+    assert!(table_report(&tbl, &[]).contains("score (number)"));
+}
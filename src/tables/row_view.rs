@@ -0,0 +1,149 @@
+use super::table::Table;
+use super::column::Column;
+use std::cmp::Ordering;
+
+/// Direction in which the rows of a view are sorted by a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending
+}
+
+/// Comparison accepted by the numeric filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual
+}
+
+/// Filter typed for a single column of a result view. Filters are parsed from
+/// short expressions: "null" and "not null" match by nullity; a comparison operator
+/// (<, <=, >, >=, = or !=) followed by a number compares numeric columns by value;
+/// "=" followed by text matches the whole field; any other text matches fields
+/// containing it, ignoring case.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowFilter {
+    Null,
+    NotNull,
+    Compare(Comparison, f64),
+    Equals(String),
+    Contains(String)
+}
+
+impl RowFilter {
+
+    /// Parses the filter expression, returning None if it is empty.
+    pub fn parse(expr : &str) -> Option<Self> {
+        let expr = expr.trim();
+        if expr.is_empty() {
+            return None;
+        }
+        match &expr.to_lowercase()[..] {
+            "null" => return Some(RowFilter::Null),
+            "not null" => return Some(RowFilter::NotNull),
+            _ => { }
+        }
+        let ops = [
+            ("<=", Comparison::LessEq),
+            (">=", Comparison::GreaterEq),
+            ("!=", Comparison::NotEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal)
+        ];
+        for (prefix, cmp) in ops.iter() {
+            if expr.starts_with(prefix) {
+                let operand = expr[prefix.len()..].trim();
+                return match (operand.parse::<f64>(), cmp) {
+                    (Ok(value), _) => Some(RowFilter::Compare(*cmp, value)),
+                    (Err(_), Comparison::Equal) => Some(RowFilter::Equals(operand.to_string())),
+                    _ => Some(RowFilter::Contains(expr.to_lowercase()))
+                };
+            }
+        }
+        Some(RowFilter::Contains(expr.to_lowercase()))
+    }
+
+    /// Whether the row of the informed table column passes the filter.
+    pub fn accepts(&self, tbl : &Table, col_ix : usize, row : usize) -> bool {
+        let col : &Column = match tbl.get_column(col_ix) {
+            Some(col) => col,
+            None => return true
+        };
+        match self {
+            RowFilter::Null => col.is_null(row),
+            RowFilter::NotNull => !col.is_null(row),
+            RowFilter::Compare(cmp, value) => {
+                let field = match col.numeric_value(row) {
+                    Some(field) => field,
+                    None => return false
+                };
+                match cmp {
+                    Comparison::Less => field < *value,
+                    Comparison::LessEq => field <= *value,
+                    Comparison::Greater => field > *value,
+                    Comparison::GreaterEq => field >= *value,
+                    Comparison::Equal => field == *value,
+                    Comparison::NotEqual => field != *value
+                }
+            },
+            RowFilter::Equals(txt) => !col.is_null(row) &&
+                tbl.display_value(row, col_ix).map(|v| &v[..] == &txt[..] ).unwrap_or(false),
+            RowFilter::Contains(txt) => !col.is_null(row) &&
+                tbl.display_value(row, col_ix).map(|v| v.to_lowercase().contains(&txt[..]) ).unwrap_or(false)
+        }
+    }
+
+}
+
+/// Returns the indices of the table rows that pass all filters (informed as pairs of
+/// column index and filter), in the order they should be shown. Sorting is stable, so rows
+/// with equal values keep their original relative order.
+pub fn row_order(tbl : &Table, sort : Option<(usize, SortOrder)>, filters : &[(usize, RowFilter)]) -> Vec<usize> {
+    let nrows = tbl.shape().0;
+    let mut order : Vec<usize> = (0..nrows)
+        .filter(|r| filters.iter().all(|(c, f)| f.accepts(tbl, *c, *r) ) )
+        .collect();
+    if let Some((col_ix, sort_order)) = sort {
+        if let Some(col) = tbl.get_column(col_ix) {
+            order.sort_by(|a, b| {
+                match sort_order {
+                    SortOrder::Ascending => col.cmp_rows(*a, *b),
+                    SortOrder::Descending => col.cmp_rows(*b, *a)
+                }
+            });
+        }
+    }
+    order
+}
+
+#[test]
+fn sort_and_filter_rows() {
+    use super::nullable_column::NullableColumn;
+    let tbl = Table::new(
+        None,
+        vec![String::from("id"), String::from("name"), String::from("score")],
+        vec![
+            Column::I64(vec![1, 2, 3, 4]),
+            Column::Str(vec!["Ann".into(), "bob".into(), "Carl".into(), "Bea".into()]),
+            NullableColumn::from(vec![Some(9.5), None, Some(10.0), Some(2.0)]).to_column()
+        ]
+    ).unwrap();
+    assert_eq!(row_order(&tbl, Some((2, SortOrder::Ascending)), &[]), vec![3, 0, 2, 1]);
+    assert_eq!(row_order(&tbl, Some((2, SortOrder::Descending)), &[]), vec![1, 2, 0, 3]);
+    assert_eq!(row_order(&tbl, Some((1, SortOrder::Ascending)), &[]), vec![0, 3, 2, 1]);
+    assert_eq!(RowFilter::parse(" >= 9 "), Some(RowFilter::Compare(Comparison::GreaterEq, 9.0)));
+    assert_eq!(RowFilter::parse(""), None);
+    let filters = vec![(2, RowFilter::parse(">= 9").unwrap())];
+    assert_eq!(row_order(&tbl, None, &filters), vec![0, 2]);
+    let filters = vec![(1, RowFilter::parse("B").unwrap()), (2, RowFilter::parse("not null").unwrap())];
+    assert_eq!(row_order(&tbl, None, &filters), vec![3]);
+    let filters = vec![(2, RowFilter::parse("NULL").unwrap())];
+    assert_eq!(row_order(&tbl, None, &filters), vec![1]);
+    let filters = vec![(1, RowFilter::parse("=bob").unwrap())];
+    assert_eq!(row_order(&tbl, None, &filters), vec![1]);
+}
@@ -0,0 +1,221 @@
+use sqlparser::ast::Statement;
+use super::sql::parse_sql;
+use super::split::{self, split_sql, Dialect};
+use super::macros::{self, Commands, Variables};
+use super::meta::MetaCommand;
+
+/// Safety settings of a connection, kept by its SqlListener together with the query limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SafetyMode {
+
+    /// Refuse any statement that writes to the database. The engine is also
+    /// configured as read-only, so statements that escape the client-side
+    /// classification are still rejected by the server.
+    pub read_only : bool,
+
+    /// Connection points to a production database: destructive statements
+    /// require a typed confirmation before being sent.
+    pub production : bool
+}
+
+/// Effect of a statement over the database, as far as the client can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementClass {
+
+    /// Queries, transaction control and settings which do not affect the
+    /// read-only mode of the session.
+    Read,
+
+    /// Statements which add data or objects (insert, copy from, create, grant)
+    /// and any other statement not listed here.
+    Write,
+
+    /// Statements which change or remove existing data or objects (update, delete,
+    /// drop, alter, truncate and revoke, also within a with clause) and settings
+    /// which lift the read-only mode of the session.
+    Destructive
+}
+
+fn classify_parsed(stmt : &Statement, dialect : Dialect) -> StatementClass {
+    match stmt {
+        Statement::Query(_) => StatementClass::Read,
+        Statement::Insert{ .. } | Statement::Copy{ .. } => StatementClass::Write,
+        Statement::Update{ .. } | Statement::Delete{ .. } |
+        Statement::Drop{ .. } | Statement::AlterTable{ .. } => StatementClass::Destructive,
+        other => classify_raw(&other.to_string(), dialect)
+    }
+}
+
+/// Words which may precede the statement analyzed by explain analyze.
+const EXPLAIN_OPTIONS : [&'static str; 13] = [
+    "analyze", "analyse", "verbose", "costs", "settings", "buffers", "wal",
+    "timing", "summary", "format", "true", "false", "on"
+];
+
+/// Classifies a statement by its words (as returned by split::statement_words).
+fn classify_words(words : &[String]) -> StatementClass {
+    let has = |word : &str| words.iter().any(|w| w == word );
+    let modifies = || has("update") || has("delete") || has("truncate") || has("drop") || has("alter");
+    match words.first().map(|w| &w[..] ) {
+        Some("select") if has("into") => StatementClass::Write,
+        Some("select") | Some("values") | Some("table") | Some("show") | Some("describe") |
+        Some("begin") | Some("start") | Some("commit") | Some("end") | Some("rollback") |
+        Some("savepoint") | Some("release") => StatementClass::Read,
+        Some("with") if modifies() => StatementClass::Destructive,
+        Some("with") if has("insert") || has("into") => StatementClass::Write,
+        Some("with") => StatementClass::Read,
+        Some("explain") if has("analyze") || has("analyse") => {
+
+            // The analyzed statement is executed.
+            let stmt : Vec<String> = words[1..].iter()
+                .skip_while(|w| EXPLAIN_OPTIONS.contains(&&w[..]) )
+                .cloned()
+                .collect();
+            classify_words(&stmt[..])
+        },
+        Some("explain") => StatementClass::Read,
+        Some("copy") if has("to") => StatementClass::Read,
+        Some("set") | Some("reset") | Some("pragma") => {
+            let lifts_read_only = words.iter().any(|w| w.contains("read_only") || w == "query_only" ) ||
+                (has("read") && has("write")) ||
+                (words[0] == "reset" && has("all"));
+            if lifts_read_only {
+                StatementClass::Destructive
+            } else {
+                StatementClass::Read
+            }
+        },
+        Some("create") if has("replace") => StatementClass::Destructive,
+        Some("update") | Some("delete") | Some("drop") | Some("alter") | Some("truncate") |
+        Some("revoke") => StatementClass::Destructive,
+        _ => StatementClass::Write
+    }
+}
+
+/// The class of whichever statement has the larger effect.
+fn strongest(a : StatementClass, b : StatementClass) -> StatementClass {
+    match (a, b) {
+        (StatementClass::Destructive, _) | (_, StatementClass::Destructive) => StatementClass::Destructive,
+        (StatementClass::Write, _) | (_, StatementClass::Write) => StatementClass::Write,
+        _ => StatementClass::Read
+    }
+}
+
+/// Classifies a meta-command. Listing and display commands only read the catalog, \copy is
+/// classified as the copy statement it runs, and includes by the statements of the included
+/// file. Variables are resolved before a sequence is sent, so \set (and includes which cannot
+/// be resolved here, e.g. relative paths) are taken as writes.
+fn classify_meta(stmt : &str, dialect : Dialect) -> StatementClass {
+    let name = stmt[1..].split(char::is_whitespace).next().unwrap_or("");
+    match name {
+        "i" | "include" => {
+            match macros::expand(stmt, None, &mut Variables::new(), Commands::Keep) {
+                Ok(exp) => match classify(&exp.sql, dialect) {
+                    Ok(stmts) => stmts.iter().fold(StatementClass::Read, |class, (_, c)| strongest(class, *c) ),
+                    Err(_) => StatementClass::Write
+                },
+                Err(_) => StatementClass::Write
+            }
+        },
+        _ => match MetaCommand::parse(stmt) {
+            Ok(MetaCommand::Copy(arg)) => classify_raw(&format!("copy {}", arg), dialect),
+            Ok(_) => StatementClass::Read,
+            Err(_) => StatementClass::Write
+        }
+    }
+}
+
+/// Classifies a statement which could not be parsed by its words. Statements which cannot
+/// be read are taken as writes.
+fn classify_raw(stmt : &str, dialect : Dialect) -> StatementClass {
+    let trimmed = stmt.trim_start();
+    if trimmed.starts_with('\\') {
+        return classify_meta(trimmed, dialect);
+    }
+    match split::statement_words(stmt, dialect) {
+        Ok(words) => classify_words(&words[..]),
+        Err(_) => StatementClass::Write
+    }
+}
+
+/// Classifies each statement of a sequence, returning it together with its text.
+/// The sequence is parsed as a whole first; if parsing fails (e.g. because of
+/// engine-specific syntax), the sequence is split according to the dialect and each
+/// statement is classified by its words.
+pub fn classify(sql : &str, dialect : Dialect) -> Result<Vec<(String, StatementClass)>, String> {
+    match parse_sql(sql) {
+        Ok(stmts) => Ok(stmts.iter().map(|s| (s.to_string(), classify_parsed(s, dialect)) ).collect()),
+        Err(_) => {
+            let stmts = split_sql(sql, dialect)?;
+            Ok(stmts.into_iter().map(|(s, _)| { let class = classify_raw(&s, dialect); (s, class) }).collect())
+        }
+    }
+}
+
+/// Returns an error naming the first statement which writes to the database.
+pub fn check_read_only(sql : &str, dialect : Dialect) -> Result<(), String> {
+    for (stmt, class) in classify(sql, dialect)? {
+        if class != StatementClass::Read {
+            let first_line = stmt.lines().next().unwrap_or("");
+            return Err(format!("Statement refused (connection is read-only): {}", first_line));
+        }
+    }
+    Ok(())
+}
+
+/// Statements of the sequence which require confirmation at production connections.
+pub fn destructive_statements(sql : &str, dialect : Dialect) -> Vec<String> {
+    classify(sql, dialect)
+        .map(|stmts| {
+            stmts.into_iter()
+                .filter(|(_, class)| *class == StatementClass::Destructive )
+                .map(|(stmt, _)| stmt )
+                .collect()
+        })
+        .unwrap_or(Vec::new())
+}
+
+#[test]
+fn classify_statements() {
+    let pg = Dialect::Postgres;
+    assert!(check_read_only("select * from a; select 1;", pg).is_ok());
+    assert!(check_read_only("select 1; delete from a;", pg).is_err());
+    assert!(check_read_only("truncate table a;", pg).is_err());
+    assert!(check_read_only("copy a from stdin;", pg).is_err());
+    assert!(check_read_only("copy a to stdout;", pg).is_ok());
+    assert_eq!(destructive_statements("insert into a values (1); drop table b;", pg).len(), 1);
+
+    // Statements which are not known to be reads are taken as writes.
+    assert!(check_read_only("create table c (a integer);", pg).is_err());
+    assert!(check_read_only("create index on a (b);", pg).is_err());
+    assert!(check_read_only("grant select on a to someone;", pg).is_err());
+    assert!(check_read_only("vacuum a;", pg).is_err());
+    assert!(check_read_only("select 'delete' from a; set search_path = public; \\d a", pg).is_ok());
+    assert!(check_read_only("explain select * from a;", pg).is_ok());
+    assert_eq!(destructive_statements("explain analyze delete from a;", pg).len(), 1);
+    assert_eq!(destructive_statements("with d as (delete from a returning *) select * from d;", pg).len(), 1);
+    assert!(check_read_only("with i as (insert into a values (1) returning *) select * from i;", pg).is_err());
+    assert!(check_read_only("with t as (select 1) select * from t;", pg).is_ok());
+    assert_eq!(destructive_statements("set default_transaction_read_only = off;", pg).len(), 1);
+    assert_eq!(destructive_statements("set session characteristics as transaction read write;", pg).len(), 1);
+    assert_eq!(destructive_statements("pragma query_only = 0;", Dialect::Sqlite).len(), 1);
+}
+
+#[test]
+fn classify_meta_commands() {
+    let pg = Dialect::Postgres;
+    assert!(check_read_only("\\copy a from 'a.csv' csv", pg).is_err());
+    assert!(check_read_only("\\copy (select * from a) to 'a.csv' csv", pg).is_ok());
+    assert!(check_read_only("\\dt\n\\timing on", pg).is_ok());
+    assert!(check_read_only("\\set n 1", pg).is_err());
+
+    // Includes are classified by the statements of the included file.
+    let dir = std::env::temp_dir().join(format!("queries-safety-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("read.sql"), "select 1;").unwrap();
+    std::fs::write(dir.join("drop.sql"), "select 1;\ndrop table a;").unwrap();
+    assert!(check_read_only(&format!("\\i {}", dir.join("read.sql").display()), pg).is_ok());
+    assert_eq!(destructive_statements(&format!("\\i {}", dir.join("drop.sql").display()), pg).len(), 1);
+    assert!(check_read_only("\\i relative.sql", pg).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,298 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::edit;
+
+/// Directory holding the snippet files, relative to the working directory
+/// (next to assets/editor, which holds the other editor resources).
+pub const SNIPPETS_DIR : &'static str = "assets/snippets";
+
+/// A SQL template, stored as a .sql file named after the snippet. The leading comment lines
+/// of the file describe the snippet; the remaining text is inserted at the editor.
+///
+/// The body may hold tab stops written as ${1} or ${1:default text}, which are visited in
+/// increasing order (${0}, if present, is visited last). Stops sharing an index are filled
+/// with the text given at the first of them. The body may also hold the context variables
+/// $SCHEMA, $TABLE (qualified by the schema, if it is known), $COLUMN (the first selected
+/// column) and $COLUMNS (all selected columns, separated by commas), which are replaced by
+/// the current selection (quoted when their names are not plain lowercase identifiers), or by
+/// their lowercase name when nothing is selected. Any other
+/// $ sequence, such as a ${command} macro, is kept as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub name : String,
+    pub description : String,
+    pub body : String
+}
+
+impl Snippet {
+
+    pub fn parse(name : &str, content : &str) -> Self {
+        let mut description = Vec::new();
+        let mut lines = content.lines().peekable();
+        while let Some(line) = lines.peek() {
+            if line.trim_start().starts_with("--") {
+                description.push(line.trim_start()[2..].trim().to_string());
+                lines.next();
+            } else {
+                break;
+            }
+        }
+        let body = lines.collect::<Vec<_>>().join("\n").trim_matches('\n').to_string();
+        Self { name : name.to_string(), description : description.join(" "), body }
+    }
+
+    /// Content of the snippet file.
+    pub fn to_file_content(&self) -> String {
+        let mut content = String::new();
+        if !self.description.is_empty() {
+            content += &format!("-- {}\n", self.description);
+        }
+        content += &self.body;
+        content.push('\n');
+        content
+    }
+
+    /// Fills the snippet with the selection, returning the text to be inserted and the
+    /// character offsets (start and end, relative to the text) of the tab stops, grouped
+    /// by index, in the order they should be visited.
+    pub fn instantiate(&self, ctx : &SnippetContext) -> (String, Vec<Vec<(usize, usize)>>) {
+        let mut text = String::new();
+        let mut stops : Vec<(usize, usize, usize)> = Vec::new();
+        let mut rest = &self.body[..];
+        while let Some(pos) = rest.find("${") {
+            let (before, after) = rest.split_at(pos);
+            text += &ctx.substitute(before);
+            match parse_stop(&after[2..]) {
+                Some((ix, default, len)) => {
+                    let start = text.chars().count();
+                    text += &ctx.substitute(default);
+                    stops.push((if ix == 0 { usize::MAX } else { ix }, start, text.chars().count()));
+                    rest = &after[2+len..];
+                },
+                None => {
+                    text += "${";
+                    rest = &after[2..];
+                }
+            }
+        }
+        text += &ctx.substitute(rest);
+        stops.sort_by_key(|(ix, start, _)| (*ix, *start) );
+        let mut groups : Vec<(usize, Vec<(usize, usize)>)> = Vec::new();
+        for (ix, start, end) in stops {
+            match groups.last_mut() {
+                Some((last_ix, group)) if *last_ix == ix => group.push((start, end)),
+                _ => groups.push((ix, vec![(start, end)]))
+            }
+        }
+        (text, groups.into_iter().map(|(_, group)| group ).collect())
+    }
+
+}
+
+/// Parses the text of a tab stop after ${, returning its index, default text and the length of
+/// the stop text (up to and including the closing brace). Returns None if the text is not a stop.
+fn parse_stop(text : &str) -> Option<(usize, &str, usize)> {
+    let end = text.find('}')?;
+    let inner = &text[..end];
+    let (ix, default) = match inner.find(':') {
+        Some(pos) => (&inner[..pos], &inner[pos+1..]),
+        None => (inner, "")
+    };
+    if ix.is_empty() || !ix.chars().all(|c| c.is_ascii_digit() ) {
+        return None;
+    }
+    Some((ix.parse().ok()?, default, end + 1))
+}
+
+/// Objects currently selected at the schema tree or at the table notebook.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnippetContext {
+    pub schema : Option<String>,
+    pub table : Option<String>,
+    pub columns : Vec<String>
+}
+
+/// Name as written at a statement: plain lowercase identifiers are kept as they are, and any
+/// other name (with uppercase letters, spaces or symbols) is quoted.
+fn identifier(name : &str) -> String {
+    let plain = name.chars().next().map(|c| c.is_ascii_lowercase() || c == '_' ).unwrap_or(false) &&
+        name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' );
+    if plain {
+        name.to_string()
+    } else {
+        edit::quote_identifier(name)
+    }
+}
+
+impl SnippetContext {
+
+    fn substitute(&self, text : &str) -> String {
+        let schema = self.schema.as_ref().map(|s| identifier(s) );
+        let table = match (&schema, &self.table) {
+            (Some(schema), Some(table)) => format!("{}.{}", schema, identifier(table)),
+            (None, Some(table)) => identifier(table),
+            (_, None) => String::from("table")
+        };
+        let columns : Vec<String> = self.columns.iter().map(|c| identifier(c) ).collect();
+        let all_columns = if columns.is_empty() {
+            String::from("columns")
+        } else {
+            columns.join(", ")
+        };
+        text.replace("$COLUMNS", &all_columns)
+            .replace("$COLUMN", columns.get(0).map(|c| &c[..] ).unwrap_or("column"))
+            .replace("$TABLE", &table)
+            .replace("$SCHEMA", schema.as_ref().map(|s| &s[..] ).unwrap_or("schema"))
+    }
+
+}
+
+/// Snippet files of a directory.
+pub struct SnippetLibrary {
+    dir : PathBuf
+}
+
+impl SnippetLibrary {
+
+    pub fn new(dir : &Path) -> Self {
+        Self { dir : dir.to_path_buf() }
+    }
+
+    pub fn open_default() -> Self {
+        Self::new(Path::new(SNIPPETS_DIR))
+    }
+
+    fn path(&self, name : &str) -> PathBuf {
+        self.dir.join(format!("{}.sql", name))
+    }
+
+    /// Snippets of the library, in alphabetical order.
+    pub fn list(&self) -> Result<Vec<Snippet>, String> {
+        let mut snippets = Vec::new();
+        if !self.dir.exists() {
+            return Ok(snippets);
+        }
+        let entries = fs::read_dir(&self.dir).map_err(|e| format!("Unable to read snippets: {}", e) )?;
+        for entry in entries.filter_map(|e| e.ok() ) {
+            let path = entry.path();
+            if let Some(snippet) = read_snippet(&path) {
+                snippets.push(snippet?);
+            }
+        }
+        snippets.sort_by(|a, b| a.name.cmp(&b.name) );
+        Ok(snippets)
+    }
+
+    /// Copies the snippet files into the library. A snippet with the name of a different snippet
+    /// of the library is saved under the first free name with a numeric suffix (e.g. report_2), so
+    /// no snippet is replaced. Returns the names of the imported snippets with the names they were
+    /// saved under.
+    pub fn import(&self, paths : &[PathBuf]) -> Result<Vec<(String, String)>, String> {
+        let mut imported = Vec::new();
+        for path in paths.iter() {
+            let mut snippet = read_snippet(path)
+                .ok_or(format!("{} is not a snippet (.sql) file", path.display()))??;
+            let name = snippet.name.clone();
+            let mut n = 1;
+            loop {
+                match read_snippet(&self.path(&snippet.name)) {
+                    Some(Ok(existing)) if existing.body != snippet.body || existing.description != snippet.description => {
+                        n += 1;
+                        snippet.name = format!("{}_{}", name, n);
+                    },
+                    _ => break
+                }
+            }
+            self.save(&snippet)?;
+            imported.push((name, snippet.name));
+        }
+        Ok(imported)
+    }
+
+    /// Copies all snippets of the library into the directory, so they can be shared.
+    /// Returns the number of snippets exported.
+    pub fn export(&self, dir : &Path) -> Result<usize, String> {
+        let snippets = self.list()?;
+        for snippet in snippets.iter() {
+            SnippetLibrary::new(dir).save(snippet)?;
+        }
+        Ok(snippets.len())
+    }
+
+    pub fn save(&self, snippet : &Snippet) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Unable to create snippet directory: {}", e) )?;
+        fs::write(self.path(&snippet.name), snippet.to_file_content())
+            .map_err(|e| format!("Unable to write snippet {}: {}", snippet.name, e) )
+    }
+
+    pub fn remove(&self, name : &str) -> Result<(), String> {
+        fs::remove_file(self.path(name)).map_err(|e| format!("Unable to remove snippet {}: {}", name, e) )
+    }
+
+}
+
+/// Reads the snippet file at path, returning None if the path is not a .sql file.
+fn read_snippet(path : &Path) -> Option<Result<Snippet, String>> {
+    if path.extension().and_then(|ext| ext.to_str() ) != Some("sql") {
+        return None;
+    }
+    let name = path.file_stem()?.to_str()?;
+    Some(fs::read_to_string(path)
+        .map(|content| Snippet::parse(name, &content) )
+        .map_err(|e| format!("Unable to read snippet {}: {}", path.display(), e) ))
+}
+
+#[test]
+fn instantiate_snippet() {
+    let snippet = Snippet::parse(
+        "running_total",
+        "-- Running total\n-- over an ordered partition\nselect $COLUMNS, sum(${2:$COLUMN}) over \
+        (partition by ${1:$COLUMN} order by ${3}) from $TABLE${0};\n"
+    );
+    assert_eq!(snippet.description, "Running total over an ordered partition");
+    let ctx = SnippetContext {
+        schema : Some(String::from("sales")),
+        table : Some(String::from("orders")),
+        columns : vec![String::from("region"), String::from("amount")]
+    };
+    let (text, stops) = snippet.instantiate(&ctx);
+    assert_eq!(text, "select region, amount, sum(region) over (partition by region order by ) from sales.orders;");
+    let at = |(start, end) : (usize, usize)| text.chars().skip(start).take(end - start).collect::<String>();
+    assert_eq!(stops.len(), 4);
+    assert_eq!(at(stops[0][0]), "region");
+    assert_eq!(stops[0][0].0, text.find("region order").unwrap());
+    assert_eq!(stops[2], vec![(text.find(") from").unwrap(), text.find(") from").unwrap())]);
+    assert_eq!(stops[3][0].0, text.len() - 1);
+    let (text, stops) = Snippet::parse("mirror", "${1:id} = t.${1:id}").instantiate(&ctx);
+    assert_eq!(text, "id = t.id");
+    assert_eq!(stops, vec![vec![(0, 2), (7, 9)]]);
+    let (text, _) = Snippet::parse("macro", "select ${date +%F}, $TABLE").instantiate(&SnippetContext::default());
+    assert_eq!(text, "select ${date +%F}, table");
+}
+
+#[test]
+fn quote_context_names() {
+    let ctx = SnippetContext {
+        schema : Some(String::from("Sales")),
+        table : Some(String::from("order items")),
+        columns : vec![String::from("id"), String::from("Total")]
+    };
+    let (text, _) = Snippet::parse("q", "select $COLUMNS from $TABLE where $COLUMN > 0").instantiate(&ctx);
+    assert_eq!(text, "select id, \"Total\" from \"Sales\".\"order items\" where id > 0");
+}
+
+#[test]
+fn import_keeps_existing_snippets() {
+    let dir = std::env::temp_dir().join(format!("queries-snippets-{}", std::process::id()));
+    let library = SnippetLibrary::new(&dir.join("library"));
+    library.save(&Snippet::parse("report", "select 1")).unwrap();
+    let src = dir.join("report.sql");
+    fs::write(&src, "select 2\n").unwrap();
+    let imported = library.import(&[src.clone()]).unwrap();
+    assert_eq!(imported, vec![(String::from("report"), String::from("report_2"))]);
+    assert_eq!(library.list().unwrap().len(), 2);
+
+    // Importing the same file again finds it at report_2.
+    assert_eq!(library.import(&[src]).unwrap()[0].1, "report_2");
+    fs::remove_dir_all(&dir).unwrap();
+}
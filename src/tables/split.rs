@@ -0,0 +1,432 @@
+use std::ops::Range;
+use super::sql::ErrorDetail;
+
+/// Lexical rules which differ between the supported engines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+
+    /// Dollar-quoted strings ($$ ... $$ or $tag$ ... $tag$), escape strings
+    /// (E'...') and nested block comments.
+    Postgres,
+
+    /// Identifiers quoted with brackets ([...]) or backticks (`...`).
+    Sqlite
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+
+    /// Unquoted word (keyword or identifier), in lowercase.
+    Word(String),
+
+    LParen,
+
+    RParen,
+
+    SemiColon,
+
+    /// Client-side (psql-style) meta-command, spanning from a backslash (outside literals
+    /// and comments) to the end of the line. Holds the command name (without the backslash and the + modifier).
+    Meta(String),
+
+    /// Literals, quoted identifiers, operators and any other character. Comments
+    /// and whitespace are not represented, since they do not affect splitting.
+    Other
+}
+
+/// Position of the token at the original text, in bytes.
+type Span = (usize, usize);
+
+fn is_word_start(c : char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_word_char(c : char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Returns the byte position just after the closing delimiter of a literal starting at
+/// start (which points to the opening quote). A doubled quote character escapes it; if
+/// backslash is true, a backslash escapes the next character.
+fn end_of_quoted(sql : &str, start : usize, quote : char, backslash : bool) -> Result<usize, String> {
+    let mut chars = sql[start..].char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if backslash && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            match chars.peek() {
+                Some((_, next)) if *next == quote => { chars.next(); },
+                _ => return Ok(start + i + c.len_utf8())
+            }
+        }
+    }
+    Err(format!("Unterminated quoted literal or identifier starting at: {}", first_line(&sql[start..])))
+}
+
+/// Returns the dollar-quote tag ($$ or $tag$) starting at start, if any. Positional
+/// parameters ($1) and identifiers containing $ are not dollar quotes.
+pub(crate) fn dollar_tag(sql : &str, start : usize) -> Option<&str> {
+    let rest = &sql[start + 1..];
+    let tag_len = rest.find('$')?;
+    let tag = &rest[..tag_len];
+    let valid = match tag.chars().next() {
+        Some(c) => is_word_start(c) && tag.chars().all(|c| c.is_alphanumeric() || c == '_'),
+        None => true
+    };
+    if valid {
+        Some(&sql[start..start + tag_len + 2])
+    } else {
+        None
+    }
+}
+
+fn first_line(s : &str) -> &str {
+    s.lines().next().unwrap_or("")
+}
+
+fn tokenize(sql : &str, dialect : Dialect) -> Result<Vec<(Token, Span)>, String> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = sql[pos..].chars().next() {
+        let rest = &sql[pos..];
+        let start = pos;
+        let token = if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        } else if c == '\\' {
+            pos += rest.find('\n').unwrap_or(rest.len());
+            let name = rest[1..].split(char::is_whitespace).next().unwrap_or("");
+            Token::Meta(name.trim_end_matches('+').to_string())
+        } else if rest.starts_with("--") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        } else if rest.starts_with("/*") {
+            // Postgres block comments nest; SQLite block comments end at the first */.
+            let mut depth = 0;
+            let mut i = 0;
+            loop {
+                if rest[i..].starts_with("/*") && (depth == 0 || dialect == Dialect::Postgres) {
+                    depth += 1;
+                    i += 2;
+                } else if rest[i..].starts_with("*/") {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else if let Some(c) = rest[i..].chars().next() {
+                    i += c.len_utf8();
+                } else {
+                    return Err(format!("Unterminated comment starting at: {}", first_line(rest)));
+                }
+            }
+            pos += i;
+            continue;
+        } else if c == '\'' {
+            pos = end_of_quoted(sql, pos, '\'', false)?;
+            Token::Other
+        } else if c == '"' {
+            pos = end_of_quoted(sql, pos, '"', false)?;
+            Token::Other
+        } else if dialect == Dialect::Sqlite && c == '`' {
+            pos = end_of_quoted(sql, pos, '`', false)?;
+            Token::Other
+        } else if dialect == Dialect::Sqlite && c == '[' {
+            pos += rest.find(']')
+                .ok_or(format!("Unterminated identifier starting at: {}", first_line(rest)))? + 1;
+            Token::Other
+        } else if dialect == Dialect::Postgres && (c == 'e' || c == 'E') && rest[1..].starts_with('\'') {
+            pos = end_of_quoted(sql, pos + 1, '\'', true)?;
+            Token::Other
+        } else if is_word_start(c) {
+            let len = rest.find(|c : char| !is_word_char(c) ).unwrap_or(rest.len());
+            pos += len;
+            Token::Word(rest[..len].to_lowercase())
+        } else if dialect == Dialect::Postgres && c == '$' && dollar_tag(sql, pos).is_some() {
+            let tag = dollar_tag(sql, pos).unwrap();
+            let body_start = pos + tag.len();
+            let body_len = sql[body_start..].find(tag)
+                .ok_or(format!("Unterminated dollar-quoted string starting at: {}", first_line(rest)))?;
+            pos = body_start + body_len + tag.len();
+            Token::Other
+        } else {
+            pos += c.len_utf8();
+            match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ';' => Token::SemiColon,
+                _ => Token::Other
+            }
+        };
+        tokens.push((token, (start, pos)));
+    }
+    Ok(tokens)
+}
+
+fn is_word(tk : &Token, word : &str) -> bool {
+    match tk {
+        Token::Word(w) => &w[..] == word,
+        _ => false
+    }
+}
+
+/// Words of the statement outside parenthesis, used to classify it.
+fn top_level_words(tokens : &[(Token, Span)]) -> Vec<&str> {
+    let mut depth = 0;
+    let mut words = Vec::new();
+    for (tk, _) in tokens.iter() {
+        match tk {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::Word(w) if depth <= 0 => words.push(&w[..]),
+            _ => { }
+        }
+    }
+    words
+}
+
+/// Whether the statement body (or compound statement) of a create trigger (SQLite) or
+/// create function/procedure with begin atomic (Postgres) starts at the token at ix. Semicolons
+/// within such bodies do not end the statement.
+fn opens_block(stmt : &[(Token, Span)], ix : usize) -> bool {
+    if !is_word(&stmt[ix].0, "begin") {
+        return false;
+    }
+    let words = top_level_words(&stmt[..ix]);
+    let mut leading = words.iter()
+        .filter(|w| !["create", "or", "replace", "temp", "temporary"].contains(*w) );
+    match leading.next() {
+        Some(&"trigger") => true,
+        Some(&"function") | Some(&"procedure") => {
+            stmt.get(ix + 1).map(|(tk, _)| is_word(tk, "atomic") ).unwrap_or(false)
+        },
+        _ => false
+    }
+}
+
+/// Original text of a (non-empty) statement, from its first to its last token.
+fn statement_text(sql : &str, stmt : &[(Token, Span)]) -> String {
+    let (start, _) = stmt[0].1;
+    let (_, end) = stmt[stmt.len() - 1].1;
+    sql[start..end].to_string()
+}
+
+/// Whether the statement returns rows, and so should be run as a query.
+fn returns_rows(stmt : &[(Token, Span)]) -> bool {
+    if let Some((Token::Meta(name), _)) = stmt.first() {
+        return ["d", "dt", "dv", "df", "dn", "l"].contains(&&name[..]);
+    }
+    let words = top_level_words(stmt);
+    match words.first() {
+        Some(&"select") | Some(&"values") | Some(&"table") | Some(&"show") |
+        Some(&"explain") | Some(&"pragma") | Some(&"fetch") => true,
+        Some(&"with") => {
+            // The main statement of a common table expression is the first top-level
+            // statement keyword (the expressions themselves are within parenthesis).
+            let main = words.iter().skip(1).find(|w| {
+                ["select", "values", "table", "insert", "update", "delete"].contains(*w)
+            });
+            match main {
+                Some(&"insert") | Some(&"update") | Some(&"delete") => words.contains(&"returning"),
+                _ => true
+            }
+        },
+        Some(&"insert") | Some(&"update") | Some(&"delete") | Some(&"replace") => {
+            words.contains(&"returning")
+        },
+        _ => false
+    }
+}
+
+/// Groups the tokens into statements, returning the tokens of each non-empty statement.
+fn group_statements(tokens : &[(Token, Span)]) -> Vec<Range<usize>> {
+    let mut stmts = Vec::new();
+    let mut stmt_start = 0;
+    let mut block_depth = 0;
+    for ix in 0..tokens.len() {
+        let stmt = &tokens[stmt_start..];
+        let stmt_ix = ix - stmt_start;
+        match &tokens[ix].0 {
+            Token::Word(_) if block_depth == 0 => {
+                if opens_block(stmt, stmt_ix) {
+                    block_depth += 1;
+                }
+            },
+            Token::Word(w) => {
+                // Case expressions within the body also terminate with end.
+                match &w[..] {
+                    "begin" | "case" => block_depth += 1,
+                    "end" => block_depth -= 1,
+                    _ => { }
+                }
+            },
+            Token::SemiColon if block_depth == 0 => {
+                if ix > stmt_start {
+                    stmts.push(stmt_start..ix);
+                }
+                stmt_start = ix + 1;
+            },
+
+            // Meta-commands end at the line break, and also end any statement before them.
+            Token::Meta(_) if block_depth == 0 => {
+                if ix > stmt_start {
+                    stmts.push(stmt_start..ix);
+                }
+                stmts.push(ix..ix + 1);
+                stmt_start = ix + 1;
+            },
+            _ => { }
+        }
+    }
+    if stmt_start < tokens.len() {
+        stmts.push(stmt_start..tokens.len());
+    }
+    stmts
+}
+
+/// Splits a query sequence into its statements, verifying if each statement returns rows
+/// (select, values, table, show, explain and pragma statements, common table expressions
+/// and insert/update/delete statements with a returning clause). This is used when sqlparser
+/// is unable to parse a query due to engine-specific SQL extensions, and so does not rely
+/// on it: semicolons within literals, quoted identifiers, comments, dollar-quoted strings and
+/// trigger bodies do not split statements. Statements are returned without the trailing
+/// semicolon; statements with only comments are ignored. A backslash starts a meta-command,
+/// which extends to the end of the line and forms a statement by itself. Fails if a literal or comment
+/// is not terminated.
+pub fn split_sql(sql : &str, dialect : Dialect) -> Result<Vec<(String, bool)>, String> {
+    let tokens = tokenize(sql, dialect)?;
+    Ok(group_statements(&tokens).into_iter()
+        .map(|range| {
+            let stmt = &tokens[range];
+            (statement_text(sql, stmt), returns_rows(stmt))
+        })
+        .collect())
+}
+
+/// Unquoted words (keywords and identifiers) of a statement, in lowercase and in the order they
+/// appear. Words within literals, quoted identifiers and comments are not returned.
+pub fn statement_words(sql : &str, dialect : Dialect) -> Result<Vec<String>, String> {
+    Ok(tokenize(sql, dialect)?.into_iter()
+        .filter_map(|(token, _)| match token {
+            Token::Word(w) => Some(w),
+            _ => None
+        })
+        .collect())
+}
+
+/// Byte position (start and end) of each statement of the sequence, as split by split_sql.
+pub fn statement_spans(sql : &str, dialect : Dialect) -> Result<Vec<Span>, String> {
+    let tokens = tokenize(sql, dialect)?;
+    Ok(group_statements(&tokens).into_iter()
+        .map(|range| (tokens[range.start].1 .0, tokens[range.end - 1].1 .1) )
+        .collect())
+}
+
+/// Index of the statement (as returned by statement_spans) holding the byte position. If the
+/// position is between statements (e.g. just after a semicolon), the statement which precedes
+/// it is returned, or the first statement if there is none. Returns None for empty sequences.
+pub fn statement_at(spans : &[Span], pos : usize) -> Option<usize> {
+    spans.iter().rposition(|(start, _)| *start <= pos )
+        .or(if spans.len() > 0 { Some(0) } else { None })
+}
+
+/// Index of the token at (or, if the position is between tokens, just after) the byte position.
+fn token_at(tokens : &[(Token, Span)], pos : usize) -> Option<usize> {
+    tokens.iter().position(|(_, (_, end))| *end > pos )
+        .or(if tokens.len() > 0 { Some(tokens.len() - 1) } else { None })
+}
+
+/// Byte position (start and end) within the query sequence of the error reported for the statement
+/// at stmt_ix. When the engine informed the error position, the span of the offending token is
+/// returned; otherwise the span of the whole statement is returned. If the statement was formatted
+/// again before being sent, the offending token is matched by its index within the statement.
+pub fn locate_error(sql : &str, dialect : Dialect, stmt_ix : usize, detail : &ErrorDetail) -> Option<Span> {
+    let (start, end) = *statement_spans(sql, dialect).ok()?.get(stmt_ix)?;
+    let pos = match detail.position {
+        Some(pos) => pos,
+        None => return Some((start, end))
+    };
+    let sent = &detail.statement[..];
+    let byte_pos = sent.char_indices().nth(pos).map(|(i, _)| i ).unwrap_or(sent.len());
+    let orig_tokens = tokenize(&sql[start..end], dialect).ok()?;
+    let tk_ix = if sent.trim() == &sql[start..end] {
+        let offset = sent.len() - sent.trim_start().len();
+        token_at(&orig_tokens, byte_pos.saturating_sub(offset))
+    } else {
+        let sent_tokens = tokenize(sent, dialect).ok()?;
+        token_at(&sent_tokens, byte_pos)
+    };
+    match tk_ix.and_then(|ix| orig_tokens.get(ix) ) {
+        Some((_, (tk_start, tk_end))) => Some((start + tk_start, start + tk_end)),
+        None => Some((start, end))
+    }
+}
+
+#[test]
+fn split_statements() {
+    let pg = "select ';' as a; -- comment; here\n\
+        create function f() returns int as $body$ begin return 1; end; $body$ language plpgsql;\n\
+        Insert into t values (1) RETURNING id; VALUES (1);";
+    let stmts = split_sql(pg, Dialect::Postgres).unwrap();
+    assert_eq!(stmts.len(), 4);
+    assert_eq!(&stmts[0].0[..], "select ';' as a");
+    assert_eq!(
+        stmts.iter().map(|s| s.1 ).collect::<Vec<_>>(),
+        vec![true, false, true, true]
+    );
+
+    let lite = "create trigger tr after insert on t begin \
+        update t set a = case when new.a > 1 then 1 else 0 end; delete from u; end; \
+        /* c; */ with a as (select 1) delete from t; pragma table_info([a;b]);";
+    let stmts = split_sql(lite, Dialect::Sqlite).unwrap();
+    assert_eq!(stmts.len(), 3);
+    assert_eq!(
+        stmts.iter().map(|s| s.1 ).collect::<Vec<_>>(),
+        vec![false, false, true]
+    );
+    assert!(split_sql("select 'a;", Dialect::Sqlite).is_err());
+}
+
+#[test]
+fn error_location() {
+    let sql = "select 1;\nselect * form t;";
+    let mut detail = ErrorDetail::from_statement("select * form t");
+    detail.position = Some(9);
+    let (start, end) = locate_error(sql, Dialect::Postgres, 1, &detail).unwrap();
+    assert_eq!(&sql[start..end], "form");
+
+    // Statement formatted again at the client: token matched by its index.
+    let sql = "SELECT   a,b FROM t WHERE c = = 1";
+    let mut detail = ErrorDetail::from_statement("SELECT a, b FROM t WHERE c = = 1");
+    detail.position = Some(29);
+    let (start, end) = locate_error(sql, Dialect::Postgres, 0, &detail).unwrap();
+    assert_eq!(&sql[start..end], "=");
+    assert_eq!(start, 30);
+}
+
+#[test]
+fn statement_under_cursor() {
+    let sql = "select 1;\n\nselect 2; select 3";
+    let spans = statement_spans(sql, Dialect::Postgres).unwrap();
+    assert_eq!(statement_at(&spans, 0), Some(0));
+    assert_eq!(statement_at(&spans, 9), Some(0));
+    assert_eq!(statement_at(&spans, 11), Some(1));
+    assert_eq!(statement_at(&spans, sql.len()), Some(2));
+    assert_eq!(statement_at(&[], 0), None);
+}
+
+#[test]
+fn meta_commands() {
+    let sql = "\\timing on\nselect 1\n\\dt+ public.*\nselect '\n\\x'; \\echo done";
+    let stmts = split_sql(sql, Dialect::Postgres).unwrap();
+    assert_eq!(
+        stmts,
+        vec![
+            (String::from("\\timing on"), false),
+            (String::from("select 1"), true),
+            (String::from("\\dt+ public.*"), true),
+            (String::from("select '\n\\x'"), true),
+            (String::from("\\echo done"), false)
+        ]
+    );
+}
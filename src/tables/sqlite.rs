@@ -5,6 +5,10 @@ use super::table::*;
 use rusqlite::types::FromSql;
 use rusqlite::Row;
 use std::fmt::{self, Display};
+use rusqlite::types::ValueRef;
+use std::time::{Instant, Duration};
+use super::limits::{QueryLimits, FetchCounter};
+use super::edit::{TableColumn, EditStatement, EditValue};
 
 #[derive(Debug, Clone)]
 pub enum SqliteColumn {
@@ -160,7 +164,82 @@ impl From<SqliteColumn> for NullableColumn
     }
 }
 
-pub fn build_table_from_sqlite(mut rows : rusqlite::Rows) -> Result<Table, &'static str>
+/// Estimated size of the values at a row, in bytes.
+fn row_size(row : &Row, ncols : usize) -> usize {
+    (0..ncols).map(|i| match row.get_raw(i) {
+        ValueRef::Null => 1,
+        ValueRef::Integer(_) | ValueRef::Real(_) => 8,
+        ValueRef::Text(t) => t.len(),
+        ValueRef::Blob(b) => b.len()
+    }).sum()
+}
+
+/// Installs a progress handler that interrupts the statement about to be executed
+/// once the timeout is reached (or removes any previous handler if there is no timeout).
+pub fn set_deadline(conn : &rusqlite::Connection, limits : &QueryLimits) {
+    match limits.timeout_ms {
+        Some(ms) => {
+            let deadline = Instant::now() + Duration::from_millis(ms);
+            conn.progress_handler(1000, Some(move || Instant::now() > deadline ));
+        },
+        None => {
+            conn.progress_handler(0, None::<fn() -> bool>);
+        }
+    }
+}
+
+/// Error message for a failed statement, distinguishing statements interrupted by the deadline.
+pub fn error_message(e : &rusqlite::Error, limits : &QueryLimits) -> String {
+    match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::OperationInterrupted => {
+            limits.timeout_message()
+        },
+        e => e.to_string()
+    }
+}
+
+/// Columns of a table (optionally qualified by the schema), with their declared
+/// types and whether they belong to the primary key.
+pub fn table_columns(conn : &rusqlite::Connection, table : &str) -> Result<Vec<TableColumn>, String> {
+    let pragma = match table.find('.') {
+        Some(pos) => format!("pragma {}.table_info({});", &table[..pos], &table[pos+1..]),
+        None => format!("pragma table_info({});", table)
+    };
+    let mut stmt = conn.prepare(&pragma[..]).map_err(|e| format!("{}", e) )?;
+    let cols = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+        let sql_type : Option<String> = row.get(2)?;
+        let pk : i64 = row.get(5)?;
+        Ok(TableColumn { name : row.get(1)?, sql_type : sql_type.unwrap_or(String::new()), key : pk > 0 })
+    }).map_err(|e| format!("{}", e) )?;
+    cols.collect::<Result<Vec<_>, _>>().map_err(|e| format!("{}", e) )
+}
+
+/// Executes the statements in a single transaction, returning the number of affected rows.
+/// The transaction is rolled back if any statement fails, or if any statement that should
+/// affect a single row does not.
+pub fn apply_statements(conn : &mut rusqlite::Connection, stmts : &[EditStatement]) -> Result<usize, String> {
+    let tr = conn.transaction().map_err(|e| format!("{}", e) )?;
+    let mut n = 0;
+    for stmt in stmts.iter() {
+        let params : Vec<&dyn rusqlite::ToSql> = stmt.params.iter()
+            .map(|p| match p {
+                EditValue::Null => &rusqlite::types::Null as &dyn rusqlite::ToSql,
+                EditValue::Text(t) => t as &dyn rusqlite::ToSql,
+                EditValue::Bytes(b) => b as &dyn rusqlite::ToSql
+            })
+            .collect();
+        let changed = tr.execute(&stmt.sql[..], &params[..])
+            .map_err(|e| format!("{} failed: {}", stmt.description, e) )?;
+        if stmt.check_row && changed != 1 {
+            return Err(format!("{} not applied: the row was changed or removed since it was fetched", stmt.description));
+        }
+        n += changed;
+    }
+    tr.commit().map_err(|e| format!("{}", e) )?;
+    Ok(n)
+}
+
+pub fn build_table_from_sqlite(mut rows : rusqlite::Rows, limits : &QueryLimits) -> Result<Table, String>
     where
         NullableColumn : From<Vec<Option<i64>>>,
         NullableColumn : From<Vec<Option<f64>>>,
@@ -180,8 +259,24 @@ pub fn build_table_from_sqlite(mut rows : rusqlite::Rows) -> Result<Table, &'sta
     // let col_types : Vec<Option<&str>> = cols.iter().map(|c| c.decl_type()).collect();
     let names : Vec<_> = col_names.iter().map(|c| c.to_string()).collect();
     if names.len() == 0 {
-        return Err("No columns available");
+        return Err(String::from("No columns available"));
     }
+    let mut counter = limits.counter();
+    match read_rows(&mut rows, &names[..], None, &mut counter, limits)? {
+        Some(tbl) => Ok(tbl),
+        None => Ok(Table::new(None, names, empty_cols)?)
+    }
+}
+
+/// Reads at most max rows (all remaining rows if max is None) into a table, which is
+/// None if no rows were left.
+fn read_rows(
+    rows : &mut rusqlite::Rows,
+    names : &[String],
+    max : Option<usize>,
+    counter : &mut FetchCounter,
+    limits : &QueryLimits
+) -> Result<Option<Table>, String> {
     let mut sqlite_cols : Vec<SqliteColumn> = Vec::new();
     /*for (i, ty) in col_types.iter().enumerate() {
         if let Some(t) = ty {
@@ -191,9 +286,11 @@ pub fn build_table_from_sqlite(mut rows : rusqlite::Rows) -> Result<Table, &'sta
         }
     }*/
     let mut curr_row = 0;
-    while let Ok(row) = rows.next() {
+    while max.map(|m| curr_row < m ).unwrap_or(true) {
+        let row = rows.next().map_err(|e| error_message(&e, limits) )?;
         match row {
             Some(r) => {
+                counter.add_row(row_size(&r, names.len()))?;
                 if curr_row == 0 {
                     for c_ix in 0..names.len() {
                         sqlite_cols.push(SqliteColumn::new_from_first_value(&r, c_ix)?);
@@ -213,18 +310,52 @@ pub fn build_table_from_sqlite(mut rows : rusqlite::Rows) -> Result<Table, &'sta
         }
     }
     if curr_row == 0 {
-        Ok(Table::new(None, names, empty_cols)?)
+        Ok(None)
     } else {
         let mut null_cols : Vec<NullableColumn> = sqlite_cols
             .drain(0..sqlite_cols.len())
             .map(|c| c.into() ).collect();
         if null_cols.len() == 0 {
-            return Err("Too few columns");
+            return Err(String::from("Too few columns"));
         }
         let cols : Vec<Column> = null_cols.drain(0..null_cols.len())
             .map(|nc| nc.to_column()).collect();
-        Ok(Table::new(None, names, cols)?)
+        Ok(Some(Table::new(None, names.to_vec(), cols)?))
+    }
+}
+
+/// Runs a query, passing its rows to f in tables of at most chunk rows, read as they
+/// are needed, with the number of rows returned by the query. Rows are counted and
+/// read within a single transaction, so both see the same database state. Returns
+/// the number of rows read.
+pub fn stream_query(
+    conn : &rusqlite::Connection,
+    query : &str,
+    chunk : usize,
+    f : &mut dyn FnMut(Table, usize) -> Result<(), String>
+) -> Result<usize, String> {
+    let limits = QueryLimits::default();
+    let tr = conn.unchecked_transaction().map_err(|e| error_message(&e, &limits) )?;
+    let count = format!("select count(*) from ({}) as counted", query);
+    let total : i64 = tr.query_row(&count[..], rusqlite::NO_PARAMS, |row| row.get(0) )
+        .map_err(|e| error_message(&e, &limits) )?;
+    let mut n = 0;
+    {
+        let mut stmt = tr.prepare(query).map_err(|e| error_message(&e, &limits) )?;
+        let mut rows = stmt.query(rusqlite::NO_PARAMS).map_err(|e| error_message(&e, &limits) )?;
+        let names : Vec<String> = rows.column_names()
+            .ok_or("No columns available")?
+            .iter()
+            .map(|c| c.to_string() )
+            .collect();
+        let mut counter = limits.counter();
+        while let Some(tbl) = read_rows(&mut rows, &names[..], Some(chunk), &mut counter, &limits)? {
+            n += tbl.shape().0;
+            f(tbl, total as usize)?;
+        }
     }
+    tr.commit().map_err(|e| error_message(&e, &limits) )?;
+    Ok(n)
 }
 
 mod functions {
@@ -8,6 +8,9 @@ use std::string::ToString;
 use num_traits::cast::ToPrimitive;
 use std::str::FromStr;
 use std::default::Default;
+use super::csv_writer::{CsvDialect, LineTerminator};
+use super::export::{self, ExportColumn};
+use super::split::Dialect;
 
 /// Data-owning structure that encapsulate named columns.
 /// Implementation guarantees all columns are of the same size.
@@ -140,6 +143,21 @@ impl Table {
         self.cols.iter().map(|c| c.display_content(self.format.prec) ).collect()
     }
 
+    /// Number of decimal places used to show real numbers.
+    pub fn precision(&self) -> usize {
+        self.format.prec
+    }
+
+    /// Show a single field as text, with the same precision used by text_cols.
+    pub fn display_value(&self, row : usize, col : usize) -> Option<String> {
+        let col = self.cols.get(col)?;
+        if row < col.len() {
+            Some(col.display_value(row, self.format.prec))
+        } else {
+            None
+        }
+    }
+
     /// Returns a SQL string (valid for SQlite3/PostgreSQL subset)
     /// which will contain both the table creation and data insertion
     /// commands. Binary columns are created but will hold NULL. Fails
@@ -158,8 +176,9 @@ impl Table {
         }
     }
 
-    pub fn sql_types(&self) -> Vec<String> {
-        self.cols.iter().map(|c| c.sqlite3_type().to_string()).collect()
+    /// Types of the database columns which store the columns of this table, as named by the dialect.
+    pub fn sql_types(&self, dialect : Dialect) -> Vec<String> {
+        self.cols.iter().map(|c| c.sql_type(dialect).to_string()).collect()
     }
 
     pub fn sql_table_creation(&self, name : &str) -> Option<String> {
@@ -188,14 +207,14 @@ impl Table {
             return q;
         }
         content.remove(0);
-        let types = self.sql_types();
+        let types = self.sql_types(Dialect::Sqlite);
         q += &format!("insert into {} values ", name)[..];
         for (line_n, line) in content.iter().enumerate() {
             q += "(";
             for (i, (f, t)) in line.iter().zip(types.iter()).enumerate() {
                 match &t[..] {
-                    "text" => {
-                        let quoted = String::from("'") + f + "'";
+                    "TEXT" => {
+                        let quoted = String::from("'") + &f.replace('\'', "''") + "'";
                         q += &quoted
                     },
                     _ => { q +=&f }
@@ -216,74 +235,89 @@ impl Table {
         q
     }
 
-    /// Decide if column at ix should be displayed, according to the current display rules.
-    fn show_column(&self, ix : usize) -> bool {
-        if let Some(show) = self.format.show_only.as_ref() {
-            show.iter()
-                .find(|s| &s[..] == &self.names[ix][..] )
-                .is_some()
-        } else {
-            true
-        }
+    pub fn to_csv(&self) -> String {
+        self.csv_with(&self.format)
     }
 
-    pub fn to_csv(&self) -> String {
+    /// Writes the table as CSV, following the dialect, null and boolean representations,
+    /// numeric precision and column subset of the informed settings.
+    pub fn csv_with(&self, settings : &TableSettings) -> String {
+        let dialect = &settings.csv;
+        let null = settings.null_field.repr();
+        let cols = self.export_columns(settings);
         let mut content = String::new();
-        for row in self.text_rows() {
-            for (i, field) in row.iter().enumerate() {
-                // Skip columns that should not be shown
-                if self.show_column(i) {
-                    if i >= 1 {
-                        content += ",";
-                    }
-                    content += &field[..];
-                }
-            }
-            content += "\n";
+        if dialect.header {
+            dialect.write_record(cols.iter().map(|c| (&c.name[..], false) ), null, &mut content);
+        }
+        for r in 0..self.nrows {
+            let fields = cols.iter().map(|c| (c.text(r, settings), c.values[r].is_none()) );
+            dialect.write_record(fields, null, &mut content);
         }
         content
     }
 
-    pub fn to_markdown(&self) -> String {
-        let mut rows = self.text_rows();
-        let mut md = String::new();
-        for (i, row) in rows.drain(..).enumerate() {
-            for (j, field) in row.iter().enumerate() {
-                if self.show_column(j) {
-                    md += &format!("|{}", field);
-                }
-            }
-            md += &format!("|\n");
-            if i == 0 {
-                for j in 0..row.len() {
-                    if self.show_column(j) {
-                        let header_sep = match self.format.align {
-                            Align::Left => "|:---",
-                            Align::Center => "|:---:",
-                            Align::Right => "|---:",
-                        };
-                        md += header_sep;
-                    }
-                }
-                md += "|\n";
+    /// Columns shown according to the settings, prepared to be written by the exporters.
+    pub fn export_columns(&self, settings : &TableSettings) -> Vec<ExportColumn> {
+        self.names.iter().zip(self.cols.iter())
+            .filter(|(name, _)| settings.shows(name) )
+            .map(|(name, col)| {
+                let values = col.display_content(settings.prec).into_iter()
+                    .enumerate()
+                    .map(|(r, v)| if col.is_null(r) { None } else { Some(v) } )
+                    .collect();
+                ExportColumn { name : name.clone(), kind : col.value_kind(), values }
+            }).collect()
+    }
+
+    /// Writes the table in the format of the informed settings.
+    pub fn export(&self, settings : &TableSettings) -> String {
+        let cols = || self.export_columns(settings);
+        match settings.format {
+            Format::Csv => self.csv_with(settings),
+            Format::Markdown => export::to_markdown(&cols(), settings),
+            Format::Html => export::to_html(&cols(), settings),
+            Format::Latex => export::to_latex(&cols(), settings),
+            Format::Json => export::to_json_records(&cols()),
+            Format::JsonColumns => export::to_json_columns(&cols()),
+            Format::Ndjson => export::to_ndjson(&cols()),
+            Format::SqlInsert => {
+                let name = self.relation.as_ref().or(self.name.as_ref())
+                    .map(|n| &n[..] )
+                    .unwrap_or("query_result");
+                export::to_sql_insert(&cols(), name, settings)
             }
         }
-        md
+    }
+
+    pub fn to_markdown(&self) -> String {
+        export::to_markdown(&self.export_columns(&self.format), &self.format)
     }
 
     pub fn to_html(&self) -> String {
-        let mut html = String::new();
-        /*
-        <html>
-        <body>
-        <table>
-        <th><td>Header</td></th>
-        <tr><td>Hello</td></tr>
-        </table>
-        </body>
-        </html>
-        */
-        html
+        export::to_html(&self.export_columns(&self.format), &self.format)
+    }
+
+    /// Table with one row per field (holding the record number, the column name and the value
+    /// as text), similar to the expanded display of psql. Useful for tables with many columns.
+    pub fn expanded(&self) -> Result<Self, &'static str> {
+        let txt_cols = self.text_cols();
+        let mut records : Vec<i32> = Vec::new();
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        for r in 0..self.nrows {
+            for (c, name) in self.names.iter().enumerate() {
+                records.push((r + 1) as i32);
+                names.push(name.clone());
+                values.push(txt_cols[c][r].clone());
+            }
+        }
+        let mut tbl = Table::new(
+            self.name.clone(),
+            vec![String::from("Record"), String::from("Column"), String::from("Value")],
+            vec![Column::from(records), Column::from(names), Column::from(values)]
+        )?;
+        tbl.relation = self.relation.clone();
+        Ok(tbl)
     }
 
     pub fn shape(&self) -> (usize, usize) {
@@ -330,12 +364,7 @@ impl Table {
 impl Display for Table {
 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut content = match self.format.format {
-            Format::Csv => self.to_csv(),
-            Format::Markdown => self.to_markdown(),
-            Format::Html => unimplemented!()
-        };
-        write!(f, "{}", content)
+        write!(f, "{}", self.export(&self.format))
     }
 
 }
@@ -450,11 +479,26 @@ impl<'a> Columns<'a> {
 
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Format {
     Csv,
     Markdown,
-    Html
+    Html,
+
+    /// Tabular environment with booktabs rules.
+    Latex,
+
+    /// Array of records.
+    Json,
+
+    /// Object holding one array per column.
+    JsonColumns,
+
+    /// One JSON record per line.
+    Ndjson,
+
+    /// Script with one insert statement per row.
+    SqlInsert
 }
 
 impl FromStr for Format {
@@ -466,6 +510,11 @@ impl FromStr for Format {
             "CSV" => Ok(Format::Csv),
             "HTML" => Ok(Format::Html),
             "Markdown" => Ok(Format::Markdown),
+            "LaTeX" => Ok(Format::Latex),
+            "JSON (records)" => Ok(Format::Json),
+            "JSON (columns)" => Ok(Format::JsonColumns),
+            "NDJSON" => Ok(Format::Ndjson),
+            "SQL insert" => Ok(Format::SqlInsert),
             _ => Err(())
         }
     }
@@ -487,6 +536,26 @@ pub enum BoolField {
     Integer
 }
 
+impl BoolField {
+
+    /// Text written in place of a boolean value.
+    pub fn repr(&self, value : bool) -> &'static str {
+        match (self, value) {
+            (BoolField::Char, true) => "t",
+            (BoolField::Char, false) => "f",
+            (BoolField::CharUpper, true) => "T",
+            (BoolField::CharUpper, false) => "F",
+            (BoolField::Word, true) => "true",
+            (BoolField::Word, false) => "false",
+            (BoolField::WordUpper, true) => "TRUE",
+            (BoolField::WordUpper, false) => "FALSE",
+            (BoolField::Integer, true) => "1",
+            (BoolField::Integer, false) => "0"
+        }
+    }
+
+}
+
 impl FromStr for BoolField {
 
     type Err = ();
@@ -510,6 +579,19 @@ pub enum NullField {
     Omit
 }
 
+impl NullField {
+
+    /// Text written in place of null values.
+    pub fn repr(&self) -> &'static str {
+        match self {
+            NullField::Word => "null",
+            NullField::WordUpper => "NULL",
+            NullField::Omit => ""
+        }
+    }
+
+}
+
 impl FromStr for NullField {
 
     type Err = ();
@@ -531,7 +613,23 @@ pub struct TableSettings {
     pub bool_field : BoolField,
     pub null_field : NullField,
     pub prec : usize,
-    pub show_only : Option<Vec<String>>
+    pub show_only : Option<Vec<String>>,
+    pub csv : CsvDialect,
+
+    /// Whether HTML tables carry their own style (borders and padding) at each element.
+    pub inline_css : bool
+}
+
+impl TableSettings {
+
+    /// Whether the column with the informed name is exported.
+    pub fn shows(&self, name : &str) -> bool {
+        match &self.show_only {
+            Some(show) => show.iter().any(|s| &s[..] == name ),
+            None => true
+        }
+    }
+
 }
 
 impl Default for TableSettings {
@@ -543,7 +641,9 @@ impl Default for TableSettings {
             bool_field : BoolField::Word,
             null_field : NullField::Omit,
             prec : 8,
-            show_only : None
+            show_only : None,
+            csv : CsvDialect::default(),
+            inline_css : false
         }
     }
 
@@ -561,7 +661,9 @@ pub fn full_csv_display(tbl : &mut Table, cols : Vec<String>) -> String {
         bool_field : BoolField::Char,
         null_field : NullField::WordUpper,
         prec : 12,
-        show_only : show
+        show_only : show,
+        csv : CsvDialect { terminator : LineTerminator::Lf, ..CsvDialect::default() },
+        inline_css : false
     };
     tbl.update_format(fmt);
     format!("{}", tbl)
@@ -0,0 +1,315 @@
+use super::table::Table;
+use super::sql::SqlEngine;
+use super::environment::{DBObject, DBType};
+use super::split::Dialect;
+use super::upload::{self, UploadMode};
+use super::edit::{self, EditStatement};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::mpsc::Sender;
+
+/// Rows read from the source connection at a time, each chunk being written to the
+/// target connection in a single transaction.
+pub const DEFAULT_CHUNK : usize = 5000;
+
+/// Rows copied from the source connection into a table of the target connection.
+#[derive(Debug, Clone)]
+pub struct TransferItem {
+
+    /// Query which reads the rows at the source connection.
+    pub query : String,
+
+    /// Table written at the target connection (qualified by the target schema, if any).
+    pub name : String,
+
+    /// Columns of the source table, empty for query results (whose columns are only known
+    /// when the rows are read).
+    pub names : Vec<String>,
+
+    /// Types of the created columns, mapped from the source table. Columns without a type are
+    /// created from the values read.
+    pub types : Vec<Option<String>>
+}
+
+impl TransferItem {
+
+    /// Copies a whole table, as listed at the schema of the source connection (of dialect src). Types
+    /// are mapped to the dialect of the target connection (dst). Columns of a PostgreSQL source whose
+    /// values are not read into typed columns (e.g. timestamps, json or uuid) are read as text, then
+    /// converted back by the type of the target column. Timestamps with time zone copied into SQLite
+    /// are written as ISO-8601 text with their offset.
+    pub fn table(schema : Option<&str>, obj : &DBObject, src : Dialect, dst : Dialect) -> Option<Self> {
+        match obj {
+            DBObject::Table{ name, cols } => {
+                let names : Vec<String> = cols.iter().map(|(n, _)| n.clone() ).collect();
+                let selected : Vec<String> = cols.iter().map(|(n, ty)| {
+                    let quoted = edit::quote_identifier(n);
+                    match (src, ty) {
+                        (Dialect::Postgres, DBType::TimestampTz) if dst == Dialect::Sqlite => {
+                            format!("to_char({}, 'YYYY-MM-DD\"T\"HH24:MI:SS.USTZH:TZM') as {}", quoted, quoted)
+                        },
+                        (Dialect::Postgres, DBType::TimestampTz) |
+                        (Dialect::Postgres, DBType::Text) | (Dialect::Postgres, DBType::Time) |
+                        (Dialect::Postgres, DBType::Timestamp) | (Dialect::Postgres, DBType::Json) |
+                        (Dialect::Postgres, DBType::Xml) | (Dialect::Postgres, DBType::Array) |
+                        (Dialect::Postgres, DBType::Unknown) => format!("{}::text as {}", quoted, quoted),
+                        _ => quoted
+                    }
+                }).collect();
+                let source = match schema {
+                    Some(schema) => format!("{}.{}", edit::quote_identifier(schema), edit::quote_identifier(name)),
+                    None => edit::quote_identifier(name)
+                };
+                Some(Self {
+                    query : format!("select {} from {}", selected.join(", "), source),
+                    name : name.clone(),
+                    types : cols.iter().map(|(_, ty)| ty.sql_type(dst).map(|t| t.to_string() ) ).collect(),
+                    names
+                })
+            },
+            DBObject::Schema{ .. } => None
+        }
+    }
+
+    /// Copies the result of a query into the named table.
+    pub fn query(query : &str, name : &str) -> Result<Self, String> {
+        let query = query.trim().trim_end_matches(';').trim();
+        let name = name.trim();
+        if query.is_empty() {
+            return Err(String::from("Inform the query to transfer"));
+        }
+        if name.is_empty() {
+            return Err(String::from("Inform the name of the target table"));
+        }
+        Ok(Self { query : query.to_string(), name : name.to_string(), names : Vec::new(), types : Vec::new() })
+    }
+
+    /// Types of the created columns, taken from the source table or, when it has no counterpart at
+    /// the target, from the values read (if there are none, the column is created as text).
+    fn resolve_types(&self, names : &[String], read : Option<&Table>, dialect : Dialect) -> Vec<String> {
+        names.iter().enumerate().map(|(i, _)| {
+            self.types.get(i)
+                .cloned()
+                .and_then(|ty| ty )
+                .or(read.and_then(|tbl| tbl.get_column(i) ).map(|c| c.sql_type(dialect).to_string() ))
+                .unwrap_or(String::from(match dialect {
+                    Dialect::Postgres => "text",
+                    Dialect::Sqlite => "TEXT"
+                }))
+        }).collect()
+    }
+
+}
+
+/// Progress of a transfer running at a background thread.
+#[derive(Debug, Clone)]
+pub enum TransferStatus {
+    Progress(String),
+    Finished(Result<String, String>)
+}
+
+/// Copy of tables or query results from one open connection into another. Tables are
+/// created (or replaced, or appended to) at the target, then the rows are copied in chunks,
+/// and the number of rows written is verified against the number of rows at the source.
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub items : Vec<TransferItem>,
+
+    /// Schema of the target connection where the tables are written, created if it does not
+    /// exist. Only PostgreSQL connections have schemas.
+    pub schema : Option<String>,
+
+    pub mode : UploadMode,
+
+    pub chunk : usize
+}
+
+/// The source connection is held while its rows are written to the target, so the target is
+/// never waited for (two transfers in opposite directions would otherwise wait for each other).
+fn lock(engine : &Arc<Mutex<SqlEngine>>) -> Result<MutexGuard<SqlEngine>, String> {
+    engine.try_lock().map_err(|_| String::from("The connection is busy (wait for the running query to finish)") )
+}
+
+impl Transfer {
+
+    /// Name of a table at the target connection.
+    fn target_name(&self, name : &str) -> String {
+        match &self.schema {
+            Some(schema) => format!("{}.{}", edit::quote_identifier(schema), edit::quote_identifier(name)),
+            None => edit::quote_identifier(name)
+        }
+    }
+
+    /// Copies a single item, returning the number of rows copied. The source connection is
+    /// busy while its rows are read. The rows read are verified against the rows counted
+    /// at the same source snapshot, and against the rows added to the target table.
+    fn copy_item(
+        &self,
+        item : &TransferItem,
+        src : &Arc<Mutex<SqlEngine>>,
+        dst : &Arc<Mutex<SqlEngine>>,
+        dialect : Dialect,
+        progress : &Sender<TransferStatus>
+    ) -> Result<usize, String> {
+        let target = self.target_name(&item.name);
+        let counted = format!("select * from {}", target);
+        let mut expected = 0;
+        let before = match self.mode {
+            UploadMode::Append => lock(dst)?.count_rows(&counted)?,
+            _ => 0
+        };
+        let mut types : Option<Vec<String>> = None;
+        let mut written = 0;
+        let read = {
+            let mut write_chunk = |chunk : Table, total : usize| -> Result<(), String> {
+                expected = total;
+                let mut engine = lock(dst)?;
+                if types.is_none() {
+                    let names = chunk.names();
+                    let resolved = match self.mode {
+                        UploadMode::Append => {
+                            let existing = engine.table_columns(&target)?;
+                            upload::existing_types(&target, &names[..], &existing[..])?
+                        },
+                        _ => {
+                            let resolved = item.resolve_types(&names[..], Some(&chunk), dialect);
+                            let replace = self.mode == UploadMode::Replace;
+                            engine.apply_edits(&upload::create_statements(&target, &names[..], &resolved[..], replace)[..])?;
+                            resolved
+                        }
+                    };
+                    types = Some(resolved);
+                }
+                let stmts = upload::insert_statements(&chunk, &target, types.as_ref().unwrap(), dialect, written);
+                engine.apply_edits(&stmts[..])?;
+                written += chunk.shape().0;
+                let _ = progress.send(TransferStatus::Progress(format!("{}: {} of {} rows", item.name, written, expected)));
+                Ok(())
+            };
+            lock(src)?.stream_query(&item.query, self.chunk.max(1), &mut write_chunk)?
+        };
+
+        // Tables without rows are still created, if their columns are known.
+        if types.is_none() && self.mode != UploadMode::Append {
+            if item.names.is_empty() {
+                return Err(format!("Query for {} returned no rows, so its table was not created", item.name));
+            }
+            let resolved = item.resolve_types(&item.names[..], None, dialect);
+            let replace = self.mode == UploadMode::Replace;
+            lock(dst)?.apply_edits(&upload::create_statements(&target, &item.names[..], &resolved[..], replace)[..])?;
+        }
+
+        let after = lock(dst)?.count_rows(&counted)?;
+        if read != expected || after != before + read {
+            return Err(format!(
+                "Row counts of {} differ: {} rows at the source, {} rows read and {} rows at the target",
+                item.name,
+                expected,
+                read,
+                after.saturating_sub(before)
+            ));
+        }
+        Ok(read)
+    }
+
+    /// Copies all items from the source to the target connection (which must be different connections),
+    /// reporting the progress after each chunk. Each chunk is written in its own transaction, so a failed
+    /// transfer keeps the tables and rows written before the failure.
+    pub fn run(
+        &self,
+        src : &Arc<Mutex<SqlEngine>>,
+        dst : &Arc<Mutex<SqlEngine>>,
+        progress : &Sender<TransferStatus>
+    ) -> Result<String, String> {
+        if Arc::ptr_eq(src, dst) {
+            return Err(String::from("Choose different source and target connections"));
+        }
+        if self.items.is_empty() {
+            return Err(String::from("Nothing to transfer"));
+        }
+        let dialect = lock(dst)?.dialect();
+        if let Some(schema) = &self.schema {
+            if dialect == Dialect::Sqlite {
+                return Err(String::from("SQLite databases have no schemas"));
+            }
+            if self.mode != UploadMode::Append {
+                let stmt = EditStatement {
+                    sql : format!("CREATE SCHEMA IF NOT EXISTS {}", edit::quote_identifier(schema)),
+                    params : Vec::new(),
+                    check_row : false,
+                    description : format!("Creation of schema {}", schema)
+                };
+                lock(dst)?.apply_edits(&[stmt])?;
+            }
+        }
+        let mut total = 0;
+        for item in self.items.iter() {
+            total += self.copy_item(item, src, dst, dialect, progress)?;
+        }
+        Ok(format!("Transferred {} table(s) ({} rows). Row counts verified.", self.items.len(), total))
+    }
+
+}
+
+#[test]
+fn transfer_tables() {
+    use super::limits::QueryLimits;
+    use std::sync::mpsc::channel;
+    let src = Arc::new(Mutex::new(SqlEngine::try_new_local(String::new()).unwrap()));
+    let dst = Arc::new(Mutex::new(SqlEngine::try_new_local(String::new()).unwrap()));
+    let setup = "create table sales(id integer, note text); \
+        insert into sales values (1, 'a'), (2, null), (3, 'c'); \
+        create table empty(flag boolean);";
+    src.lock().unwrap().try_run(String::from(setup), false, &QueryLimits::default()).unwrap();
+    let sales = DBObject::Table {
+        name : String::from("sales"),
+        cols : vec![(String::from("id"), DBType::I32), (String::from("note"), DBType::Text)]
+    };
+    let empty = DBObject::Table { name : String::from("empty"), cols : vec![(String::from("flag"), DBType::Bool)] };
+    let item = TransferItem::table(None, &sales, Dialect::Sqlite, Dialect::Sqlite).unwrap();
+    assert_eq!(item.query, "select \"id\", \"note\" from \"sales\"");
+    assert_eq!(item.types, vec![Some(String::from("INTEGER")), Some(String::from("TEXT"))]);
+    let pg_item = TransferItem::table(Some("main"), &empty, Dialect::Sqlite, Dialect::Postgres).unwrap();
+    assert_eq!(pg_item.query, "select \"flag\" from \"main\".\"empty\"");
+    assert_eq!(pg_item.types, vec![Some(String::from("boolean"))]);
+
+    // Columns of a PostgreSQL source which are not read into typed columns are read as text.
+    let events = DBObject::Table {
+        name : String::from("events"),
+        cols : vec![
+            (String::from("id"), "bigint".parse().unwrap()),
+            (String::from("at"), "timestamp with time zone".parse().unwrap()),
+            (String::from("payload"), "jsonb".parse().unwrap())
+        ]
+    };
+    let pg_item = TransferItem::table(Some("public"), &events, Dialect::Postgres, Dialect::Postgres).unwrap();
+    assert_eq!(pg_item.query, "select \"id\", \"at\"::text as \"at\", \"payload\"::text as \"payload\" from \"public\".\"events\"");
+    assert_eq!(pg_item.types, vec![Some(String::from("bigint")), Some(String::from("timestamptz")), Some(String::from("jsonb"))]);
+    let sqlite_item = TransferItem::table(None, &events, Dialect::Postgres, Dialect::Sqlite).unwrap();
+    assert!(sqlite_item.query.contains("to_char(\"at\", 'YYYY-MM-DD\"T\"HH24:MI:SS.USTZH:TZM') as \"at\""));
+    assert_eq!(sqlite_item.types, vec![Some(String::from("INTEGER")), Some(String::from("TEXT")), Some(String::from("TEXT"))]);
+
+    let (sender, receiver) = channel();
+    let mut transfer = Transfer {
+        items : vec![item, TransferItem::table(None, &empty, Dialect::Sqlite, Dialect::Sqlite).unwrap()],
+        schema : None,
+        mode : UploadMode::Create,
+        chunk : 2
+    };
+    assert!(transfer.run(&src, &dst, &sender).is_ok());
+    assert_eq!(dst.lock().unwrap().count_rows("select * from sales").unwrap(), 3);
+    assert_eq!(dst.lock().unwrap().count_rows("select * from empty").unwrap(), 0);
+    let steps : Vec<TransferStatus> = receiver.try_iter().collect();
+    assert_eq!(steps.len(), 2);
+
+    // Tables already exist, unless replaced; rows can be appended to them.
+    assert!(transfer.run(&src, &dst, &sender).is_err());
+    transfer.mode = UploadMode::Append;
+    transfer.items = vec![TransferItem::query("select * from sales where id > 1;", "sales").unwrap()];
+    assert!(transfer.run(&src, &dst, &sender).is_ok());
+    assert_eq!(dst.lock().unwrap().count_rows("select * from sales").unwrap(), 5);
+    transfer.mode = UploadMode::Replace;
+    assert!(transfer.run(&src, &dst, &sender).is_ok());
+    assert_eq!(dst.lock().unwrap().count_rows("select * from sales").unwrap(), 2);
+    assert!(transfer.run(&src, &src, &sender).is_err());
+    assert!(TransferItem::query(" ; ", "sales").is_err());
+}
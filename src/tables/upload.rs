@@ -0,0 +1,215 @@
+use super::table::Table;
+use super::column::{Column, ValueKind};
+use super::split::Dialect;
+use super::edit::{self, TableColumn, EditStatement, EditValue, Params};
+
+/// Most parameters bound to a single insert statement (the default limit
+/// of SQLite before 3.32; PostgreSQL accepts many more).
+const MAX_PARAMS : usize = 999;
+
+/// What happens to the database table a table is uploaded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadMode {
+
+    /// Creates the table, failing if it already exists.
+    Create,
+
+    /// Inserts the rows into an existing table, matching the columns by name.
+    Append,
+
+    /// Drops the table if it exists, then creates it.
+    Replace
+}
+
+impl UploadMode {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UploadMode::Create => "create",
+            UploadMode::Append => "append",
+            UploadMode::Replace => "replace"
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "create" => Some(UploadMode::Create),
+            "append" => Some(UploadMode::Append),
+            "replace" => Some(UploadMode::Replace),
+            _ => None
+        }
+    }
+
+}
+
+/// Value at a cell, bound as text read back by the database column type. SQLite has no
+/// boolean literals at its integer columns, so booleans are written as 1 and 0.
+fn cell_value(col : &Column, row : usize, dialect : Dialect) -> EditValue {
+    if col.is_null(row) {
+        return EditValue::Null;
+    }
+    if let Some(b) = col.bytes_value(row) {
+        return EditValue::Bytes(b.to_vec());
+    }
+    match col.exact_value(row) {
+        Some(v) if dialect == Dialect::Sqlite && col.value_kind() == ValueKind::Bool => {
+            EditValue::Text(String::from(if v == "true" { "1" } else { "0" }))
+        },
+        Some(v) => EditValue::Text(v),
+        None => EditValue::Null
+    }
+}
+
+/// Statements creating the database table dst, with columns of the informed names and types,
+/// preceded by its removal if replace is true.
+pub fn create_statements(dst : &str, names : &[String], types : &[String], replace : bool) -> Vec<EditStatement> {
+    let mut stmts = Vec::new();
+    if replace {
+        stmts.push(EditStatement {
+            sql : format!("DROP TABLE IF EXISTS {}", dst),
+            params : Vec::new(),
+            check_row : false,
+            description : format!("Removal of table {}", dst)
+        });
+    }
+    let cols : Vec<String> = names.iter()
+        .zip(types.iter())
+        .map(|(name, ty)| format!("{} {}", edit::quote_identifier(name), ty) )
+        .collect();
+    stmts.push(EditStatement {
+        sql : format!("CREATE TABLE {} ({})", dst, cols.join(", ")),
+        params : Vec::new(),
+        check_row : false,
+        description : format!("Creation of table {}", dst)
+    });
+    stmts
+}
+
+/// Types of the columns of the database table dst which receive the columns of a table, matched
+/// by name. Fails if any column is missing (existing holds the columns of the database table).
+pub fn existing_types(dst : &str, names : &[String], existing : &[TableColumn]) -> Result<Vec<String>, String> {
+    if existing.is_empty() {
+        return Err(format!("Table {} not found", dst));
+    }
+    names.iter().map(|name| {
+        existing.iter()
+            .find(|c| &c.name == name )
+            .map(|c| c.sql_type.clone() )
+            .ok_or(format!("Column {} not found at table {}", name, dst))
+    }).collect()
+}
+
+/// Inserts of the rows of the table into the database table dst, in batches within the
+/// parameter limit. Values are converted to the informed types of the database columns.
+/// first_row is the position of the first row of the table among all rows being
+/// written, so tables written in chunks are described by their position.
+pub fn insert_statements(
+    tbl : &Table,
+    dst : &str,
+    types : &[String],
+    dialect : Dialect,
+    first_row : usize
+) -> Vec<EditStatement> {
+    let names = tbl.names();
+    let cols : Vec<&Column> = (0..names.len()).filter_map(|c| tbl.get_column(c) ).collect();
+    let quoted : Vec<String> = names.iter().map(|name| edit::quote_identifier(name) ).collect();
+    let nrows = tbl.shape().0;
+    let batch = (MAX_PARAMS / cols.len().max(1)).max(1);
+    let mut stmts = Vec::new();
+    let mut first = 0;
+    while first < nrows {
+        let last = (first + batch).min(nrows);
+        let mut params = Params::new(dialect);
+        let mut rows = Vec::new();
+        for r in first..last {
+            let values : Vec<String> = cols.iter()
+                .zip(types.iter())
+                .map(|(col, ty)| params.push(cell_value(col, r, dialect), ty) )
+                .collect();
+            rows.push(format!("({})", values.join(", ")));
+        }
+        stmts.push(EditStatement {
+            sql : format!("INSERT INTO {} ({}) VALUES {}", dst, quoted.join(", "), rows.join(", ")),
+            params : params.values,
+            check_row : false,
+            description : format!("Insert of rows {} to {}", first_row + first + 1, first_row + last)
+        });
+        first = last;
+    }
+    stmts
+}
+
+/// Statements writing the table to the database table dst in a single transaction: the table
+/// creation (preceded by its removal when replacing it), then inserts of batches of rows. When
+/// appending, existing holds the columns of the database table, which must have all the table
+/// columns (values are converted to the types of the database columns).
+pub fn upload_statements(
+    tbl : &Table,
+    dst : &str,
+    mode : UploadMode,
+    dialect : Dialect,
+    existing : &[TableColumn]
+) -> Result<Vec<EditStatement>, String> {
+    let dst = dst.trim();
+    if dst.is_empty() {
+        return Err(String::from("Inform the name of the database table"));
+    }
+    let names = tbl.names();
+    if names.is_empty() {
+        return Err(String::from("Table has no columns"));
+    }
+    let (mut stmts, types) = match mode {
+        UploadMode::Append => (Vec::new(), existing_types(dst, &names[..], existing)?),
+        UploadMode::Create | UploadMode::Replace => {
+            let types = tbl.sql_types(dialect);
+            (create_statements(dst, &names[..], &types[..], mode == UploadMode::Replace), types)
+        }
+    };
+    stmts.extend(insert_statements(tbl, dst, &types[..], dialect, 0));
+    Ok(stmts)
+}
+
+#[test]
+fn upload_tables() {
+    use super::nullable_column::NullableColumn;
+    use rust_decimal::Decimal;
+    let tbl = Table::new(
+        None,
+        vec![String::from("id"), String::from("price"), String::from("paid"), String::from("note")],
+        vec![
+            Column::I32(vec![1, 2]),
+            Column::Numeric(vec![Decimal::new(150, 2), Decimal::new(2, 0)]),
+            Column::Bool(vec![true, false]),
+            NullableColumn::from(vec![Some(String::from("it's")), None]).to_column()
+        ]
+    ).unwrap();
+    let stmts = upload_statements(&tbl, "sales", UploadMode::Replace, Dialect::Postgres, &[]).unwrap();
+    assert_eq!(stmts.len(), 3);
+    assert_eq!(stmts[0].sql, "DROP TABLE IF EXISTS sales");
+    assert_eq!(stmts[1].sql, "CREATE TABLE sales (\"id\" integer, \"price\" numeric, \"paid\" boolean, \"note\" text)");
+    assert!(stmts[2].sql.ends_with("VALUES ($1::text::integer, $2::text::numeric, $3::text::boolean, $4::text::text), \
+        ($5::text::integer, $6::text::numeric, $7::text::boolean, $8::text::text)"));
+    assert_eq!(stmts[2].params[1], EditValue::Text(String::from("1.50")));
+    assert_eq!(stmts[2].params[7], EditValue::Null);
+
+    let stmts = upload_statements(&tbl, "sales", UploadMode::Create, Dialect::Sqlite, &[]).unwrap();
+    assert_eq!(stmts[0].sql, "CREATE TABLE sales (\"id\" INTEGER, \"price\" NUMERIC, \"paid\" INTEGER, \"note\" TEXT)");
+    assert_eq!(stmts[1].params[2], EditValue::Text(String::from("1")));
+    assert_eq!(stmts[1].params[3], EditValue::Text(String::from("it's")));
+
+    let existing : Vec<TableColumn> = ["id", "price", "paid", "note", "created"].iter()
+        .map(|name| TableColumn { name : name.to_string(), sql_type : String::from("text"), key : false } )
+        .collect();
+    let stmts = upload_statements(&tbl, "sales", UploadMode::Append, Dialect::Postgres, &existing[..]).unwrap();
+    assert_eq!(stmts.len(), 1);
+    assert!(upload_statements(&tbl, "sales", UploadMode::Append, Dialect::Postgres, &existing[..2]).is_err());
+    assert!(upload_statements(&tbl, "sales", UploadMode::Append, Dialect::Postgres, &[]).is_err());
+    assert!(upload_statements(&tbl, " ", UploadMode::Create, Dialect::Postgres, &[]).is_err());
+
+    // Rows are split into batches within the parameter limit.
+    let wide = Table::new(None, (0..400).map(|c| format!("c{}", c) ).collect(), vec![Column::I64(vec![1, 2, 3]); 400]).unwrap();
+    let stmts = upload_statements(&wide, "wide", UploadMode::Create, Dialect::Sqlite, &[]).unwrap();
+    assert_eq!(stmts.len(), 3);
+    assert_eq!(stmts[1].params.len(), 800);
+    assert_eq!(stmts[2].description, "Insert of rows 3 to 3");
+}
@@ -0,0 +1,359 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+use toml::value::Table;
+use super::table::{TableSettings, Format, Align, BoolField, NullField};
+use super::limits::QueryLimits;
+use super::safety::SafetyMode;
+use super::csv_writer::{CsvDialect, LineTerminator, Encoding};
+
+/// Workspace saved on exit (and periodically, by the autosave), relative to the working directory.
+pub const WORKSPACE_PATH : &'static str = "registry/workspace.toml";
+
+/// A file open at the editor when the workspace was saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenFile {
+
+    /// Name shown at the file list.
+    pub name : String,
+
+    /// Empty if the file was never saved to disk.
+    pub path : Option<PathBuf>,
+
+    /// Text of the editor. Only kept when the file has no path or when the
+    /// text differs from what is saved at the path.
+    pub content : Option<String>,
+
+    /// Character offset of the cursor.
+    pub cursor : usize
+}
+
+/// Parameters of the connection that was active when the workspace was saved. The
+/// password is never stored, so remote connections must be re-opened by the user.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnProfile {
+    pub host : String,
+    pub user : String,
+    pub database : String,
+
+    /// SQLite3 database or CSV files the connection was opened from.
+    pub files : Vec<PathBuf>,
+    pub limits : QueryLimits,
+    pub safety : SafetyMode
+}
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub files : Vec<OpenFile>,
+
+    /// Index of the file selected at the file list.
+    pub selected : usize,
+    pub connection : Option<ConnProfile>,
+
+    /// Path of the plot layout XML.
+    pub layout : Option<String>,
+    pub table_settings : TableSettings
+}
+
+impl Workspace {
+
+    /// Reads the workspace saved at path. Returns None if no workspace was saved yet.
+    pub fn load(path : &Path) -> Result<Option<Self>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).map_err(|e| format!("Unable to read workspace: {}", e) )?;
+        let value = content.parse::<Value>().map_err(|e| format!("Invalid workspace file: {}", e) )?;
+        Self::from_value(&value).map(|ws| Some(ws) )
+    }
+
+    /// Writes the workspace to a temporary file which then replaces the file at path, so a
+    /// crash during the autosave never leaves a truncated workspace behind.
+    pub fn save(&self, path : &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Unable to create workspace directory: {}", e) )?;
+        }
+        let tmp_path = path.with_extension("toml.tmp");
+        fs::write(&tmp_path, self.to_value().to_string())
+            .map_err(|e| format!("Unable to write workspace: {}", e) )?;
+        fs::rename(&tmp_path, path).map_err(|e| format!("Unable to write workspace: {}", e) )
+    }
+
+    fn to_value(&self) -> Value {
+        let mut tbl = Table::new();
+        tbl.insert(String::from("selected"), Value::Integer(self.selected as i64));
+        if let Some(layout) = &self.layout {
+            tbl.insert(String::from("layout"), Value::String(layout.clone()));
+        }
+        tbl.insert(String::from("table_settings"), settings_to_value(&self.table_settings));
+        if let Some(conn) = &self.connection {
+            tbl.insert(String::from("connection"), profile_to_value(conn));
+        }
+        let files = self.files.iter().map(|f| {
+            let mut file_tbl = Table::new();
+            file_tbl.insert(String::from("name"), Value::String(f.name.clone()));
+            if let Some(path) = f.path.as_ref().and_then(|p| p.to_str() ) {
+                file_tbl.insert(String::from("path"), Value::String(path.to_string()));
+            }
+            if let Some(content) = &f.content {
+                file_tbl.insert(String::from("content"), Value::String(content.clone()));
+            }
+            file_tbl.insert(String::from("cursor"), Value::Integer(f.cursor as i64));
+            Value::Table(file_tbl)
+        }).collect();
+        tbl.insert(String::from("files"), Value::Array(files));
+        Value::Table(tbl)
+    }
+
+    fn from_value(value : &Value) -> Result<Self, String> {
+        let mut files = Vec::new();
+        if let Some(file_values) = value.get("files").and_then(|f| f.as_array() ) {
+            for f in file_values.iter() {
+                files.push(OpenFile {
+                    name : string_field(f, "name").ok_or(String::from("File entry without name"))?,
+                    path : string_field(f, "path").map(PathBuf::from),
+                    content : string_field(f, "content"),
+                    cursor : uint_field(f, "cursor").unwrap_or(0)
+                });
+            }
+        }
+        let connection = match value.get("connection") {
+            Some(conn) => Some(profile_from_value(conn)),
+            None => None
+        };
+        let table_settings = match value.get("table_settings") {
+            Some(settings) => settings_from_value(settings)?,
+            None => TableSettings::default()
+        };
+        Ok(Self {
+            files,
+            selected : uint_field(value, "selected").unwrap_or(0),
+            connection,
+            layout : string_field(value, "layout"),
+            table_settings
+        })
+    }
+
+}
+
+fn string_field(value : &Value, field : &str) -> Option<String> {
+    value.get(field).and_then(|v| v.as_str() ).map(|s| s.to_string() )
+}
+
+fn uint_field(value : &Value, field : &str) -> Option<usize> {
+    value.get(field).and_then(|v| v.as_integer() ).filter(|i| *i >= 0 ).map(|i| i as usize )
+}
+
+fn profile_to_value(conn : &ConnProfile) -> Value {
+    let mut tbl = Table::new();
+    tbl.insert(String::from("host"), Value::String(conn.host.clone()));
+    tbl.insert(String::from("user"), Value::String(conn.user.clone()));
+    tbl.insert(String::from("database"), Value::String(conn.database.clone()));
+    let files = conn.files.iter()
+        .filter_map(|p| p.to_str() )
+        .map(|p| Value::String(p.to_string()) )
+        .collect();
+    tbl.insert(String::from("files"), Value::Array(files));
+    if let Some(timeout) = conn.limits.timeout_ms {
+        tbl.insert(String::from("timeout_ms"), Value::Integer(timeout as i64));
+    }
+    if let Some(rows) = conn.limits.max_rows {
+        tbl.insert(String::from("max_rows"), Value::Integer(rows as i64));
+    }
+    if let Some(bytes) = conn.limits.max_bytes {
+        tbl.insert(String::from("max_bytes"), Value::Integer(bytes as i64));
+    }
+    tbl.insert(String::from("read_only"), Value::Boolean(conn.safety.read_only));
+    tbl.insert(String::from("production"), Value::Boolean(conn.safety.production));
+    Value::Table(tbl)
+}
+
+fn profile_from_value(value : &Value) -> ConnProfile {
+    let files = value.get("files")
+        .and_then(|f| f.as_array() )
+        .map(|files| files.iter().filter_map(|f| f.as_str() ).map(PathBuf::from).collect() )
+        .unwrap_or(Vec::new());
+    let flag = |field : &str| value.get(field).and_then(|v| v.as_bool() ).unwrap_or(false);
+    ConnProfile {
+        host : string_field(value, "host").unwrap_or(String::new()),
+        user : string_field(value, "user").unwrap_or(String::new()),
+        database : string_field(value, "database").unwrap_or(String::new()),
+        files,
+        limits : QueryLimits {
+            timeout_ms : uint_field(value, "timeout_ms").map(|t| t as u64 ),
+            max_rows : uint_field(value, "max_rows"),
+            max_bytes : uint_field(value, "max_bytes")
+        },
+        safety : SafetyMode { read_only : flag("read_only"), production : flag("production") }
+    }
+}
+
+fn settings_to_value(settings : &TableSettings) -> Value {
+    let format = match settings.format {
+        Format::Csv => "csv",
+        Format::Markdown => "markdown",
+        Format::Html => "html",
+        Format::Latex => "latex",
+        Format::Json => "json",
+        Format::JsonColumns => "json_columns",
+        Format::Ndjson => "ndjson",
+        Format::SqlInsert => "sql_insert"
+    };
+    let align = match settings.align {
+        Align::Left => "left",
+        Align::Center => "center",
+        Align::Right => "right"
+    };
+    let bool_field = match settings.bool_field {
+        BoolField::Char => "char",
+        BoolField::CharUpper => "char_upper",
+        BoolField::Word => "word",
+        BoolField::WordUpper => "word_upper",
+        BoolField::Integer => "integer"
+    };
+    let null_field = match settings.null_field {
+        NullField::Word => "word",
+        NullField::WordUpper => "word_upper",
+        NullField::Omit => "omit"
+    };
+    let mut tbl = Table::new();
+    tbl.insert(String::from("format"), Value::String(format.to_string()));
+    tbl.insert(String::from("align"), Value::String(align.to_string()));
+    tbl.insert(String::from("bool_field"), Value::String(bool_field.to_string()));
+    tbl.insert(String::from("null_field"), Value::String(null_field.to_string()));
+    tbl.insert(String::from("prec"), Value::Integer(settings.prec as i64));
+    tbl.insert(String::from("delimiter"), Value::String(settings.csv.delimiter.to_string()));
+    let terminator = match settings.csv.terminator {
+        LineTerminator::CrLf => "crlf",
+        LineTerminator::Lf => "lf"
+    };
+    tbl.insert(String::from("terminator"), Value::String(terminator.to_string()));
+    tbl.insert(String::from("header"), Value::Boolean(settings.csv.header));
+    let encoding = match settings.csv.encoding {
+        Encoding::Utf8 => "utf8",
+        Encoding::Utf8Bom => "utf8_bom",
+        Encoding::Latin1 => "latin1"
+    };
+    tbl.insert(String::from("encoding"), Value::String(encoding.to_string()));
+    tbl.insert(String::from("inline_css"), Value::Boolean(settings.inline_css));
+    if let Some(cols) = &settings.show_only {
+        let cols = cols.iter().map(|c| Value::String(c.clone()) ).collect();
+        tbl.insert(String::from("show_only"), Value::Array(cols));
+    }
+    Value::Table(tbl)
+}
+
+fn settings_from_value(value : &Value) -> Result<TableSettings, String> {
+    let default = TableSettings::default();
+    let field = |name : &str| string_field(value, name).unwrap_or(String::new());
+    let format = match &field("format")[..] {
+        "csv" | "" => Format::Csv,
+        "markdown" => Format::Markdown,
+        "html" => Format::Html,
+        "latex" => Format::Latex,
+        "json" => Format::Json,
+        "json_columns" => Format::JsonColumns,
+        "ndjson" => Format::Ndjson,
+        "sql_insert" => Format::SqlInsert,
+        other => return Err(format!("Invalid table format: {}", other))
+    };
+    let align = match &field("align")[..] {
+        "left" | "" => Align::Left,
+        "center" => Align::Center,
+        "right" => Align::Right,
+        other => return Err(format!("Invalid alignment: {}", other))
+    };
+    let bool_field = match &field("bool_field")[..] {
+        "char" => BoolField::Char,
+        "char_upper" => BoolField::CharUpper,
+        "word" | "" => BoolField::Word,
+        "word_upper" => BoolField::WordUpper,
+        "integer" => BoolField::Integer,
+        other => return Err(format!("Invalid boolean field: {}", other))
+    };
+    let null_field = match &field("null_field")[..] {
+        "word" => NullField::Word,
+        "word_upper" => NullField::WordUpper,
+        "omit" | "" => NullField::Omit,
+        other => return Err(format!("Invalid null field: {}", other))
+    };
+    let terminator = match &field("terminator")[..] {
+        "crlf" | "" => LineTerminator::CrLf,
+        "lf" => LineTerminator::Lf,
+        other => return Err(format!("Invalid line terminator: {}", other))
+    };
+    let encoding = match &field("encoding")[..] {
+        "utf8" | "" => Encoding::Utf8,
+        "utf8_bom" => Encoding::Utf8Bom,
+        "latin1" => Encoding::Latin1,
+        other => return Err(format!("Invalid encoding: {}", other))
+    };
+    let csv = CsvDialect {
+        delimiter : field("delimiter").chars().next().unwrap_or(default.csv.delimiter),
+        terminator,
+        header : value.get("header").and_then(|h| h.as_bool() ).unwrap_or(default.csv.header),
+        encoding
+    };
+    let show_only = value.get("show_only")
+        .and_then(|cols| cols.as_array() )
+        .map(|cols| cols.iter().filter_map(|c| c.as_str() ).map(|c| c.to_string() ).collect() );
+    Ok(TableSettings {
+        format,
+        align,
+        bool_field,
+        null_field,
+        prec : uint_field(value, "prec").unwrap_or(default.prec),
+        show_only,
+        csv,
+        inline_css : value.get("inline_css").and_then(|c| c.as_bool() ).unwrap_or(default.inline_css)
+    })
+}
+
+#[test]
+fn workspace_round_trip() {
+    let mut table_settings = TableSettings::default();
+    table_settings.null_field = NullField::WordUpper;
+    table_settings.prec = 3;
+    table_settings.csv.delimiter = '\t';
+    table_settings.csv.encoding = Encoding::Latin1;
+    table_settings.format = Format::JsonColumns;
+    table_settings.inline_css = true;
+    let ws = Workspace {
+        files : vec![
+            OpenFile {
+                name : String::from("Untitled 1"),
+                path : None,
+                content : Some(String::from("select 1;\n-- \"quoted\" and 'single'\n")),
+                cursor : 9
+            },
+            OpenFile {
+                name : String::from("sql/report.sql"),
+                path : Some(PathBuf::from("/home/user/sql/report.sql")),
+                content : None,
+                cursor : 0
+            }
+        ],
+        selected : 1,
+        connection : Some(ConnProfile {
+            host : String::from("localhost"),
+            user : String::from("user"),
+            database : String::from("sales"),
+            files : Vec::new(),
+            limits : QueryLimits { timeout_ms : Some(30000), max_rows : None, max_bytes : Some(1024) },
+            safety : SafetyMode { read_only : true, production : false }
+        }),
+        layout : Some(String::from("/home/user/layouts/scatter.xml")),
+        table_settings
+    };
+    let dir = std::env::temp_dir().join(format!("queries_workspace_{}", std::process::id()));
+    let path = dir.join("workspace.toml");
+    ws.save(&path).unwrap();
+    let loaded = Workspace::load(&path).unwrap().unwrap();
+    assert_eq!(loaded.files, ws.files);
+    assert_eq!(loaded.selected, 1);
+    assert_eq!(loaded.connection, ws.connection);
+    assert_eq!(loaded.layout, ws.layout);
+    assert_eq!(format!("{:?}", loaded.table_settings), format!("{:?}", ws.table_settings));
+    assert!(Workspace::load(&dir.join("missing.toml")).unwrap().is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,299 @@
+use gtk::*;
+use gtk::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::thread;
+use std::sync::mpsc::{self, TryRecvError};
+use crate::tables::environment::{TableEnvironment, DBObject};
+use crate::tables::transfer::{Transfer, TransferItem, TransferStatus, DEFAULT_CHUNK};
+use crate::tables::upload::UploadMode;
+use crate::status_stack::{StatusStack, Status};
+use crate::schema_tree::SchemaTree;
+
+/// Copies whole tables or a query result from one open connection into another (e.g. from a
+/// PostgreSQL server into a SQLite file). The rows are copied at a background thread, with
+/// the progress shown at the status stack.
+#[derive(Clone)]
+pub struct TransferWindow {
+    dialog : Dialog,
+    label : Label,
+    src_combo : ComboBoxText,
+    dst_combo : ComboBoxText,
+    kind_combo : ComboBoxText,
+    mode_combo : ComboBoxText,
+    stack : Stack,
+    tables_list : ListBox,
+    query_view : TextView,
+    name_entry : Entry,
+    schema_entry : Entry,
+    chunk_spin : SpinButton,
+
+    /// Tables of the source connection with their schemas (None for SQLite) and the
+    /// check buttons which choose them, in the order they are listed.
+    tables : Rc<RefCell<Vec<(Option<String>, DBObject, CheckButton)>>>,
+
+    /// Set while a transfer runs, since only one runs at a time.
+    running : Rc<RefCell<bool>>,
+    t_env : Rc<RefCell<TableEnvironment>>,
+    status_stack : StatusStack,
+    schema_tree : SchemaTree
+}
+
+impl TransferWindow {
+
+    pub fn build(
+        builder : &Builder,
+        t_env : Rc<RefCell<TableEnvironment>>,
+        status_stack : StatusStack,
+        schema_tree : SchemaTree
+    ) -> Self {
+        let dialog : Dialog = builder.get_object("transfer_dialog").unwrap();
+        let label : Label = builder.get_object("transfer_label").unwrap();
+        let src_combo : ComboBoxText = builder.get_object("transfer_src_combo").unwrap();
+        let dst_combo : ComboBoxText = builder.get_object("transfer_dst_combo").unwrap();
+        let kind_combo : ComboBoxText = builder.get_object("transfer_kind_combo").unwrap();
+        let mode_combo : ComboBoxText = builder.get_object("transfer_mode_combo").unwrap();
+        let stack : Stack = builder.get_object("transfer_stack").unwrap();
+        let tables_list : ListBox = builder.get_object("transfer_tables_list").unwrap();
+        let query_view : TextView = builder.get_object("transfer_query_view").unwrap();
+        let name_entry : Entry = builder.get_object("transfer_name_entry").unwrap();
+        let schema_entry : Entry = builder.get_object("transfer_schema_entry").unwrap();
+        let chunk_spin : SpinButton = builder.get_object("transfer_chunk_spin").unwrap();
+        chunk_spin.set_value(DEFAULT_CHUNK as f64);
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            glib::signal::Inhibit(true)
+        });
+        {
+            let stack = stack.clone();
+            kind_combo.connect_changed(move |combo| {
+                let kind = combo.get_active_id().map(|id| id.to_string() ).unwrap_or(String::from("tables"));
+                stack.set_visible_child_name(&kind);
+            });
+        }
+        let transfer_win = Self {
+            dialog,
+            label,
+            src_combo,
+            dst_combo,
+            kind_combo,
+            mode_combo,
+            stack,
+            tables_list,
+            query_view,
+            name_entry,
+            schema_entry,
+            chunk_spin,
+            tables : Rc::new(RefCell::new(Vec::new())),
+            running : Rc::new(RefCell::new(false)),
+            t_env,
+            status_stack,
+            schema_tree
+        };
+        {
+            let transfer_win = transfer_win.clone();
+            transfer_win.src_combo.clone().connect_changed(move |_| {
+                transfer_win.update_tables();
+            });
+        }
+        transfer_win
+    }
+
+    /// Lists the open connections, choosing the current one as the source and
+    /// another one as the target.
+    fn update_connections(&self) -> usize {
+        let (names, current) = match self.t_env.try_borrow() {
+            Ok(env) => (env.connection_names(), env.active_connection()),
+            Err(_) => {
+                println!("Unable to borrow table environment");
+                return 0;
+            }
+        };
+        self.src_combo.remove_all();
+        self.dst_combo.remove_all();
+        for name in names.iter() {
+            self.src_combo.append(Some(&name[..]), &name[..]);
+            self.dst_combo.append(Some(&name[..]), &name[..]);
+        }
+        if let Some(current) = current {
+            self.src_combo.set_active_id(Some(&current[..]));
+            if let Some(other) = names.iter().find(|n| *n != &current ) {
+                self.dst_combo.set_active_id(Some(&other[..]));
+            }
+        }
+        names.len()
+    }
+
+    /// Lists the tables of the source connection, each with a check button to choose it.
+    fn update_tables(&self) {
+        for row in self.tables_list.get_children() {
+            self.tables_list.remove(&row);
+        }
+        let mut tables = Vec::new();
+        if let Some(name) = self.src_combo.get_active_id() {
+            let engine = match self.t_env.try_borrow() {
+                Ok(env) => env.connection_engine(name.as_str(), false).map(|(engine, _)| engine ),
+                Err(_) => Err(String::from("Unable to borrow table environment"))
+            };
+            let objs = match engine {
+                Ok(engine) => match engine.try_lock() {
+                    Ok(mut engine) => engine.get_db_info().unwrap_or(Vec::new()),
+                    Err(_) => {
+                        self.label.set_text("The source connection is busy");
+                        Vec::new()
+                    }
+                },
+                Err(e) => {
+                    println!("{}", e);
+                    Vec::new()
+                }
+            };
+            for obj in objs {
+                match obj {
+                    DBObject::Schema{ name, children } => {
+                        for child in children {
+                            tables.push((Some(name.clone()), child));
+                        }
+                    },
+                    obj => tables.push((None, obj))
+                }
+            }
+        }
+        let mut listed = Vec::new();
+        for (schema, obj) in tables {
+            let ncols = match &obj {
+                DBObject::Table{ cols, .. } => cols.len(),
+                _ => 0
+            };
+            let text = match &schema {
+                Some(schema) => format!("{}.{} ({} columns)", schema, obj, ncols),
+                None => format!("{} ({} columns)", obj, ncols)
+            };
+            let check = CheckButton::with_label(&text);
+            check.set_margin_start(6);
+            check.set_margin_end(6);
+            self.tables_list.insert(&check, -1);
+            listed.push((schema, obj, check));
+        }
+        self.tables_list.show_all();
+        *self.tables.borrow_mut() = listed;
+    }
+
+    /// Reads the transfer chosen at the dialog and starts it at a background thread.
+    fn start(&self) -> Result<(), String> {
+        if *self.running.borrow() {
+            return Err(String::from("A transfer is already running"));
+        }
+        let src_name = self.src_combo.get_active_id().ok_or(String::from("Choose the source connection"))?;
+        let dst_name = self.dst_combo.get_active_id().ok_or(String::from("Choose the target connection"))?;
+        if src_name == dst_name {
+            return Err(String::from("Choose different source and target connections"));
+        }
+        let mode = self.mode_combo.get_active_id()
+            .and_then(|id| UploadMode::from_name(id.as_str()) )
+            .unwrap_or(UploadMode::Create);
+        let ((src, _), (dst, safety)) = match self.t_env.try_borrow() {
+            Ok(env) => (env.connection_engine(src_name.as_str(), false)?, env.connection_engine(dst_name.as_str(), true)?),
+            Err(_) => return Err(String::from("Unable to borrow table environment"))
+        };
+        if safety.production && mode == UploadMode::Replace {
+            return Err(format!("Tables of {} cannot be replaced, since it is a production connection", dst_name));
+        }
+        let src_dialect = src.try_lock().map_err(|_| String::from("The source connection is busy") )?.dialect();
+        let dst_dialect = dst.try_lock().map_err(|_| String::from("The target connection is busy") )?.dialect();
+        let items = match self.kind_combo.get_active_id().as_ref().map(|id| id.as_str() ) {
+            Some("query") => {
+                let buffer = self.query_view.get_buffer().ok_or(String::from("Query unavailable"))?;
+                let query = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+                    .map(|q| q.to_string() )
+                    .unwrap_or(String::new());
+                vec![TransferItem::query(&query, &self.name_entry.get_text())?]
+            },
+            _ => {
+                let tables = self.tables.borrow();
+                let items : Vec<TransferItem> = tables.iter()
+                    .filter(|(_, _, check)| check.get_active() )
+                    .filter_map(|(schema, obj, _)| TransferItem::table(schema.as_deref(), obj, src_dialect, dst_dialect) )
+                    .collect();
+                if items.is_empty() {
+                    return Err(String::from("Choose the tables to transfer"));
+                }
+                items
+            }
+        };
+        let schema = self.schema_entry.get_text().trim().to_string();
+        let transfer = Transfer {
+            items,
+            schema : if schema.is_empty() { None } else { Some(schema) },
+            mode,
+            chunk : self.chunk_spin.get_value_as_int().max(1) as usize
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let res = transfer.run(&src, &dst, &sender);
+            let _ = sender.send(TransferStatus::Finished(res));
+        });
+        *self.running.borrow_mut() = true;
+        self.status_stack.update(Status::StatementExecuted(format!("Transferring from {} to {}", src_name, dst_name)));
+        let running = self.running.clone();
+        let status_stack = self.status_stack.clone();
+        let schema_tree = self.schema_tree.clone();
+        let t_env = self.t_env.clone();
+        glib::timeout_add_local(200, move || {
+            loop {
+                match receiver.try_recv() {
+                    Ok(TransferStatus::Progress(msg)) => {
+                        status_stack.update(Status::StatementExecuted(msg));
+                    },
+                    Ok(TransferStatus::Finished(res)) => {
+                        *running.borrow_mut() = false;
+                        match res {
+                            Ok(msg) => status_stack.update(Status::StatementExecuted(msg)),
+                            Err(e) => status_stack.update(Status::SqlErr(format!("Transfer failed: {}", e)))
+                        }
+
+                        // Shows the new tables if the target is the current connection.
+                        schema_tree.repopulate(t_env.clone());
+                        return glib::Continue(false);
+                    },
+                    Err(TryRecvError::Empty) => return glib::Continue(true),
+                    Err(TryRecvError::Disconnected) => {
+                        *running.borrow_mut() = false;
+                        return glib::Continue(false);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Shows the dialog, which stays open, showing the error, if the transfer cannot be started.
+    pub fn show(&self) {
+        // Choosing the source connection lists its tables.
+        let n = self.update_connections();
+        if *self.running.borrow() {
+            self.label.set_text("A transfer is running. Its progress is shown at the status bar.");
+        } else if n < 2 {
+            self.label.set_text("Open the source and the target connections to transfer data between them");
+        } else {
+            self.label.set_text(
+                "Tables are created at the target and the rows are copied in chunks, \
+                verifying the row counts at the end. The source connection is busy during the transfer."
+            );
+        }
+        loop {
+            match self.dialog.run() {
+                ResponseType::Other(1) => {
+                    match self.start() {
+                        Ok(_) => break,
+                        Err(e) => self.label.set_text(&e)
+                    }
+                },
+                _ => break
+            }
+        }
+        self.dialog.hide();
+    }
+
+}
@@ -158,14 +158,19 @@ pub fn set_tables_from_query(
     } else {
         tables_nb.clear();
         table_bar.set_copy_to();
-        for table in all_tbls.iter() {
+        for (i, table) in all_tbls.iter().enumerate() {
             let info = table.table_info();
             tables_nb.create_data_table(
                 TableSource::Database(info.0, info.1),
-                table.text_rows(),
+                table,
                 workspace.clone(),
                 table_bar.clone()
             );
+            if let Some(diff) = table_env.get_diff(i) {
+                if let Some(tbl_wid) = tables_nb.expose_table(i) {
+                    tbl_wid.show_diff(diff);
+                }
+            }
         }
     }
 }
@@ -183,22 +188,11 @@ pub fn add_external_table(
 ) -> Result<(), String> {
     match Table::new_from_text(txt) {
         Ok(tbl) => {
-            let rows = tbl.text_rows();
-            if let Ok(mut t_env) = table_env.try_borrow_mut() {
-                if let Err(e) = t_env.append_external_table(tbl) {
-                    Err(format!("Error appending table: {}", e))?;
-                }
-            } else {
-                Err(format!("Unable to borrow table environment"))?;
-            }
             // If external table is opened by file, name as file name, without the extension,
             // and use blank page as icon.
             // If external table is opened by program, use Std. Output (progname) as name,
             // and use bash-symbolic as icon.
-            table_bar.set_copy_from();
-            tables_nb.create_data_table(source, rows, workspace.clone(), table_bar.clone() );
-            status_stack.update(Status::Ok);
-            Ok(())
+            add_table(table_env, tables_nb, source, tbl, workspace, table_bar, status_stack)
         },
         Err(e) => {
             status_stack.update(Status::SqlErr(e.into()));
@@ -207,6 +201,31 @@ pub fn add_external_table(
     }
 }
 
+/// Add a table held in memory (such as a table derived from the other
+/// tables) after the tables at the environment/notebook.
+pub fn add_table(
+    table_env : &Rc<RefCell<TableEnvironment>>,
+    tables_nb : &TableNotebook,
+    source : TableSource,
+    tbl : Table,
+    workspace : &PlotWorkspace,
+    table_bar : &TableBar,
+    status_stack : &StatusStack
+) -> Result<(), String> {
+    let shown = tbl.clone();
+    if let Ok(mut t_env) = table_env.try_borrow_mut() {
+        if let Err(e) = t_env.append_external_table(tbl) {
+            Err(format!("Error appending table: {}", e))?;
+        }
+    } else {
+        Err(format!("Unable to borrow table environment"))?;
+    }
+    table_bar.set_copy_from();
+    tables_nb.create_data_table(source, &shown, workspace.clone(), table_bar.clone() );
+    status_stack.update(Status::Ok);
+    Ok(())
+}
+
 fn exec_dir() -> Result<String, &'static str> {
     let exe_path = env::current_exe().map_err(|_| "Could not get executable path")?;
     let exe_dir = exe_path.as_path().parent().ok_or("CLI executable has no parent dir")?